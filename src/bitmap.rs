@@ -0,0 +1,297 @@
+use super::datatype::DataType;
+
+pub const ERRMSG_BADFIELDTYPE: &str = "ERR Invalid bitfield type. Use \
+	something like i16 u8. Note that u64 is not supported but i64 is.";
+pub const ERRMSG_BADOFFSET: &str =
+	"ERR bit offset is not an integer or out of range";
+pub const ERRMSG_BADBIT: &str = "ERR bit is not an integer or out of range";
+pub const ERRMSG_BADPOSBIT: &str = "ERR The bit argument must be 1 or 0.";
+const ERRMSG_BADOVERFLOW: &str = "ERR Invalid OVERFLOW type specified";
+const ERRMSG_NUMNAI: &str = "ERR value is not an integer or out of range";
+const ERRMSG_ROONLYGET: &str =
+	"ERR BITFIELD_RO only supports the GET subcommand";
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+
+// the largest bit offset, as strings are at most 512MB long
+const MAX_OFFSET: u64 = (512 << 23) - 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+	Wrap,
+	Sat,
+	Fail
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Encoding {
+	signed: bool,
+	bits: u32
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Field {
+	Get(Encoding, u64),
+	Set(Encoding, u64, i64),
+	IncrBy(Encoding, u64, i64),
+	Overflow(Overflow)
+}
+
+pub fn parse_offset(s: &str) -> Result<u64, &'static str> {
+	match s.parse::<u64>() {
+		Ok(n) if n <= MAX_OFFSET => Ok(n),
+		_ => Err(ERRMSG_BADOFFSET)
+	}
+}
+
+pub fn getbit(v: &[u8], off: u64) -> u8 {
+	match v.get((off / 8) as usize) {
+		Some(b) => (b >> (7 - off % 8)) & 1,
+		None => 0
+	}
+}
+
+// set the bit at off, growing v as needed, and return the previous bit
+pub fn setbit(v: &mut Vec<u8>, off: u64, bit: u8) -> u8 {
+	let i = (off / 8) as usize;
+	if v.len() <= i {
+		v.resize(i + 1, 0);
+	}
+	let mask = 1u8 << (7 - off % 8);
+	let old = (v[i] & mask != 0) as u8;
+	match bit {
+		0 => v[i] &= !mask,
+		_ => v[i] |= mask
+	}
+	old
+}
+
+// first and last index of a START END range over len units, None if empty
+fn span(start: i64, end: i64, len: u64) -> Option<(u64, u64)> {
+	let len = len as i64;
+	let start = if 0 > start {(start + len).max(0)} else {start};
+	let end = if 0 > end {(end + len).max(0)} else {end};
+	let end = end.min(len - 1);
+	match 0 < len && start <= end {
+		true => Some((start as u64, end as u64)),
+		false => None
+	}
+}
+
+// every bit of v
+pub fn whole(v: &[u8]) -> Option<(u64, u64)> {
+	match v.is_empty() {
+		true => None,
+		false => Some((0, v.len() as u64 * 8 - 1))
+	}
+}
+
+// a START END [BYTE | BIT] range as a span of bits
+pub fn bit_span(v: &[u8], prms: &[String])
+	-> Result<Option<(u64, u64)>, &'static str> {
+	let start = prms[0].parse::<i64>().map_err(|_| ERRMSG_NUMNAI)?;
+	let end = match prms.get(1) {
+		Some(e) => e.parse::<i64>().map_err(|_| ERRMSG_NUMNAI)?,
+		None => -1
+	};
+	let bits = match prms.get(2).map(|u| u.to_ascii_lowercase()).as_deref() {
+		Some("bit") => true,
+		Some("byte") | None => false,
+		_ => return Err(ERRMSG_SYNERR)
+	};
+	let len = v.len() as u64;
+	Ok(match bits {
+		true => span(start, end, len * 8),
+		false => span(start, end, len).map(|(s, e)| (s * 8, e * 8 + 7))
+	})
+}
+
+pub fn count(v: &[u8], span: Option<(u64, u64)>) -> u64 {
+	match span {
+		Some((s, e)) => (s..=e).map(|i| getbit(v, i) as u64).sum(),
+		None => 0
+	}
+}
+
+// the first bit set to bit within span, -1 if there is none
+pub fn pos(v: &[u8], bit: u8, span: Option<(u64, u64)>, end_given: bool)
+	-> i64 {
+	let (s, e) = match span {
+		Some(span) => span,
+		None => return -1
+	};
+	match (s..=e).find(|i| bit == getbit(v, *i)) {
+		Some(i) => i as i64,
+		// clear bits go on past the end of the string
+		None if 0 == bit && !end_given => e as i64 + 1,
+		None => -1
+	}
+}
+
+// AND, OR, XOR or NOT of the sources, the shorter ones padded with zeros
+pub fn op(name: &str, srcs: &[Vec<u8>]) -> Vec<u8> {
+	let len = srcs.iter().map(|s| s.len()).max().unwrap_or(0);
+	let byte = |s: &Vec<u8>, i: usize| s.get(i).copied().unwrap_or(0);
+	(0..len)
+		.map(|i| {
+			let mut it = srcs.iter().map(|s| byte(s, i));
+			let first = it.next().unwrap_or(0);
+			match name {
+				"and" => it.fold(first, |a, b| a & b),
+				"or" => it.fold(first, |a, b| a | b),
+				"xor" => it.fold(first, |a, b| a ^ b),
+				_ => !first
+			}
+		})
+		.collect()
+}
+
+fn parse_encoding(s: &str) -> Result<Encoding, &'static str> {
+	let signed = match s.chars().next() {
+		Some('i') | Some('I') => true,
+		Some('u') | Some('U') => false,
+		_ => return Err(ERRMSG_BADFIELDTYPE)
+	};
+	match s[1..].parse::<u32>() {
+		Ok(bits) if 0 < bits && bits <= if signed {64} else {63} =>
+			Ok(Encoding {signed, bits}),
+		_ => Err(ERRMSG_BADFIELDTYPE)
+	}
+}
+
+// N, or #N meaning N times the width of the field
+fn parse_field_offset(s: &str, enc: &Encoding) -> Result<u64, &'static str> {
+	let off = match s.strip_prefix('#') {
+		Some(n) => n.parse::<u64>().ok()
+			.and_then(|n| n.checked_mul(enc.bits as u64)),
+		None => s.parse::<u64>().ok()
+	};
+	match off {
+		Some(n) if n + enc.bits as u64 - 1 <= MAX_OFFSET => Ok(n),
+		_ => Err(ERRMSG_BADOFFSET)
+	}
+}
+
+// the subcommands of BITFIELD past the key, GET only when read_only
+pub fn parse_fields(prms: &[String], read_only: bool)
+	-> Result<Vec<Field>, &'static str> {
+	let mut fields = Vec::new();
+	let mut i = 0;
+	while i < prms.len() {
+		let sub = prms[i].to_ascii_lowercase();
+		if read_only && "get" != sub {
+			return Err(ERRMSG_ROONLYGET);
+		}
+		let arity = match sub.as_str() {
+			"overflow" => 1,
+			"get" => 2,
+			"set" | "incrby" => 3,
+			_ => return Err(ERRMSG_SYNERR)
+		};
+		let args = match prms.get(i + 1..i + 1 + arity) {
+			Some(args) => args,
+			None => return Err(ERRMSG_SYNERR)
+		};
+		if "overflow" == sub {
+			fields.push(Field::Overflow(
+				match args[0].to_ascii_lowercase().as_str() {
+					"wrap" => Overflow::Wrap,
+					"sat" => Overflow::Sat,
+					"fail" => Overflow::Fail,
+					_ => return Err(ERRMSG_BADOVERFLOW)
+				}
+			));
+			i += 2;
+			continue;
+		}
+		let enc = parse_encoding(&args[0])?;
+		let off = parse_field_offset(&args[1], &enc)?;
+		fields.push(match sub.as_str() {
+			"get" => Field::Get(enc, off),
+			_ => {
+				let n = args[2].parse::<i64>().map_err(|_| ERRMSG_NUMNAI)?;
+				match sub.as_str() {
+					"set" => Field::Set(enc, off, n),
+					_ => Field::IncrBy(enc, off, n)
+				}
+			}
+		});
+		i += 1 + arity;
+	}
+	Ok(fields)
+}
+
+fn get_field(v: &[u8], enc: &Encoding, off: u64) -> i64 {
+	let mut n: u64 = 0;
+	for i in 0..enc.bits as u64 {
+		n = (n << 1) | getbit(v, off + i) as u64;
+	}
+	match enc.signed && 64 > enc.bits && 0 != n >> (enc.bits - 1) {
+		true => (n | (u64::MAX << enc.bits)) as i64,
+		false => n as i64
+	}
+}
+
+fn set_field(v: &mut Vec<u8>, enc: &Encoding, off: u64, n: i64) {
+	let n = n as u64;
+	for i in 0..enc.bits as u64 {
+		setbit(v, off + i, ((n >> (enc.bits as u64 - 1 - i)) & 1) as u8);
+	}
+}
+
+// n brought within the range of enc as the overflow mode says, None when
+// the operation is to fail
+fn fit(n: i128, enc: &Encoding, mode: Overflow) -> Option<i64> {
+	let (min, max): (i128, i128) = match enc.signed {
+		true => (-(1 << (enc.bits - 1)), (1 << (enc.bits - 1)) - 1),
+		false => (0, (1 << enc.bits) - 1)
+	};
+	if min <= n && n <= max {
+		return Some(n as i64);
+	}
+	match mode {
+		Overflow::Fail => None,
+		Overflow::Sat => Some(n.clamp(min, max) as i64),
+		Overflow::Wrap =>
+			Some(((n - min).rem_euclid(max - min + 1) + min) as i64)
+	}
+}
+
+// run the fields against v, returning the replies and whether v changed
+pub fn apply(v: &mut Vec<u8>, fields: &[Field]) -> (Vec<DataType>, bool) {
+	let mut mode = Overflow::Wrap;
+	let mut changed = false;
+	let mut replies = Vec::new();
+	for f in fields {
+		match f {
+			Field::Overflow(m) => mode = *m,
+			Field::Get(enc, off) =>
+				replies.push(DataType::Integer(get_field(v, enc, *off))),
+			Field::Set(enc, off, n) => {
+				let old = get_field(v, enc, *off);
+				replies.push(match fit(*n as i128, enc, mode) {
+					Some(n) => {
+						set_field(v, enc, *off, n);
+						changed = true;
+						DataType::Integer(old)
+					},
+					None => DataType::Null
+				});
+			},
+			Field::IncrBy(enc, off, n) => {
+				let old = get_field(v, enc, *off);
+				replies.push(match fit(old as i128 + *n as i128, enc, mode) {
+					Some(n) => {
+						set_field(v, enc, *off, n);
+						changed = true;
+						DataType::Integer(n)
+					},
+					None => DataType::Null
+				});
+			}
+		}
+	}
+	(replies, changed)
+}
+
+#[cfg(test)]
+mod tests;
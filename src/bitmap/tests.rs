@@ -0,0 +1,72 @@
+use serial_test::serial;
+use super::*;
+
+fn strs(v: &[&str]) -> Vec<String> {
+	v.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	let mut v: Vec<u8> = Vec::new();
+	assert_eq!(setbit(&mut v, 7, 1), 0);
+	assert_eq!(setbit(&mut v, 7, 1), 1);
+	assert_eq!(v, vec![1u8]);
+	assert_eq!(getbit(&v, 7), 1);
+	assert_eq!(getbit(&v, 100), 0);
+	let v = b"foobar".to_vec();
+	assert_eq!(count(&v, whole(&v)), 26);
+	assert_eq!(count(&v, bit_span(&v, &strs(&["1", "1"])).unwrap()), 6);
+	assert_eq!(
+		count(&v, bit_span(&v, &strs(&["5", "30", "bit"])).unwrap()),
+		17
+	);
+	let v = vec![0xffu8, 0xf0, 0x00];
+	assert_eq!(pos(&v, 0, whole(&v), false), 12);
+	let span = bit_span(&v, &strs(&["2", "-1"])).unwrap();
+	assert_eq!(pos(&v, 1, span, true), -1);
+	let ones = vec![0xffu8];
+	assert_eq!(pos(&ones, 0, whole(&ones), false), 8);
+	assert_eq!(pos(&ones, 0, whole(&ones), true), -1);
+	assert_eq!(
+		op("and", &[b"foof".to_vec(), b"ab".to_vec()]),
+		vec![b'a' & b'f', b'b' & b'o', 0, 0]
+	);
+	assert_eq!(op("not", &[vec![0x0f]]), vec![0xf0]);
+	assert_eq!(parse_offset("4294967296"), Err(ERRMSG_BADOFFSET));
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	let mut v: Vec<u8> = Vec::new();
+	let fields = parse_fields(&strs(&[
+		"set", "i8", "0", "100", "incrby", "i8", "0", "100", "get", "u4", "#1"
+	]), false).unwrap();
+	assert_eq!(apply(&mut v, &fields), (vec![
+		DataType::Integer(0),
+		DataType::Integer(-56),
+		DataType::Integer(8)
+	], true));
+	let fields = parse_fields(&strs(&[
+		"overflow", "sat", "incrby", "u2", "100", "7",
+		"overflow", "fail", "incrby", "u2", "102", "7"
+	]), false).unwrap();
+	assert_eq!(apply(&mut v, &fields), (vec![
+		DataType::Integer(3),
+		DataType::Null
+	], true));
+	assert_eq!(
+		parse_fields(&strs(&["set", "u64", "0", "1"]), false),
+		Err(ERRMSG_BADFIELDTYPE)
+	);
+	assert_eq!(
+		parse_fields(&strs(&["set", "u8", "0", "1"]), true),
+		Err(ERRMSG_ROONLYGET)
+	);
+	let fields = parse_fields(&strs(&["get", "i64", "0"]), true).unwrap();
+	assert_eq!(
+		apply(&mut vec![0xff; 8], &fields).0,
+		vec![DataType::Integer(-1)]
+	);
+}
@@ -0,0 +1,226 @@
+use std::f64::consts::LN_2;
+
+use super::bytes;
+use super::hll::murmur64a;
+
+pub const ERRMSG_BADDATA: &str = "ERR received bad data";
+const ERRMSG_BADCAPACITY: &str = "ERR bad capacity";
+const ERRMSG_BADERROR: &str = "ERR bad error rate";
+const ERRMSG_BADEXPANSION: &str = "ERR bad expansion";
+const ERRMSG_CAPACITYRANGE: &str = "ERR (capacity should be larger than 0)";
+const ERRMSG_ERRORRANGE: &str = "ERR (0 < error rate range < 1)";
+const ERRMSG_EXPANSIONRANGE: &str =
+	"ERR expansion should be greater or equal to 1";
+const ERRMSG_FULL: &str = "ERR non scaling filter is full";
+const ERRMSG_NONSCALING: &str = "ERR Nonscaling filters cannot expand";
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+
+const MAGIC: &[u8] = b"KBF1";
+const SEED: u64 = 0xc6a4_a793_5bd1_e995;
+// every filter added when scaling halves the error rate of the previous
+const TIGHTENING: f64 = 0.5;
+
+// one fixed size filter of the chain
+#[derive(Clone, Debug, PartialEq)]
+struct Layer {
+	bits: Vec<u8>,
+	hashes: u64,
+	capacity: u64,
+	items: u64
+}
+
+impl Layer {
+	fn new(capacity: u64, error: f64) -> Layer {
+		let nbits = (capacity as f64 * -error.ln() / (LN_2 * LN_2)).ceil();
+		Layer {
+			bits: vec![0; (nbits as u64).max(64).div_ceil(8) as usize],
+			hashes: (-error.log2()).ceil().max(1.0) as u64,
+			capacity,
+			items: 0
+		}
+	}
+
+	// the bits set for an element hashing to h
+	fn positions(&self, h: (u64, u64)) -> Vec<usize> {
+		let n = 8 * self.bits.len() as u64;
+		(0..self.hashes)
+			.map(|i| (h.0.wrapping_add(i.wrapping_mul(h.1)) % n) as usize)
+			.collect()
+	}
+
+	fn contains(&self, h: (u64, u64)) -> bool {
+		self.positions(h).iter()
+			.all(|p| 0 != self.bits[p / 8] & (1 << (p % 8)))
+	}
+
+	fn insert(&mut self, h: (u64, u64)) {
+		for p in self.positions(h) {
+			self.bits[p / 8] |= 1 << (p % 8);
+		}
+		self.items += 1;
+	}
+}
+
+// a scalable Bloom filter, chaining a larger filter on whenever the last
+// one is full, unless expansion is 0
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bloom {
+	error: f64,
+	expansion: u64,
+	layers: Vec<Layer>
+}
+
+impl Eq for Bloom {}
+
+impl Default for Bloom {
+	fn default() -> Bloom {
+		Bloom::new(0.01, 100, 2)
+	}
+}
+
+// ERROR_RATE CAPACITY [EXPANSION expansion] [NONSCALING]
+pub fn parse_reserve(prms: &[String]) -> Result<Bloom, &'static str> {
+	let error = match prms[0].parse::<f64>() {
+		Ok(e) if 0.0 < e && e < 1.0 => e,
+		Ok(_) => return Err(ERRMSG_ERRORRANGE),
+		Err(_) => return Err(ERRMSG_BADERROR)
+	};
+	let capacity = match prms[1].parse::<i64>() {
+		Ok(n) if 0 < n => n as u64,
+		Ok(_) => return Err(ERRMSG_CAPACITYRANGE),
+		Err(_) => return Err(ERRMSG_BADCAPACITY)
+	};
+	let (mut expansion, mut nonscaling) = (None, false);
+	let mut i = 2;
+	while i < prms.len() {
+		match prms[i].to_ascii_lowercase().as_str() {
+			"expansion" => {
+				i += 1;
+				expansion = match prms.get(i).map(|s| s.parse::<i64>()) {
+					Some(Ok(n)) if 0 < n => Some(n as u64),
+					Some(Ok(_)) => return Err(ERRMSG_EXPANSIONRANGE),
+					_ => return Err(ERRMSG_BADEXPANSION)
+				};
+			},
+			"nonscaling" => nonscaling = true,
+			_ => return Err(ERRMSG_SYNERR)
+		}
+		i += 1;
+	}
+	match (expansion, nonscaling) {
+		(Some(_), true) => Err(ERRMSG_NONSCALING),
+		(_, true) => Ok(Bloom::new(error, capacity, 0)),
+		(e, false) => Ok(Bloom::new(error, capacity, e.unwrap_or(2)))
+	}
+}
+
+fn hash(el: &[u8]) -> (u64, u64) {
+	let a = murmur64a(el, SEED);
+	(a, murmur64a(el, a))
+}
+
+impl Bloom {
+	pub fn new(error: f64, capacity: u64, expansion: u64) -> Bloom {
+		Bloom {error, expansion, layers: vec![Layer::new(capacity, error)]}
+	}
+
+	pub fn contains(&self, el: &[u8]) -> bool {
+		let h = hash(el);
+		self.layers.iter().any(|l| l.contains(h))
+	}
+
+	// whether el was added, not being in the filter already
+	pub fn add(&mut self, el: &[u8]) -> Result<bool, &'static str> {
+		let h = hash(el);
+		if self.layers.iter().any(|l| l.contains(h)) {
+			return Ok(false);
+		}
+		let last = self.layers.last().unwrap();
+		if last.capacity <= last.items {
+			if 0 == self.expansion {
+				return Err(ERRMSG_FULL);
+			}
+			let tightening = TIGHTENING.powi(self.layers.len() as i32);
+			let l = Layer::new(
+				last.capacity.saturating_mul(self.expansion),
+				self.error * tightening
+			);
+			self.layers.push(l);
+		}
+		self.layers.last_mut().unwrap().insert(h);
+		Ok(true)
+	}
+
+	pub fn capacity(&self) -> u64 {
+		self.layers.iter().map(|l| l.capacity).sum()
+	}
+
+	// bytes taken by the bits of all the filters
+	pub fn size(&self) -> usize {
+		self.layers.iter().map(|l| l.bits.len()).sum()
+	}
+
+	pub fn filters(&self) -> usize {
+		self.layers.len()
+	}
+
+	pub fn items(&self) -> u64 {
+		self.layers.iter().map(|l| l.items).sum()
+	}
+
+	pub fn expansion(&self) -> Option<u64> {
+		match self.expansion {
+			0 => None,
+			n => Some(n)
+		}
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut v = MAGIC.to_vec();
+		v.extend_from_slice(&self.error.to_bits().to_le_bytes());
+		v.extend_from_slice(&self.expansion.to_le_bytes());
+		v.extend_from_slice(&(self.layers.len() as u64).to_le_bytes());
+		for l in self.layers.iter() {
+			for n in [l.hashes, l.capacity, l.items, l.bits.len() as u64] {
+				v.extend_from_slice(&n.to_le_bytes());
+			}
+			v.extend_from_slice(&l.bits);
+		}
+		v
+	}
+
+	pub fn parse(mut b: &[u8]) -> Result<Bloom, &'static str> {
+		match read(&mut b) {
+			Some(bf) if b.is_empty() => Ok(bf),
+			_ => Err(ERRMSG_BADDATA)
+		}
+	}
+}
+
+// the filter serialized at the start of b
+fn read(b: &mut &[u8]) -> Option<Bloom> {
+	if MAGIC != bytes::take(b, MAGIC.len())? {
+		return None;
+	}
+	let error = f64::from_bits(bytes::take_u64(b)?);
+	let expansion = bytes::take_u64(b)?;
+	let mut layers = vec![];
+	for _ in 0..bytes::take_u64(b)? {
+		let hashes = bytes::take_u64(b)?;
+		let capacity = bytes::take_u64(b)?;
+		let items = bytes::take_u64(b)?;
+		let n = bytes::take_u64(b)? as usize;
+		let bits = bytes::take(b, n)?.to_vec();
+		if 0 == hashes || 64 < hashes || bits.is_empty() {
+			return None;
+		}
+		layers.push(Layer {bits, hashes, capacity, items});
+	}
+	match 0.0 < error && error < 1.0 && !layers.is_empty() {
+		true => Some(Bloom {error, expansion, layers}),
+		false => None
+	}
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,36 @@
+use serial_test::serial;
+use super::*;
+
+fn prms(ps: &[&str]) -> Vec<String> {
+	ps.iter().map(|p| p.to_string()).collect()
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	assert_eq!(parse_reserve(&prms(&["1", "10"])), Err(ERRMSG_ERRORRANGE));
+	assert_eq!(parse_reserve(&prms(&["x", "10"])), Err(ERRMSG_BADERROR));
+	assert_eq!(parse_reserve(&prms(&["0.1", "0"])), Err(ERRMSG_CAPACITYRANGE));
+	let both = prms(&["0.1", "10", "expansion", "2", "nonscaling"]);
+	assert_eq!(parse_reserve(&both), Err(ERRMSG_NONSCALING));
+	let mut bf = parse_reserve(&prms(&["0.01", "100", "expansion", "4"]))
+		.unwrap();
+	for i in 0..1000 {
+		bf.add(format!("a{}", i).as_bytes()).unwrap();
+	}
+	assert!((0..1000).all(|i| bf.contains(format!("a{}", i).as_bytes())));
+	let misses = (0..1000)
+		.filter(|i| bf.contains(format!("b{}", i).as_bytes()))
+		.count();
+	assert!(misses < 20);
+	assert_eq!(bf.add(b"a1"), Ok(false));
+	assert_eq!((bf.filters(), bf.capacity()), (3, 2100));
+	assert_eq!(bf.expansion(), Some(4));
+	assert!(1000 - 20 < bf.items() && bf.items() <= 1000);
+	assert_eq!(Bloom::parse(&bf.to_bytes()), Ok(bf.clone()));
+	assert_eq!(Bloom::parse(&bf.to_bytes()[1..]), Err(ERRMSG_BADDATA));
+	let mut bf = parse_reserve(&prms(&["0.1", "1", "nonscaling"])).unwrap();
+	assert_eq!(bf.add(b"x"), Ok(true));
+	assert_eq!(bf.add(b"y"), Err(ERRMSG_FULL));
+	assert_eq!(bf.expansion(), None);
+}
@@ -0,0 +1,47 @@
+// Values are kept in Strings holding one char per byte, U+0000 to U+00FF,
+// so that anything a client sends comes back unchanged. Chars beyond that
+// range only appear in text produced by the server itself and go out UTF-8
+// encoded.
+
+// the string holding bytes b
+pub fn decode(b: &[u8]) -> String {
+	b.iter().map(|c| *c as char).collect()
+}
+
+// the bytes held by string s
+pub fn encode(s: &str) -> Vec<u8> {
+	let mut b = Vec::with_capacity(s.len());
+	for c in s.chars() {
+		match u8::try_from(c) {
+			Ok(c) => b.push(c),
+			Err(_) =>
+				b.extend_from_slice(c.encode_utf8(&mut [0u8; 4]).as_bytes())
+		}
+	}
+	b
+}
+
+// the number of bytes held by string s
+pub fn len(s: &str) -> usize {
+	s.chars().map(|c| if u8::try_from(c).is_ok() {1} else {c.len_utf8()}).sum()
+}
+
+// the next n bytes of b, advancing b past them
+pub fn take<'a>(b: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+	if b.len() < n {
+		return None;
+	}
+	let (head, tail) = b.split_at(n);
+	*b = tail;
+	Some(head)
+}
+
+// the little endian u64 b starts with, advancing b past it
+pub fn take_u64(b: &mut &[u8]) -> Option<u64> {
+	let mut n = [0u8; 8];
+	n.copy_from_slice(take(b, 8)?);
+	Some(u64::from_le_bytes(n))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,15 @@
+use serial_test::serial;
+use super::*;
+
+#[test]
+#[serial]
+fn plan1() {
+	let raw: Vec<u8> = (0..=255u8).collect();
+	let s = decode(&raw);
+	assert_eq!(encode(&s), raw);
+	assert_eq!(len(&s), 256);
+	assert_eq!(encode("abc"), b"abc".to_vec());
+	assert_eq!(encode("\u{20ac}"), "\u{20ac}".as_bytes().to_vec());
+	assert_eq!(len("\u{20ac}"), 3);
+	assert_eq!(decode("é".as_bytes()), "\u{c3}\u{a9}");
+}
@@ -4,11 +4,11 @@ use getopts::Options;
 use std::env;
 use std::error::Error;
 
-pub fn do_args() -> Result<(bool, String, String, usize), Box<dyn Error>> {
-	let mut to_quit = false;
-	let mut bindaddr = String::from("0.0.0.0:6379");
-	let mut thpoolsize: usize = 64;
-	let mut datafilepath = String::from("");
+use super::config;
+
+// parse the command line, loading the config file first so that explicit
+// options take precedence over it; returns whether to quit right away
+pub fn do_args() -> Result<bool, Box<dyn Error>> {
 	let args: Vec<String> = env::args().collect();
 	let progname = args[0].clone();
 
@@ -18,6 +18,11 @@ pub fn do_args() -> Result<(bool, String, String, usize), Box<dyn Error>> {
 		"bind address for inbound connections\n(default: \"0.0.0.0:6379\")",
 		"ADDR"
 	);
+	opts.optopt(
+		"c", "config",
+		"load settings from a redis.conf-style file",
+		"PATH"
+	);
 	opts.optopt(
 		"d", "datapath",
 		"restore data from file",
@@ -26,29 +31,35 @@ pub fn do_args() -> Result<(bool, String, String, usize), Box<dyn Error>> {
 	opts.optopt("t", "thpool", "threadpool size (default: 64)", "SIZE");
 	opts.optflag("h", "help", "print this help menu");
 
-	match opts.parse(&args[1..]) {
-		Ok(m) => {
-			if m.opt_present("h") {
-				print_usage(&progname, opts);
-				to_quit = true;
-			}
+	let m = opts.parse(&args[1..])?;
+	if m.opt_present("h") {
+		print_usage(&progname, opts);
+		return Ok(true);
+	}
 
-			if let Some(s) = m.opt_str("b") {
-				bindaddr = s.to_string();
-			}
+	if let Some(s) = m.opt_str("c") {
+		config::load(&s)?;
+	}
 
-			if let Some(s) = m.opt_str("t") {
-				thpoolsize = usize::from_str_radix(&s, 10).unwrap();
-			}
+	if let Some(s) = m.opt_str("b") {
+		match s.rsplit_once(':') {
+			Some((host, port)) => {
+				config::set_startup("bind", host)?;
+				config::set_startup("port", port)?;
+			},
+			None => config::set_startup("bind", &s)?
+		}
+	}
 
-			if let Some(s) = m.opt_str("d") {
-				datafilepath = s.to_string();
-			}
+	if let Some(s) = m.opt_str("t") {
+		config::set_startup("thpool", &s)?;
+	}
 
-			Ok((to_quit, datafilepath, bindaddr, thpoolsize))
-		},
-		Err(e) => Err(Box::new(e))
+	if let Some(s) = m.opt_str("d") {
+		config::set_startup("datapath", &s)?;
 	}
+
+	Ok(false)
 }
 
 fn print_usage(progname: &str, opts: Options) {
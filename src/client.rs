@@ -0,0 +1,407 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::net::{Shutdown, TcpStream};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+use super::datatype::DataType;
+
+const ERRMSG_BADNAME: &str =
+	"ERR Client names cannot contain spaces, newlines or special characters.";
+const ERRMSG_NOCLIENT: &str = "ERR No such client";
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+const ERRMSG_TIMEOUTNAI: &str = "ERR timeout is not an integer or out of range";
+
+// commands whose first parameter is a subcommand worth reporting
+const SUBCMDS: &[&str] = &["client", "config"];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Reply {
+	On,
+	Off,
+	// the CLIENT REPLY SKIP command itself is being answered
+	SkipThis,
+	// the command following CLIENT REPLY SKIP is being answered
+	SkipNext
+}
+
+struct Client {
+	id: u64,
+	addr: String,
+	laddr: String,
+	name: String,
+	lib_name: String,
+	lib_ver: String,
+	created: Instant,
+	last_active: Instant,
+	db: usize,
+	last_cmd: String,
+	qbuf: usize,
+	obuf: usize,
+	flags: String,
+	reply: Reply,
+	no_evict: bool,
+	// replication offset right after the last write of this client
+	last_write: u64,
+	stream: Option<TcpStream>
+}
+
+lazy_static! {
+	static ref CLIENTS: Mutex<HashMap<u64, Client>> =
+		Mutex::new(HashMap::new());
+	// deadline and whether every command (not just writes) is paused
+	static ref PAUSE: Mutex<Option<(Instant, bool)>> = Mutex::new(None);
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+	static CURRENT: Cell<u64> = const { Cell::new(0) };
+}
+
+pub fn register(stream: &TcpStream) -> u64 {
+	let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+	let now = Instant::now();
+	let c = Client {
+		id,
+		addr: match stream.peer_addr() {
+			Ok(a) => a.to_string(),
+			Err(_) => String::from("?:0")
+		},
+		laddr: match stream.local_addr() {
+			Ok(a) => a.to_string(),
+			Err(_) => String::from("?:0")
+		},
+		name: String::new(),
+		lib_name: String::new(),
+		lib_ver: String::new(),
+		created: now,
+		last_active: now,
+		db: 0,
+		last_cmd: String::from("NULL"),
+		qbuf: 0,
+		obuf: 0,
+		flags: String::from("N"),
+		reply: Reply::On,
+		no_evict: false,
+		last_write: 0,
+		stream: stream.try_clone().ok()
+	};
+	CLIENTS.lock().unwrap().insert(id, c);
+	id
+}
+
+pub fn unregister(id: u64) {
+	CLIENTS.lock().unwrap().remove(&id);
+}
+
+pub fn exists(id: u64) -> bool {
+	CLIENTS.lock().unwrap().contains_key(&id)
+}
+
+pub fn count() -> usize {
+	CLIENTS.lock().unwrap().len()
+}
+
+pub fn set_current(id: u64) {
+	CURRENT.with(|c| c.set(id));
+}
+
+pub fn current() -> u64 {
+	CURRENT.with(|c| c.get())
+}
+
+pub fn addr(id: u64) -> String {
+	match CLIENTS.lock().unwrap().get(&id) {
+		Some(c) => c.addr.clone(),
+		None => String::new()
+	}
+}
+
+pub fn set_flag(id: u64, flag: char) {
+	if let Some(c) = CLIENTS.lock().unwrap().get_mut(&id) {
+		if "N" == c.flags {
+			c.flags.clear();
+		}
+		if !c.flags.contains(flag) {
+			c.flags.push(flag);
+		}
+	}
+}
+
+pub fn clear_flag(id: u64, flag: char) {
+	if let Some(c) = CLIENTS.lock().unwrap().get_mut(&id) {
+		c.flags.retain(|f| f != flag);
+		if c.flags.is_empty() {
+			c.flags.push('N');
+		}
+	}
+}
+
+pub fn set_last_write(id: u64, offset: u64) {
+	if let Some(c) = CLIENTS.lock().unwrap().get_mut(&id) {
+		c.last_write = offset;
+	}
+}
+
+pub fn last_write(id: u64) -> u64 {
+	CLIENTS.lock().unwrap().get(&id).map_or(0, |c| c.last_write)
+}
+
+// record the command about to be executed along with the buffer sizes
+pub fn touch(id: u64, cmd: &str, prms: &[String], qbuf: usize, obuf: usize) {
+	if let Some(c) = CLIENTS.lock().unwrap().get_mut(&id) {
+		c.last_active = Instant::now();
+		c.last_cmd = match prms.first() {
+			Some(sub) if SUBCMDS.contains(&cmd) =>
+				format!("{}|{}", cmd, sub.to_ascii_lowercase()),
+			_ => cmd.to_string()
+		};
+		c.qbuf = qbuf;
+		c.obuf = obuf;
+	}
+}
+
+// whether the reply to the current command should reach the client
+pub fn reply_wanted(id: u64) -> bool {
+	match CLIENTS.lock().unwrap().get_mut(&id) {
+		Some(c) => match c.reply {
+			Reply::On => true,
+			Reply::Off => false,
+			Reply::SkipThis => {
+				c.reply = Reply::SkipNext;
+				false
+			},
+			Reply::SkipNext => {
+				c.reply = Reply::On;
+				false
+			}
+		},
+		None => true
+	}
+}
+
+// block the caller for as long as a CLIENT PAUSE applies to it
+pub fn wait_unpaused(is_write: bool) {
+	loop {
+		let remaining = match *PAUSE.lock().unwrap() {
+			Some((deadline, all)) if all || is_write =>
+				deadline.saturating_duration_since(Instant::now()),
+			_ => Duration::ZERO
+		};
+		if remaining.is_zero() {
+			return;
+		}
+		thread::sleep(remaining.min(Duration::from_millis(10)));
+	}
+}
+
+pub fn getname() -> Result<DataType, &'static str> {
+	match CLIENTS.lock().unwrap().get(&current()) {
+		Some(c) if !c.name.is_empty() => Ok(DataType::bulkStr(&c.name)),
+		_ => Ok(DataType::Null)
+	}
+}
+
+pub fn setname(name: &str) -> Result<DataType, &'static str> {
+	if name.bytes().any(|b| !(b'!'..=b'~').contains(&b)) {
+		return Err(ERRMSG_BADNAME);
+	}
+	if let Some(c) = CLIENTS.lock().unwrap().get_mut(&current()) {
+		c.name = name.to_string();
+	}
+	Ok(DataType::str("OK"))
+}
+
+pub fn setinfo(attr: &str, v: &str) -> Result<DataType, &'static str> {
+	if v.bytes().any(|b| !(b'!'..=b'~').contains(&b)) {
+		return Err(ERRMSG_BADNAME);
+	}
+	let mut clients = CLIENTS.lock().unwrap();
+	let c = match clients.get_mut(&current()) {
+		Some(c) => c,
+		None => return Ok(DataType::str("OK"))
+	};
+	match attr.to_ascii_lowercase().as_str() {
+		"lib-name" => c.lib_name = v.to_string(),
+		"lib-ver" => c.lib_ver = v.to_string(),
+		_ => return Err(ERRMSG_SYNERR)
+	}
+	Ok(DataType::str("OK"))
+}
+
+pub fn id() -> Result<DataType, &'static str> {
+	Ok(DataType::Integer(current() as i64))
+}
+
+pub fn info() -> Result<DataType, &'static str> {
+	match CLIENTS.lock().unwrap().get(&current()) {
+		Some(c) => Ok(DataType::bulkStr(&describe(c))),
+		None => Ok(DataType::bulkStr(""))
+	}
+}
+
+pub fn list(ids: &[String]) -> Result<DataType, &'static str> {
+	let mut wanted: Vec<u64> = Vec::new();
+	for s in ids.iter() {
+		match s.parse::<u64>() {
+			Ok(n) => wanted.push(n),
+			Err(_) => return Err(ERRMSG_SYNERR)
+		}
+	}
+	let clients = CLIENTS.lock().unwrap();
+	let mut cs = clients.values()
+		.filter(|c| wanted.is_empty() || wanted.contains(&c.id))
+		.collect::<Vec<_>>();
+	cs.sort_by_key(|c| c.id);
+	Ok(DataType::bulkStr(
+		&cs.iter().map(|c| describe(c)).collect::<Vec<_>>().join("")
+	))
+}
+
+// CLIENT KILL ip:port, or CLIENT KILL <filter value> [ filter value ... ]
+pub fn kill(prms: &[String]) -> Result<DataType, &'static str> {
+	let me = current();
+	let mut clients = CLIENTS.lock().unwrap();
+	if 1 == prms.len() {
+		return match clients.values().find(|c| c.addr == prms[0]) {
+			Some(c) => {
+				shutdown(c);
+				Ok(DataType::str("OK"))
+			},
+			None => Err(ERRMSG_NOCLIENT)
+		};
+	}
+	if !prms.len().is_multiple_of(2) {
+		return Err(ERRMSG_SYNERR);
+	}
+	let mut skipme = true;
+	let mut filters: Vec<(String, &str)> = Vec::new();
+	for pair in prms.chunks(2) {
+		let f = pair[0].to_ascii_lowercase();
+		match f.as_str() {
+			"skipme" => skipme = match pair[1].to_ascii_lowercase().as_str() {
+				"yes" => true,
+				"no" => false,
+				_ => return Err(ERRMSG_SYNERR)
+			},
+			"id" | "maxage" => if pair[1].parse::<u64>().is_err() {
+				return Err(ERRMSG_SYNERR);
+			} else {
+				filters.push((f, &pair[1]));
+			},
+			"addr" | "laddr" | "user" => filters.push((f, &pair[1])),
+			_ => return Err(ERRMSG_SYNERR)
+		}
+	}
+	let mut cnt = 0i64;
+	for c in clients.values_mut() {
+		if skipme && me == c.id {
+			continue;
+		}
+		let hit = filters.iter().all(|(f, v)| match f.as_str() {
+			"id" => c.id.to_string() == *v,
+			"addr" => c.addr == *v,
+			"laddr" => c.laddr == *v,
+			// there is no ACL yet, so everybody is the default user
+			"user" => "default" == *v,
+			"maxage" => c.created.elapsed().as_secs()
+				>= v.parse::<u64>().unwrap_or(0),
+			_ => false
+		});
+		if hit {
+			shutdown(c);
+			cnt += 1;
+		}
+	}
+	Ok(DataType::Integer(cnt))
+}
+
+pub fn pause(timeout: &str, mode: Option<&str>)
+	-> Result<DataType, &'static str> {
+	let ms = match timeout.parse::<u64>() {
+		Ok(n) => n,
+		Err(_) => return Err(ERRMSG_TIMEOUTNAI)
+	};
+	let all = match mode.map(|s| s.to_ascii_lowercase()).as_deref() {
+		None | Some("all") => true,
+		Some("write") => false,
+		Some(_) => return Err(ERRMSG_SYNERR)
+	};
+	let deadline = Instant::now() + Duration::from_millis(ms);
+	let mut p = PAUSE.lock().unwrap();
+	// a pause never shortens or weakens the one already in place
+	*p = match *p {
+		Some((d, a)) if d > Instant::now() =>
+			Some((d.max(deadline), a || all)),
+		_ => Some((deadline, all))
+	};
+	Ok(DataType::str("OK"))
+}
+
+pub fn unpause() -> Result<DataType, &'static str> {
+	*PAUSE.lock().unwrap() = None;
+	Ok(DataType::str("OK"))
+}
+
+pub fn reply(mode: &str) -> Result<DataType, &'static str> {
+	let r = match mode.to_ascii_lowercase().as_str() {
+		"on" => Reply::On,
+		"off" => Reply::Off,
+		"skip" => Reply::SkipThis,
+		_ => return Err(ERRMSG_SYNERR)
+	};
+	if let Some(c) = CLIENTS.lock().unwrap().get_mut(&current()) {
+		// SKIP while OFF keeps the client silent
+		if !(Reply::SkipThis == r && Reply::Off == c.reply) {
+			c.reply = r;
+		}
+	}
+	Ok(DataType::str("OK"))
+}
+
+pub fn no_evict(mode: &str) -> Result<DataType, &'static str> {
+	let flag = match mode.to_ascii_lowercase().as_str() {
+		"on" => true,
+		"off" => false,
+		_ => return Err(ERRMSG_SYNERR)
+	};
+	if let Some(c) = CLIENTS.lock().unwrap().get_mut(&current()) {
+		c.no_evict = flag;
+	}
+	Ok(DataType::str("OK"))
+}
+
+fn shutdown(c: &Client) {
+	if let Some(s) = &c.stream {
+		let _ = s.shutdown(Shutdown::Both);
+	}
+}
+
+fn describe(c: &Client) -> String {
+	let mut flags = c.flags.clone();
+	if c.no_evict {
+		flags.push('e');
+	}
+	format!(
+		"id={} addr={} laddr={} name={} age={} idle={} flags={} db={} \
+			qbuf={} obl={} cmd={} user=default lib-name={} lib-ver={}\n",
+		c.id,
+		c.addr,
+		c.laddr,
+		c.name,
+		c.created.elapsed().as_secs(),
+		c.last_active.elapsed().as_secs(),
+		flags,
+		c.db,
+		c.qbuf,
+		c.obuf,
+		c.last_cmd,
+		c.lib_name,
+		c.lib_ver
+	)
+}
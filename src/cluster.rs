@@ -0,0 +1,1147 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::RwLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use phf::phf_map;
+
+use super::bytes;
+use super::config;
+use super::datatype::DataType;
+use super::kv;
+use super::logger::{self, Level};
+use super::replication;
+use super::request::Request;
+use super::script;
+use super::stream;
+
+pub const SLOTS: usize = 16384;
+
+const ERRMSG_BADNODE: &str = "ERR Unknown node";
+const ERRMSG_BADPORT: &str = "ERR Invalid port";
+const ERRMSG_BADSLOT: &str = "ERR Invalid or out of range slot";
+const ERRMSG_BUSYSLOT: &str = "ERR Slot is already busy";
+const ERRMSG_CROSSSLOT: &str =
+	"CROSSSLOT Keys in request don't hash to the same slot";
+const ERRMSG_DBOOR: &str = "ERR DB index is out of range";
+const ERRMSG_DISABLED: &str =
+	"ERR This instance has cluster support disabled";
+const ERRMSG_IOERR: &str = "IOERR error or timeout talking to target instance";
+const ERRMSG_NOTOWNER: &str = "ERR I'm not the owner of this hash slot";
+const ERRMSG_OWNER: &str = "ERR I'm already the owner of this hash slot";
+const ERRMSG_SLOTHASKEYS: &str = "ERR Can't assign hashslot to a different \
+	node while I still hold keys for this hash slot.";
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+const ERRMSG_TIMEOUTNAI: &str = "ERR timeout is not an integer or out of range";
+const ERRMSG_TRYAGAIN: &str =
+	"TRYAGAIN Multiple keys request during rehashing of slot";
+const ERRMSG_UNASSIGNED: &str = "ERR Slot is already unassigned";
+const ERRMSG_UNSERVED: &str = "CLUSTERDOWN Hash slot not served";
+
+// where the keys of a command sit among its parameters: first and last
+// index (negative counting from the end) and the step in between
+static KEYSPECS: phf::Map<&str, (usize, i64, usize)> = phf_map! {
+	"append" => (0, 0, 1),
+	"bf.add" => (0, 0, 1),
+	"bf.card" => (0, 0, 1),
+	"bf.exists" => (0, 0, 1),
+	"bf.info" => (0, 0, 1),
+	"bf.loadchunk" => (0, 0, 1),
+	"bf.madd" => (0, 0, 1),
+	"bf.mexists" => (0, 0, 1),
+	"bf.reserve" => (0, 0, 1),
+	"bf.scandump" => (0, 0, 1),
+	"bitcount" => (0, 0, 1),
+	"bitfield" => (0, 0, 1),
+	"bitfield_ro" => (0, 0, 1),
+	"bitop" => (1, -1, 1),
+	"bitpos" => (0, 0, 1),
+	"cf.add" => (0, 0, 1),
+	"cf.addnx" => (0, 0, 1),
+	"cf.count" => (0, 0, 1),
+	"cf.del" => (0, 0, 1),
+	"cf.exists" => (0, 0, 1),
+	"cf.loadchunk" => (0, 0, 1),
+	"cf.reserve" => (0, 0, 1),
+	"cf.scandump" => (0, 0, 1),
+	"cms.incrby" => (0, 0, 1),
+	"cms.initbydim" => (0, 0, 1),
+	"cms.initbyprob" => (0, 0, 1),
+	"cms.loadchunk" => (0, 0, 1),
+	"cms.query" => (0, 0, 1),
+	"cms.scandump" => (0, 0, 1),
+	"decr" => (0, 0, 1),
+	"decrby" => (0, 0, 1),
+	"del" => (0, -1, 1),
+	"dump" => (0, 0, 1),
+	"geoadd" => (0, 0, 1),
+	"geodist" => (0, 0, 1),
+	"geohash" => (0, 0, 1),
+	"geopos" => (0, 0, 1),
+	"geosearch" => (0, 0, 1),
+	"geosearchstore" => (0, 1, 1),
+	"get" => (0, 0, 1),
+	"getbit" => (0, 0, 1),
+	"getdel" => (0, 0, 1),
+	"getex" => (0, 0, 1),
+	"getrange" => (0, 0, 1),
+	"getset" => (0, 0, 1),
+	"hdel" => (0, 0, 1),
+	"hexists" => (0, 0, 1),
+	"hexpire" => (0, 0, 1),
+	"hexpireat" => (0, 0, 1),
+	"hexpiretime" => (0, 0, 1),
+	"hget" => (0, 0, 1),
+	"hgetall" => (0, 0, 1),
+	"hgetdel" => (0, 0, 1),
+	"hgetex" => (0, 0, 1),
+	"hincrby" => (0, 0, 1),
+	"hincrbyfloat" => (0, 0, 1),
+	"hkeys" => (0, 0, 1),
+	"hlen" => (0, 0, 1),
+	"hmget" => (0, 0, 1),
+	"hmset" => (0, 0, 1),
+	"hpersist" => (0, 0, 1),
+	"hpexpire" => (0, 0, 1),
+	"hpexpireat" => (0, 0, 1),
+	"hpexpiretime" => (0, 0, 1),
+	"hpttl" => (0, 0, 1),
+	"hrandfield" => (0, 0, 1),
+	"hset" => (0, 0, 1),
+	"hsetnx" => (0, 0, 1),
+	"hstrlen" => (0, 0, 1),
+	"httl" => (0, 0, 1),
+	"hvals" => (0, 0, 1),
+	"incr" => (0, 0, 1),
+	"incrby" => (0, 0, 1),
+	"incrbyfloat" => (0, 0, 1),
+	"json.arrappend" => (0, 0, 1),
+	"json.arrinsert" => (0, 0, 1),
+	"json.arrlen" => (0, 0, 1),
+	"json.arrpop" => (0, 0, 1),
+	"json.del" => (0, 0, 1),
+	"json.get" => (0, 0, 1),
+	"json.mget" => (0, -2, 1),
+	"json.numincrby" => (0, 0, 1),
+	"json.objkeys" => (0, 0, 1),
+	"json.set" => (0, 0, 1),
+	"json.strappend" => (0, 0, 1),
+	"json.type" => (0, 0, 1),
+	"lcs" => (0, 1, 1),
+	"lindex" => (0, 0, 1),
+	"linsert" => (0, 0, 1),
+	"llen" => (0, 0, 1),
+	"lmove" => (0, 1, 1),
+	"lpop" => (0, 0, 1),
+	"lpos" => (0, 0, 1),
+	"lpush" => (0, 0, 1),
+	"lpushx" => (0, 0, 1),
+	"lrange" => (0, 0, 1),
+	"lrem" => (0, 0, 1),
+	"lset" => (0, 0, 1),
+	"ltrim" => (0, 0, 1),
+	"mget" => (0, -1, 1),
+	"mset" => (0, -1, 2),
+	"msetnx" => (0, -1, 2),
+	"pfadd" => (0, 0, 1),
+	"pfcount" => (0, -1, 1),
+	"pfmerge" => (0, -1, 1),
+	"pttl" => (0, 0, 1),
+	"rename" => (0, 1, 1),
+	"renamenx" => (0, 1, 1),
+	"restore" => (0, 0, 1),
+	"rpop" => (0, 0, 1),
+	"rpoplpush" => (0, 1, 1),
+	"rpush" => (0, 0, 1),
+	"rpushx" => (0, 0, 1),
+	"sadd" => (0, 0, 1),
+	"scard" => (0, 0, 1),
+	"sdiff" => (0, -1, 1),
+	"sdiffstore" => (0, -1, 1),
+	"set" => (0, 0, 1),
+	"setbit" => (0, 0, 1),
+	"setrange" => (0, 0, 1),
+	"sinter" => (0, -1, 1),
+	"sinterstore" => (0, -1, 1),
+	"sismember" => (0, 0, 1),
+	"smembers" => (0, 0, 1),
+	"smismember" => (0, 0, 1),
+	"smove" => (0, 1, 1),
+	"spop" => (0, 0, 1),
+	"srandmember" => (0, 0, 1),
+	"srem" => (0, 0, 1),
+	"strlen" => (0, 0, 1),
+	"substr" => (0, 0, 1),
+	"sunion" => (0, -1, 1),
+	"sunionstore" => (0, -1, 1),
+	"tdigest.add" => (0, 0, 1),
+	"tdigest.cdf" => (0, 0, 1),
+	"tdigest.create" => (0, 0, 1),
+	"tdigest.loadchunk" => (0, 0, 1),
+	"tdigest.max" => (0, 0, 1),
+	"tdigest.min" => (0, 0, 1),
+	"tdigest.quantile" => (0, 0, 1),
+	"tdigest.rank" => (0, 0, 1),
+	"tdigest.scandump" => (0, 0, 1),
+	"topk.add" => (0, 0, 1),
+	"topk.incrby" => (0, 0, 1),
+	"topk.list" => (0, 0, 1),
+	"topk.loadchunk" => (0, 0, 1),
+	"topk.query" => (0, 0, 1),
+	"topk.reserve" => (0, 0, 1),
+	"topk.scandump" => (0, 0, 1),
+	"ts.add" => (0, 0, 1),
+	"ts.create" => (0, 0, 1),
+	"ts.createrule" => (0, 1, 1),
+	"ts.decrby" => (0, 0, 1),
+	"ts.deleterule" => (0, 1, 1),
+	"ts.get" => (0, 0, 1),
+	"ts.incrby" => (0, 0, 1),
+	"ts.info" => (0, 0, 1),
+	"ts.loadchunk" => (0, 0, 1),
+	"ts.madd" => (0, -1, 3),
+	"ts.range" => (0, 0, 1),
+	"ts.revrange" => (0, 0, 1),
+	"ts.scandump" => (0, 0, 1),
+	"ttl" => (0, 0, 1),
+	"xack" => (0, 0, 1),
+	"xadd" => (0, 0, 1),
+	"xautoclaim" => (0, 0, 1),
+	"xclaim" => (0, 0, 1),
+	"xdel" => (0, 0, 1),
+	"xgroup" => (1, 1, 1),
+	"xinfo" => (1, 1, 1),
+	"xlen" => (0, 0, 1),
+	"xpending" => (0, 0, 1),
+	"xrange" => (0, 0, 1),
+	"xrevrange" => (0, 0, 1),
+	"xsetid" => (0, 0, 1),
+	"xtrim" => (0, 0, 1),
+	"zadd" => (0, 0, 1),
+	"zrem" => (0, 0, 1)
+};
+
+struct Node {
+	id: String,
+	host: String,
+	port: u16,
+	epoch: u64,
+	// last time a heartbeat went through, either way
+	seen: Option<Instant>
+}
+
+struct State {
+	myid: String,
+	// every known node, this one first
+	nodes: Vec<Node>,
+	// owner id of each slot
+	slots: Vec<Option<String>>,
+	migrating: HashMap<usize, String>,
+	importing: HashMap<usize, String>,
+	current_epoch: u64
+}
+
+lazy_static! {
+	static ref STATE: RwLock<State> = RwLock::new(State {
+		myid: String::new(),
+		nodes: Vec::new(),
+		slots: vec![None; SLOTS],
+		migrating: HashMap::new(),
+		importing: HashMap::new(),
+		current_epoch: 0
+	});
+}
+
+thread_local! {
+	// set by ASKING for the next command of the connection only
+	static ASKING: Cell<bool> = const { Cell::new(false) };
+}
+
+impl State {
+	fn node(&self, id: &str) -> Option<&Node> {
+		self.nodes.iter().find(|n| n.id == id)
+	}
+
+	fn endpoint(&self, id: &str) -> String {
+		match self.node(id) {
+			Some(n) => format!("{}:{}", n.host, n.port),
+			None => String::from(":0")
+		}
+	}
+
+	fn epoch(&self, id: &str) -> u64 {
+		self.node(id).map_or(0, |n| n.epoch)
+	}
+
+	fn owned_by(&self, id: &str) -> Vec<usize> {
+		(0..SLOTS).filter(|s| self.slots[*s].as_deref() == Some(id)).collect()
+	}
+
+	fn bump_epoch(&mut self) {
+		self.current_epoch += 1;
+		let epoch = self.current_epoch;
+		self.nodes[0].epoch = epoch;
+	}
+}
+
+pub fn enabled() -> bool {
+	"yes" == config::get("cluster-enabled")
+}
+
+// load the node table from cluster-config-file, or start a new one
+pub fn init() -> Result<(), String> {
+	let path = config::get("cluster-config-file");
+	let mut st = STATE.write().unwrap();
+	match fs::read_to_string(&path) {
+		Ok(s) => load(&mut st, &s).map_err(|e| format!("{}: {}", path, e))?,
+		Err(_) => {
+			st.myid = replication::new_replid();
+			st.nodes = vec![Node {
+				id: st.myid.clone(),
+				host: announce_ip(None),
+				port: config::get("port").parse::<u16>().unwrap_or(0),
+				epoch: 0,
+				seen: None
+			}];
+			save(&st);
+		}
+	}
+	logger::log(Level::Notice, &format!("Cluster node id {}", st.myid));
+	Ok(())
+}
+
+// keep every other node informed of our slots and of the nodes we know
+pub fn start_bus() {
+	thread::spawn(|| {
+		let mut links: HashMap<String, BufReader<TcpStream>> = HashMap::new();
+		loop {
+			thread::sleep(Duration::from_millis(500));
+			let peers = {
+				let st = STATE.read().unwrap();
+				st.nodes[1..].iter()
+					.map(|n| (n.id.clone(), format!("{}:{}", n.host, n.port)))
+					.collect::<Vec<_>>()
+			};
+			links.retain(|id, _| peers.iter().any(|(p, _)| p == id));
+			for (id, addr) in peers {
+				let ok = match links.remove(&id) {
+					Some(link) => Ok(link),
+					None => connect(&addr, Duration::from_secs(1))
+				}.and_then(|mut link| {
+					let req = heartbeat_req(link.get_ref());
+					exchange(&mut link, &req).map(|_| link)
+				});
+				match ok {
+					Ok(link) => {
+						links.insert(id.clone(), link);
+						let mut st = STATE.write().unwrap();
+						let node = st.nodes.iter_mut().find(|n| n.id == id);
+						if let Some(n) = node {
+							n.seen = Some(Instant::now());
+						}
+					},
+					Err(e) => logger::log(
+						Level::Debug,
+						&format!("Cluster bus to {}: {}", addr, e)
+					)
+				}
+			}
+		}
+	});
+}
+
+// CRC16-CCITT (XModem), as used for Redis hash slots
+pub fn crc16(bytes: &[u8]) -> u16 {
+	let mut crc = 0u16;
+	for b in bytes {
+		crc ^= (*b as u16) << 8;
+		for _ in 0..8 {
+			crc = if 0 != crc & 0x8000 {(crc << 1) ^ 0x1021} else {crc << 1};
+		}
+	}
+	crc
+}
+
+// only the part between the first { and the next } is hashed, if any
+pub fn key_slot(k: &str) -> usize {
+	let b = &bytes::encode(k)[..];
+	let tag = match b.iter().position(|c| b'{' == *c) {
+		Some(i) => match b[i + 1..].iter().position(|c| b'}' == *c) {
+			Some(0) | None => b,
+			Some(j) => &b[i + 1..i + 1 + j]
+		},
+		None => b
+	};
+	crc16(tag) as usize % SLOTS
+}
+
+pub fn keys(req: &Request) -> Vec<&str> {
+	let prms = &req.parameters;
+	if "migrate" == req.command {
+		return match prms.iter().position(|p| p.eq_ignore_ascii_case("keys")) {
+			Some(i) if 5 <= i && prms[2].is_empty() =>
+				prms[i + 1..].iter().map(|s| s.as_str()).collect(),
+			_ => prms.get(2).map(|s| s.as_str()).into_iter().collect()
+		};
+	}
+	if matches!(
+		req.command.as_str(),
+		"eval" | "eval_ro" | "evalsha" | "evalsha_ro" | "fcall" | "fcall_ro"
+	) {
+		return match script::split_keys(&prms[1..]) {
+			Ok((ks, _)) => ks.iter().map(|s| s.as_str()).collect(),
+			Err(_) => vec![]
+		};
+	}
+	if matches!(req.command.as_str(), "lmpop" | "sintercard") {
+		let n = prms.first().and_then(|n| n.parse::<usize>().ok()).unwrap_or(0);
+		return prms.iter().skip(1).take(n).map(|s| s.as_str()).collect();
+	}
+	if matches!(req.command.as_str(), "cms.merge" | "tdigest.merge") {
+		let n = prms.get(1).and_then(|n| n.parse::<usize>().ok()).unwrap_or(0);
+		return prms.iter().take(n.saturating_add(2)).enumerate()
+			.filter(|(i, _)| 1 != *i)
+			.map(|(_, s)| s.as_str())
+			.collect();
+	}
+	if matches!(req.command.as_str(), "xread" | "xreadgroup") {
+		return match stream::parse_read(prms) {
+			Ok(r) => {
+				let first = prms.len() - 2 * r.keys.len();
+				prms[first..first + r.keys.len()].iter().map(|s| s.as_str())
+					.collect()
+			},
+			Err(_) => vec![]
+		};
+	}
+	let (first, last, step) = match KEYSPECS.get(req.command.as_str()) {
+		Some(spec) => *spec,
+		None => return vec![]
+	};
+	let last = if 0 > last {prms.len() as i64 + last} else {last};
+	if 0 > last {
+		return vec![];
+	}
+	prms.iter().enumerate()
+		.skip(first)
+		.take(last as usize + 1 - first)
+		.step_by(step)
+		.map(|(_, s)| s.as_str())
+		.collect()
+}
+
+// check whether this node may serve the keys of req, otherwise give the
+// error redirecting the client
+pub fn route(req: &Request) -> Result<(), DataType> {
+	let asking = ASKING.with(|a| a.replace(false));
+	if !enabled() {
+		return Ok(());
+	}
+	let ks = keys(req);
+	let slot = match ks.first() {
+		Some(k) => key_slot(k),
+		None => return Ok(())
+	};
+	if ks.iter().any(|k| slot != key_slot(k)) {
+		return Err(DataType::err(ERRMSG_CROSSSLOT));
+	}
+	let st = STATE.read().unwrap();
+	match &st.slots[slot] {
+		Some(owner) if *owner == st.myid => {
+			let target = match st.migrating.get(&slot) {
+				Some(target) => target,
+				None => return Ok(())
+			};
+			// keys already moved are served by the importing node
+			match ks.iter().filter(|k| !kv::contains(k)).count() {
+				0 => Ok(()),
+				n if n == ks.len() => Err(DataType::err(&format!(
+					"ASK {} {}",
+					slot,
+					st.endpoint(target)
+				))),
+				_ => Err(DataType::err(ERRMSG_TRYAGAIN))
+			}
+		},
+		_ if asking && st.importing.contains_key(&slot) => Ok(()),
+		Some(owner) => Err(DataType::err(&format!(
+			"MOVED {} {}",
+			slot,
+			st.endpoint(owner)
+		))),
+		None => Err(DataType::err(ERRMSG_UNSERVED))
+	}
+}
+
+pub fn asking() -> Result<DataType, &'static str> {
+	if !enabled() {
+		return Err(ERRMSG_DISABLED);
+	}
+	ASKING.with(|a| a.set(true));
+	Ok(DataType::str("OK"))
+}
+
+pub fn check_enabled() -> Result<(), &'static str> {
+	if enabled() {Ok(())} else {Err(ERRMSG_DISABLED)}
+}
+
+pub fn keyslot(k: &str) -> Result<DataType, &'static str> {
+	Ok(DataType::Integer(key_slot(k) as i64))
+}
+
+pub fn countkeysinslot(s: &str) -> Result<DataType, &'static str> {
+	let slot = parse_slot(s)?;
+	Ok(DataType::Integer(
+		kv::keys_where(|k| slot == key_slot(k), usize::MAX).len() as i64
+	))
+}
+
+pub fn getkeysinslot(s: &str, n: &str) -> Result<DataType, &'static str> {
+	let slot = parse_slot(s)?;
+	let count = match n.parse::<usize>() {
+		Ok(n) => n,
+		Err(_) => return Err("ERR Invalid number of keys")
+	};
+	Ok(DataType::List(
+		kv::keys_where(|k| slot == key_slot(k), count).iter()
+			.map(|k| DataType::bulkStr(k))
+			.collect()
+	))
+}
+
+pub fn myid() -> Result<DataType, &'static str> {
+	Ok(DataType::bulkStr(&STATE.read().unwrap().myid))
+}
+
+// ADDSLOTS and DELSLOTS, every slot being checked before any is changed
+pub fn assign(ss: &[usize], add: bool) -> Result<DataType, &'static str> {
+	let mut st = STATE.write().unwrap();
+	for s in ss.iter() {
+		match (&st.slots[*s], add) {
+			(Some(_), true) => return Err(ERRMSG_BUSYSLOT),
+			(None, false) => return Err(ERRMSG_UNASSIGNED),
+			_ => {}
+		}
+	}
+	let me = if add {Some(st.myid.clone())} else {None};
+	for s in ss.iter() {
+		st.slots[*s] = me.clone();
+		st.migrating.remove(s);
+		st.importing.remove(s);
+	}
+	save(&st);
+	Ok(DataType::str("OK"))
+}
+
+pub fn parse_slots(ss: &[String]) -> Result<Vec<usize>, &'static str> {
+	ss.iter().map(|s| parse_slot(s)).collect()
+}
+
+pub fn parse_ranges(ss: &[String]) -> Result<Vec<usize>, &'static str> {
+	if !ss.len().is_multiple_of(2) {
+		return Err(ERRMSG_SYNERR);
+	}
+	let mut slots: Vec<usize> = Vec::new();
+	for x in ss.chunks(2) {
+		let (a, b) = (parse_slot(&x[0])?, parse_slot(&x[1])?);
+		if a > b {
+			return Err(ERRMSG_BADSLOT);
+		}
+		slots.extend(a..=b);
+	}
+	Ok(slots)
+}
+
+pub fn setslot(s: &str, how: &str, id: Option<&str>)
+	-> Result<DataType, &'static str> {
+	let slot = parse_slot(s)?;
+	let mut st = STATE.write().unwrap();
+	if let Some(id) = id {
+		if st.node(id).is_none() {
+			return Err(ERRMSG_BADNODE);
+		}
+	}
+	let mine = st.slots[slot].as_deref() == Some(st.myid.as_str());
+	match (how.to_ascii_lowercase().as_str(), id) {
+		("migrating", Some(id)) => {
+			if !mine {
+				return Err(ERRMSG_NOTOWNER);
+			}
+			st.migrating.insert(slot, id.to_string());
+		},
+		("importing", Some(id)) => {
+			if mine {
+				return Err(ERRMSG_OWNER);
+			}
+			st.importing.insert(slot, id.to_string());
+		},
+		("stable", None) => {
+			st.migrating.remove(&slot);
+			st.importing.remove(&slot);
+		},
+		("node", Some(id)) => {
+			if mine && id != st.myid
+				&& !kv::keys_where(|k| slot == key_slot(k), 1).is_empty() {
+				return Err(ERRMSG_SLOTHASKEYS);
+			}
+			st.slots[slot] = Some(id.to_string());
+			st.migrating.remove(&slot);
+			// a newly imported slot must win against the old owner's claim
+			if id == st.myid && st.importing.remove(&slot).is_some() {
+				st.bump_epoch();
+			}
+		},
+		_ => return Err(ERRMSG_SYNERR)
+	}
+	save(&st);
+	Ok(DataType::str("OK"))
+}
+
+// introduce this node to the one at host:port, which then tells everybody
+// else through the bus
+pub fn meet(host: &str, port: &str) -> Result<DataType, &'static str> {
+	let port = match port.parse::<u16>() {
+		Ok(n) => n,
+		Err(_) => return Err(ERRMSG_BADPORT)
+	};
+	let addr = format!("{}:{}", host, port);
+	let mut link = match connect(&addr, Duration::from_secs(1)) {
+		Ok(link) => link,
+		Err(_) => return Err(ERRMSG_IOERR)
+	};
+	let req = heartbeat_req(link.get_ref());
+	let id = match exchange(&mut link, &req).and_then(|_| exchange(
+		&mut link,
+		&Request {
+			command: String::from("cluster"),
+			parameters: vec![String::from("myid")]
+		}
+	)) {
+		Ok(id) => id,
+		Err(_) => return Err(ERRMSG_IOERR)
+	};
+	let mut st = STATE.write().unwrap();
+	if id != st.myid && st.node(&id).is_none() {
+		st.nodes.push(Node {
+			id,
+			host: host.to_string(),
+			port,
+			epoch: 0,
+			seen: Some(Instant::now())
+		});
+		save(&st);
+	}
+	Ok(DataType::str("OK"))
+}
+
+// CLUSTER HEARTBEAT id host port epoch slots [id@host:port ...]
+pub fn heartbeat(prms: &[String]) -> Result<DataType, &'static str> {
+	if 5 > prms.len() {
+		return Err(ERRMSG_SYNERR);
+	}
+	let (id, host) = (&prms[0], &prms[1]);
+	let (port, epoch) = match (prms[2].parse::<u16>(), prms[3].parse::<u64>()) {
+		(Ok(p), Ok(e)) if is_id(id) => (p, e),
+		_ => return Err(ERRMSG_SYNERR)
+	};
+	let claims = match parse_claims(&prms[4]) {
+		Some(c) => c,
+		None => return Err(ERRMSG_SYNERR)
+	};
+	let mut st = STATE.write().unwrap();
+	if *id == st.myid {
+		return Err(ERRMSG_SYNERR);
+	}
+	let mut changed = match st.nodes.iter_mut().find(|n| n.id == *id) {
+		Some(n) => {
+			let changed = n.host != *host || n.port != port || n.epoch != epoch;
+			n.host = host.clone();
+			n.port = port;
+			n.epoch = epoch;
+			n.seen = Some(Instant::now());
+			changed
+		},
+		None => {
+			st.nodes.push(Node {
+				id: id.clone(),
+				host: host.clone(),
+				port,
+				epoch,
+				seen: Some(Instant::now())
+			});
+			true
+		}
+	};
+	st.current_epoch = st.current_epoch.max(epoch);
+	for (s, claimed) in claims.iter().enumerate() {
+		let owner = st.slots[s].clone();
+		let take = match owner.as_deref() {
+			_ if !claimed => {
+				if owner.as_deref() == Some(id.as_str()) {
+					st.slots[s] = None;
+					changed = true;
+				}
+				false
+			},
+			None => true,
+			Some(o) if o == id => false,
+			// the claim with the most recent configuration wins
+			Some(o) => epoch > st.epoch(o)
+		};
+		if take {
+			st.slots[s] = Some(id.clone());
+			st.migrating.remove(&s);
+			changed = true;
+		}
+	}
+	for n in prms[5..].iter() {
+		let (nid, host, port) = match n.split_once('@')
+			.and_then(|(i, a)| a.rsplit_once(':').map(|(h, p)| (i, h, p))) {
+			Some((i, h, p)) if is_id(i) => match p.parse::<u16>() {
+				Ok(p) => (i, h, p),
+				Err(_) => continue
+			},
+			_ => continue
+		};
+		if nid != st.myid && st.node(nid).is_none() {
+			st.nodes.push(Node {
+				id: nid.to_string(),
+				host: host.to_string(),
+				port,
+				epoch: 0,
+				seen: None
+			});
+			changed = true;
+		}
+	}
+	if changed {
+		save(&st);
+	}
+	Ok(DataType::str("OK"))
+}
+
+pub fn info() -> Result<DataType, &'static str> {
+	let st = STATE.read().unwrap();
+	let assigned = st.slots.iter().filter(|s| s.is_some()).count();
+	let size = st.nodes.iter()
+		.filter(|n| st.slots.iter().any(|s| s.as_deref() == Some(&n.id)))
+		.count();
+	Ok(DataType::bulkStr(&format!(
+		"cluster_enabled:1\r\ncluster_state:{}\r\ncluster_slots_assigned:{}\r\n\
+			cluster_slots_ok:{}\r\ncluster_known_nodes:{}\r\n\
+			cluster_size:{}\r\ncluster_current_epoch:{}\r\n\
+			cluster_my_epoch:{}\r\n",
+		if SLOTS == assigned {"ok"} else {"fail"},
+		assigned,
+		assigned,
+		st.nodes.len(),
+		size,
+		st.current_epoch,
+		st.nodes[0].epoch
+	)))
+}
+
+pub fn nodes() -> Result<DataType, &'static str> {
+	Ok(DataType::bulkStr(&describe(&STATE.read().unwrap())))
+}
+
+pub fn slots() -> Result<DataType, &'static str> {
+	let st = STATE.read().unwrap();
+	let mut out: Vec<DataType> = Vec::new();
+	for n in st.nodes.iter() {
+		for (a, b) in ranges(&st.owned_by(&n.id)) {
+			out.push(DataType::List(vec![
+				DataType::Integer(a as i64),
+				DataType::Integer(b as i64),
+				DataType::List(vec![
+					DataType::bulkStr(&n.host),
+					DataType::Integer(n.port as i64),
+					DataType::bulkStr(&n.id),
+					DataType::EmptyList
+				])
+			]));
+		}
+	}
+	out.sort_by_key(|r| match r {
+		DataType::List(l) => match l[0] {
+			DataType::Integer(a) => a,
+			_ => 0
+		},
+		_ => 0
+	});
+	Ok(DataType::List(out))
+}
+
+pub fn shards() -> Result<DataType, &'static str> {
+	let st = STATE.read().unwrap();
+	Ok(DataType::List(st.nodes.iter().map(|n| {
+		let health = if n.id == st.myid || n.seen.is_some() {
+			"online"
+		} else {
+			"unknown"
+		};
+		DataType::List(vec![
+			DataType::bulkStr("slots"),
+			DataType::List(ranges(&st.owned_by(&n.id)).iter()
+				.flat_map(|(a, b)| vec![
+					DataType::Integer(*a as i64),
+					DataType::Integer(*b as i64)
+				])
+				.collect()),
+			DataType::bulkStr("nodes"),
+			DataType::List(vec![DataType::List(vec![
+				DataType::bulkStr("id"),
+				DataType::bulkStr(&n.id),
+				DataType::bulkStr("port"),
+				DataType::Integer(n.port as i64),
+				DataType::bulkStr("ip"),
+				DataType::bulkStr(&n.host),
+				DataType::bulkStr("endpoint"),
+				DataType::bulkStr(&n.host),
+				DataType::bulkStr("role"),
+				DataType::bulkStr("master"),
+				DataType::bulkStr("replication-offset"),
+				DataType::Integer(replication::offset() as i64),
+				DataType::bulkStr("health"),
+				DataType::bulkStr(health)
+			])])
+		])
+	}).collect()))
+}
+
+// MIGRATE host port key|"" db timeout [COPY] [REPLACE] [AUTH password]
+// [AUTH2 username password] [KEYS key ...]
+pub fn migrate(req: &Request) -> Result<DataType, &'static str> {
+	let prms = &req.parameters;
+	if prms[1].parse::<u16>().is_err() {
+		return Err(ERRMSG_BADPORT);
+	}
+	if Ok(0) != prms[3].parse::<i64>() {
+		return Err(ERRMSG_DBOOR);
+	}
+	let timeout = match prms[4].parse::<u64>() {
+		Ok(0) => Duration::from_secs(1),
+		Ok(n) => Duration::from_millis(n),
+		Err(_) => return Err(ERRMSG_TIMEOUTNAI)
+	};
+	let (mut copy, mut replace) = (false, false);
+	let mut auth: Vec<String> = Vec::new();
+	let mut i = 5;
+	while i < prms.len() {
+		match prms[i].to_ascii_lowercase().as_str() {
+			"copy" => copy = true,
+			"replace" => replace = true,
+			"auth" if i + 1 < prms.len() => {
+				auth = prms[i + 1..i + 2].to_vec();
+				i += 1;
+			},
+			"auth2" if i + 2 < prms.len() => {
+				auth = prms[i + 1..i + 3].to_vec();
+				i += 2;
+			},
+			"keys" if prms[2].is_empty() && i + 1 < prms.len() => break,
+			_ => return Err(ERRMSG_SYNERR)
+		}
+		i += 1;
+	}
+	let ks = keys(req);
+	if ks.is_empty() || ks.iter().any(|k| k.is_empty()) {
+		return Err(ERRMSG_SYNERR);
+	}
+	let payloads = ks.iter()
+		.filter_map(|k| match kv::dump(k) {
+			Ok(DataType::BulkString(p)) => Some((k.to_string(), p)),
+			_ => None
+		})
+		.collect::<Vec<_>>();
+	if payloads.is_empty() {
+		return Ok(DataType::str("NOKEY"));
+	}
+	let mut link = match connect(&format!("{}:{}", prms[0], prms[1]), timeout)
+	{
+		Ok(link) => link,
+		Err(_) => return Ok(DataType::err(ERRMSG_IOERR))
+	};
+	let mut reqs: Vec<Request> = Vec::new();
+	if !auth.is_empty() {
+		reqs.push(Request {command: String::from("auth"), parameters: auth});
+	}
+	for (k, p) in payloads.iter() {
+		if enabled() {
+			reqs.push(Request {
+				command: String::from("asking"),
+				parameters: vec![]
+			});
+		}
+		let mut parameters = vec![k.clone(), String::from("0"), p.clone()];
+		if replace {
+			parameters.push(String::from("REPLACE"));
+		}
+		reqs.push(Request {command: String::from("restore"), parameters});
+	}
+	for req in reqs.iter() {
+		match exchange(&mut link, req) {
+			Ok(_) => if !copy && "restore" == req.command {
+				let _ = kv::del(&vec![req.parameters[0].clone()]);
+			},
+			Err(e) if e.starts_with("io:") =>
+				return Ok(DataType::err(ERRMSG_IOERR)),
+			Err(e) => return Ok(DataType::err(&format!(
+				"ERR Target instance replied with error: {}",
+				e
+			)))
+		}
+	}
+	Ok(DataType::str("OK"))
+}
+
+fn parse_slot(s: &str) -> Result<usize, &'static str> {
+	match s.parse::<usize>() {
+		Ok(n) if n < SLOTS => Ok(n),
+		_ => Err(ERRMSG_BADSLOT)
+	}
+}
+
+// "a-b,c,..." as sent in heartbeats, or "-" for none
+fn parse_claims(s: &str) -> Option<Vec<bool>> {
+	let mut claims = vec![false; SLOTS];
+	if "-" == s {
+		return Some(claims);
+	}
+	for r in s.split(',') {
+		let (a, b) = match r.split_once('-') {
+			Some((a, b)) =>
+				(a.parse::<usize>().ok()?, b.parse::<usize>().ok()?),
+			None => {
+				let a = r.parse::<usize>().ok()?;
+				(a, a)
+			}
+		};
+		if a > b || b >= SLOTS {
+			return None;
+		}
+		claims[a..=b].iter_mut().for_each(|c| *c = true);
+	}
+	Some(claims)
+}
+
+// contiguous runs of sorted slots
+fn ranges(slots: &[usize]) -> Vec<(usize, usize)> {
+	let mut out: Vec<(usize, usize)> = Vec::new();
+	for s in slots.iter() {
+		match out.last_mut() {
+			Some((_, b)) if *b + 1 == *s => *b = *s,
+			_ => out.push((*s, *s))
+		}
+	}
+	out
+}
+
+fn format_ranges(slots: &[usize]) -> Vec<String> {
+	ranges(slots).iter()
+		.map(|(a, b)| if a == b {a.to_string()} else {format!("{}-{}", a, b)})
+		.collect()
+}
+
+fn heartbeat_req(link: &TcpStream) -> Request {
+	let local = link.local_addr().ok().map(|a| a.ip().to_string());
+	let mut st = STATE.write().unwrap();
+	let host = announce_ip(local);
+	st.nodes[0].host = host.clone();
+	let claims = format_ranges(&st.owned_by(&st.myid)).join(",");
+	let mut parameters = vec![
+		String::from("heartbeat"),
+		st.myid.clone(),
+		host,
+		st.nodes[0].port.to_string(),
+		st.nodes[0].epoch.to_string(),
+		if claims.is_empty() {String::from("-")} else {claims}
+	];
+	parameters.extend(st.nodes[1..].iter()
+		.map(|n| format!("{}@{}:{}", n.id, n.host, n.port)));
+	Request {command: String::from("cluster"), parameters}
+}
+
+fn announce_ip(local: Option<String>) -> String {
+	let ip = config::get("cluster-announce-ip");
+	if !ip.is_empty() {
+		return ip;
+	}
+	match local {
+		Some(ip) => ip,
+		None => match config::get("bind").as_str() {
+			"0.0.0.0" | "::" => String::from("127.0.0.1"),
+			bind => bind.to_string()
+		}
+	}
+}
+
+fn connect(addr: &str, timeout: Duration)
+	-> Result<BufReader<TcpStream>, String> {
+	let sa = match addr.to_socket_addrs().map(|mut a| a.next()) {
+		Ok(Some(sa)) => sa,
+		_ => return Err(format!("io: cannot resolve {}", addr))
+	};
+	let stream = TcpStream::connect_timeout(&sa, timeout)
+		.map_err(|e| format!("io: {}", e))?;
+	let _ = stream.set_read_timeout(Some(timeout));
+	let _ = stream.set_write_timeout(Some(timeout));
+	Ok(BufReader::new(stream))
+}
+
+// send one request and read its reply, which must be a simple string, an
+// error, an integer or a bulk string
+fn exchange(link: &mut BufReader<TcpStream>, req: &Request)
+	-> Result<String, String> {
+	link.get_mut().write_all(&replication::encode(req))
+		.map_err(|e| format!("io: {}", e))?;
+	let ln = read_line(link)?;
+	match (ln.chars().next(), ln.get(1..).unwrap_or("")) {
+		(Some('+'), s) | (Some(':'), s) => Ok(s.to_string()),
+		(Some('-'), e) => Err(e.to_string()),
+		(Some('$'), "-1") => Ok(String::new()),
+		(Some('$'), _) => read_line(link),
+		_ => Err(String::from("io: protocol error"))
+	}
+}
+
+fn read_line(link: &mut BufReader<TcpStream>) -> Result<String, String> {
+	let mut ln = String::new();
+	match link.read_line(&mut ln) {
+		Ok(0) => Err(String::from("io: connection closed")),
+		Ok(_) => Ok(ln.trim_end_matches(['\r', '\n']).to_string()),
+		Err(e) => Err(format!("io: {}", e))
+	}
+}
+
+fn is_id(s: &str) -> bool {
+	40 == s.len() && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+// one line per node as for CLUSTER NODES, also the cluster-config-file format
+fn describe(st: &State) -> String {
+	let mut out = String::new();
+	for n in st.nodes.iter() {
+		let myself = n.id == st.myid;
+		let linked = myself || n.seen
+			.is_some_and(|t| t.elapsed() < Duration::from_secs(5));
+		out.push_str(&format!(
+			"{} {}:{}@{} {} - 0 0 {} {}",
+			n.id,
+			n.host,
+			n.port,
+			n.port as u32 + 10000,
+			if myself {"myself,master"} else {"master"},
+			n.epoch,
+			if linked {"connected"} else {"disconnected"}
+		));
+		for r in format_ranges(&st.owned_by(&n.id)) {
+			out.push(' ');
+			out.push_str(&r);
+		}
+		if myself {
+			let mut m = st.migrating.iter().collect::<Vec<_>>();
+			m.sort();
+			for (s, id) in m {
+				out.push_str(&format!(" [{}->-{}]", s, id));
+			}
+			let mut m = st.importing.iter().collect::<Vec<_>>();
+			m.sort();
+			for (s, id) in m {
+				out.push_str(&format!(" [{}-<-{}]", s, id));
+			}
+		}
+		out.push('\n');
+	}
+	out
+}
+
+fn load(st: &mut State, s: &str) -> Result<(), String> {
+	let mut nodes: Vec<Node> = Vec::new();
+	for ln in s.lines() {
+		let toks = ln.split_whitespace().collect::<Vec<_>>();
+		if toks.is_empty() {
+			continue;
+		}
+		if "vars" == toks[0] {
+			st.current_epoch = toks.get(2)
+				.and_then(|e| e.parse::<u64>().ok())
+				.unwrap_or(0);
+			continue;
+		}
+		let bad = || format!("bad line '{}'", ln);
+		let (host, port) = match toks.get(1)
+			.map(|a| a.split('@').next().unwrap_or(""))
+			.and_then(|a| a.rsplit_once(':')) {
+			Some((h, p)) if 8 <= toks.len() && is_id(toks[0]) =>
+				(h, p.parse::<u16>().map_err(|_| bad())?),
+			_ => return Err(bad())
+		};
+		let id = toks[0].to_string();
+		for r in toks[8..].iter() {
+			let inner = r.strip_prefix('[').and_then(|r| r.strip_suffix(']'));
+			if let Some(sm) = inner {
+				if let Some((s, to)) = sm.split_once("->-") {
+					if let Ok(s) = parse_slot(s) {
+						st.migrating.insert(s, to.to_string());
+					}
+				} else if let Some((s, from)) = sm.split_once("-<-") {
+					if let Ok(s) = parse_slot(s) {
+						st.importing.insert(s, from.to_string());
+					}
+				}
+				continue;
+			}
+			let claims = parse_claims(r)
+				.ok_or_else(|| format!("bad slots '{}'", r))?;
+			for (s, c) in claims.iter().enumerate() {
+				if *c {
+					st.slots[s] = Some(id.clone());
+				}
+			}
+		}
+		let node = Node {
+			id: id.clone(),
+			host: host.to_string(),
+			port,
+			epoch: toks[6].parse::<u64>().unwrap_or(0),
+			seen: None
+		};
+		if toks[2].split(',').any(|f| "myself" == f) {
+			st.myid = id;
+			nodes.insert(0, node);
+		} else {
+			nodes.push(node);
+		}
+	}
+	if st.myid.is_empty() {
+		return Err(String::from("no myself node"));
+	}
+	// the port may have changed since the file was written
+	nodes[0].port = config::get("port").parse::<u16>().unwrap_or(0);
+	st.nodes = nodes;
+	Ok(())
+}
+
+fn save(st: &State) {
+	let path = config::get("cluster-config-file");
+	let s = format!(
+		"{}vars currentEpoch {} lastVoteEpoch 0\n",
+		describe(st),
+		st.current_epoch
+	);
+	if let Err(e) = fs::write(&path, s) {
+		logger::log(
+			Level::Warning,
+			&format!("Could not save cluster config to {}: {}", path, e)
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,90 @@
+use serial_test::serial;
+use super::*;
+
+fn req(cmd: &str, prms: &[&str]) -> Request {
+	Request {
+		command: cmd.to_string(),
+		parameters: prms.iter().map(|s| s.to_string()).collect()
+	}
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	assert_eq!(crc16(b"123456789"), 0x31c3);
+	assert_eq!(key_slot("foo"), 12182);
+	assert_eq!(key_slot("{user1000}.following"), key_slot("user1000"));
+	// an empty tag hashes the whole key
+	assert_eq!(key_slot("{}x"), crc16(b"{}x") as usize % SLOTS);
+	assert_eq!(keys(&req("get", &["k"])), vec!["k"]);
+	assert_eq!(keys(&req("mset", &["a", "1", "b", "2"])), vec!["a", "b"]);
+	assert_eq!(keys(&req("smove", &["s", "d", "m"])), vec!["s", "d"]);
+	assert_eq!(keys(&req("incrbyfloat", &["k", "0.5"])), vec!["k"]);
+	assert_eq!(keys(&req("lcs", &["a", "b", "LEN"])), vec!["a", "b"]);
+	assert_eq!(keys(&req("msetnx", &["a", "1", "b", "2"])), vec!["a", "b"]);
+	assert_eq!(keys(&req("lmove", &["a", "b", "LEFT", "RIGHT"])),
+		vec!["a", "b"]);
+	assert_eq!(keys(&req("lmpop", &["2", "a", "b", "LEFT"])), vec!["a", "b"]);
+	assert_eq!(keys(&req("hexpire", &["h", "10", "FIELDS", "1", "f"])),
+		vec!["h"]);
+	assert_eq!(keys(&req("ping", &[])), Vec::<&str>::new());
+	assert_eq!(
+		keys(&req("migrate", &["h", "1", "", "0", "5", "KEYS", "a", "b"])),
+		vec!["a", "b"]
+	);
+	assert_eq!(format_ranges(&[0, 1, 2, 5, 7, 8]), vec!["0-2", "5", "7-8"]);
+	let claims = parse_claims("0-2,5").unwrap();
+	assert_eq!(claims.iter().filter(|c| **c).count(), 4);
+	assert!(parse_claims("3-1").is_none());
+	assert!(parse_claims("16384").is_none());
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	let me = "a".repeat(40);
+	let other = "b".repeat(40);
+	let path = std::env::temp_dir().join("kyvi-cluster-plan2.conf");
+	let _ = config::set_startup("cluster-config-file", path.to_str().unwrap());
+	{
+		let mut st = STATE.write().unwrap();
+		st.myid = me.clone();
+		st.nodes = vec![Node {
+			id: me.clone(),
+			host: String::from("127.0.0.1"),
+			port: 7000,
+			epoch: 0,
+			seen: None
+		}];
+		st.slots = vec![Some(me.clone()); SLOTS];
+	}
+	let hb = |epoch: &str, slots: &str| heartbeat(&[
+		other.clone(),
+		String::from("127.0.0.1"),
+		String::from("7001"),
+		epoch.to_string(),
+		slots.to_string(),
+		format!("{}@127.0.0.1:7002", "c".repeat(40))
+	]);
+	// a claim no newer than ours does not take slots away
+	assert_eq!(hb("0", "0-9"), Ok(DataType::str("OK")));
+	assert_eq!(STATE.read().unwrap().owned_by(&other), Vec::<usize>::new());
+	assert_eq!(STATE.read().unwrap().nodes.len(), 3);
+	assert_eq!(hb("1", "0-9"), Ok(DataType::str("OK")));
+	assert_eq!(
+		STATE.read().unwrap().owned_by(&other),
+		(0..10).collect::<Vec<_>>()
+	);
+	// slots no longer claimed by their owner become unassigned
+	assert_eq!(hb("1", "0-4"), Ok(DataType::str("OK")));
+	assert_eq!(STATE.read().unwrap().slots[5], None);
+	assert_eq!(hb("x", "0-4"), Err(ERRMSG_SYNERR));
+	let st = STATE.read().unwrap();
+	assert_eq!(st.endpoint(&other), "127.0.0.1:7001");
+	assert_eq!(st.current_epoch, 1);
+	assert!(fs::read_to_string(&path).unwrap().ends_with(
+		"vars currentEpoch 1 lastVoteEpoch 0\n"
+	));
+	let _ = fs::remove_file(&path);
+	let _ = config::set_startup("cluster-config-file", "nodes.conf");
+}
@@ -0,0 +1,195 @@
+use super::bytes;
+use super::hll::murmur64a;
+
+pub const ERRMSG_BADDATA: &str = "ERR received bad data";
+pub const ERRMSG_EXISTS: &str = "ERR CMS: key already exists";
+pub const ERRMSG_NOKEY: &str = "ERR CMS: key does not exist";
+const ERRMSG_BADDEPTH: &str = "ERR CMS: invalid depth";
+const ERRMSG_BADERROR: &str = "ERR CMS: invalid overestimation value";
+const ERRMSG_BADINCR: &str = "ERR CMS: Cannot parse number";
+const ERRMSG_BADNUMKEYS: &str = "ERR CMS: invalid numkeys";
+const ERRMSG_BADPROB: &str = "ERR CMS: invalid prob value";
+const ERRMSG_BADWEIGHT: &str = "ERR CMS: invalid weight value";
+const ERRMSG_BADWIDTH: &str = "ERR CMS: invalid width";
+const ERRMSG_DIMS: &str = "ERR CMS: width/depth is not equal";
+const ERRMSG_OVERFLOW: &str = "ERR CMS: INCRBY overflow";
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+
+const MAGIC: &[u8] = b"KCM1";
+const MAX_COUNTERS: usize = 1 << 26;
+
+// a Count-Min sketch, depth rows of width counters, an item counting in one
+// counter of every row and being estimated by the smallest of them
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Sketch {
+	width: usize,
+	depth: usize,
+	count: u64,
+	counters: Vec<u64>
+}
+
+// WIDTH DEPTH
+pub fn parse_dims(prms: &[String]) -> Result<Sketch, &'static str> {
+	let width = match prms[0].parse::<usize>() {
+		Ok(n) if 0 < n => n,
+		_ => return Err(ERRMSG_BADWIDTH)
+	};
+	match prms[1].parse::<usize>() {
+		Ok(n) if 0 < n && n.saturating_mul(width) <= MAX_COUNTERS =>
+			Ok(Sketch::new(width, n)),
+		_ => Err(ERRMSG_BADDEPTH)
+	}
+}
+
+// ERROR PROBABILITY, the overestimation as a share of all the counts and
+// the probability of going beyond it
+pub fn parse_prob(prms: &[String]) -> Result<Sketch, &'static str> {
+	let error = match prms[0].parse::<f64>() {
+		Ok(e) if 0.0 < e && e < 1.0 => e,
+		_ => return Err(ERRMSG_BADERROR)
+	};
+	let prob = match prms[1].parse::<f64>() {
+		Ok(p) if 0.0 < p && p < 1.0 => p,
+		_ => return Err(ERRMSG_BADPROB)
+	};
+	let width = (2.0 / error).ceil() as usize;
+	let depth = (prob.log10() / 0.5f64.log10()).ceil().max(1.0) as usize;
+	match width.saturating_mul(depth) <= MAX_COUNTERS {
+		true => Ok(Sketch::new(width, depth)),
+		false => Err(ERRMSG_BADERROR)
+	}
+}
+
+// ITEM INCREMENT [ITEM INCREMENT ...]
+pub fn parse_incrs(prms: &[String])
+	-> Result<Vec<(Vec<u8>, u64)>, &'static str> {
+	prms.chunks(2).map(|p| match p.get(1).map(|n| n.parse::<u64>()) {
+		Some(Ok(n)) => Ok((bytes::encode(&p[0]), n)),
+		_ => Err(ERRMSG_BADINCR)
+	}).collect()
+}
+
+// NUMKEYS SOURCE [SOURCE ...] [WEIGHTS WEIGHT [WEIGHT ...]], the sources
+// and their weights
+pub fn parse_merge(prms: &[String])
+	-> Result<Vec<(&str, u64)>, &'static str> {
+	let n = match prms[0].parse::<usize>() {
+		Ok(n) if 0 < n && n < prms.len() => n,
+		_ => return Err(ERRMSG_BADNUMKEYS)
+	};
+	let srcs = prms[1..=n].iter().map(|s| s.as_str());
+	let weights = match &prms[n + 1..] {
+		[] => vec![1; n],
+		[w, ws @ ..] if w.eq_ignore_ascii_case("weights") && n == ws.len() =>
+			ws.iter()
+				.map(|w| w.parse::<u64>().map_err(|_| ERRMSG_BADWEIGHT))
+				.collect::<Result<Vec<u64>, &str>>()?,
+		_ => return Err(ERRMSG_SYNERR)
+	};
+	Ok(srcs.zip(weights).collect())
+}
+
+impl Sketch {
+	pub fn new(width: usize, depth: usize) -> Sketch {
+		Sketch {width, depth, count: 0, counters: vec![0; width * depth]}
+	}
+
+	// the counter of item in each row
+	fn cells(&self, item: &[u8]) -> Vec<usize> {
+		(0..self.depth)
+			.map(|i| i * self.width
+				+ (murmur64a(item, i as u64) % self.width as u64) as usize)
+			.collect()
+	}
+
+	pub fn query(&self, item: &[u8]) -> u64 {
+		self.cells(item).iter().map(|c| self.counters[*c]).min().unwrap_or(0)
+	}
+
+	// count item n more times, returning its estimate
+	pub fn incr(&mut self, item: &[u8], n: u64) -> Result<u64, &'static str> {
+		let cells = self.cells(item);
+		if cells.iter().any(|c| self.counters[*c].checked_add(n).is_none()) ||
+			self.count.checked_add(n).is_none() {
+			return Err(ERRMSG_OVERFLOW);
+		}
+		for c in cells.iter() {
+			self.counters[*c] += n;
+		}
+		self.count += n;
+		Ok(self.query(item))
+	}
+
+	// the sum of the sketches, each times its weight
+	pub fn merge(&mut self, srcs: &[(&Sketch, u64)])
+		-> Result<(), &'static str> {
+		if srcs.iter().any(|(s, _)| (s.width, s.depth) != self.dims()) {
+			return Err(ERRMSG_DIMS);
+		}
+		let mut counters = vec![0u64; self.counters.len()];
+		let mut count = 0u64;
+		for (s, w) in srcs.iter() {
+			for (c, n) in counters.iter_mut().zip(s.counters.iter()) {
+				*c = n.checked_mul(*w).and_then(|n| c.checked_add(n))
+					.ok_or(ERRMSG_OVERFLOW)?;
+			}
+			count = s.count.checked_mul(*w).and_then(|n| count.checked_add(n))
+				.ok_or(ERRMSG_OVERFLOW)?;
+		}
+		self.counters = counters;
+		self.count = count;
+		Ok(())
+	}
+
+	pub fn dims(&self) -> (usize, usize) {
+		(self.width, self.depth)
+	}
+
+	pub fn count(&self) -> u64 {
+		self.count
+	}
+
+	// bytes taken by the counters
+	pub fn size(&self) -> usize {
+		8 * self.counters.len()
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut v = MAGIC.to_vec();
+		for n in [self.width as u64, self.depth as u64, self.count] {
+			v.extend_from_slice(&n.to_le_bytes());
+		}
+		for c in self.counters.iter() {
+			v.extend_from_slice(&c.to_le_bytes());
+		}
+		v
+	}
+
+	pub fn parse(mut b: &[u8]) -> Result<Sketch, &'static str> {
+		match read(&mut b) {
+			Some(s) if b.is_empty() => Ok(s),
+			_ => Err(ERRMSG_BADDATA)
+		}
+	}
+}
+
+// the sketch serialized at the start of b
+fn read(b: &mut &[u8]) -> Option<Sketch> {
+	if MAGIC != bytes::take(b, MAGIC.len())? {
+		return None;
+	}
+	let width = bytes::take_u64(b)? as usize;
+	let depth = bytes::take_u64(b)? as usize;
+	let count = bytes::take_u64(b)?;
+	if 0 == width || 0 == depth || b.len() / 8 != width.saturating_mul(depth) {
+		return None;
+	}
+	let mut counters = Vec::with_capacity(width * depth);
+	for _ in 0..width * depth {
+		counters.push(bytes::take_u64(b)?);
+	}
+	Some(Sketch {width, depth, count, counters})
+}
+
+#[cfg(test)]
+mod tests;
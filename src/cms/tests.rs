@@ -0,0 +1,37 @@
+use serial_test::serial;
+use super::*;
+
+fn prms(ps: &[&str]) -> Vec<String> {
+	ps.iter().map(|p| p.to_string()).collect()
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	assert_eq!(parse_dims(&prms(&["0", "5"])), Err(ERRMSG_BADWIDTH));
+	assert_eq!(parse_dims(&prms(&["5", "x"])), Err(ERRMSG_BADDEPTH));
+	assert_eq!(parse_prob(&prms(&["0.001", "0.01"])).unwrap().dims(),
+		(2000, 7));
+	assert_eq!(parse_prob(&prms(&["2", "0.01"])), Err(ERRMSG_BADERROR));
+	assert_eq!(parse_incrs(&prms(&["a", "-1"])), Err(ERRMSG_BADINCR));
+	let mut s = Sketch::new(100, 5);
+	assert_eq!(s.incr(b"a", 3), Ok(3));
+	assert_eq!(s.incr(b"a", 2), Ok(5));
+	for i in 0..50 {
+		s.incr(format!("x{}", i).as_bytes(), 1).unwrap();
+	}
+	assert!(5 <= s.query(b"a") && s.query(b"a") < 8);
+	assert_eq!(s.incr(b"a", u64::MAX), Err(ERRMSG_OVERFLOW));
+	assert_eq!(s.count(), 55);
+	let mut t = Sketch::new(100, 5);
+	t.merge(&[(&s, 2), (&s, 1)]).unwrap();
+	assert_eq!((t.count(), t.query(b"a")), (165, 3 * s.query(b"a")));
+	assert_eq!(t.merge(&[(&Sketch::new(10, 5), 1)]), Err(ERRMSG_DIMS));
+	let srcs = prms(&["2", "a", "b", "weights", "3", "4"]);
+	assert_eq!(parse_merge(&srcs), Ok(vec![("a", 3), ("b", 4)]));
+	assert_eq!(parse_merge(&prms(&["2", "a", "b", "weights", "3"])),
+		Err(ERRMSG_SYNERR));
+	assert_eq!(parse_merge(&prms(&["3", "a", "b"])), Err(ERRMSG_BADNUMKEYS));
+	assert_eq!(Sketch::parse(&t.to_bytes()), Ok(t.clone()));
+	assert_eq!(Sketch::parse(&t.to_bytes()[..40]), Err(ERRMSG_BADDATA));
+}
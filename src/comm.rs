@@ -1,20 +1,84 @@
+use std::io::{self, Read, Write};
 use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
 use threadpool::ThreadPool;
 
+use super::client;
 use super::command::process;
+use super::config;
+use super::logger::{self, Level};
+use super::stats;
+use super::tracking;
+
+const POLL: Duration = Duration::from_secs(1);
+
+// a registered client, forgotten once its connection is done with, even when
+// a command panicked on the way
+struct Registration(u64);
+
+impl Drop for Registration {
+	fn drop(&mut self) {
+		tracking::forget(self.0);
+		client::unregister(self.0);
+	}
+}
+
+// the reading side of a connection, waking up every POLL to apply the idle
+// timeout as currently configured
+struct Incoming<'a>(&'a TcpStream);
+
+impl Read for &Incoming<'_> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let idle = Instant::now();
+		let mut stream = self.0;
+		loop {
+			match stream.read(buf) {
+				Err(e) if matches!(
+					e.kind(),
+					io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+				) => {
+					let timeout = config::get_u64("timeout");
+					if 0 < timeout && idle.elapsed().as_secs() >= timeout {
+						return Err(e);
+					}
+				},
+				res => return res
+			}
+		}
+	}
+}
 
 pub fn listen_to(pool: &ThreadPool, bindaddr: &str) -> std::io::Result<()> {
 	let listener: TcpListener = TcpListener::bind(bindaddr)?;
 	for stream in listener.incoming() {
 		match stream {
-			Ok(stream) => pool.execute(move || {handle_client(stream);}),
-			Err(e) => eprintln!("Unhandled error: {:?}", e)
+			Ok(mut stream) => {
+				stats::incr(&stats::CONNECTIONS_RECEIVED);
+				if client::count() >= config::get_usize("maxclients") {
+					stats::incr(&stats::REJECTED_CONNECTIONS);
+					let _ = stream.write_all(
+						b"-ERR max number of clients reached\r\n"
+					);
+					continue;
+				}
+				pool.execute(move || {handle_client(stream);})
+			},
+			Err(e) => logger::log(
+				Level::Warning,
+				&format!("Unhandled error: {:?}", e)
+			)
 		}
 	}
 	Ok(())
 }
 
 fn handle_client(stream: TcpStream) {
-	println!("Accepted connection from: {}", stream.peer_addr().unwrap());
-	process(&stream, &stream);
+	let id = client::register(&stream);
+	let _registration = Registration(id);
+	logger::log(
+		Level::Verbose,
+		&format!("Accepted connection from: {}", client::addr(id))
+	);
+	let _ = stream.set_read_timeout(Some(POLL));
+	process(&Incoming(&stream), &stream, id);
 }
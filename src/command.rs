@@ -1,10 +1,24 @@
 use std::io::{BufReader, BufWriter, Read, Write};
-use phf::phf_map;
+use std::time::Instant;
+use phf::{phf_map, phf_set};
 
+use super::client;
+use super::cluster;
+use super::config;
 use super::datatype::DataType;
+use super::function;
 use super::kv;
+use super::logger::{self, Level};
+use super::monitor;
 use super::parser::parse;
+use super::pubsub;
+use super::replication;
 use super::request::Request;
+use super::script;
+use super::search;
+use super::stats;
+use super::stream;
+use super::tracking;
 
 struct Command<'a> {
 	function: fn(&Request) -> Result<DataType, &str>,
@@ -13,6 +27,8 @@ struct Command<'a> {
 	doc: &'a str
 }
 
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+
 const UNITS: &'static[&'static str] = &["", "k", "M", "G", "T", "P", "E"];
 
 static CMDS: phf::Map<&str, Command> = phf_map! {
@@ -22,11 +38,239 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		validation: |r| {2 == r.parameters.len()},
 		doc: "append value to the string stored at the key."
 	},
+	"asking" => Command {
+		function: cmd_asking,
+		syntax: "asking",
+		validation: |r| {0 == r.parameters.len()},
+		doc: "let the next command reach a slot being imported."
+	},
+	"bf.add" => Command {
+		function: cmd_bf_add,
+		syntax: "bf.add KEY ITEM",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "add an item to the Bloom filter stored at key, creating it \
+			when missing."
+	},
+	"bf.card" => Command {
+		function: cmd_bf_card,
+		syntax: "bf.card KEY",
+		validation: |r| {1 == r.parameters.len()},
+		doc: "get the number of items added to the Bloom filter stored at \
+			key."
+	},
+	"bf.exists" => Command {
+		function: cmd_bf_exists,
+		syntax: "bf.exists KEY ITEM",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "check if an item may be in the Bloom filter stored at key."
+	},
+	"bf.info" => Command {
+		function: cmd_bf_info,
+		syntax: "bf.info KEY \
+			[ CAPACITY | SIZE | FILTERS | ITEMS | EXPANSION ]",
+		validation: |r| {matches!(r.parameters.len(), 1 | 2)},
+		doc: "get information about the Bloom filter stored at key."
+	},
+	"bf.loadchunk" => Command {
+		function: cmd_loadchunk,
+		syntax: "bf.loadchunk KEY ITERATOR DATA",
+		validation: |r| {3 == r.parameters.len()},
+		doc: "restore the Bloom filter at key from data got by bf.scandump."
+	},
+	"bf.madd" => Command {
+		function: cmd_bf_madd,
+		syntax: "bf.madd KEY ITEM [ ITEM ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "add items to the Bloom filter stored at key, creating it when \
+			missing."
+	},
+	"bf.mexists" => Command {
+		function: cmd_bf_mexists,
+		syntax: "bf.mexists KEY ITEM [ ITEM ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "check if items may be in the Bloom filter stored at key."
+	},
+	"bf.reserve" => Command {
+		function: cmd_bf_reserve,
+		syntax: "bf.reserve KEY ERROR_RATE CAPACITY [ EXPANSION expansion ] \
+			[ NONSCALING ]",
+		validation: |r| {2 < r.parameters.len() && 7 > r.parameters.len()},
+		doc: "create an empty Bloom filter at key."
+	},
+	"bf.scandump" => Command {
+		function: cmd_scandump,
+		syntax: "bf.scandump KEY ITERATOR",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "dump the Bloom filter stored at key, for bf.loadchunk."
+	},
+	"bitcount" => Command {
+		function: cmd_bitcount,
+		syntax: "bitcount KEY [ START END [ BYTE | BIT ] ]",
+		validation: |r| {matches!(r.parameters.len(), 1 | 3 | 4)},
+		doc: "count the bits set in the string stored at key, within the \
+			given range of bytes or bits."
+	},
+	"bitfield" => Command {
+		function: cmd_bitfield,
+		syntax: "bitfield KEY [ GET encoding offset \
+			| [ OVERFLOW <WRAP | SAT | FAIL> ] \
+			<SET encoding offset value | INCRBY encoding offset increment> \
+			... ]",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "get, set or increment integers of arbitrary width stored \
+			in the string at key."
+	},
+	"bitfield_ro" => Command {
+		function: cmd_bitfield_ro,
+		syntax: "bitfield_ro KEY [ GET encoding offset ... ]",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "get integers of arbitrary width stored in the string at key."
+	},
+	"bitop" => Command {
+		function: cmd_bitop,
+		syntax: "bitop <AND | OR | XOR | NOT> DESTKEY KEY [ KEY ... ]",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "combine the strings stored at the keys bit by bit and store \
+			the result at destkey."
+	},
+	"bitpos" => Command {
+		function: cmd_bitpos,
+		syntax: "bitpos KEY BIT [ START [ END [ BYTE | BIT ] ] ]",
+		validation: |r| {1 < r.parameters.len() && 6 > r.parameters.len()},
+		doc: "find the first bit set or clear in the string stored at key."
+	},
+	"cf.add" => Command {
+		function: cmd_cf_add,
+		syntax: "cf.add KEY ITEM",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "add an item to the cuckoo filter stored at key, creating it \
+			when missing."
+	},
+	"cf.addnx" => Command {
+		function: cmd_cf_addnx,
+		syntax: "cf.addnx KEY ITEM",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "add an item to the cuckoo filter stored at key, unless it may \
+			be there already."
+	},
+	"cf.count" => Command {
+		function: cmd_cf_count,
+		syntax: "cf.count KEY ITEM",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "get how many times an item may have been added to the cuckoo \
+			filter stored at key."
+	},
+	"cf.del" => Command {
+		function: cmd_cf_del,
+		syntax: "cf.del KEY ITEM",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "delete an item once from the cuckoo filter stored at key."
+	},
+	"cf.exists" => Command {
+		function: cmd_cf_exists,
+		syntax: "cf.exists KEY ITEM",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "check if an item may be in the cuckoo filter stored at key."
+	},
+	"cf.loadchunk" => Command {
+		function: cmd_loadchunk,
+		syntax: "cf.loadchunk KEY ITERATOR DATA",
+		validation: |r| {3 == r.parameters.len()},
+		doc: "restore the cuckoo filter at key from data got by cf.scandump."
+	},
+	"cf.reserve" => Command {
+		function: cmd_cf_reserve,
+		syntax: "cf.reserve KEY CAPACITY [ BUCKETSIZE size ] \
+			[ MAXITERATIONS n ] [ EXPANSION expansion ]",
+		validation: |r| {1 < r.parameters.len() && 9 > r.parameters.len()},
+		doc: "create an empty cuckoo filter at key."
+	},
+	"cf.scandump" => Command {
+		function: cmd_scandump,
+		syntax: "cf.scandump KEY ITERATOR",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "dump the cuckoo filter stored at key, for cf.loadchunk."
+	},
 	"client" => Command {
 		function: cmd_client,
-		syntax: "client SETINFO <LIB-NAME libname | LIB-VER libver>",
+		syntax: "client <ID | INFO | LIST [ ID id ... ] | GETNAME \
+			| SETNAME name | SETINFO <LIB-NAME libname | LIB-VER libver> \
+			| KILL <addr | filter value [ filter value ... ]> \
+			| PAUSE timeout [ WRITE | ALL ] | UNPAUSE \
+			| REPLY <ON | OFF | SKIP> | NO-EVICT <ON | OFF> \
+			| TRACKING <ON | OFF> [ REDIRECT id ] [ PREFIX prefix ... ] \
+			[ BCAST ] [ OPTIN ] [ OPTOUT ] [ NOLOOP ] \
+			| CACHING <YES | NO> | GETREDIR | TRACKINGINFO>",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "inspect and manage client connections."
+	},
+	"cluster" => Command {
+		function: cmd_cluster,
+		syntax: "cluster <KEYSLOT key | COUNTKEYSINSLOT slot \
+			| GETKEYSINSLOT slot count | ADDSLOTS slot [ slot ... ] \
+			| ADDSLOTSRANGE start end [ start end ... ] \
+			| DELSLOTS slot [ slot ... ] | MEET ip port | MYID | NODES \
+			| SLOTS | SHARDS | INFO \
+			| SETSLOT slot <IMPORTING id | MIGRATING id | NODE id | STABLE>>",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "inspect and manage the hash slots of a cluster."
+	},
+	"cms.incrby" => Command {
+		function: cmd_cms_incrby,
+		syntax: "cms.incrby KEY ITEM INCREMENT \
+			[ ITEM INCREMENT ... ]",
+		validation: |r| {
+			2 < r.parameters.len() && 1 == r.parameters.len() % 2
+		},
+		doc: "count items more times in the Count-Min sketch stored at key."
+	},
+	"cms.initbydim" => Command {
+		function: cmd_cms_initbydim,
+		syntax: "cms.initbydim KEY WIDTH DEPTH",
+		validation: |r| {3 == r.parameters.len()},
+		doc: "create a Count-Min sketch of the given dimensions at key."
+	},
+	"cms.initbyprob" => Command {
+		function: cmd_cms_initbyprob,
+		syntax: "cms.initbyprob KEY ERROR PROBABILITY",
+		validation: |r| {3 == r.parameters.len()},
+		doc: "create a Count-Min sketch at key, sized for the given \
+			overestimation and probability of going beyond it."
+	},
+	"cms.loadchunk" => Command {
+		function: cmd_loadchunk,
+		syntax: "cms.loadchunk KEY ITERATOR DATA",
 		validation: |r| {3 == r.parameters.len()},
-		doc: "set client library information."
+		doc: "restore the Count-Min sketch at key from data got by \
+			cms.scandump."
+	},
+	"cms.merge" => Command {
+		function: cmd_cms_merge,
+		syntax: "cms.merge DESTINATION NUMKEYS SOURCE [ SOURCE ... ] \
+			[ WEIGHTS WEIGHT [ WEIGHT ... ] ]",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "sum Count-Min sketches up into the one at destination."
+	},
+	"cms.query" => Command {
+		function: cmd_cms_query,
+		syntax: "cms.query KEY ITEM [ ITEM ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "get the estimated counts of items in the Count-Min sketch \
+			stored at key."
+	},
+	"cms.scandump" => Command {
+		function: cmd_scandump,
+		syntax: "cms.scandump KEY ITERATOR",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "dump the Count-Min sketch stored at key, for cms.loadchunk."
+	},
+	"config" => Command {
+		function: cmd_config,
+		syntax: "config <GET parameter [ parameter ... ] \
+			| SET parameter value [ parameter value ... ] | REWRITE \
+			| RESETSTAT | HELP>",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "read or change server settings."
 	},
 	"decr" => Command {
 		function: cmd_decr,
@@ -46,18 +290,186 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		validation: |r| {0 < r.parameters.len()},
 		doc: "remove the value associated with the key(s)."
 	},
+	"dump" => Command {
+		function: cmd_dump,
+		syntax: "dump KEY",
+		validation: |r| {1 == r.parameters.len()},
+		doc: "serialize the value stored at the key for RESTORE."
+	},
+	"eval" => Command {
+		function: cmd_eval,
+		syntax: "eval SCRIPT NUMKEYS [ KEY ... ] [ ARG ... ]",
+		validation: |r| {2 <= r.parameters.len()},
+		doc: "run a Lua script atomically, with KEYS and ARGV set from \
+			the parameters."
+	},
+	"eval_ro" => Command {
+		function: cmd_eval_ro,
+		syntax: "eval_ro SCRIPT NUMKEYS [ KEY ... ] [ ARG ... ]",
+		validation: |r| {2 <= r.parameters.len()},
+		doc: "run a Lua script that may not modify data."
+	},
+	"evalsha" => Command {
+		function: cmd_evalsha,
+		syntax: "evalsha SHA1 NUMKEYS [ KEY ... ] [ ARG ... ]",
+		validation: |r| {2 <= r.parameters.len()},
+		doc: "run a cached Lua script by its SHA1 digest."
+	},
+	"evalsha_ro" => Command {
+		function: cmd_evalsha_ro,
+		syntax: "evalsha_ro SHA1 NUMKEYS [ KEY ... ] [ ARG ... ]",
+		validation: |r| {2 <= r.parameters.len()},
+		doc: "run a cached Lua script that may not modify data."
+	},
+	"fcall" => Command {
+		function: cmd_fcall,
+		syntax: "fcall FUNCTION NUMKEYS [ KEY ... ] [ ARG ... ]",
+		validation: |r| {2 <= r.parameters.len()},
+		doc: "call a function of a loaded library."
+	},
+	"fcall_ro" => Command {
+		function: cmd_fcall_ro,
+		syntax: "fcall_ro FUNCTION NUMKEYS [ KEY ... ] [ ARG ... ]",
+		validation: |r| {2 <= r.parameters.len()},
+		doc: "call a function flagged no-writes of a loaded library."
+	},
+	"flushall" => Command {
+		function: cmd_flushall,
+		syntax: "flushall",
+		validation: |r| {0 == r.parameters.len()},
+		doc: "remove all keys."
+	},
+	"ft._list" => Command {
+		function: cmd_ft_list,
+		syntax: "ft._list",
+		validation: |r| {r.parameters.is_empty()},
+		doc: "list the names of the search indexes."
+	},
+	"ft.create" => Command {
+		function: cmd_ft_create,
+		syntax: "ft.create INDEX [ ON HASH ] [ PREFIX count prefix ... ] \
+			SCHEMA field [ AS alias ] TEXT [ NOSTEM ] [ WEIGHT weight ] | \
+			TAG [ SEPARATOR sep ] [ CASESENSITIVE ] | NUMERIC | \
+			VECTOR FLAT | HNSW nargs TYPE FLOAT32 | FLOAT64 DIM dim \
+			DISTANCE_METRIC L2 | IP | COSINE [ M m ] \
+			[ EF_CONSTRUCTION n ] [ EF_RUNTIME n ] ... [ SORTABLE ] ...",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "create a search index over the hashes whose keys start with \
+			one of the prefixes."
+	},
+	"ft.dropindex" => Command {
+		function: cmd_ft_dropindex,
+		syntax: "ft.dropindex INDEX [ DD ]",
+		validation: |r| {matches!(r.parameters.len(), 1 | 2)},
+		doc: "drop a search index, and the hashes it covers with DD."
+	},
+	"ft.info" => Command {
+		function: cmd_ft_info,
+		syntax: "ft.info INDEX",
+		validation: |r| {1 == r.parameters.len()},
+		doc: "describe a search index."
+	},
+	"ft.search" => Command {
+		function: cmd_ft_search,
+		syntax: "ft.search INDEX QUERY [ NOCONTENT ] [ VERBATIM ] \
+			[ WITHSCORES ] [ RETURN count field ... ] \
+			[ SORTBY field [ ASC | DESC ] ] [ LIMIT offset num ] \
+			[ PARAMS nargs name value ... ] [ DIALECT dialect ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "search the hashes covered by an index, for those matching the \
+			words, phrases, prefixes, tags and ranges of the query, or \
+			nearest to a vector by its KNN clause."
+	},
+	"function" => Command {
+		function: cmd_function,
+		syntax: "function DELETE LIBRARY | DUMP | FLUSH [ ASYNC | SYNC ] | \
+			KILL | LIST [ LIBRARYNAME PATTERN ] [ WITHCODE ] | \
+			LOAD [ REPLACE ] CODE | \
+			RESTORE PAYLOAD [ APPEND | FLUSH | REPLACE ]",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "manage the function libraries."
+	},
+	"geoadd" => Command {
+		function: cmd_geoadd,
+		syntax: "geoadd KEY [ NX | XX ] [ CH ] LONGITUDE LATITUDE MEMBER \
+			[ LONGITUDE LATITUDE MEMBER ... ]",
+		validation: |r| {3 < r.parameters.len()},
+		doc: "add the members at the given positions to the geospatial \
+			index stored at key."
+	},
+	"geodist" => Command {
+		function: cmd_geodist,
+		syntax: "geodist KEY MEMBER1 MEMBER2 [ M | KM | FT | MI ]",
+		validation: |r| {matches!(r.parameters.len(), 3 | 4)},
+		doc: "get the distance between two members of the geospatial index \
+			stored at key."
+	},
+	"geohash" => Command {
+		function: cmd_geohash,
+		syntax: "geohash KEY [ MEMBER ... ]",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "get the geohash strings of members of the geospatial index \
+			stored at key."
+	},
+	"geopos" => Command {
+		function: cmd_geopos,
+		syntax: "geopos KEY [ MEMBER ... ]",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "get the positions of members of the geospatial index stored \
+			at key."
+	},
+	"geosearch" => Command {
+		function: cmd_geosearch,
+		syntax: "geosearch KEY <FROMMEMBER member | FROMLONLAT longitude \
+			latitude> <BYRADIUS radius <M | KM | FT | MI> | BYBOX width \
+			height <M | KM | FT | MI>> [ ASC | DESC ] [ COUNT count [ ANY ] ] \
+			[ WITHCOORD ] [ WITHDIST ] [ WITHHASH ]",
+		validation: |r| {4 < r.parameters.len()},
+		doc: "find the members of the geospatial index stored at key within \
+			an area."
+	},
+	"geosearchstore" => Command {
+		function: cmd_geosearchstore,
+		syntax: "geosearchstore DESTINATION SOURCE <FROMMEMBER member \
+			| FROMLONLAT longitude latitude> <BYRADIUS radius \
+			<M | KM | FT | MI> | BYBOX width height <M | KM | FT | MI>> \
+			[ ASC | DESC ] [ COUNT count [ ANY ] ] [ STOREDIST ]",
+		validation: |r| {5 < r.parameters.len()},
+		doc: "store the members of the geospatial index stored at source \
+			within an area at destination."
+	},
 	"get" => Command {
 		function: cmd_get,
 		syntax: "get KEY",
 		validation: |r| {1 == r.parameters.len()},
 		doc: "obtain value associated with the key."
 	},
+	"getbit" => Command {
+		function: cmd_getbit,
+		syntax: "getbit KEY OFFSET",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "get the bit at offset in the string stored at key."
+	},
 	"getdel" => Command {
 		function: cmd_getdel,
 		syntax: "getdel KEY",
 		validation: |r| {1 == r.parameters.len()},
 		doc: "obtain the value of the key and delete it."
 	},
+	"getex" => Command {
+		function: cmd_getex,
+		syntax: "getex KEY [ EX SECONDS | PX MS | EXAT TIMESTAMP | \
+			PXAT MS-TIMESTAMP | PERSIST ]",
+		validation: |r| {(1..4).contains(&r.parameters.len())},
+		doc: "obtain the value of the key, and set or remove its expiry."
+	},
+	"getrange" => Command {
+		function: cmd_getrange,
+		syntax: "getrange KEY START END",
+		validation: |r| {3 == r.parameters.len()},
+		doc: "obtain the part of the value of the key from start to end, \
+			negative offsets counting from its end."
+	},
 	"getset" => Command {
 		function: cmd_getset,
 		syntax: "getset KEY VALUE",
@@ -82,6 +494,29 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		validation: |r| {2 == r.parameters.len()},
 		doc: "return 1 if field exists, 0 if not, in the hash stored at key"
 	},
+	"hexpire" => Command {
+		function: cmd_hexpire,
+		syntax: "hexpire KEY SECONDS \
+			[ NX | XX | GT | LT ] FIELDS NUMFIELDS FIELD [ FIELD ... ]",
+		validation: |r| {4 < r.parameters.len()},
+		doc: "set the time to live in seconds of fields of the hash stored \
+			at key"
+	},
+	"hexpireat" => Command {
+		function: cmd_hexpire,
+		syntax: "hexpireat KEY TIMESTAMP \
+			[ NX | XX | GT | LT ] FIELDS NUMFIELDS FIELD [ FIELD ... ]",
+		validation: |r| {4 < r.parameters.len()},
+		doc: "set the unix time in seconds at which fields of the hash \
+			stored at key expire"
+	},
+	"hexpiretime" => Command {
+		function: cmd_httl,
+		syntax: "hexpiretime KEY FIELDS NUMFIELDS FIELD [ FIELD ... ]",
+		validation: |r| {3 < r.parameters.len()},
+		doc: "get the unix time in seconds at which fields of the hash \
+			stored at key expire"
+	},
 	"hget" => Command {
 		function: cmd_hget,
 		syntax: "hget KEY FIELD",
@@ -94,6 +529,21 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		validation: |r| {1 == r.parameters.len()},
 		doc: "get all fields and values from the hash stored at key"
 	},
+	"hgetdel" => Command {
+		function: cmd_hgetdel,
+		syntax: "hgetdel KEY FIELDS NUMFIELDS FIELD [ FIELD ... ]",
+		validation: |r| {3 < r.parameters.len()},
+		doc: "get the values of fields of the hash stored at key and delete \
+			them"
+	},
+	"hgetex" => Command {
+		function: cmd_hgetex,
+		syntax: "hgetex KEY [ EX SECONDS | PX MS | EXAT TIMESTAMP | \
+			PXAT MS-TIMESTAMP | PERSIST ] FIELDS NUMFIELDS FIELD [ FIELD ... ]",
+		validation: |r| {3 < r.parameters.len()},
+		doc: "get the values of fields of the hash stored at key, and set or \
+			remove their expiry"
+	},
 	"hincrby" => Command {
 		function: cmd_hincrby,
 		syntax: "hincrby KEY FIELD INCR",
@@ -101,6 +551,13 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		doc: "increment the numerical value of the field in the hash \
 			stored at key by increment"
 	},
+	"hincrbyfloat" => Command {
+		function: cmd_hincrbyfloat,
+		syntax: "hincrbyfloat KEY FIELD INCR",
+		validation: |r| {3 == r.parameters.len()},
+		doc: "increment the floating point value of the field in the hash \
+			stored at key by increment"
+	},
 	"hkeys" => Command {
 		function: cmd_hkeys,
 		syntax: "hkeys KEY",
@@ -128,6 +585,48 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		},
 		doc: "set specified fields to values in the hash stored at key"
 	},
+	"hpersist" => Command {
+		function: cmd_hpersist,
+		syntax: "hpersist KEY FIELDS NUMFIELDS FIELD [ FIELD ... ]",
+		validation: |r| {3 < r.parameters.len()},
+		doc: "remove the expiry of fields of the hash stored at key"
+	},
+	"hpexpire" => Command {
+		function: cmd_hexpire,
+		syntax: "hpexpire KEY MS \
+			[ NX | XX | GT | LT ] FIELDS NUMFIELDS FIELD [ FIELD ... ]",
+		validation: |r| {4 < r.parameters.len()},
+		doc: "set the time to live in milliseconds of fields of the hash \
+			stored at key"
+	},
+	"hpexpireat" => Command {
+		function: cmd_hexpire,
+		syntax: "hpexpireat KEY MS-TIMESTAMP \
+			[ NX | XX | GT | LT ] FIELDS NUMFIELDS FIELD [ FIELD ... ]",
+		validation: |r| {4 < r.parameters.len()},
+		doc: "set the unix time in milliseconds at which fields of the hash \
+			stored at key expire"
+	},
+	"hpexpiretime" => Command {
+		function: cmd_httl,
+		syntax: "hpexpiretime KEY FIELDS NUMFIELDS FIELD [ FIELD ... ]",
+		validation: |r| {3 < r.parameters.len()},
+		doc: "get the unix time in milliseconds at which fields of the hash \
+			stored at key expire"
+	},
+	"hpttl" => Command {
+		function: cmd_httl,
+		syntax: "hpttl KEY FIELDS NUMFIELDS FIELD [ FIELD ... ]",
+		validation: |r| {3 < r.parameters.len()},
+		doc: "get the time to live in milliseconds of fields of the hash \
+			stored at key"
+	},
+	"hrandfield" => Command {
+		function: cmd_hrandfield,
+		syntax: "hrandfield KEY [ COUNT [ WITHVALUES ] ]",
+		validation: |r| {(1..4).contains(&r.parameters.len())},
+		doc: "get random fields of the hash stored at key"
+	},
 	"hset" => Command {
 		function: cmd_hset,
 		syntax: "hset KEY FIELD VALUE [ FIELD VALUE ... ]",
@@ -145,6 +644,20 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		doc: "set specified non-existing fields to values in the hash \
 			stored at key"
 	},
+	"hstrlen" => Command {
+		function: cmd_hstrlen,
+		syntax: "hstrlen KEY FIELD",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "get the length of the value of the field in the hash stored \
+			at key"
+	},
+	"httl" => Command {
+		function: cmd_httl,
+		syntax: "httl KEY FIELDS NUMFIELDS FIELD [ FIELD ... ]",
+		validation: |r| {3 < r.parameters.len()},
+		doc: "get the time to live in seconds of fields of the hash stored \
+			at key"
+	},
 	"hvals" => Command {
 		function: cmd_hvals,
 		syntax: "hvals KEY",
@@ -163,11 +676,99 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		validation: |r| {2 == r.parameters.len()},
 		doc: "increment value stored at the key by the integer provided."
 	},
+	"incrbyfloat" => Command {
+		function: cmd_incrbyfloat,
+		syntax: "incrbyfloat KEY VALUE",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "increment value stored at the key by the float provided."
+	},
 	"info" => Command {
 		function: cmd_info,
-		syntax: "info",
-		validation: |r| {0 == r.parameters.len()},
-		doc: "display system info."
+		syntax: "info [ SECTION ]",
+		validation: |r| {2 > r.parameters.len()},
+		doc: "display system info, or only the given section \
+			(server, replication)."
+	},
+	"json.arrappend" => Command {
+		function: cmd_json_arrappend,
+		syntax: "json.arrappend KEY PATH VALUE [ VALUE ... ]",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "append JSON values to the arrays at path in the document \
+			stored at key."
+	},
+	"json.arrinsert" => Command {
+		function: cmd_json_arrinsert,
+		syntax: "json.arrinsert KEY PATH INDEX VALUE [ VALUE ... ]",
+		validation: |r| {3 < r.parameters.len()},
+		doc: "insert JSON values before index into the arrays at path in \
+			the document stored at key."
+	},
+	"json.arrlen" => Command {
+		function: cmd_json_arrlen,
+		syntax: "json.arrlen KEY [ PATH ]",
+		validation: |r| {matches!(r.parameters.len(), 1 | 2)},
+		doc: "get the lengths of the arrays at path in the document stored \
+			at key."
+	},
+	"json.arrpop" => Command {
+		function: cmd_json_arrpop,
+		syntax: "json.arrpop KEY [ PATH [ INDEX ] ]",
+		validation: |r| {matches!(r.parameters.len(), 1..=3)},
+		doc: "remove and return the elements at index of the arrays at path \
+			in the document stored at key."
+	},
+	"json.del" => Command {
+		function: cmd_json_del,
+		syntax: "json.del KEY [ PATH ]",
+		validation: |r| {matches!(r.parameters.len(), 1 | 2)},
+		doc: "delete the values at path in the document stored at key."
+	},
+	"json.get" => Command {
+		function: cmd_json_get,
+		syntax: "json.get KEY [ INDENT indent ] [ NEWLINE newline ] \
+			[ SPACE space ] [ PATH ... ]",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "get the values at the paths in the document stored at key, \
+			serialized as JSON."
+	},
+	"json.mget" => Command {
+		function: cmd_json_mget,
+		syntax: "json.mget KEY [ KEY ... ] PATH",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "get the values at path in the documents stored at the keys."
+	},
+	"json.numincrby" => Command {
+		function: cmd_json_numincrby,
+		syntax: "json.numincrby KEY PATH VALUE",
+		validation: |r| {3 == r.parameters.len()},
+		doc: "increment the numbers at path in the document stored at key."
+	},
+	"json.objkeys" => Command {
+		function: cmd_json_objkeys,
+		syntax: "json.objkeys KEY [ PATH ]",
+		validation: |r| {matches!(r.parameters.len(), 1 | 2)},
+		doc: "get the keys of the objects at path in the document stored at \
+			key."
+	},
+	"json.set" => Command {
+		function: cmd_json_set,
+		syntax: "json.set KEY PATH VALUE [ NX | XX ]",
+		validation: |r| {matches!(r.parameters.len(), 3 | 4)},
+		doc: "set the JSON value at path in the document stored at key."
+	},
+	"json.strappend" => Command {
+		function: cmd_json_strappend,
+		syntax: "json.strappend KEY [ PATH ] VALUE",
+		validation: |r| {matches!(r.parameters.len(), 2 | 3)},
+		doc: "append a JSON string to the strings at path in the document \
+			stored at key."
+	},
+	"json.type" => Command {
+		function: cmd_json_type,
+		syntax: "json.type KEY [ PATH ]",
+		validation: |r| {matches!(r.parameters.len(), 1 | 2)},
+		doc: "get the types of the values at path in the document stored at \
+			key."
 	},
 	"keys" => Command {
 		function: cmd_keys,
@@ -175,6 +776,14 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		validation: |r| {1 == r.parameters.len()},
 		doc: "list keys matching the REGEX pattern."
 	},
+	"lcs" => Command {
+		function: cmd_lcs,
+		syntax: "lcs KEY1 KEY2 [ LEN ] [ IDX ] [ MINMATCHLEN LEN ] \
+			[ WITHMATCHLEN ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "find the longest common subsequence of the values of two \
+			keys, its length, or the ranges it matches."
+	},
 	"lindex" => Command {
 		function: cmd_lindex,
 		syntax: "lindex KEY INDEX",
@@ -194,6 +803,20 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		validation: |r| {1 == r.parameters.len()},
 		doc: "get the length of the list stored at the key"
 	},
+	"lmove" => Command {
+		function: cmd_lmove,
+		syntax: "lmove SOURCE DESTINATION LEFT|RIGHT LEFT|RIGHT",
+		validation: |r| {4 == r.parameters.len()},
+		doc: "remove an element from one end of the list stored at source \
+			and add it to one end of the list stored at destination"
+	},
+	"lmpop" => Command {
+		function: cmd_lmpop,
+		syntax: "lmpop NUMKEYS KEY [ KEY ... ] LEFT|RIGHT [ COUNT NUMBER ]",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "remove and return the values from one end of the first \
+			non-empty list among the keys"
+	},
 	"lpop" => Command {
 		function: cmd_lpop,
 		syntax: "lpop KEY [ NUMBER ]",
@@ -201,6 +824,14 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		doc: "remove and return the values from the beginning of the list \
 			stored at key"
 	},
+	"lpos" => Command {
+		function: cmd_lpos,
+		syntax: "lpos KEY ELEMENT [ RANK RANK ] [ COUNT NUMBER ] \
+			[ MAXLEN LEN ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "get the indexes of the matches of element in the list stored \
+			at key"
+	},
 	"lpush" => Command {
 		function: cmd_lpush,
 		syntax: "lpush KEY VALUE [ VALUE ... ]",
@@ -246,18 +877,137 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		validation: |r| {1 < r.parameters.len()},
 		doc: "get values stored at specified keys."
 	},
+	"migrate" => Command {
+		function: cmd_migrate,
+		syntax: "migrate HOST PORT <KEY | \"\"> DESTINATION-DB TIMEOUT \
+			[ COPY ] [ REPLACE ] [ AUTH password | AUTH2 username password ] \
+			[ KEYS key [ key ... ] ]",
+		validation: |r| {5 <= r.parameters.len()},
+		doc: "move keys to another instance."
+	},
+	"monitor" => Command {
+		function: cmd_monitor,
+		syntax: "monitor",
+		validation: |r| {0 == r.parameters.len()},
+		doc: "stream every command processed by the server."
+	},
 	"mset" => Command {
 		function: cmd_mset,
 		syntax: "mset KEY VALUE [ KEY VALUE ... ]",
 		validation: |r| {1 < r.parameters.len()},
 		doc: "store values with the specified keys."
 	},
+	"msetnx" => Command {
+		function: cmd_msetnx,
+		syntax: "msetnx KEY VALUE [ KEY VALUE ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "store values with the specified keys, unless any exists."
+	},
+	"pfadd" => Command {
+		function: cmd_pfadd,
+		syntax: "pfadd KEY [ ELEMENT [ ELEMENT ... ] ]",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "add the elements to the HyperLogLog stored at key."
+	},
+	"pfcount" => Command {
+		function: cmd_pfcount,
+		syntax: "pfcount KEY [ KEY ... ]",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "estimate the number of distinct elements added to the \
+			HyperLogLogs stored at the keys."
+	},
+	"pfmerge" => Command {
+		function: cmd_pfmerge,
+		syntax: "pfmerge DESTKEY [ SOURCEKEY [ SOURCEKEY ... ] ]",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "merge the HyperLogLogs stored at the source keys into destkey."
+	},
+	"ping" => Command {
+		function: cmd_ping,
+		syntax: "ping [ MESSAGE ]",
+		validation: |r| {2 > r.parameters.len()},
+		doc: "check the connection, echoing the message if any."
+	},
+	"psubscribe" => Command {
+		function: cmd_psubscribe,
+		syntax: "psubscribe PATTERN [ PATTERN ... ]",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "receive the messages published to channels matching the \
+			given glob-style patterns."
+	},
+	"psync" => Command {
+		function: cmd_psync,
+		syntax: "psync REPLICATIONID OFFSET",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "turn the connection into a replication stream, resuming \
+			from the offset when possible."
+	},
+	"pttl" => Command {
+		function: cmd_pttl,
+		syntax: "pttl KEY",
+		validation: |r| {1 == r.parameters.len()},
+		doc: "get the time to live of the key in milliseconds."
+	},
+	"publish" => Command {
+		function: cmd_publish,
+		syntax: "publish CHANNEL MESSAGE",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "post a message to a channel, returning the number of \
+			subscribers that received it."
+	},
+	"pubsub" => Command {
+		function: cmd_pubsub,
+		syntax: "pubsub CHANNELS [ PATTERN ] | NUMPAT | \
+			NUMSUB [ CHANNEL ... ]",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "inspect the state of the pub/sub channels."
+	},
+	"punsubscribe" => Command {
+		function: cmd_punsubscribe,
+		syntax: "punsubscribe [ PATTERN ... ]",
+		validation: |_| {true},
+		doc: "stop receiving messages for the given patterns, or all of \
+			them."
+	},
 	"quit" => Command {
 		function: cmd_quit,
 		syntax: "quit",
 		validation: |r| {0 == r.parameters.len()},
 		doc: "close current connection and quit."
 	},
+	"replconf" => Command {
+		function: cmd_replconf,
+		syntax: "replconf OPTION VALUE [ OPTION VALUE ... ]",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "exchange replication settings between replica and primary."
+	},
+	"replicaof" => Command {
+		function: cmd_replicaof,
+		syntax: "replicaof <HOST PORT | NO ONE>",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "replicate the given primary, or stop replicating."
+	},
+	"rename" => Command {
+		function: cmd_rename,
+		syntax: "rename KEY NEWKEY",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "rename a key, overwriting the new key if it exists."
+	},
+	"renamenx" => Command {
+		function: cmd_renamenx,
+		syntax: "renamenx KEY NEWKEY",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "rename a key only if the new key does not exist yet."
+	},
+	"restore" => Command {
+		function: cmd_restore,
+		syntax: "restore KEY TTL SERIALIZED-VALUE [ REPLACE ]",
+		validation: |r| {
+			3 == r.parameters.len() || (4 == r.parameters.len()
+				&& r.parameters[3].eq_ignore_ascii_case("replace"))
+		},
+		doc: "create a key from a value serialized by DUMP."
+	},
 	"rpop" => Command {
 		function: cmd_rpop,
 		syntax: "rpop KEY [ NUMBER ]",
@@ -265,6 +1015,13 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		doc: "remove and return the values from the end of the list stored \
 			at key"
 	},
+	"rpoplpush" => Command {
+		function: cmd_rpoplpush,
+		syntax: "rpoplpush SOURCE DESTINATION",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "remove the last element of the list stored at source and add \
+			it at the beginning of the list stored at destination"
+	},
 	"rpush" => Command {
 		function: cmd_rpush,
 		syntax: "rpush KEY VALUE [ VALUE ... ]",
@@ -305,12 +1062,32 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		doc: "get members that only exist in the set stored at the first \
 			key and store them in a new set stored at the destination"
 	},
+	"script" => Command {
+		function: cmd_script,
+		syntax: "script <LOAD script | EXISTS sha1 [ sha1 ... ] \
+			| FLUSH [ ASYNC | SYNC ] | KILL>",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "manage the Lua script cache and the running script."
+	},
 	"set" => Command {
 		function: cmd_set,
 		syntax: "set KEY VALUE",
 		validation: |r| {2 == r.parameters.len()},
 		doc: "record the given key value pair."
 	},
+	"setbit" => Command {
+		function: cmd_setbit,
+		syntax: "setbit KEY OFFSET VALUE",
+		validation: |r| {3 == r.parameters.len()},
+		doc: "set or clear the bit at offset in the string stored at key."
+	},
+	"setrange" => Command {
+		function: cmd_setrange,
+		syntax: "setrange KEY OFFSET VALUE",
+		validation: |r| {3 == r.parameters.len()},
+		doc: "overwrite the value of the key from offset on, padding it \
+			with zero bytes if shorter."
+	},
 	"sismember" => Command {
 		function: cmd_sismember,
 		syntax: "sismember KEY VALUE",
@@ -318,6 +1095,12 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		doc: "return if the specified value is a member of the set stored \
 			at key"
 	},
+	"slaveof" => Command {
+		function: cmd_replicaof,
+		syntax: "slaveof <HOST PORT | NO ONE>",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "replicate the given primary, or stop replicating."
+	},
 	"smembers" => Command {
 		function: cmd_smembers,
 		syntax: "smembers KEY",
@@ -344,6 +1127,13 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		doc: "get values that exist in all of the sets stored at the given \
 			keys"
 	},
+	"sintercard" => Command {
+		function: cmd_sintercard,
+		syntax: "sintercard NUMKEYS KEY [ KEY ... ] [ LIMIT NUMBER ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "count the values that exist in all of the sets stored at the \
+			given keys, stopping at limit"
+	},
 	"sinterstore" => Command {
 		function: cmd_sinterstore,
 		syntax: "sinter DESTINATION KEY [ KEY ... ]",
@@ -370,6 +1160,31 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		validation: |r| {1 < r.parameters.len()},
 		doc: "remove specified values from the set stored at key"
 	},
+	"strlen" => Command {
+		function: cmd_strlen,
+		syntax: "strlen KEY",
+		validation: |r| {1 == r.parameters.len()},
+		doc: "get the length of the value of the key."
+	},
+	"sync" => Command {
+		function: cmd_psync,
+		syntax: "sync",
+		validation: |r| {0 == r.parameters.len()},
+		doc: "turn the connection into a replication stream."
+	},
+	"subscribe" => Command {
+		function: cmd_subscribe,
+		syntax: "subscribe CHANNEL [ CHANNEL ... ]",
+		validation: |r| {0 < r.parameters.len()},
+		doc: "receive the messages published to the given channels."
+	},
+	"substr" => Command {
+		function: cmd_getrange,
+		syntax: "substr KEY START END",
+		validation: |r| {3 == r.parameters.len()},
+		doc: "obtain the part of the value of the key from start to end, \
+			negative offsets counting from its end."
+	},
 	"sunion" => Command {
 		function: cmd_sunion,
 		syntax: "sunion KEY [ KEY ... ]",
@@ -382,106 +1197,989 @@ static CMDS: phf::Map<&str, Command> = phf_map! {
 		validation: |r| {1 < r.parameters.len()},
 		doc: "get all unique values from all sets stored by the given \
 			keys and store them in a new set at destination"
-	}
-};
-
-pub fn process<R: Read + Copy, W: Write>(r: R, w: W) {
-	let mut reader: BufReader<R> = BufReader::new(r);
-	let mut writer: BufWriter<W> = BufWriter::new(w);
-	loop {
-		if let Err(_) = writer.flush() {return;}
-		match parse(&mut reader) {
-			Ok(req) => {
-				match CMDS.get(req.command.as_str()) {
-					Some(cmd) => {
-						if (cmd.validation)(&req) {
-							if let Err(_) = write!(
-									writer,
-									"{}",
-									match (cmd.function)(&req) {
-										Ok(dt_v) => dt_v,
-										Err(e) => DataType::err(&e.to_string())
-									}
-								) {
-								return;
-							}
-							if "quit" == req.command.as_str() {
-								let _ = writer.flush();
-								return;
-							}
-						} else if let Err(_) = write!(
-								writer,
-								"{}",
-								DataType::err(&format!(
-									"ERR correct syntax: \"{}\"",
-									cmd.syntax
-								))
-							) {
-							return;
-						}
-					},
-					None => if let Err(_) = write!(
-							writer,
-							"{}",
-							DataType::err(&format!(
-								"ERR unknown command \"{}\"",
-								req.command
-							))
-						) {
-							return;
-						}
-				}
-			},
-			Err(e) => {
-				if let Err(_) = write!(writer, "{}", DataType::err(e)) {
-					eprintln!("Error: {:?}", e);
-				}
-				match e {
-					"ERR EOF reached" | "ERR Connection error" => {return;},
-					_ => {}
-				}
-			}
-		}
-	}
-}
-
-fn cmd_append(req: &Request) -> Result<DataType, &str> {
-	kv::append(
-		req.parameters.iter().nth(0).unwrap().as_str(),
-		req.parameters.iter().nth(1).unwrap().as_str()
-	)
-}
-
-fn cmd_client(_req: &Request) -> Result<DataType, &str> {
-	// TODO:shinkou:2025-03-06:Implement client command
-	Ok(DataType::str("OK"))
-}
-
-fn cmd_decr(req: &Request) -> Result<DataType, &str> {
-	kv::decr(req.parameters.iter().nth(0).unwrap().as_str())
-}
-
-fn cmd_decrby(req: &Request) -> Result<DataType, &str> {
-	kv::decrby(
-		req.parameters.iter().nth(0).unwrap().as_str(),
-		req.parameters.iter().nth(1).unwrap().as_str()
-	)
-}
-
-fn cmd_del(req: &Request) -> Result<DataType, &str> {
-	kv::del(&req.parameters)
-}
-
-fn cmd_get(req: &Request) -> Result<DataType, &str> {
-	kv::get(req.parameters.iter().nth(0).unwrap())
-}
-
-fn cmd_getdel(req: &Request) -> Result<DataType, &str> {
-	kv::getdel(req.parameters.iter().nth(0).unwrap())
-}
-
-fn cmd_getset(req: &Request) -> Result<DataType, &str> {
-	kv::getset(
+	},
+	"tdigest.add" => Command {
+		function: cmd_tdigest_add,
+		syntax: "tdigest.add KEY VALUE [ VALUE ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "add values to the t-digest stored at key."
+	},
+	"tdigest.cdf" => Command {
+		function: cmd_tdigest_cdf,
+		syntax: "tdigest.cdf KEY VALUE [ VALUE ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "get the estimated shares of values in the t-digest stored at \
+			key below the given ones."
+	},
+	"tdigest.create" => Command {
+		function: cmd_tdigest_create,
+		syntax: "tdigest.create KEY [ COMPRESSION compression ]",
+		validation: |r| {matches!(r.parameters.len(), 1 | 3)},
+		doc: "create an empty t-digest at key."
+	},
+	"tdigest.loadchunk" => Command {
+		function: cmd_loadchunk,
+		syntax: "tdigest.loadchunk KEY ITERATOR DATA",
+		validation: |r| {3 == r.parameters.len()},
+		doc: "restore the t-digest at key from data got by tdigest.scandump."
+	},
+	"tdigest.max" => Command {
+		function: cmd_tdigest_max,
+		syntax: "tdigest.max KEY",
+		validation: |r| {1 == r.parameters.len()},
+		doc: "get the largest value in the t-digest stored at key."
+	},
+	"tdigest.merge" => Command {
+		function: cmd_tdigest_merge,
+		syntax: "tdigest.merge DESTINATION NUMKEYS SOURCE [ SOURCE ... ] \
+			[ COMPRESSION compression ] [ OVERRIDE ]",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "merge t-digests into the one at destination."
+	},
+	"tdigest.min" => Command {
+		function: cmd_tdigest_min,
+		syntax: "tdigest.min KEY",
+		validation: |r| {1 == r.parameters.len()},
+		doc: "get the smallest value in the t-digest stored at key."
+	},
+	"tdigest.quantile" => Command {
+		function: cmd_tdigest_quantile,
+		syntax: "tdigest.quantile KEY QUANTILE [ QUANTILE ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "get the estimated values at quantiles of the t-digest stored \
+			at key."
+	},
+	"tdigest.rank" => Command {
+		function: cmd_tdigest_rank,
+		syntax: "tdigest.rank KEY VALUE [ VALUE ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "get the estimated ranks of values in the t-digest stored at \
+			key."
+	},
+	"tdigest.scandump" => Command {
+		function: cmd_scandump,
+		syntax: "tdigest.scandump KEY ITERATOR",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "dump the t-digest stored at key, for tdigest.loadchunk."
+	},
+	"topk.add" => Command {
+		function: cmd_topk_add,
+		syntax: "topk.add KEY ITEM [ ITEM ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "count items once more in the Top-K sketch stored at key, \
+			getting the items they pushed out of the top."
+	},
+	"topk.incrby" => Command {
+		function: cmd_topk_incrby,
+		syntax: "topk.incrby KEY ITEM INCREMENT \
+			[ ITEM INCREMENT ... ]",
+		validation: |r| {
+			2 < r.parameters.len() && 1 == r.parameters.len() % 2
+		},
+		doc: "count items more times in the Top-K sketch stored at key, \
+			getting the items they pushed out of the top."
+	},
+	"topk.list" => Command {
+		function: cmd_topk_list,
+		syntax: "topk.list KEY [ WITHCOUNT ]",
+		validation: |r| {matches!(r.parameters.len(), 1 | 2)},
+		doc: "get the top items of the Top-K sketch stored at key."
+	},
+	"topk.loadchunk" => Command {
+		function: cmd_loadchunk,
+		syntax: "topk.loadchunk KEY ITERATOR DATA",
+		validation: |r| {3 == r.parameters.len()},
+		doc: "restore the Top-K sketch at key from data got by \
+			topk.scandump."
+	},
+	"topk.query" => Command {
+		function: cmd_topk_query,
+		syntax: "topk.query KEY ITEM [ ITEM ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "check if items are among the top of the Top-K sketch stored \
+			at key."
+	},
+	"topk.reserve" => Command {
+		function: cmd_topk_reserve,
+		syntax: "topk.reserve KEY TOPK [ WIDTH DEPTH DECAY ]",
+		validation: |r| {matches!(r.parameters.len(), 2 | 5)},
+		doc: "create an empty Top-K sketch at key."
+	},
+	"topk.scandump" => Command {
+		function: cmd_scandump,
+		syntax: "topk.scandump KEY ITERATOR",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "dump the Top-K sketch stored at key, for topk.loadchunk."
+	},
+	"ts.add" => Command {
+		function: cmd_ts_add,
+		syntax: "ts.add KEY TIMESTAMP VALUE [ RETENTION retention ] \
+			[ ENCODING encoding ] [ CHUNK_SIZE size ] \
+			[ DUPLICATE_POLICY policy ] [ ON_DUPLICATE policy ] \
+			[ LABELS label value ... ]",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "add a sample to the time series stored at key, creating it \
+			when missing."
+	},
+	"ts.create" => Command {
+		function: cmd_ts_create,
+		syntax: "ts.create KEY [ RETENTION retention ] \
+			[ ENCODING encoding ] [ CHUNK_SIZE size ] \
+			[ DUPLICATE_POLICY policy ] [ LABELS label value ... ]",
+		validation: |r| {!r.parameters.is_empty()},
+		doc: "create an empty time series at key."
+	},
+	"ts.createrule" => Command {
+		function: cmd_ts_createrule,
+		syntax: "ts.createrule SOURCE DESTINATION AGGREGATION aggregator \
+			bucketduration [ aligntimestamp ]",
+		validation: |r| {matches!(r.parameters.len(), 5 | 6)},
+		doc: "downsample samples added to the time series at source into \
+			the one at destination."
+	},
+	"ts.decrby" => Command {
+		function: cmd_ts_decrby,
+		syntax: "ts.decrby KEY SUBTRAHEND [ TIMESTAMP timestamp ] \
+			[ RETENTION retention ] [ ENCODING encoding ] \
+			[ CHUNK_SIZE size ] [ DUPLICATE_POLICY policy ] \
+			[ LABELS label value ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "add a sample of the last value of the time series stored at \
+			key less the subtrahend."
+	},
+	"ts.deleterule" => Command {
+		function: cmd_ts_deleterule,
+		syntax: "ts.deleterule SOURCE DESTINATION",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "stop downsampling the time series at source into the one at \
+			destination."
+	},
+	"ts.get" => Command {
+		function: cmd_ts_get,
+		syntax: "ts.get KEY",
+		validation: |r| {1 == r.parameters.len()},
+		doc: "get the last sample of the time series stored at key."
+	},
+	"ts.incrby" => Command {
+		function: cmd_ts_incrby,
+		syntax: "ts.incrby KEY ADDEND [ TIMESTAMP timestamp ] \
+			[ RETENTION retention ] [ ENCODING encoding ] \
+			[ CHUNK_SIZE size ] [ DUPLICATE_POLICY policy ] \
+			[ LABELS label value ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "add a sample of the last value of the time series stored at \
+			key plus the addend."
+	},
+	"ts.info" => Command {
+		function: cmd_ts_info,
+		syntax: "ts.info KEY",
+		validation: |r| {1 == r.parameters.len()},
+		doc: "get information about the time series stored at key."
+	},
+	"ts.loadchunk" => Command {
+		function: cmd_loadchunk,
+		syntax: "ts.loadchunk KEY ITERATOR DATA",
+		validation: |r| {3 == r.parameters.len()},
+		doc: "restore the time series at key from data got by ts.scandump."
+	},
+	"ts.madd" => Command {
+		function: cmd_ts_madd,
+		syntax: "ts.madd KEY TIMESTAMP VALUE [ KEY TIMESTAMP VALUE ... ]",
+		validation: |r| {
+			!r.parameters.is_empty() && 0 == r.parameters.len() % 3
+		},
+		doc: "add samples to the time series stored at the given keys."
+	},
+	"ts.mrange" => Command {
+		function: cmd_ts_mrange,
+		syntax: "ts.mrange FROMTIMESTAMP TOTIMESTAMP [ FILTER_BY_TS ts ... ] \
+			[ FILTER_BY_VALUE min max ] [ WITHLABELS | \
+			SELECTED_LABELS label ... ] [ COUNT count ] [ ALIGN align ] \
+			[ AGGREGATION aggregator bucketduration ] FILTER filter ...",
+		validation: |r| {3 < r.parameters.len()},
+		doc: "get the samples within the range of the time series whose \
+			labels match the filter."
+	},
+	"ts.mrevrange" => Command {
+		function: cmd_ts_mrevrange,
+		syntax: "ts.mrevrange FROMTIMESTAMP TOTIMESTAMP \
+			[ FILTER_BY_TS ts ... ] [ FILTER_BY_VALUE min max ] \
+			[ WITHLABELS | SELECTED_LABELS label ... ] [ COUNT count ] \
+			[ ALIGN align ] [ AGGREGATION aggregator bucketduration ] \
+			FILTER filter ...",
+		validation: |r| {3 < r.parameters.len()},
+		doc: "get the samples within the range of the time series whose \
+			labels match the filter, latest first."
+	},
+	"ts.queryindex" => Command {
+		function: cmd_ts_queryindex,
+		syntax: "ts.queryindex FILTER [ FILTER ... ]",
+		validation: |r| {!r.parameters.is_empty()},
+		doc: "get the keys of the time series whose labels match the \
+			filter."
+	},
+	"ts.range" => Command {
+		function: cmd_ts_range,
+		syntax: "ts.range KEY FROMTIMESTAMP TOTIMESTAMP \
+			[ FILTER_BY_TS ts ... ] [ FILTER_BY_VALUE min max ] \
+			[ COUNT count ] [ ALIGN align ] \
+			[ AGGREGATION aggregator bucketduration ]",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "get the samples of the time series stored at key within the \
+			range."
+	},
+	"ts.revrange" => Command {
+		function: cmd_ts_revrange,
+		syntax: "ts.revrange KEY FROMTIMESTAMP TOTIMESTAMP \
+			[ FILTER_BY_TS ts ... ] [ FILTER_BY_VALUE min max ] \
+			[ COUNT count ] [ ALIGN align ] \
+			[ AGGREGATION aggregator bucketduration ]",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "get the samples of the time series stored at key within the \
+			range, latest first."
+	},
+	"ts.scandump" => Command {
+		function: cmd_scandump,
+		syntax: "ts.scandump KEY ITERATOR",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "dump the time series stored at key, for ts.loadchunk."
+	},
+	"ttl" => Command {
+		function: cmd_ttl,
+		syntax: "ttl KEY",
+		validation: |r| {1 == r.parameters.len()},
+		doc: "get the time to live of the key in seconds."
+	},
+	"unsubscribe" => Command {
+		function: cmd_unsubscribe,
+		syntax: "unsubscribe [ CHANNEL ... ]",
+		validation: |_| {true},
+		doc: "stop receiving messages for the given channels, or all of \
+			them."
+	},
+	"wait" => Command {
+		function: cmd_wait,
+		syntax: "wait NUMREPLICAS TIMEOUT",
+		validation: |r| {2 == r.parameters.len()},
+		doc: "block until the given number of replicas acknowledged the \
+			writes of the connection, or the timeout in ms expires."
+	},
+	"xack" => Command {
+		function: cmd_xack,
+		syntax: "xack KEY GROUP ID [ ID ... ]",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "acknowledge entries delivered to a consumer group, removing \
+			them from its pending entries list."
+	},
+	"xadd" => Command {
+		function: cmd_xadd,
+		syntax: "xadd KEY [ NOMKSTREAM ] [ <MAXLEN | MINID> [ = | ~ ] \
+			THRESHOLD [ LIMIT count ] ] <* | ID> FIELD VALUE \
+			[ FIELD VALUE ... ]",
+		validation: |r| {3 < r.parameters.len()},
+		doc: "append an entry to the stream stored at key, trimming it if \
+			asked to."
+	},
+	"xautoclaim" => Command {
+		function: cmd_xautoclaim,
+		syntax: "xautoclaim KEY GROUP CONSUMER MIN-IDLE-TIME START \
+			[ COUNT count ] [ JUSTID ]",
+		validation: |r| {4 < r.parameters.len()},
+		doc: "transfer the pending entries idle for long enough to another \
+			consumer, scanning from start."
+	},
+	"xclaim" => Command {
+		function: cmd_xclaim,
+		syntax: "xclaim KEY GROUP CONSUMER MIN-IDLE-TIME ID [ ID ... ] \
+			[ IDLE ms ] [ TIME unix-time-ms ] [ RETRYCOUNT count ] [ FORCE ] \
+			[ JUSTID ] [ LASTID id ]",
+		validation: |r| {4 < r.parameters.len()},
+		doc: "transfer the given pending entries to another consumer."
+	},
+	"xdel" => Command {
+		function: cmd_xdel,
+		syntax: "xdel KEY ID [ ID ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "remove entries from the stream stored at key."
+	},
+	"xgroup" => Command {
+		function: cmd_xgroup,
+		syntax: "xgroup <CREATE key group <id | $> [ MKSTREAM ] \
+			[ ENTRIESREAD entries-read ] \
+			| SETID key group <id | $> [ ENTRIESREAD entries-read ] \
+			| DESTROY key group | CREATECONSUMER key group consumer \
+			| DELCONSUMER key group consumer>",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "manage the consumer groups of a stream and their consumers."
+	},
+	"xinfo" => Command {
+		function: cmd_xinfo,
+		syntax: "xinfo <STREAM key [ FULL [ COUNT count ] ] | GROUPS key \
+			| CONSUMERS key group>",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "describe a stream, its consumer groups or their consumers."
+	},
+	"xlen" => Command {
+		function: cmd_xlen,
+		syntax: "xlen KEY",
+		validation: |r| {1 == r.parameters.len()},
+		doc: "get the number of entries in the stream stored at key."
+	},
+	"xpending" => Command {
+		function: cmd_xpending,
+		syntax: "xpending KEY GROUP [ [ IDLE min-idle-time ] START END COUNT \
+			[ CONSUMER ] ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "inspect the entries delivered to a consumer group but not \
+			acknowledged yet."
+	},
+	"xrange" => Command {
+		function: cmd_xrange,
+		syntax: "xrange KEY START END [ COUNT count ]",
+		validation: |r| {3 == r.parameters.len() || 5 == r.parameters.len()},
+		doc: "get the entries of the stream stored at key within the range \
+			of IDs."
+	},
+	"xread" => Command {
+		function: cmd_xread,
+		syntax: "xread [ COUNT count ] [ BLOCK ms ] STREAMS KEY [ KEY ... ] \
+			ID [ ID ... ]",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "get the entries following the given IDs from one or more \
+			streams, waiting up to ms for some if asked to."
+	},
+	"xreadgroup" => Command {
+		function: cmd_xreadgroup,
+		syntax: "xreadgroup GROUP group consumer [ COUNT count ] \
+			[ BLOCK ms ] [ NOACK ] STREAMS KEY [ KEY ... ] ID [ ID ... ]",
+		validation: |r| {5 < r.parameters.len()},
+		doc: "get entries from one or more streams on behalf of a consumer \
+			of a group, new ones with the ID >."
+	},
+	"xrevrange" => Command {
+		function: cmd_xrevrange,
+		syntax: "xrevrange KEY END START [ COUNT count ]",
+		validation: |r| {3 == r.parameters.len() || 5 == r.parameters.len()},
+		doc: "get the entries of the stream stored at key within the range \
+			of IDs, in reverse order."
+	},
+	"xsetid" => Command {
+		function: cmd_xsetid,
+		syntax: "xsetid KEY LAST-ID [ ENTRIESADDED entries-added ] \
+			[ MAXDELETEDID max-deleted-id ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "set the last ID of the stream stored at key."
+	},
+	"xtrim" => Command {
+		function: cmd_xtrim,
+		syntax: "xtrim KEY <MAXLEN | MINID> [ = | ~ ] THRESHOLD \
+			[ LIMIT count ]",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "remove the oldest entries of the stream stored at key."
+	},
+	"zadd" => Command {
+		function: cmd_zadd,
+		syntax: "zadd KEY [ NX | XX ] [ CH ] SCORE MEMBER \
+			[ SCORE MEMBER ... ]",
+		validation: |r| {2 < r.parameters.len()},
+		doc: "add the members with their scores to the sorted set stored at \
+			key."
+	},
+	"zrem" => Command {
+		function: cmd_zrem,
+		syntax: "zrem KEY MEMBER [ MEMBER ... ]",
+		validation: |r| {1 < r.parameters.len()},
+		doc: "remove the members from the sorted set stored at key."
+	}
+};
+
+// commands that modify data: held back by CLIENT PAUSE WRITE, refused by
+// read-only replicas and fed to the replication stream
+static WRITE_CMDS: phf::Set<&str> = phf_set! {
+	"append", "bf.add", "bf.loadchunk", "bf.madd", "bf.reserve", "bitfield",
+	"bitop", "cf.add", "cf.addnx", "cf.del", "cf.loadchunk", "cf.reserve",
+	"cms.incrby", "cms.initbydim", "cms.initbyprob", "cms.loadchunk",
+	"cms.merge", "decr", "decrby", "del", "flushall", "ft.create",
+	"ft.dropindex", "geoadd", "geosearchstore", "getdel", "getex", "getset",
+	"hdel", "hexpire", "hexpireat", "hgetdel", "hgetex", "hincrby",
+	"hincrbyfloat", "hmset", "hpersist", "hpexpire", "hpexpireat", "hset",
+	"hsetnx", "incr", "incrby", "incrbyfloat", "json.arrappend",
+	"json.arrinsert", "json.arrpop", "json.del", "json.numincrby", "json.set",
+	"json.strappend", "linsert", "lmove", "lmpop", "lpop", "lpush", "lpushx",
+	"lrem", "lset", "ltrim", "migrate", "mset", "msetnx", "pfadd", "pfmerge",
+	"rename", "renamenx", "restore", "rpop", "rpoplpush", "rpush", "rpushx",
+	"sadd", "sdiffstore", "set", "setbit", "setrange", "sinterstore", "smove",
+	"spop", "srem", "sunionstore", "tdigest.add", "tdigest.create",
+	"tdigest.loadchunk", "tdigest.merge", "topk.add", "topk.incrby",
+	"topk.loadchunk", "topk.reserve", "ts.add", "ts.create", "ts.createrule",
+	"ts.decrby", "ts.deleterule", "ts.incrby", "ts.loadchunk", "ts.madd",
+	"xack", "xadd", "xautoclaim", "xclaim", "xdel", "xgroup", "xreadgroup",
+	"xsetid", "xtrim", "zadd", "zrem"
+};
+
+// commands that leave the keyspace alone, or lock it by themselves, and
+// thus never wait for a running script
+static UNSHARED_CMDS: phf::Set<&str> = phf_set! {
+	"client", "cluster", "eval", "eval_ro", "evalsha", "evalsha_ro", "fcall",
+	"fcall_ro", "function", "monitor", "ping", "psubscribe", "psync",
+	"punsubscribe", "quit", "replconf", "script", "subscribe", "sync",
+	"unsubscribe", "wait"
+};
+
+// FUNCTION subcommands that change the libraries
+static FUNCTION_WRITES: phf::Set<&str> = phf_set! {
+	"delete", "flush", "load", "restore"
+};
+
+pub fn process<R: Read + Copy + Send, W: Write>(r: R, w: W, id: u64) {
+	let mut reader: BufReader<R> = BufReader::new(r);
+	let mut writer: BufWriter<W> = BufWriter::new(w);
+	let addr = client::addr(id);
+	client::set_current(id);
+	loop {
+		if let Err(_) = writer.flush() {return;}
+		match parse(&mut reader) {
+			Ok(req) => {
+				client::touch(
+					id,
+					&req.command,
+					&req.parameters,
+					reader.buffer().len(),
+					writer.buffer().len()
+				);
+				let reply = match resolve(&req) {
+					Ok(cmd) => match cluster::route(&req) {
+						Ok(_) => run(cmd, &req, &addr),
+						Err(e) => e
+					},
+					Err(e) => e
+				};
+				tracking::done();
+				if !matches!(reply, DataType::SimpleError(_))
+					&& matches!(req.command.as_str(), "psync" | "sync") {
+					replication::serve_replica(
+						&mut reader,
+						&mut writer,
+						id,
+						&req
+					);
+					return;
+				}
+				if !matches!(reply, DataType::SimpleError(_))
+					&& matches!(
+						req.command.as_str(),
+						"psubscribe" | "punsubscribe" | "subscribe"
+							| "unsubscribe"
+					) {
+					if !pubsub::serve(&mut reader, &mut writer, id, &req) {
+						let _ = writer.flush();
+						return;
+					}
+					continue;
+				}
+				if client::reply_wanted(id) {
+					if let Err(_) = writer.write_all(&reply.to_bytes()) {
+						return;
+					}
+				}
+				if let DataType::SimpleError(_) = reply {
+					continue;
+				}
+				if "quit" == req.command.as_str() {
+					let _ = writer.flush();
+					return;
+				}
+				if "monitor" == req.command.as_str() {
+					client::set_flag(id, 'O');
+					watch(&mut writer);
+					return;
+				}
+			},
+			Err(e) => {
+				if let Err(_) = write!(writer, "{}", DataType::err(e)) {
+					eprintln!("Error: {:?}", e);
+				}
+				match e {
+					"ERR EOF reached" | "ERR Connection error" => {return;},
+					_ => {}
+				}
+			}
+		}
+	}
+}
+
+pub fn is_write(req: &Request) -> bool {
+	match req.command.as_str() {
+		"function" => req.parameters.first().is_some_and(|sub|
+			FUNCTION_WRITES.contains(sub.to_ascii_lowercase().as_str())
+		),
+		cmd => WRITE_CMDS.contains(cmd)
+	}
+}
+
+// look up, validate and run a request without any connection bookkeeping,
+// as done when loading data or applying a replication stream
+pub fn execute(req: &Request) -> DataType {
+	match resolve(req) {
+		Ok(cmd) => call(cmd, req),
+		Err(e) => e
+	}
+}
+
+// apply every request read from r, discarding the replies
+pub fn replay<R: Read>(r: R) {
+	let mut reader: BufReader<R> = BufReader::new(r);
+	loop {
+		match parse(&mut reader) {
+			Ok(req) => if let DataType::SimpleError(e) = execute(&req) {
+				logger::log(Level::Debug, &e);
+			},
+			Err("ERR EOF reached") | Err("ERR Connection error") => return,
+			Err(e) => logger::log(Level::Debug, e)
+		}
+	}
+}
+
+fn resolve(req: &Request) -> Result<&'static Command<'static>, DataType> {
+	match CMDS.get(req.command.as_str()) {
+		Some(cmd) if (cmd.validation)(req) => Ok(cmd),
+		Some(cmd) => Err(DataType::err(&format!(
+			"ERR correct syntax: \"{}\"",
+			cmd.syntax
+		))),
+		None => Err(DataType::err(&format!(
+			"ERR unknown command \"{}\"",
+			req.command
+		)))
+	}
+}
+
+// run a validated request on behalf of a connected client
+fn run(cmd: &Command, req: &Request, addr: &str) -> DataType {
+	let is_write = is_write(req);
+	// never pause CLIENT so that UNPAUSE gets through
+	if "client" != req.command.as_str() {
+		client::wait_unpaused(is_write);
+	}
+	let unshared = UNSHARED_CMDS.contains(req.command.as_str());
+	let mut shared = match unshared {
+		true => None,
+		false => match script::shared() {
+			Ok(g) => Some(g),
+			Err(e) => return DataType::err(e)
+		}
+	};
+	monitor::feed(0, addr, req);
+	stats::incr(&stats::COMMANDS_PROCESSED);
+	let block = match req.command.as_str() {
+		"xread" | "xreadgroup" => stream::parse_read(&req.parameters).ok()
+			.and_then(|r| r.block),
+		_ => None
+	};
+	let block = match block {
+		Some(block) => block,
+		None => return attempt(cmd, req, is_write)
+	};
+	// a blocked read retries whenever a stream gets new entries, without
+	// holding back scripts in the meantime
+	let req = &Request {
+		command: req.command.clone(),
+		parameters: kv::xpin(&req.parameters)
+	};
+	let deadline = match block.is_zero() {
+		true => None,
+		false => Some(Instant::now() + block)
+	};
+	loop {
+		let seen = stream::version();
+		let reply = attempt(cmd, req, is_write);
+		if DataType::Null != reply {
+			return reply;
+		}
+		drop(shared.take());
+		if !stream::wait(seen, deadline) {
+			return reply;
+		}
+		shared = match script::shared() {
+			Ok(g) => Some(g),
+			Err(e) => return DataType::err(e)
+		};
+	}
+}
+
+// call a request, with the checks and the propagation due to writes
+fn attempt(cmd: &Command, req: &Request, is_write: bool) -> DataType {
+	if !is_write {
+		return call(cmd, req);
+	}
+	if let Err(e) = replication::check_writable() {
+		return DataType::err(e);
+	}
+	let _guard = replication::write_guard();
+	let reply = call(cmd, req);
+	if !matches!(reply, DataType::SimpleError(_)) {
+		replication::propagate(req, &reply);
+	}
+	reply
+}
+
+fn call(cmd: &Command, req: &Request) -> DataType {
+	match (cmd.function)(req) {
+		Ok(dt_v) => {
+			tracking::remember(req);
+			dt_v
+		},
+		Err(e) => DataType::err(&e.to_string())
+	}
+}
+
+// turn the connection into a monitor until the peer goes away
+fn watch<W: Write>(writer: &mut BufWriter<W>) {
+	let rx = monitor::register();
+	if writer.flush().is_err() {
+		return;
+	}
+	while let Ok(line) = rx.recv() {
+		if writer.write_all(line.as_bytes()).is_err()
+			|| writer.flush().is_err() {
+			return;
+		}
+	}
+}
+
+fn cmd_append(req: &Request) -> Result<DataType, &str> {
+	kv::append(
+		req.parameters.iter().nth(0).unwrap().as_str(),
+		req.parameters.iter().nth(1).unwrap().as_str()
+	)
+}
+
+fn cmd_asking(_req: &Request) -> Result<DataType, &str> {
+	cluster::asking()
+}
+
+fn cmd_bf_add(req: &Request) -> Result<DataType, &str> {
+	kv::bf_add(&req.parameters[0], &req.parameters[1..], false)
+}
+
+fn cmd_bf_card(req: &Request) -> Result<DataType, &str> {
+	kv::bf_card(&req.parameters[0])
+}
+
+fn cmd_bf_exists(req: &Request) -> Result<DataType, &str> {
+	kv::bf_exists(&req.parameters[0], &req.parameters[1..], false)
+}
+
+fn cmd_bf_info(req: &Request) -> Result<DataType, &str> {
+	kv::bf_info(&req.parameters[0], req.parameters.get(1))
+}
+
+fn cmd_bf_madd(req: &Request) -> Result<DataType, &str> {
+	kv::bf_add(&req.parameters[0], &req.parameters[1..], true)
+}
+
+fn cmd_bf_mexists(req: &Request) -> Result<DataType, &str> {
+	kv::bf_exists(&req.parameters[0], &req.parameters[1..], true)
+}
+
+fn cmd_bf_reserve(req: &Request) -> Result<DataType, &str> {
+	kv::bf_reserve(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_bitcount(req: &Request) -> Result<DataType, &str> {
+	kv::bitcount(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_bitfield(req: &Request) -> Result<DataType, &str> {
+	kv::bitfield(&req.parameters[0], &req.parameters[1..], false)
+}
+
+fn cmd_bitfield_ro(req: &Request) -> Result<DataType, &str> {
+	kv::bitfield(&req.parameters[0], &req.parameters[1..], true)
+}
+
+fn cmd_bitop(req: &Request) -> Result<DataType, &str> {
+	kv::bitop(&req.parameters[0], &req.parameters[1], &req.parameters[2..])
+}
+
+fn cmd_bitpos(req: &Request) -> Result<DataType, &str> {
+	kv::bitpos(&req.parameters[0], &req.parameters[1], &req.parameters[2..])
+}
+
+fn cmd_cf_add(req: &Request) -> Result<DataType, &str> {
+	kv::cf_add(&req.parameters[0], &req.parameters[1], false)
+}
+
+fn cmd_cf_addnx(req: &Request) -> Result<DataType, &str> {
+	kv::cf_add(&req.parameters[0], &req.parameters[1], true)
+}
+
+fn cmd_cf_count(req: &Request) -> Result<DataType, &str> {
+	kv::cf_count(&req.parameters[0], &req.parameters[1])
+}
+
+fn cmd_cf_del(req: &Request) -> Result<DataType, &str> {
+	kv::cf_del(&req.parameters[0], &req.parameters[1])
+}
+
+fn cmd_cf_exists(req: &Request) -> Result<DataType, &str> {
+	kv::cf_exists(&req.parameters[0], &req.parameters[1])
+}
+
+fn cmd_cf_reserve(req: &Request) -> Result<DataType, &str> {
+	kv::cf_reserve(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_client(req: &Request) -> Result<DataType, &str> {
+	let prms = &req.parameters[1..];
+	match (req.parameters[0].to_ascii_lowercase().as_str(), prms.len()) {
+		("caching", 1) => match prms[0].to_ascii_lowercase().as_str() {
+			"yes" => tracking::caching(true),
+			"no" => tracking::caching(false),
+			_ => Err(ERRMSG_SYNERR)
+		},
+		("getname", 0) => client::getname(),
+		("getredir", 0) => tracking::getredir(),
+		("id", 0) => client::id(),
+		("info", 0) => client::info(),
+		("kill", n) if 0 < n => client::kill(prms),
+		("list", 0) => client::list(&[]),
+		("list", n) if 1 < n && prms[0].eq_ignore_ascii_case("id") =>
+			client::list(&prms[1..]),
+		("no-evict", 1) => client::no_evict(&prms[0]),
+		("pause", 1) => client::pause(&prms[0], None),
+		("pause", 2) => client::pause(&prms[0], Some(&prms[1])),
+		("reply", 1) => client::reply(&prms[0]),
+		("setinfo", 2) => client::setinfo(&prms[0], &prms[1]),
+		("setname", 1) => client::setname(&prms[0]),
+		("tracking", n) if 0 < n => tracking::tracking(prms),
+		("trackinginfo", 0) => tracking::trackinginfo(),
+		("unpause", 0) => client::unpause(),
+		_ => Err(ERRMSG_SYNERR)
+	}
+}
+
+fn cmd_cluster(req: &Request) -> Result<DataType, &str> {
+	cluster::check_enabled()?;
+	let prms = &req.parameters[1..];
+	match (req.parameters[0].to_ascii_lowercase().as_str(), prms.len()) {
+		("addslots", n) if 0 < n =>
+			cluster::assign(&cluster::parse_slots(prms)?, true),
+		("addslotsrange", n) if 0 < n =>
+			cluster::assign(&cluster::parse_ranges(prms)?, true),
+		("countkeysinslot", 1) => cluster::countkeysinslot(&prms[0]),
+		("delslots", n) if 0 < n =>
+			cluster::assign(&cluster::parse_slots(prms)?, false),
+		("getkeysinslot", 2) => cluster::getkeysinslot(&prms[0], &prms[1]),
+		// sent by the other nodes over the cluster bus
+		("heartbeat", _) => cluster::heartbeat(prms),
+		("info", 0) => cluster::info(),
+		("keyslot", 1) => cluster::keyslot(&prms[0]),
+		("meet", 2) => cluster::meet(&prms[0], &prms[1]),
+		("myid", 0) => cluster::myid(),
+		("nodes", 0) => cluster::nodes(),
+		("setslot", 2) => cluster::setslot(&prms[0], &prms[1], None),
+		("setslot", 3) =>
+			cluster::setslot(&prms[0], &prms[1], Some(&prms[2])),
+		("shards", 0) => cluster::shards(),
+		("slots", 0) => cluster::slots(),
+		_ => Err(ERRMSG_SYNERR)
+	}
+}
+
+fn cmd_cms_incrby(req: &Request) -> Result<DataType, &str> {
+	kv::cms_incrby(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_cms_initbydim(req: &Request) -> Result<DataType, &str> {
+	kv::cms_init(&req.parameters[0], &req.parameters[1..], false)
+}
+
+fn cmd_cms_initbyprob(req: &Request) -> Result<DataType, &str> {
+	kv::cms_init(&req.parameters[0], &req.parameters[1..], true)
+}
+
+fn cmd_cms_merge(req: &Request) -> Result<DataType, &str> {
+	kv::cms_merge(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_cms_query(req: &Request) -> Result<DataType, &str> {
+	kv::cms_query(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_config(req: &Request) -> Result<DataType, &str> {
+	let prms = &req.parameters[1..];
+	match (req.parameters[0].to_ascii_lowercase().as_str(), prms.len()) {
+		("get", n) if 0 < n => config::config_get(prms),
+		// CONFIG SET errors name the offending parameter
+		("set", n) if 0 < n => match config::config_set(prms) {
+			Ok(dt) => Ok(dt),
+			Err(e) => Ok(DataType::err(&e))
+		},
+		("help", 0) => Ok(DataType::bulkStr(&config::describe())),
+		("resetstat", 0) => config::config_resetstat(),
+		("rewrite", 0) => config::config_rewrite(),
+		_ => Err(ERRMSG_SYNERR)
+	}
+}
+
+fn cmd_decr(req: &Request) -> Result<DataType, &str> {
+	kv::decr(req.parameters.iter().nth(0).unwrap().as_str())
+}
+
+fn cmd_decrby(req: &Request) -> Result<DataType, &str> {
+	kv::decrby(
+		req.parameters.iter().nth(0).unwrap().as_str(),
+		req.parameters.iter().nth(1).unwrap().as_str()
+	)
+}
+
+fn cmd_del(req: &Request) -> Result<DataType, &str> {
+	kv::del(&req.parameters)
+}
+
+fn cmd_dump(req: &Request) -> Result<DataType, &str> {
+	kv::dump(&req.parameters[0])
+}
+
+fn cmd_eval(req: &Request) -> Result<DataType, &str> {
+	script::eval(&req.parameters[0], &req.parameters[1..], false)
+}
+
+fn cmd_eval_ro(req: &Request) -> Result<DataType, &str> {
+	script::eval(&req.parameters[0], &req.parameters[1..], true)
+}
+
+fn cmd_evalsha(req: &Request) -> Result<DataType, &str> {
+	script::evalsha(&req.parameters[0], &req.parameters[1..], false)
+}
+
+fn cmd_evalsha_ro(req: &Request) -> Result<DataType, &str> {
+	script::evalsha(&req.parameters[0], &req.parameters[1..], true)
+}
+
+fn cmd_fcall(req: &Request) -> Result<DataType, &str> {
+	function::fcall(&req.parameters[0], &req.parameters[1..], false)
+}
+
+fn cmd_fcall_ro(req: &Request) -> Result<DataType, &str> {
+	function::fcall(&req.parameters[0], &req.parameters[1..], true)
+}
+
+fn cmd_flushall(_req: &Request) -> Result<DataType, &str> {
+	kv::flushall();
+	Ok(DataType::str("OK"))
+}
+
+fn cmd_ft_create(req: &Request) -> Result<DataType, &str> {
+	kv::ft_create(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_ft_dropindex(req: &Request) -> Result<DataType, &str> {
+	match req.parameters.get(1).map(|o| o.to_ascii_lowercase()) {
+		None => kv::ft_dropindex(&req.parameters[0], false),
+		Some(o) if "dd" == o => kv::ft_dropindex(&req.parameters[0], true),
+		_ => Err(ERRMSG_SYNERR)
+	}
+}
+
+fn cmd_ft_info(req: &Request) -> Result<DataType, &str> {
+	kv::ft_info(&req.parameters[0])
+}
+
+fn cmd_ft_list(_req: &Request) -> Result<DataType, &str> {
+	Ok(search::list())
+}
+
+fn cmd_ft_search(req: &Request) -> Result<DataType, &str> {
+	kv::ft_search(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_function(req: &Request) -> Result<DataType, &str> {
+	let prms: Vec<String> = req.parameters[1..].iter()
+		.map(|p| p.to_ascii_lowercase())
+		.collect();
+	let prms: Vec<&str> = prms.iter().map(|p| p.as_str()).collect();
+	let code = req.parameters.last().unwrap();
+	match (req.parameters[0].to_ascii_lowercase().as_str(), &prms[..]) {
+		("delete", [_]) => function::delete(&req.parameters[1]),
+		("dump", []) => function::dump(),
+		("flush", []) | ("flush", ["async" | "sync"]) => function::flush(),
+		("kill", []) => function::kill(),
+		("list", rest) => {
+			let mut pattern: Option<&str> = None;
+			let mut with_code = false;
+			let mut i = 0;
+			while i < rest.len() {
+				match rest[i] {
+					"libraryname" if i + 1 < rest.len() => {
+						pattern = Some(&req.parameters[i + 2]);
+						i += 1;
+					},
+					"withcode" => with_code = true,
+					_ => return Err(ERRMSG_SYNERR)
+				}
+				i += 1;
+			}
+			function::list(pattern, with_code)
+		},
+		("load", [_]) => function::load(code, false),
+		("load", ["replace", _]) => function::load(code, true),
+		("restore", [_]) => function::restore(&req.parameters[1], "append"),
+		("restore", [_, policy @ ("append" | "flush" | "replace")]) =>
+			function::restore(&req.parameters[1], policy),
+		_ => Err(ERRMSG_SYNERR)
+	}
+}
+
+fn cmd_geoadd(req: &Request) -> Result<DataType, &str> {
+	kv::geoadd(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_geodist(req: &Request) -> Result<DataType, &str> {
+	let p = &req.parameters;
+	kv::geodist(&p[0], &p[1], &p[2], p.get(3))
+}
+
+fn cmd_geohash(req: &Request) -> Result<DataType, &str> {
+	kv::geohash(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_geopos(req: &Request) -> Result<DataType, &str> {
+	kv::geopos(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_geosearch(req: &Request) -> Result<DataType, &str> {
+	kv::geosearch(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_geosearchstore(req: &Request) -> Result<DataType, &str> {
+	let p = &req.parameters;
+	kv::geosearchstore(&p[0], &p[1], &p[2..])
+}
+
+fn cmd_get(req: &Request) -> Result<DataType, &str> {
+	kv::get(req.parameters.iter().nth(0).unwrap())
+}
+
+fn cmd_getbit(req: &Request) -> Result<DataType, &str> {
+	kv::getbit(&req.parameters[0], &req.parameters[1])
+}
+
+fn cmd_getdel(req: &Request) -> Result<DataType, &str> {
+	kv::getdel(req.parameters.iter().nth(0).unwrap())
+}
+
+fn cmd_getex(req: &Request) -> Result<DataType, &str> {
+	kv::getex(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_getrange(req: &Request) -> Result<DataType, &str> {
+	kv::getrange(&req.parameters[0], &req.parameters[1], &req.parameters[2])
+}
+
+fn cmd_getset(req: &Request) -> Result<DataType, &str> {
+	kv::getset(
 		req.parameters.iter().nth(0).unwrap().as_str(),
 		req.parameters.iter().nth(1).unwrap().as_str()
 	)
@@ -527,6 +2225,10 @@ fn cmd_hexists(req: &Request) -> Result<DataType, &str> {
 	)
 }
 
+fn cmd_hexpire(req: &Request) -> Result<DataType, &str> {
+	kv::hexpire(&req.command, &req.parameters)
+}
+
 fn cmd_hget(req: &Request) -> Result<DataType, &str> {
 	kv::hget(
 		req.parameters.iter().nth(0).unwrap().as_str(),
@@ -538,6 +2240,14 @@ fn cmd_hgetall(req: &Request) -> Result<DataType, &str> {
 	kv::hgetall(req.parameters.iter().nth(0).unwrap())
 }
 
+fn cmd_hgetdel(req: &Request) -> Result<DataType, &str> {
+	kv::hgetdel(&req.parameters)
+}
+
+fn cmd_hgetex(req: &Request) -> Result<DataType, &str> {
+	kv::hgetex(&req.parameters)
+}
+
 fn cmd_hincrby(req: &Request) -> Result<DataType, &str> {
 	kv::hincrby(
 		req.parameters.iter().nth(0).unwrap().as_str(),
@@ -546,6 +2256,11 @@ fn cmd_hincrby(req: &Request) -> Result<DataType, &str> {
 	)
 }
 
+fn cmd_hincrbyfloat(req: &Request) -> Result<DataType, &str> {
+	kv::hincrbyfloat(&req.parameters[0], &req.parameters[1],
+		&req.parameters[2])
+}
+
 fn cmd_hkeys(req: &Request) -> Result<DataType, &str> {
 	kv::hkeys(req.parameters.iter().nth(0).unwrap().as_str())
 }
@@ -558,6 +2273,14 @@ fn cmd_hmget(req: &Request) -> Result<DataType, &str> {
 	kv::hmget(&req.parameters[0], req.parameters[1..].to_vec())
 }
 
+fn cmd_hpersist(req: &Request) -> Result<DataType, &str> {
+	kv::hpersist(&req.parameters)
+}
+
+fn cmd_hrandfield(req: &Request) -> Result<DataType, &str> {
+	kv::hrandfield(&req.parameters[0], &req.parameters[1..])
+}
+
 fn cmd_hset(req: &Request) -> Result<DataType, &str> {
 	kv::hset(&req.parameters[0], req.parameters[1..].to_vec(), &false)
 }
@@ -566,6 +2289,14 @@ fn cmd_hsetnx(req: &Request) -> Result<DataType, &str> {
 	kv::hset(&req.parameters[0], req.parameters[1..].to_vec(), &true)
 }
 
+fn cmd_hstrlen(req: &Request) -> Result<DataType, &str> {
+	kv::hstrlen(&req.parameters[0], &req.parameters[1])
+}
+
+fn cmd_httl(req: &Request) -> Result<DataType, &str> {
+	kv::httl(&req.command, &req.parameters)
+}
+
 fn cmd_hvals(req: &Request) -> Result<DataType, &str> {
 	kv::hvals(req.parameters.iter().nth(0).unwrap().as_str())
 }
@@ -581,7 +2312,23 @@ fn cmd_incrby(req: &Request) -> Result<DataType, &str> {
 	)
 }
 
-fn cmd_info(_req: &Request) -> Result<DataType, &str> {
+fn cmd_incrbyfloat(req: &Request) -> Result<DataType, &str> {
+	kv::incrbyfloat(&req.parameters[0], &req.parameters[1])
+}
+
+fn cmd_info(req: &Request) -> Result<DataType, &str> {
+	let section = req.parameters.first().map(|s| s.to_ascii_lowercase());
+	let server = match section.as_deref() {
+		None | Some("all") | Some("everything") | Some("default") =>
+			info_server() + "\r\n" + &replication::info(),
+		Some("server") => info_server(),
+		Some("replication") => replication::info(),
+		Some(_) => String::new()
+	};
+	Ok(DataType::bulkStr(&server))
+}
+
+fn info_server() -> String {
 	let kv_memsize = kv::memsize();
 	let idx = if 0 < kv_memsize {
 		kv_memsize.ilog2() / 1024i64.ilog2()
@@ -604,13 +2351,81 @@ fn cmd_info(_req: &Request) -> Result<DataType, &str> {
 			},
 		None => format!("Data size: {}B\r\n", memsize)
 	};
-	Ok(DataType::bulkStr(&ss))
+	format!(
+		"{}Connected clients: {}\r\n\
+			Total connections received: {}\r\n\
+			Rejected connections: {}\r\n\
+			Total commands processed: {}\r\n",
+		ss,
+		client::count(),
+		stats::get(&stats::CONNECTIONS_RECEIVED),
+		stats::get(&stats::REJECTED_CONNECTIONS),
+		stats::get(&stats::COMMANDS_PROCESSED)
+	)
+}
+
+fn cmd_json_arrappend(req: &Request) -> Result<DataType, &str> {
+	let p = &req.parameters;
+	kv::json_arrappend(&p[0], &p[1], &p[2..])
+}
+
+fn cmd_json_arrinsert(req: &Request) -> Result<DataType, &str> {
+	let p = &req.parameters;
+	kv::json_arrinsert(&p[0], &p[1], Some(&p[2]), &p[3..])
+}
+
+fn cmd_json_arrlen(req: &Request) -> Result<DataType, &str> {
+	kv::json_arrlen(&req.parameters[0], req.parameters.get(1))
+}
+
+fn cmd_json_arrpop(req: &Request) -> Result<DataType, &str> {
+	let p = &req.parameters;
+	kv::json_arrpop(&p[0], p.get(1), p.get(2))
+}
+
+fn cmd_json_del(req: &Request) -> Result<DataType, &str> {
+	kv::json_del(&req.parameters[0], req.parameters.get(1))
+}
+
+fn cmd_json_get(req: &Request) -> Result<DataType, &str> {
+	kv::json_get(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_json_mget(req: &Request) -> Result<DataType, &str> {
+	let (path, ks) = req.parameters.split_last().unwrap();
+	kv::json_mget(ks, path)
+}
+
+fn cmd_json_numincrby(req: &Request) -> Result<DataType, &str> {
+	let p = &req.parameters;
+	kv::json_numincrby(&p[0], &p[1], &p[2])
+}
+
+fn cmd_json_objkeys(req: &Request) -> Result<DataType, &str> {
+	kv::json_objkeys(&req.parameters[0], req.parameters.get(1))
+}
+
+fn cmd_json_set(req: &Request) -> Result<DataType, &str> {
+	let p = &req.parameters;
+	kv::json_set(&p[0], &p[1], &p[2], &p[3..])
+}
+
+fn cmd_json_strappend(req: &Request) -> Result<DataType, &str> {
+	kv::json_strappend(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_json_type(req: &Request) -> Result<DataType, &str> {
+	kv::json_type(&req.parameters[0], req.parameters.get(1))
 }
 
 fn cmd_keys(req: &Request) -> Result<DataType, &str> {
 	kv::keys(req.parameters.iter().nth(0).unwrap().as_str())
 }
 
+fn cmd_lcs(req: &Request) -> Result<DataType, &str> {
+	kv::lcs(&req.parameters[0], &req.parameters[1], &req.parameters[2..])
+}
+
 fn cmd_lindex(req: &Request) -> Result<DataType, &str> {
 	kv::lindex(
 		req.parameters.iter().nth(0).unwrap(),
@@ -631,6 +2446,19 @@ fn cmd_llen(req: &Request) -> Result<DataType, &str> {
 	kv::llen(req.parameters.iter().nth(0).unwrap().as_str())
 }
 
+fn cmd_loadchunk(req: &Request) -> Result<DataType, &str> {
+	kv::loadchunk(&req.command, &req.parameters[0], &req.parameters[2])
+}
+
+fn cmd_lmove(req: &Request) -> Result<DataType, &str> {
+	kv::lmove(&req.parameters[0], &req.parameters[1], &req.parameters[2],
+		&req.parameters[3])
+}
+
+fn cmd_lmpop(req: &Request) -> Result<DataType, &str> {
+	kv::lmpop(&req.parameters)
+}
+
 fn cmd_lpop(req: &Request) -> Result<DataType, &str> {
 	kv::lpop(
 		req.parameters.iter().nth(0).unwrap().as_str(),
@@ -642,6 +2470,10 @@ fn cmd_lpop(req: &Request) -> Result<DataType, &str> {
 	)
 }
 
+fn cmd_lpos(req: &Request) -> Result<DataType, &str> {
+	kv::lpos(&req.parameters[0], &req.parameters[1], &req.parameters[2..])
+}
+
 fn cmd_lpush(req: &Request) -> Result<DataType, &str> {
 	kv::lpush(&req.parameters[0], req.parameters[1..].to_vec(), false)
 }
@@ -686,14 +2518,106 @@ fn cmd_mget(req: &Request) -> Result<DataType, &str> {
 	kv::mget(&req.parameters)
 }
 
+fn cmd_migrate(req: &Request) -> Result<DataType, &str> {
+	cluster::migrate(req)
+}
+
+fn cmd_monitor(_req: &Request) -> Result<DataType, &str> {
+	Ok(DataType::str("OK"))
+}
+
 fn cmd_mset(req: &Request) -> Result<DataType, &str> {
 	kv::mset(&req.parameters)
 }
 
+fn cmd_msetnx(req: &Request) -> Result<DataType, &str> {
+	kv::msetnx(&req.parameters)
+}
+
+fn cmd_pfadd(req: &Request) -> Result<DataType, &str> {
+	kv::pfadd(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_pfcount(req: &Request) -> Result<DataType, &str> {
+	kv::pfcount(&req.parameters)
+}
+
+fn cmd_pfmerge(req: &Request) -> Result<DataType, &str> {
+	kv::pfmerge(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_ping(req: &Request) -> Result<DataType, &str> {
+	match req.parameters.first() {
+		Some(msg) => Ok(DataType::bulkStr(msg)),
+		None => Ok(DataType::str("PONG"))
+	}
+}
+
+// the actual handshake happens once process() hands the connection over
+fn cmd_psync(_req: &Request) -> Result<DataType, &str> {
+	Ok(DataType::str("OK"))
+}
+
+fn cmd_psubscribe(_req: &Request) -> Result<DataType, &str> {
+	Ok(DataType::str("OK"))
+}
+
+fn cmd_pttl(req: &Request) -> Result<DataType, &str> {
+	kv::ttl(&req.parameters[0], true)
+}
+
+fn cmd_publish(req: &Request) -> Result<DataType, &str> {
+	let n = pubsub::publish(&req.parameters[0], &req.parameters[1]);
+	Ok(DataType::Integer(n as i64))
+}
+
+fn cmd_pubsub(req: &Request) -> Result<DataType, &str> {
+	let prms = &req.parameters[1..];
+	match (req.parameters[0].to_ascii_lowercase().as_str(), prms.len()) {
+		("channels", 0) => pubsub::channels(None),
+		("channels", 1) => pubsub::channels(Some(&prms[0])),
+		("numpat", 0) => pubsub::numpat(),
+		("numsub", _) => pubsub::numsub(prms),
+		_ => Err(ERRMSG_SYNERR)
+	}
+}
+
+fn cmd_punsubscribe(_req: &Request) -> Result<DataType, &str> {
+	Ok(DataType::str("OK"))
+}
+
 fn cmd_quit(_req: &Request) -> Result<DataType, &str> {
 	Ok(DataType::str("OK"))
 }
 
+fn cmd_replconf(req: &Request) -> Result<DataType, &str> {
+	replication::replconf(&req.parameters)
+}
+
+fn cmd_replicaof(req: &Request) -> Result<DataType, &str> {
+	replication::replicaof(&req.parameters[0], &req.parameters[1])
+}
+
+fn cmd_rename(req: &Request) -> Result<DataType, &str> {
+	kv::rename(&req.parameters[0], &req.parameters[1], false)
+}
+
+fn cmd_renamenx(req: &Request) -> Result<DataType, &str> {
+	kv::rename(&req.parameters[0], &req.parameters[1], true)
+}
+
+// the TTL is checked but has no effect, restored keys do not expire
+fn cmd_restore(req: &Request) -> Result<DataType, &str> {
+	if req.parameters[1].parse::<u64>().is_err() {
+		return Err("ERR Invalid TTL value, must be >= 0");
+	}
+	kv::restore(
+		&req.parameters[0],
+		&req.parameters[2],
+		4 == req.parameters.len()
+	)
+}
+
 fn cmd_rpop(req: &Request) -> Result<DataType, &str> {
 	kv::rpop(
 		req.parameters.iter().nth(0).unwrap().as_str(),
@@ -705,6 +2629,10 @@ fn cmd_rpop(req: &Request) -> Result<DataType, &str> {
 	)
 }
 
+fn cmd_rpoplpush(req: &Request) -> Result<DataType, &str> {
+	kv::lmove(&req.parameters[0], &req.parameters[1], "right", "left")
+}
+
 fn cmd_rpush(req: &Request) -> Result<DataType, &str> {
 	kv::rpush(&req.parameters[0], req.parameters[1..].to_vec(), &false)
 }
@@ -717,6 +2645,10 @@ fn cmd_sadd(req: &Request) -> Result<DataType, &str> {
 	kv::sadd(&req.parameters[0], req.parameters[1..].to_vec())
 }
 
+fn cmd_scandump(req: &Request) -> Result<DataType, &str> {
+	kv::scandump(&req.command, &req.parameters[0], &req.parameters[1])
+}
+
 fn cmd_scard(req: &Request) -> Result<DataType, &str> {
 	kv::scard(req.parameters.iter().nth(0).unwrap().as_str())
 }
@@ -733,6 +2665,21 @@ fn cmd_sdiffstore(req: &Request) -> Result<DataType, &str> {
 	)
 }
 
+fn cmd_script(req: &Request) -> Result<DataType, &str> {
+	let prms = &req.parameters[1..];
+	match (req.parameters[0].to_ascii_lowercase().as_str(), prms.len()) {
+		("exists", n) if 0 < n => script::script_exists(prms),
+		("flush", 0) => script::script_flush(),
+		("flush", 1) if matches!(
+			prms[0].to_ascii_lowercase().as_str(),
+			"async" | "sync"
+		) => script::script_flush(),
+		("kill", 0) => script::script_kill(),
+		("load", 1) => script::script_load(&prms[0]),
+		_ => Err(ERRMSG_SYNERR)
+	}
+}
+
 fn cmd_set(req: &Request) -> Result<DataType, &str> {
 	kv::set(
 		req.parameters.iter().nth(0).unwrap().as_str(),
@@ -740,6 +2687,14 @@ fn cmd_set(req: &Request) -> Result<DataType, &str> {
 	)
 }
 
+fn cmd_setbit(req: &Request) -> Result<DataType, &str> {
+	kv::setbit(&req.parameters[0], &req.parameters[1], &req.parameters[2])
+}
+
+fn cmd_setrange(req: &Request) -> Result<DataType, &str> {
+	kv::setrange(&req.parameters[0], &req.parameters[1], &req.parameters[2])
+}
+
 fn cmd_sismember(req: &Request) -> Result<DataType, &str> {
 	kv::sismember(
 		req.parameters.iter().nth(0).unwrap().as_str(),
@@ -767,6 +2722,10 @@ fn cmd_sinter(req: &Request) -> Result<DataType, &str> {
 	kv::sinter(&req.parameters[0], req.parameters[1..].to_vec())
 }
 
+fn cmd_sintercard(req: &Request) -> Result<DataType, &str> {
+	kv::sintercard(&req.parameters)
+}
+
 fn cmd_sinterstore(req: &Request) -> Result<DataType, &str> {
 	kv::sinterstore(
 		req.parameters.iter().nth(0).unwrap().as_str(),
@@ -794,7 +2753,8 @@ fn cmd_srandmember(req: &Request) -> Result<DataType, &str> {
 			req.parameters.iter().nth(1).unwrap().as_str()
 		} else {
 			"1"
-		}
+		},
+		1 == req.parameters.len()
 	)
 }
 
@@ -802,6 +2762,14 @@ fn cmd_srem(req: &Request) -> Result<DataType, &str> {
 	kv::srem(&req.parameters[0], req.parameters[1..].to_vec())
 }
 
+fn cmd_strlen(req: &Request) -> Result<DataType, &str> {
+	kv::strlen(&req.parameters[0])
+}
+
+fn cmd_subscribe(_req: &Request) -> Result<DataType, &str> {
+	Ok(DataType::str("OK"))
+}
+
 fn cmd_sunion(req: &Request) -> Result<DataType, &str> {
 	kv::sunion(req.parameters.clone())
 }
@@ -809,3 +2777,236 @@ fn cmd_sunion(req: &Request) -> Result<DataType, &str> {
 fn cmd_sunionstore(req: &Request) -> Result<DataType, &str> {
 	kv::sunionstore(&req.parameters[0], req.parameters[1..].to_vec())
 }
+
+fn cmd_tdigest_add(req: &Request) -> Result<DataType, &str> {
+	kv::tdigest_add(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_tdigest_cdf(req: &Request) -> Result<DataType, &str> {
+	kv::tdigest_cdf(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_tdigest_create(req: &Request) -> Result<DataType, &str> {
+	kv::tdigest_create(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_tdigest_max(req: &Request) -> Result<DataType, &str> {
+	kv::tdigest_max(&req.parameters[0])
+}
+
+fn cmd_tdigest_merge(req: &Request) -> Result<DataType, &str> {
+	kv::tdigest_merge(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_tdigest_min(req: &Request) -> Result<DataType, &str> {
+	kv::tdigest_min(&req.parameters[0])
+}
+
+fn cmd_tdigest_quantile(req: &Request) -> Result<DataType, &str> {
+	kv::tdigest_quantile(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_tdigest_rank(req: &Request) -> Result<DataType, &str> {
+	kv::tdigest_rank(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_topk_add(req: &Request) -> Result<DataType, &str> {
+	kv::topk_add(&req.parameters[0], &req.parameters[1..], false)
+}
+
+fn cmd_topk_incrby(req: &Request) -> Result<DataType, &str> {
+	kv::topk_add(&req.parameters[0], &req.parameters[1..], true)
+}
+
+fn cmd_topk_list(req: &Request) -> Result<DataType, &str> {
+	kv::topk_list(&req.parameters[0], req.parameters.get(1))
+}
+
+fn cmd_topk_query(req: &Request) -> Result<DataType, &str> {
+	kv::topk_query(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_topk_reserve(req: &Request) -> Result<DataType, &str> {
+	kv::topk_reserve(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_ts_add(req: &Request) -> Result<DataType, &str> {
+	kv::ts_add(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_ts_create(req: &Request) -> Result<DataType, &str> {
+	kv::ts_create(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_ts_createrule(req: &Request) -> Result<DataType, &str> {
+	kv::ts_createrule(
+		&req.parameters[0], &req.parameters[1], &req.parameters[2..]
+	)
+}
+
+fn cmd_ts_decrby(req: &Request) -> Result<DataType, &str> {
+	kv::ts_incrby(&req.parameters[0], &req.parameters[1..], true)
+}
+
+fn cmd_ts_deleterule(req: &Request) -> Result<DataType, &str> {
+	kv::ts_deleterule(&req.parameters[0], &req.parameters[1])
+}
+
+fn cmd_ts_get(req: &Request) -> Result<DataType, &str> {
+	kv::ts_get(&req.parameters[0])
+}
+
+fn cmd_ts_incrby(req: &Request) -> Result<DataType, &str> {
+	kv::ts_incrby(&req.parameters[0], &req.parameters[1..], false)
+}
+
+fn cmd_ts_info(req: &Request) -> Result<DataType, &str> {
+	kv::ts_info(&req.parameters[0])
+}
+
+fn cmd_ts_madd(req: &Request) -> Result<DataType, &str> {
+	kv::ts_madd(&req.parameters)
+}
+
+fn cmd_ts_mrange(req: &Request) -> Result<DataType, &str> {
+	kv::ts_mrange(&req.parameters, false)
+}
+
+fn cmd_ts_mrevrange(req: &Request) -> Result<DataType, &str> {
+	kv::ts_mrange(&req.parameters, true)
+}
+
+fn cmd_ts_queryindex(req: &Request) -> Result<DataType, &str> {
+	kv::ts_queryindex(&req.parameters)
+}
+
+fn cmd_ts_range(req: &Request) -> Result<DataType, &str> {
+	kv::ts_range(&req.parameters[0], &req.parameters[1..], false)
+}
+
+fn cmd_ts_revrange(req: &Request) -> Result<DataType, &str> {
+	kv::ts_range(&req.parameters[0], &req.parameters[1..], true)
+}
+
+fn cmd_ttl(req: &Request) -> Result<DataType, &str> {
+	kv::ttl(&req.parameters[0], false)
+}
+
+fn cmd_unsubscribe(_req: &Request) -> Result<DataType, &str> {
+	Ok(DataType::str("OK"))
+}
+
+fn cmd_wait(req: &Request) -> Result<DataType, &str> {
+	replication::wait(&req.parameters[0], &req.parameters[1])
+}
+
+fn cmd_xack(req: &Request) -> Result<DataType, &str> {
+	kv::xack(&req.parameters[0], &req.parameters[1], &req.parameters[2..])
+}
+
+fn cmd_xadd(req: &Request) -> Result<DataType, &str> {
+	kv::xadd(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_xautoclaim(req: &Request) -> Result<DataType, &str> {
+	kv::xautoclaim(
+		&req.parameters[0],
+		&req.parameters[1],
+		&req.parameters[2],
+		&req.parameters[3..]
+	)
+}
+
+fn cmd_xclaim(req: &Request) -> Result<DataType, &str> {
+	kv::xclaim(
+		&req.parameters[0],
+		&req.parameters[1],
+		&req.parameters[2],
+		&req.parameters[3..]
+	)
+}
+
+fn cmd_xdel(req: &Request) -> Result<DataType, &str> {
+	kv::xdel(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_xgroup(req: &Request) -> Result<DataType, &str> {
+	let sub = req.parameters[0].to_ascii_lowercase();
+	match (sub.as_str(), req.parameters.len()) {
+		("create", 4..=7) | ("setid", 4 | 6) | ("destroy", 3)
+			| ("createconsumer", 4) | ("delconsumer", 4) =>
+			kv::xgroup(&sub, &req.parameters[1], &req.parameters[2..]),
+		_ => Err(ERRMSG_SYNERR)
+	}
+}
+
+fn cmd_xinfo(req: &Request) -> Result<DataType, &str> {
+	let sub = req.parameters[0].to_ascii_lowercase();
+	match (sub.as_str(), req.parameters.len()) {
+		("stream", 2..=5) | ("groups", 2) | ("consumers", 3) =>
+			kv::xinfo(&sub, &req.parameters[1], &req.parameters[2..]),
+		_ => Err(ERRMSG_SYNERR)
+	}
+}
+
+fn cmd_xlen(req: &Request) -> Result<DataType, &str> {
+	kv::xlen(&req.parameters[0])
+}
+
+fn cmd_xpending(req: &Request) -> Result<DataType, &str> {
+	kv::xpending(&req.parameters[0], &req.parameters[1], &req.parameters[2..])
+}
+
+fn cmd_xrange(req: &Request) -> Result<DataType, &str> {
+	xrange(req, false)
+}
+
+fn cmd_xread(req: &Request) -> Result<DataType, &str> {
+	match stream::parse_read(&req.parameters)?.group {
+		Some(_) => Err(ERRMSG_SYNERR),
+		None => kv::xread(&req.parameters)
+	}
+}
+
+fn cmd_xreadgroup(req: &Request) -> Result<DataType, &str> {
+	match stream::parse_read(&req.parameters)?.group {
+		Some(_) => kv::xread(&req.parameters),
+		None => Err(ERRMSG_SYNERR)
+	}
+}
+
+fn cmd_xrevrange(req: &Request) -> Result<DataType, &str> {
+	xrange(req, true)
+}
+
+fn cmd_xsetid(req: &Request) -> Result<DataType, &str> {
+	kv::xsetid(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_xtrim(req: &Request) -> Result<DataType, &str> {
+	kv::xtrim(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_zadd(req: &Request) -> Result<DataType, &str> {
+	kv::zadd(&req.parameters[0], &req.parameters[1..])
+}
+
+fn cmd_zrem(req: &Request) -> Result<DataType, &str> {
+	kv::zrem(&req.parameters[0], &req.parameters[1..])
+}
+
+fn xrange(req: &Request, rev: bool) -> Result<DataType, &str> {
+	let count = match req.parameters.get(3) {
+		Some(c) if c.eq_ignore_ascii_case("count") =>
+			Some(req.parameters[4].as_str()),
+		Some(_) => return Err(ERRMSG_SYNERR),
+		None => None
+	};
+	kv::xrange(
+		&req.parameters[0],
+		&req.parameters[1],
+		&req.parameters[2],
+		count,
+		rev
+	)
+}
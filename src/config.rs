@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use phf::phf_map;
+
+use super::datatype::DataType;
+use super::glob;
+use super::stats;
+
+const ERRMSG_NOCONFIG: &str =
+	"ERR The server is running without a config file";
+const ERRMSG_REWRITEFAIL: &str = "ERR Rewriting config file failed";
+
+struct Param<'a> {
+	default: &'a str,
+	mutable: bool,
+	validation: fn(&str) -> bool,
+	doc: &'a str
+}
+
+static PARAMS: phf::Map<&str, Param> = phf_map! {
+	"bind" => Param {
+		default: "0.0.0.0",
+		mutable: false,
+		validation: |v| {!v.is_empty()},
+		doc: "address to accept inbound connections on"
+	},
+	"busy-reply-threshold" => Param {
+		default: "5000",
+		mutable: true,
+		validation: |v| {v.parse::<u64>().is_ok()},
+		doc: "milliseconds a script runs before other clients get BUSY \
+			replies and SCRIPT KILL becomes possible"
+	},
+	"cluster-announce-ip" => Param {
+		default: "",
+		mutable: true,
+		validation: |_| {true},
+		doc: "address other cluster nodes reach this one at, guessed if empty"
+	},
+	"cluster-config-file" => Param {
+		default: "nodes.conf",
+		mutable: false,
+		validation: |v| {!v.is_empty()},
+		doc: "file the cluster node table is kept in"
+	},
+	"cluster-enabled" => Param {
+		default: "no",
+		mutable: false,
+		validation: |v| {matches!(v, "yes" | "no")},
+		doc: "whether to run as a cluster node"
+	},
+	"datapath" => Param {
+		default: "",
+		mutable: true,
+		validation: |_| {true},
+		doc: "file to restore data from and save data to"
+	},
+	"logfile" => Param {
+		default: "",
+		mutable: true,
+		validation: |_| {true},
+		doc: "file to append log messages to, standard output if empty"
+	},
+	"loglevel" => Param {
+		default: "notice",
+		mutable: true,
+		validation: |v| {
+			matches!(v, "debug" | "verbose" | "notice" | "warning" | "nothing")
+		},
+		doc: "minimum severity of log messages"
+	},
+	"maxclients" => Param {
+		default: "10000",
+		mutable: true,
+		validation: |v| {matches!(v.parse::<usize>(), Ok(n) if 0 < n)},
+		doc: "maximum number of simultaneously connected clients"
+	},
+	"notify-keyspace-events" => Param {
+		default: "",
+		mutable: true,
+		validation: |v| {v.chars().all(|c| "AEKg$lshzxetd".contains(c))},
+		doc: "classes of keyspace events published to subscribers, any of \
+			K (keyspace channels), E (keyevent channels), g (generic), \
+			$ (string), l (list), s (set), h (hash), x (expired), \
+			e (evicted) and A (all classes)"
+	},
+	"port" => Param {
+		default: "6379",
+		mutable: false,
+		validation: |v| {v.parse::<u16>().is_ok()},
+		doc: "TCP port to accept inbound connections on"
+	},
+	"proto-max-bulk-len" => Param {
+		default: "536870912",
+		mutable: true,
+		validation: |v| {matches!(v.parse::<usize>(), Ok(n) if 0 < n)},
+		doc: "maximum length of a single bulk string in a request"
+	},
+	"repl-backlog-size" => Param {
+		default: "1048576",
+		mutable: true,
+		validation: |v| {matches!(v.parse::<usize>(), Ok(n) if 0 < n)},
+		doc: "bytes of replication stream kept for partial resyncs"
+	},
+	"repl-ping-replica-period" => Param {
+		default: "10",
+		mutable: true,
+		validation: |v| {matches!(v.parse::<u64>(), Ok(n) if 0 < n)},
+		doc: "seconds between pings a primary sends to its replicas"
+	},
+	"repl-timeout" => Param {
+		default: "60",
+		mutable: true,
+		validation: |v| {matches!(v.parse::<u64>(), Ok(n) if 0 < n)},
+		doc: "seconds of silence before a replica drops its primary link"
+	},
+	"replica-read-only" => Param {
+		default: "yes",
+		mutable: true,
+		validation: |v| {matches!(v, "yes" | "no")},
+		doc: "whether replicas refuse writes from their own clients"
+	},
+	"save" => Param {
+		default: "0",
+		mutable: true,
+		validation: |v| {v.parse::<u64>().is_ok()},
+		doc: "seconds between periodic saves to datapath, 0 to save only \
+			on shutdown"
+	},
+	"thpool" => Param {
+		default: "64",
+		mutable: false,
+		validation: |v| {matches!(v.parse::<usize>(), Ok(n) if 0 < n)},
+		doc: "threadpool size"
+	},
+	"timeout" => Param {
+		default: "0",
+		mutable: true,
+		validation: |v| {v.parse::<u64>().is_ok()},
+		doc: "seconds before an idle client is disconnected, 0 to never"
+	}
+};
+
+lazy_static! {
+	static ref VALUES: RwLock<HashMap<String, String>> = RwLock::new(
+		PARAMS.entries()
+			.map(|(k, p)| (k.to_string(), p.default.to_string()))
+			.collect()
+	);
+	static ref CONFIG_FILE: RwLock<Option<String>> = RwLock::new(None);
+}
+
+pub fn get(name: &str) -> String {
+	match VALUES.read().unwrap().get(name) {
+		Some(v) => v.clone(),
+		None => String::new()
+	}
+}
+
+pub fn get_u64(name: &str) -> u64 {
+	get(name).parse::<u64>().unwrap_or(0)
+}
+
+pub fn get_usize(name: &str) -> usize {
+	get(name).parse::<usize>().unwrap_or(0)
+}
+
+// apply a value coming from the command line or the config file
+pub fn set_startup(name: &str, v: &str) -> Result<(), String> {
+	let n = name.to_ascii_lowercase();
+	match PARAMS.get(n.as_str()) {
+		Some(p) if (p.validation)(v) => {
+			VALUES.write().unwrap().insert(n, v.to_string());
+			Ok(())
+		},
+		Some(_) => Err(format!("argument '{}' is invalid for '{}'", v, n)),
+		None => Err(format!("unknown option '{}'", n))
+	}
+}
+
+pub fn load(path: &str) -> Result<(), String> {
+	let f = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+	for (i, ln) in BufReader::new(f).lines().enumerate() {
+		let ln = ln.map_err(|e| format!("{}: {}", path, e))?;
+		let fatal = |msg: &str| format!(
+			"*** FATAL CONFIG FILE ERROR ***\nReading the configuration \
+				file, at line {}\n>>> '{}'\n{}",
+			i + 1,
+			ln.trim(),
+			msg
+		);
+		let toks = match split_line(&ln) {
+			Some(toks) => toks,
+			None => return Err(fatal("Unbalanced quotes in configuration line"))
+		};
+		if toks.is_empty() {
+			continue;
+		}
+		if "save" == toks[0] {
+			match save_interval(&toks[1..]) {
+				Some(v) => set_startup("save", &v).map_err(|e| fatal(&e))?,
+				None => return Err(fatal("Invalid save parameters"))
+			}
+			continue;
+		}
+		if 2 != toks.len() {
+			return Err(fatal("Bad directive or wrong number of arguments"));
+		}
+		set_startup(&toks[0], &toks[1]).map_err(|e| fatal(&e))?;
+	}
+	*CONFIG_FILE.write().unwrap() = Some(path.to_string());
+	Ok(())
+}
+
+pub fn config_get(patterns: &[String]) -> Result<DataType, &'static str> {
+	let values = VALUES.read().unwrap();
+	let mut names = PARAMS.keys()
+		.filter(|k| patterns.iter().any(|p| {
+			glob::matches(&p.to_ascii_lowercase(), k)
+		}))
+		.collect::<Vec<_>>();
+	names.sort();
+	Ok(DataType::List(
+		names.iter().flat_map(|k| vec![
+			DataType::bulkStr(k),
+			DataType::bulkStr(values.get(**k).map_or("", |v| v.as_str()))
+		]).collect()
+	))
+}
+
+pub fn config_set(nvs: &[String]) -> Result<DataType, String> {
+	if !nvs.len().is_multiple_of(2) {
+		return Err(String::from("ERR wrong number of arguments"));
+	}
+	// validate everything first so that a bad pair changes nothing
+	let mut pending: Vec<(String, String)> = Vec::new();
+	for x in nvs.chunks(2) {
+		let n = x[0].to_ascii_lowercase();
+		let failed = |why: &str| format!(
+			"ERR CONFIG SET failed (possibly related to argument '{}') - {}",
+			n, why
+		);
+		match PARAMS.get(n.as_str()) {
+			Some(p) if !p.mutable => return Err(failed("can't set immutable \
+				config")),
+			Some(p) if !(p.validation)(&x[1]) => return Err(failed(
+				"argument couldn't be parsed"
+			)),
+			Some(_) => pending.push((n, x[1].clone())),
+			None => return Err(failed("Unknown option or number of \
+				arguments for CONFIG SET"))
+		}
+	}
+	let mut values = VALUES.write().unwrap();
+	pending.into_iter().for_each(|(n, v)| {values.insert(n, v);});
+	Ok(DataType::str("OK"))
+}
+
+pub fn config_rewrite() -> Result<DataType, &'static str> {
+	let path = match CONFIG_FILE.read().unwrap().clone() {
+		Some(p) => p,
+		None => return Err(ERRMSG_NOCONFIG)
+	};
+	let old = fs::read_to_string(&path).unwrap_or_default();
+	// the old file stays in place until the new one is complete
+	let tmppath = format!("{}.tmp", path);
+	match fs::write(&tmppath, rewrite(&old))
+		.and_then(|_| fs::rename(&tmppath, &path)) {
+		Ok(_) => Ok(DataType::str("OK")),
+		Err(_) => {
+			let _ = fs::remove_file(&tmppath);
+			Err(ERRMSG_REWRITEFAIL)
+		}
+	}
+}
+
+pub fn config_resetstat() -> Result<DataType, &'static str> {
+	stats::reset();
+	Ok(DataType::str("OK"))
+}
+
+pub fn describe() -> String {
+	let mut names = PARAMS.keys().collect::<Vec<_>>();
+	names.sort();
+	names.iter()
+		.map(|k| format!("{} - {}", k, PARAMS.get(k).unwrap().doc))
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+// keep comments and layout, update known directives in place, and append
+// whatever differs from the defaults but was not in the file yet
+fn rewrite(old: &str) -> String {
+	let values = VALUES.read().unwrap();
+	let mut seen: Vec<String> = Vec::new();
+	let mut out = String::new();
+	for ln in old.lines() {
+		let name = match split_line(ln) {
+			Some(toks) if !toks.is_empty() => toks[0].to_ascii_lowercase(),
+			_ => {
+				out.push_str(ln);
+				out.push('\n');
+				continue;
+			}
+		};
+		if seen.contains(&name) {
+			continue;
+		}
+		match values.get(&name) {
+			Some(v) => {
+				out.push_str(&format!("{} {}\n", name, quote(v)));
+				seen.push(name);
+			},
+			None => {
+				out.push_str(ln);
+				out.push('\n');
+			}
+		}
+	}
+	let mut rest = PARAMS.entries()
+		.filter(|(k, p)| {
+			!seen.iter().any(|s| s == *k)
+				&& values.get(**k).is_some_and(|v| v != p.default)
+		})
+		.map(|(k, _)| k)
+		.collect::<Vec<_>>();
+	rest.sort();
+	for k in rest {
+		out.push_str(&format!("{} {}\n", k, quote(&values[*k])));
+	}
+	out
+}
+
+fn quote(v: &str) -> String {
+	if v.is_empty() || v.contains(|c: char| c.is_whitespace() || '"' == c) {
+		format!("\"{}\"", v.replace('\\', "\\\\").replace('"', "\\\""))
+	} else {
+		v.to_string()
+	}
+}
+
+// the seconds between saves from redis style "save <secs> <changes>" pairs,
+// the most frequent one winning as changes are not counted, or from a
+// single number of seconds
+fn save_interval(toks: &[String]) -> Option<String> {
+	match toks {
+		[v] if v.is_empty() => Some(String::from("0")),
+		[v] => v.parse::<u64>().ok().map(|n| n.to_string()),
+		_ if !toks.is_empty() && toks.len().is_multiple_of(2) => {
+			let mut secs = vec![];
+			for p in toks.chunks(2) {
+				p[1].parse::<u64>().ok()?;
+				secs.push(p[0].parse::<u64>().ok()?);
+			}
+			secs.into_iter().min().map(|n| n.to_string())
+		},
+		_ => None
+	}
+}
+
+// tokenize a config line, None when quotes are unbalanced
+fn split_line(ln: &str) -> Option<Vec<String>> {
+	let ln = ln.trim();
+	if ln.starts_with('#') {
+		return Some(vec![]);
+	}
+	let mut toks: Vec<String> = Vec::new();
+	let mut chars = ln.chars().peekable();
+	while let Some(&c) = chars.peek() {
+		if c.is_whitespace() {
+			chars.next();
+			continue;
+		}
+		let mut tok = String::new();
+		if '"' == c || '\'' == c {
+			chars.next();
+			loop {
+				match chars.next() {
+					Some('\\') if '"' == c => tok.push(chars.next()?),
+					Some(x) if x == c => break,
+					Some(x) => tok.push(x),
+					None => return None
+				}
+			}
+		} else {
+			while let Some(&x) = chars.peek() {
+				if x.is_whitespace() {
+					break;
+				}
+				tok.push(x);
+				chars.next();
+			}
+		}
+		toks.push(tok);
+	}
+	Some(toks)
+}
+
+#[cfg(test)]
+mod tests;
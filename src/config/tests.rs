@@ -0,0 +1,79 @@
+use serial_test::serial;
+use super::*;
+
+fn strs(v: &[&str]) -> Vec<String> {
+	v.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn plan1() {
+	assert!(glob::matches("*", "maxclients"));
+	assert!(glob::matches("max*", "maxclients"));
+	assert!(glob::matches("*client?", "maxclients"));
+	assert!(glob::matches("t[hi]*", "thpool"));
+	assert!(glob::matches("t[^h]*", "timeout"));
+	assert!(glob::matches("[a-c]ind", "bind"));
+	assert!(glob::matches("a\\*b", "a*b"));
+	assert!(!glob::matches("a\\*b", "axb"));
+	assert!(!glob::matches("max", "maxclients"));
+	assert!(!glob::matches("t[^h]*", "thpool"));
+	assert_eq!(split_line("  # comment"), Some(vec![]));
+	assert_eq!(split_line("logfile \"\""), Some(strs(&["logfile", ""])));
+	assert_eq!(
+		split_line("datapath '/tmp/my data'"),
+		Some(strs(&["datapath", "/tmp/my data"]))
+	);
+	assert_eq!(split_line("datapath \"oops"), None);
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	assert_eq!(
+		config_get(&strs(&["max*", "TIMEOUT"])),
+		Ok(DataType::List(vec![
+			DataType::bulkStr("maxclients"),
+			DataType::bulkStr("10000"),
+			DataType::bulkStr("timeout"),
+			DataType::bulkStr("0")
+		]))
+	);
+	assert!(config_set(&strs(&["port", "7000"])).is_err());
+	assert!(config_set(&strs(&["timeout", "x"])).is_err());
+	assert!(config_set(&strs(&["timeout", "5", "nosuch", "1"])).is_err());
+	assert_eq!(get("timeout"), "0");
+	assert_eq!(
+		config_set(&strs(&["timeout", "5", "loglevel", "warning"])),
+		Ok(DataType::str("OK"))
+	);
+	assert_eq!(get_u64("timeout"), 5);
+	assert_eq!(
+		rewrite("# kyvi\n\ntimeout 30\nbind 127.0.0.1\ntimeout 1\n"),
+		"# kyvi\n\ntimeout 5\nbind 0.0.0.0\nloglevel warning\n"
+	);
+	let _ = config_set(&strs(&["timeout", "0", "loglevel", "notice"]));
+}
+
+#[test]
+#[serial]
+fn plan3() {
+	assert_eq!(save_interval(&strs(&["900", "1"])), Some(String::from("900")));
+	assert_eq!(
+		save_interval(&strs(&["300", "10", "60", "10000"])),
+		Some(String::from("60"))
+	);
+	assert_eq!(save_interval(&strs(&[""])), Some(String::from("0")));
+	assert_eq!(save_interval(&strs(&["900", "x"])), None);
+	assert_eq!(save_interval(&strs(&["900", "1", "300"])), None);
+	let path = std::env::temp_dir().join("kyvi-config-plan3.conf");
+	fs::write(&path, "save 900 1\nsave \"\"\nsave 300 10 60 10000\n")
+		.unwrap();
+	assert_eq!(load(path.to_str().unwrap()), Ok(()));
+	assert_eq!(get_u64("save"), 60);
+	fs::write(&path, "save 900\n").unwrap();
+	assert_eq!(load(path.to_str().unwrap()), Ok(()));
+	assert_eq!(get_u64("save"), 900);
+	let _ = set_startup("save", "0");
+	*CONFIG_FILE.write().unwrap() = None;
+	let _ = fs::remove_file(&path);
+}
@@ -0,0 +1,253 @@
+use super::bytes;
+use super::hll::murmur64a;
+
+pub const ERRMSG_BADDATA: &str = "ERR received bad data";
+const ERRMSG_BADBUCKETSIZE: &str = "ERR Bad bucket size";
+const ERRMSG_BADCAPACITY: &str = "ERR Bad capacity";
+const ERRMSG_BADEXPANSION: &str = "ERR Bad expansion";
+const ERRMSG_BADMAXITERATIONS: &str = "ERR Bad maxIterations";
+const ERRMSG_FULL: &str = "ERR Filter is full";
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+
+const MAGIC: &[u8] = b"KCF1";
+const SEED: u64 = 0xc6a4_a793_5bd1_e995;
+
+// a cuckoo filter of one byte fingerprints, 0 marking an empty slot, in
+// tables of a power of two buckets each, a table being added when an
+// element cannot be placed by relocating others, unless expansion is 0
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cuckoo {
+	bucket_size: usize,
+	max_iterations: u64,
+	expansion: u64,
+	items: u64,
+	deletes: u64,
+	tables: Vec<Vec<u8>>
+}
+
+impl Default for Cuckoo {
+	fn default() -> Cuckoo {
+		Cuckoo::new(1024, 2, 20, 1)
+	}
+}
+
+// CAPACITY [BUCKETSIZE size] [MAXITERATIONS n] [EXPANSION expansion]
+pub fn parse_reserve(prms: &[String]) -> Result<Cuckoo, &'static str> {
+	let capacity = match prms[0].parse::<u64>() {
+		Ok(n) if 0 < n => n,
+		_ => return Err(ERRMSG_BADCAPACITY)
+	};
+	let (mut bucket_size, mut max_iterations, mut expansion) = (2, 20, 1);
+	for p in prms[1..].chunks(2) {
+		let n = match p.get(1).map(|s| s.parse::<u64>()) {
+			Some(Ok(n)) => n,
+			_ => 0
+		};
+		match p[0].to_ascii_lowercase().as_str() {
+			"bucketsize" if (1..=255).contains(&n) => bucket_size = n,
+			"bucketsize" => return Err(ERRMSG_BADBUCKETSIZE),
+			"maxiterations" if (1..=65535).contains(&n) => max_iterations = n,
+			"maxiterations" => return Err(ERRMSG_BADMAXITERATIONS),
+			"expansion" => match p.get(1).map(|s| s.parse::<u64>()) {
+				Some(Ok(n)) if n <= 32768 => expansion = n,
+				_ => return Err(ERRMSG_BADEXPANSION)
+			},
+			_ => return Err(ERRMSG_SYNERR)
+		}
+	}
+	Ok(Cuckoo::new(capacity, bucket_size, max_iterations, expansion))
+}
+
+// the fingerprint of el, and the hash picking its first bucket
+fn hash(el: &[u8]) -> (u8, u64) {
+	let h = murmur64a(el, SEED);
+	((h % 255 + 1) as u8, h)
+}
+
+// the other bucket a fingerprint fp in bucket i can go to
+fn alternate(i: usize, fp: u8, buckets: usize) -> usize {
+	(i ^ (fp as usize).wrapping_mul(0x5bd1_e995)) & (buckets - 1)
+}
+
+impl Cuckoo {
+	pub fn new(capacity: u64, bucket_size: u64, max_iterations: u64,
+		expansion: u64) -> Cuckoo {
+		let buckets = capacity.div_ceil(bucket_size).next_power_of_two();
+		Cuckoo {
+			bucket_size: bucket_size as usize,
+			max_iterations,
+			expansion: match expansion {
+				0 => 0,
+				n => n.next_power_of_two()
+			},
+			items: 0,
+			deletes: 0,
+			tables: vec![vec![0; (buckets * bucket_size) as usize]]
+		}
+	}
+
+	// the two buckets of the table t an element hashing to h can go to
+	fn buckets(&self, t: usize, fp: u8, h: u64) -> [usize; 2] {
+		let n = self.tables[t].len() / self.bucket_size;
+		let i = (h as usize) & (n - 1);
+		[i, alternate(i, fp, n)]
+	}
+
+	// the slots of bucket i in a table
+	fn slots(&self, i: usize) -> std::ops::Range<usize> {
+		i * self.bucket_size..(i + 1) * self.bucket_size
+	}
+
+	// how many times the fingerprint of el shows up where it can go
+	pub fn count(&self, el: &[u8]) -> u64 {
+		let (fp, h) = hash(el);
+		let mut n = 0;
+		for t in 0..self.tables.len() {
+			let [i, j] = self.buckets(t, fp, h);
+			let in_bucket = |b| self.tables[t][self.slots(b)].iter()
+				.filter(|s| fp == **s).count() as u64;
+			n += in_bucket(i) + if i == j {0} else {in_bucket(j)};
+		}
+		n
+	}
+
+	pub fn contains(&self, el: &[u8]) -> bool {
+		0 < self.count(el)
+	}
+
+	// place the fingerprint of el, moving others along when both of its
+	// buckets are full, and adding a table when that fails as well
+	pub fn add(&mut self, el: &[u8]) -> Result<(), &'static str> {
+		let (fp, h) = hash(el);
+		for t in 0..self.tables.len() {
+			if self.place(t, fp, h) {
+				self.items += 1;
+				return Ok(());
+			}
+		}
+		let t = self.tables.len() - 1;
+		if self.relocate(t, fp, h) {
+			self.items += 1;
+			return Ok(());
+		}
+		if 0 == self.expansion {
+			return Err(ERRMSG_FULL);
+		}
+		let size = self.tables[t].len() as u64 * self.expansion;
+		self.tables.push(vec![0; size as usize]);
+		self.place(t + 1, fp, h);
+		self.items += 1;
+		Ok(())
+	}
+
+	// put fp in an empty slot of either of its buckets in table t
+	fn place(&mut self, t: usize, fp: u8, h: u64) -> bool {
+		for b in self.buckets(t, fp, h) {
+			let r = self.slots(b);
+			if let Some(s) = self.tables[t][r].iter_mut().find(|s| 0 == **s) {
+				*s = fp;
+				return true;
+			}
+		}
+		false
+	}
+
+	// kick fingerprints in table t over to their other buckets to make
+	// room for fp, putting everything back when max_iterations is reached;
+	// the victims are picked in turn so that replicas stay identical
+	fn relocate(&mut self, t: usize, fp: u8, h: u64) -> bool {
+		let n = self.tables[t].len() / self.bucket_size;
+		let (mut fp, mut b) = (fp, self.buckets(t, fp, h)[0]);
+		let mut path = vec![];
+		for k in 0..self.max_iterations as usize {
+			let s = b * self.bucket_size + k % self.bucket_size;
+			std::mem::swap(&mut fp, &mut self.tables[t][s]);
+			path.push(s);
+			b = alternate(b, fp, n);
+			let r = self.slots(b);
+			if let Some(s) = self.tables[t][r].iter_mut().find(|s| 0 == **s) {
+				*s = fp;
+				return true;
+			}
+		}
+		for s in path.into_iter().rev() {
+			std::mem::swap(&mut fp, &mut self.tables[t][s]);
+		}
+		false
+	}
+
+	// remove one fingerprint of el, from the newest table holding it
+	pub fn del(&mut self, el: &[u8]) -> bool {
+		let (fp, h) = hash(el);
+		for t in (0..self.tables.len()).rev() {
+			for b in self.buckets(t, fp, h) {
+				let r = self.slots(b);
+				let found = self.tables[t][r].iter_mut().find(|s| fp == **s);
+				if let Some(s) = found {
+					*s = 0;
+					self.items = self.items.saturating_sub(1);
+					self.deletes += 1;
+					return true;
+				}
+			}
+		}
+		false
+	}
+
+	// bytes taken by the slots of all the tables
+	pub fn size(&self) -> usize {
+		self.tables.iter().map(|t| t.len()).sum()
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut v = MAGIC.to_vec();
+		for n in [self.bucket_size as u64, self.max_iterations,
+			self.expansion, self.items, self.deletes,
+			self.tables.len() as u64] {
+			v.extend_from_slice(&n.to_le_bytes());
+		}
+		for t in self.tables.iter() {
+			v.extend_from_slice(&(t.len() as u64).to_le_bytes());
+			v.extend_from_slice(t);
+		}
+		v
+	}
+
+	pub fn parse(mut b: &[u8]) -> Result<Cuckoo, &'static str> {
+		match read(&mut b) {
+			Some(cf) if b.is_empty() => Ok(cf),
+			_ => Err(ERRMSG_BADDATA)
+		}
+	}
+}
+
+// the filter serialized at the start of b
+fn read(b: &mut &[u8]) -> Option<Cuckoo> {
+	if MAGIC != bytes::take(b, MAGIC.len())? {
+		return None;
+	}
+	let bucket_size = bytes::take_u64(b)? as usize;
+	let max_iterations = bytes::take_u64(b)?;
+	let expansion = bytes::take_u64(b)?;
+	let items = bytes::take_u64(b)?;
+	let deletes = bytes::take_u64(b)?;
+	let mut tables = vec![];
+	for _ in 0..bytes::take_u64(b)? {
+		let n = bytes::take_u64(b)? as usize;
+		let t = bytes::take(b, n)?.to_vec();
+		match 0 < bucket_size && (t.len() / bucket_size).is_power_of_two() &&
+			t.len().is_multiple_of(bucket_size) {
+			true => tables.push(t),
+			false => return None
+		}
+	}
+	match tables.is_empty() {
+		true => None,
+		false => Some(Cuckoo {
+			bucket_size, max_iterations, expansion, items, deletes, tables
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests;
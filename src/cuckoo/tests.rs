@@ -0,0 +1,37 @@
+use serial_test::serial;
+use super::*;
+
+fn prms(ps: &[&str]) -> Vec<String> {
+	ps.iter().map(|p| p.to_string()).collect()
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	assert_eq!(parse_reserve(&prms(&["0"])), Err(ERRMSG_BADCAPACITY));
+	let bad = prms(&["10", "bucketsize", "256"]);
+	assert_eq!(parse_reserve(&bad), Err(ERRMSG_BADBUCKETSIZE));
+	assert_eq!(parse_reserve(&prms(&["10", "maxiterations"])),
+		Err(ERRMSG_BADMAXITERATIONS));
+	assert_eq!(parse_reserve(&prms(&["10", "foo", "1"])), Err(ERRMSG_SYNERR));
+	let mut cf = parse_reserve(&prms(&["64", "bucketsize", "4"])).unwrap();
+	for i in 0..500 {
+		cf.add(format!("a{}", i).as_bytes()).unwrap();
+	}
+	assert!(1 < cf.tables.len());
+	assert!((0..500).all(|i| cf.contains(format!("a{}", i).as_bytes())));
+	cf.add(b"a1").unwrap();
+	assert!(2 <= cf.count(b"a1"));
+	assert!(cf.del(b"a1") && cf.del(b"a1"));
+	assert_eq!((cf.items, cf.deletes), (499, 2));
+	assert_eq!(Cuckoo::parse(&cf.to_bytes()), Ok(cf.clone()));
+	assert_eq!(Cuckoo::parse(b"KCF1"), Err(ERRMSG_BADDATA));
+	let mut cf = parse_reserve(&prms(&["2", "bucketsize", "1",
+		"expansion", "0"])).unwrap();
+	let added = (0..10)
+		.take_while(|i| cf.add(format!("b{}", i).as_bytes()).is_ok())
+		.count();
+	assert!((1..10).contains(&added));
+	assert_eq!(cf.items, added as u64);
+	assert!((0..added).all(|i| cf.contains(format!("b{}", i).as_bytes())));
+}
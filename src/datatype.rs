@@ -1,6 +1,19 @@
 use derivative::Derivative;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
+use std::sync::Arc;
+
+use super::bloom::Bloom;
+use super::bytes;
+use super::cms;
+use super::cuckoo::Cuckoo;
+use super::json::Json;
+use super::set::Set;
+use super::stream::Stream;
+use super::tdigest::Digest;
+use super::timeseries::Series;
+use super::topk::TopK;
+use super::zset::SortedSet;
 
 #[derive(Clone, Debug, Eq, PartialEq, Derivative)]
 #[derivative(Hash)]
@@ -10,21 +23,63 @@ pub enum DataType {
 	Boolean(bool),
 	BulkError(String),
 */
+	Bloom(
+		#[derivative(Hash="ignore")]
+		Box<Bloom>
+	),
 	BulkString(String),
+	CountMin(
+		#[derivative(Hash="ignore")]
+		Box<cms::Sketch>
+	),
+	Cuckoo(
+		#[derivative(Hash="ignore")]
+		Box<Cuckoo>
+	),
+	// a stored list, List being kept for arrays in replies
+	Deque(
+		#[derivative(Hash="ignore")]
+		VecDeque<DataType>
+	),
 	EmptyList,
 	HashMap(
 		#[derivative(Hash="ignore")]
 		HashMap<DataType, DataType>
 	),
+	// shared so readers can let go of the store before going through it
 	HashSet(
 		#[derivative(Hash="ignore")]
-		HashSet<DataType>
+		Arc<Set>
 	),
 	Integer(i64),
+	Json(
+		#[derivative(Hash="ignore")]
+		Box<Json>
+	),
 	List(Vec<DataType>),
 	Null,
 	SimpleError(String),
-	SimpleString(String)
+	SimpleString(String),
+	SortedSet(
+		#[derivative(Hash="ignore")]
+		Box<SortedSet>
+	),
+	Stream(
+		#[derivative(Hash="ignore")]
+		Box<Stream>
+	),
+	TDigest(
+		#[derivative(Hash="ignore")]
+		Box<Digest>
+	),
+	TimeSeries(
+		#[derivative(Hash="ignore")]
+		Box<Series>
+	),
+	TopK(
+		#[derivative(Hash="ignore")]
+		Box<TopK>
+	)
 }
 
 #[allow(non_snake_case)]
@@ -36,9 +91,14 @@ impl DataType {
 			DataType::Boolean(_) => 1usize,
 			DataType::BulkError(s) |
 */
+			DataType::Bloom(b) => b.size(),
 			DataType::BulkString(s) |
 			DataType::SimpleError(s) |
 			DataType::SimpleString(s) => s.capacity(),
+			DataType::CountMin(s) => s.size(),
+			DataType::Cuckoo(c) => c.size(),
+			DataType::Deque(l) =>
+				l.len() + l.iter().map(|e|{e.capacity()}).sum::<usize>(),
 			DataType::EmptyList => 0usize,
 			DataType::HashMap(h) =>
 				h.len() + h.iter().map(
@@ -47,9 +107,15 @@ impl DataType {
 			DataType::HashSet(h) =>
 				h.len() + h.iter().map(|e|{e.capacity()}).sum::<usize>(),
 			DataType::Integer(_) => 8usize,
+			DataType::Json(j) => j.capacity(),
 			DataType::List(l) =>
 				l.len() + l.iter().map(|e|{e.capacity()}).sum::<usize>(),
-			DataType::Null => 0usize
+			DataType::Null => 0usize,
+			DataType::SortedSet(z) => z.capacity(),
+			DataType::Stream(s) => s.capacity(),
+			DataType::TDigest(d) => d.size(),
+			DataType::TimeSeries(s) => s.size(),
+			DataType::TopK(t) => t.size()
 		}
 	}
 
@@ -71,13 +137,18 @@ impl DataType {
 		DataType::HashMap(m.clone())
 	}
 
-	pub fn hset(s: &HashSet<DataType>) -> DataType {
-		DataType::HashSet(s.clone())
+	pub fn hset(s: Set) -> DataType {
+		DataType::HashSet(Arc::new(s))
 	}
 
 	pub fn str(s: &str) -> DataType {
 		DataType::SimpleString(s.to_string())
 	}
+
+	// the serialized form, as sent over the wire
+	pub fn to_bytes(&self) -> Vec<u8> {
+		bytes::encode(&self.to_string())
+	}
 }
 
 impl fmt::Display for DataType {
@@ -91,8 +162,21 @@ impl fmt::Display for DataType {
 			DataType::BulkError(s) =>
 				write!(f, "!{}\r\n{}\n", s.capacity(), s),
 */
+			DataType::Bloom(b) => write!(f, "{}",
+				DataType::BulkString(bytes::decode(&b.to_bytes()))),
 			DataType::BulkString(s) =>
-				write!(f, "${}\r\n{}\r\n", s.capacity(), s),
+				write!(f, "${}\r\n{}\r\n", bytes::len(s), s),
+			DataType::CountMin(s) => write!(f, "{}",
+				DataType::BulkString(bytes::decode(&s.to_bytes()))),
+			DataType::Cuckoo(c) => write!(f, "{}",
+				DataType::BulkString(bytes::decode(&c.to_bytes()))),
+			DataType::Deque(l) => {
+				write!(f, "*{}\r\n", l.len())?;
+				for e in l.iter() {
+					write!(f, "{}", e)?;
+				}
+				Ok(())
+			},
 			DataType::EmptyList => write!(f, "*0\r\n"),
 			DataType::HashMap(h) => {
 				write!(f, "*{}\r\n", h.len() * 2)?;
@@ -110,6 +194,8 @@ impl fmt::Display for DataType {
 			},
 			DataType::Integer(i) =>
 				write!(f, ":{}\r\n", i),
+			DataType::Json(j) =>
+				write!(f, "{}", DataType::BulkString(j.to_text())),
 			DataType::List(l) => {
 				write!(f, "*{}\r\n", l.len())?;
 				for e in l.iter() {
@@ -122,6 +208,23 @@ impl fmt::Display for DataType {
 				write!(f, "-{}\r\n", s),
 			DataType::SimpleString(s) =>
 				write!(f, "+{}\r\n", s),
+			DataType::SortedSet(z) => {
+				write!(f, "*{}\r\n", z.len())?;
+				for (m, _) in z.iter() {
+					write!(f, "{}", DataType::bulkStr(m))?;
+				}
+				Ok(())
+			},
+			DataType::Stream(s) => match s.range("-", "+", None, false) {
+				Ok(l) => write!(f, "{}", l),
+				Err(_) => write!(f, "*0\r\n")
+			},
+			DataType::TDigest(d) => write!(f, "{}",
+				DataType::BulkString(bytes::decode(&d.to_bytes()))),
+			DataType::TimeSeries(s) => write!(f, "{}",
+				DataType::BulkString(bytes::decode(&s.to_bytes()))),
+			DataType::TopK(t) => write!(f, "{}",
+				DataType::BulkString(bytes::decode(&t.to_bytes()))),
 		}
 	}
 }
@@ -0,0 +1,131 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+// deadlines of the keys set to expire, in ms since the epoch
+#[derive(Default)]
+struct Deadlines {
+	at: HashMap<String, u64>,
+	due: BTreeSet<(u64, String)>
+}
+
+// deadlines of the hash fields set to expire, by key then field
+#[derive(Default)]
+struct FieldDeadlines {
+	at: HashMap<String, HashMap<String, u64>>,
+	due: BTreeSet<(u64, String, String)>
+}
+
+lazy_static! {
+	static ref D: Mutex<Deadlines> = Mutex::new(Deadlines::default());
+	static ref F: Mutex<FieldDeadlines> =
+		Mutex::new(FieldDeadlines::default());
+}
+
+// forget the deadline of k, telling whether it had one
+pub fn clear(k: &str) -> bool {
+	let mut d = D.lock().unwrap();
+	match d.at.remove(k) {
+		Some(at) => d.due.remove(&(at, k.to_string())),
+		None => false
+	}
+}
+
+pub fn clear_all() {
+	let mut d = D.lock().unwrap();
+	d.at.clear();
+	d.due.clear();
+	let mut f = F.lock().unwrap();
+	f.at.clear();
+	f.due.clear();
+}
+
+// forget the deadline of field f of the hash at k, telling whether it had
+// one
+pub fn clear_field(k: &str, f: &str) -> bool {
+	let mut fd = F.lock().unwrap();
+	let at = match fd.at.get_mut(k) {
+		Some(fs) => match fs.remove(f) {
+			Some(at) => {
+				if fs.is_empty() {
+					fd.at.remove(k);
+				}
+				at
+			},
+			None => return false
+		},
+		None => return false
+	};
+	fd.due.remove(&(at, k.to_string(), f.to_string()))
+}
+
+// forget the deadlines of every field of the hash at k
+pub fn clear_fields(k: &str) {
+	let mut fd = F.lock().unwrap();
+	for (f, at) in fd.at.remove(k).unwrap_or_default() {
+		fd.due.remove(&(at, k.to_string(), f));
+	}
+}
+
+pub fn field(k: &str, f: &str) -> Option<u64> {
+	F.lock().unwrap().at.get(k).and_then(|fs| fs.get(f)).copied()
+}
+
+// the fields of the hash at k with a deadline, and their deadline
+pub fn fields(k: &str) -> Vec<(String, u64)> {
+	F.lock().unwrap().at.get(k)
+		.map(|fs| fs.iter().map(|(f, at)| (f.clone(), *at)).collect())
+		.unwrap_or_default()
+}
+
+pub fn get(k: &str) -> Option<u64> {
+	D.lock().unwrap().at.get(k).copied()
+}
+
+pub fn set(k: &str, at: u64) {
+	clear(k);
+	let mut d = D.lock().unwrap();
+	d.at.insert(k.to_string(), at);
+	d.due.insert((at, k.to_string()));
+}
+
+pub fn set_field(k: &str, f: &str, at: u64) {
+	clear_field(k, f);
+	let mut fd = F.lock().unwrap();
+	fd.at.entry(k.to_string()).or_default().insert(f.to_string(), at);
+	fd.due.insert((at, k.to_string(), f.to_string()));
+}
+
+// forget the keys whose deadline is not after now, and give them
+pub fn take_due(now: u64) -> Vec<String> {
+	let mut d = D.lock().unwrap();
+	let mut ks = vec![];
+	while d.due.first().is_some_and(|(at, _)| *at <= now) {
+		let (_, k) = d.due.pop_first().unwrap();
+		d.at.remove(&k);
+		ks.push(k);
+	}
+	ks
+}
+
+// forget the fields whose deadline is not after now, and give them with
+// their key
+pub fn take_due_fields(now: u64) -> Vec<(String, String)> {
+	let mut fd = F.lock().unwrap();
+	let mut kfs = vec![];
+	while fd.due.first().is_some_and(|(at, _, _)| *at <= now) {
+		let (_, k, f) = fd.due.pop_first().unwrap();
+		if let Some(fs) = fd.at.get_mut(&k) {
+			fs.remove(&f);
+			if fs.is_empty() {
+				fd.at.remove(&k);
+			}
+		}
+		kfs.push((k, f));
+	}
+	kfs
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,42 @@
+use serial_test::serial;
+use super::*;
+
+#[test]
+#[serial]
+fn plan1() {
+	set("a", 30);
+	set("b", 10);
+	set("c", 20);
+	set("a", 5);
+	assert_eq!(get("a"), Some(5));
+	assert!(clear("c"));
+	assert!(!clear("c"));
+	assert_eq!(take_due(4), Vec::<String>::new());
+	assert_eq!(take_due(10), vec!["a", "b"]);
+	assert_eq!(get("b"), None);
+	set("d", 1);
+	clear_all();
+	assert_eq!(take_due(u64::MAX), Vec::<String>::new());
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	set_field("h", "a", 30);
+	set_field("h", "b", 10);
+	set_field("i", "a", 20);
+	set_field("h", "a", 5);
+	assert_eq!(field("h", "a"), Some(5));
+	assert!(clear_field("i", "a"));
+	assert!(!clear_field("i", "a"));
+	assert_eq!(field("i", "a"), None);
+	let mut fs = fields("h");
+	fs.sort();
+	assert_eq!(fs, vec![("a".to_string(), 5), ("b".to_string(), 10)]);
+	assert_eq!(take_due_fields(5), vec![("h".to_string(), "a".to_string())]);
+	set_field("i", "c", 1);
+	clear_fields("h");
+	assert_eq!(fields("h"), vec![]);
+	assert_eq!(take_due_fields(u64::MAX),
+		vec![("i".to_string(), "c".to_string())]);
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::io::BufReader;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+use super::bytes;
+use super::datatype::DataType;
+use super::glob;
+use super::parser::parse;
+use super::script::{self, Registered};
+
+const ERRMSG_BADENGINE: &str = "ERR Engine not found";
+const ERRMSG_BADMETA: &str = "ERR Invalid metadata value given";
+const ERRMSG_BADNAME: &str = "ERR Library names can only contain letters, \
+	numbers, or underscores(_) and must be at least one character long";
+const ERRMSG_BADPAYLOAD: &str = "ERR payload version or checksum are wrong";
+const ERRMSG_NOFUNCS: &str = "ERR No functions registered";
+const ERRMSG_NOFUNCTION: &str = "ERR Function not found";
+const ERRMSG_NOLIB: &str = "ERR Library not found";
+const ERRMSG_NOMETA: &str = "ERR Missing library metadata";
+const ERRMSG_WRITEONRO: &str =
+	"ERR Can not execute a script with write flag using *_ro command.";
+
+// first element of a dump payload, telling it apart from a key dump
+const DUMP_HEADER: &str = "functions";
+
+#[derive(Clone)]
+struct Library {
+	code: String,
+	functions: Vec<Registered>
+}
+
+lazy_static! {
+	// libraries by name, ordered for LIST and DUMP
+	static ref LIBS: Mutex<BTreeMap<String, Library>> =
+		Mutex::new(BTreeMap::new());
+}
+
+// the library name given by the "#!lua name=<library>" line heading code
+fn header(code: &str) -> Result<String, &'static str> {
+	let line = code.lines().next().unwrap_or("");
+	let mut words = match line.strip_prefix("#!") {
+		Some(rest) => rest.split_whitespace(),
+		None => return Err(ERRMSG_NOMETA)
+	};
+	if Some("lua") != words.next() {
+		return Err(ERRMSG_BADENGINE);
+	}
+	let mut name: Option<&str> = None;
+	for w in words {
+		match w.strip_prefix("name=") {
+			Some(n) if name.is_none() => name = Some(n),
+			_ => return Err(ERRMSG_BADMETA)
+		}
+	}
+	match name {
+		Some(n) if !n.is_empty()
+			&& n.bytes().all(|b| b.is_ascii_alphanumeric() || b'_' == b) =>
+			Ok(n.to_string()),
+		Some(_) => Err(ERRMSG_BADNAME),
+		None => Err(ERRMSG_NOMETA)
+	}
+}
+
+// compile code into a library, checking it against the ones in libs
+fn compile(code: &str, libs: &BTreeMap<String, Library>, replace: bool)
+	-> Result<(String, Library), String> {
+	let name = header(code)?;
+	if !replace && libs.contains_key(&name) {
+		return Err(format!("ERR Library '{}' already exists", name));
+	}
+	let functions = script::load_library(&name, code)?;
+	if functions.is_empty() {
+		return Err(ERRMSG_NOFUNCS.to_string());
+	}
+	for (other, lib) in libs.iter().filter(|(n, _)| **n != name) {
+		if let Some(f) = functions.iter()
+			.find(|f| lib.functions.iter().any(|g| g.name == f.name)) {
+			return Err(format!(
+				"ERR Function {} already exists in library {}",
+				f.name,
+				other
+			));
+		}
+	}
+	Ok((name, Library {code: code.to_string(), functions}))
+}
+
+pub fn load(code: &str, replace: bool) -> Result<DataType, &'static str> {
+	let mut libs = LIBS.lock().unwrap();
+	match compile(code, &libs, replace) {
+		Ok((name, lib)) => {
+			libs.insert(name.clone(), lib);
+			Ok(DataType::bulkStr(&name))
+		},
+		Err(e) => Ok(DataType::err(&e))
+	}
+}
+
+pub fn fcall(name: &str, prms: &[String], read_only: bool)
+	-> Result<DataType, &'static str> {
+	let (lib, code, no_writes) = {
+		let libs = LIBS.lock().unwrap();
+		let found = libs.iter().find_map(|(n, lib)| lib.functions.iter()
+			.find(|f| f.name == name)
+			.map(|f| (n.clone(), lib.code.clone(), f.flags.clone())));
+		match found {
+			Some((n, code, flags)) =>
+				(n, code, flags.iter().any(|f| "no-writes" == f)),
+			None => return Err(ERRMSG_NOFUNCTION)
+		}
+	};
+	if read_only && !no_writes {
+		return Err(ERRMSG_WRITEONRO);
+	}
+	script::fcall(&lib, &code, name, prms, read_only || no_writes)
+}
+
+pub fn delete(name: &str) -> Result<DataType, &'static str> {
+	match LIBS.lock().unwrap().remove(name) {
+		Some(_) => Ok(DataType::str("OK")),
+		None => Err(ERRMSG_NOLIB)
+	}
+}
+
+pub fn flush() -> Result<DataType, &'static str> {
+	LIBS.lock().unwrap().clear();
+	Ok(DataType::str("OK"))
+}
+
+pub fn list(pattern: Option<&str>, with_code: bool)
+	-> Result<DataType, &'static str> {
+	let libs = LIBS.lock().unwrap();
+	Ok(DataType::List(libs.iter()
+		.filter(|(n, _)| pattern.is_none_or(|p| glob::matches(p, n)))
+		.map(|(n, lib)| {
+			let mut l = vec![
+				DataType::bulkStr("library_name"),
+				DataType::bulkStr(n),
+				DataType::bulkStr("engine"),
+				DataType::bulkStr("LUA"),
+				DataType::bulkStr("functions"),
+				DataType::List(lib.functions.iter().map(describe).collect())
+			];
+			if with_code {
+				l.push(DataType::bulkStr("library_code"));
+				l.push(DataType::bulkStr(&lib.code));
+			}
+			DataType::List(l)
+		})
+		.collect()))
+}
+
+fn describe(f: &Registered) -> DataType {
+	DataType::List(vec![
+		DataType::bulkStr("name"),
+		DataType::bulkStr(&f.name),
+		DataType::bulkStr("description"),
+		match &f.description {
+			Some(d) => DataType::bulkStr(d),
+			None => DataType::Null
+		},
+		DataType::bulkStr("flags"),
+		DataType::List(f.flags.iter().map(|s| DataType::bulkStr(s)).collect())
+	])
+}
+
+// the code of every library, for the data file and full resyncs
+pub fn codes() -> Vec<String> {
+	LIBS.lock().unwrap().values().map(|lib| lib.code.clone()).collect()
+}
+
+pub fn dump() -> Result<DataType, &'static str> {
+	let mut l = vec![DataType::bulkStr(DUMP_HEADER)];
+	l.extend(codes().iter().map(|c| DataType::bulkStr(c)));
+	Ok(DataType::BulkString(DataType::List(l).to_string()))
+}
+
+// policy is one of append, flush or replace
+pub fn restore(payload: &str, policy: &str) -> Result<DataType, &'static str> {
+	let req = match parse(&mut BufReader::new(&bytes::encode(payload)[..])) {
+		Ok(req) if DUMP_HEADER == req.command => req,
+		_ => return Err(ERRMSG_BADPAYLOAD)
+	};
+	let mut libs = LIBS.lock().unwrap();
+	let mut restored: BTreeMap<String, Library> = match policy {
+		"flush" => BTreeMap::new(),
+		_ => libs.clone()
+	};
+	// nothing changes unless every library in the payload loads
+	for code in req.parameters.iter() {
+		match compile(code, &restored, "replace" == policy) {
+			Ok((name, lib)) => {restored.insert(name, lib);},
+			Err(e) => return Ok(DataType::err(&e))
+		}
+	}
+	*libs = restored;
+	Ok(DataType::str("OK"))
+}
+
+pub fn kill() -> Result<DataType, &'static str> {
+	script::script_kill()
+}
+
+#[cfg(test)]
+mod tests;
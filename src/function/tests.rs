@@ -0,0 +1,87 @@
+use serial_test::serial;
+use super::*;
+use crate::kv;
+
+const LIB: &str = "#!lua name=mylib
+local function put(keys, args)
+	return redis.call('set', keys[1], args[1])
+end
+redis.register_function('put', put)
+redis.register_function{
+	function_name = 'peek',
+	callback = function(keys) return redis.call('get', keys[1]) end,
+	flags = {'no-writes'},
+	description = 'read a key'
+}";
+
+fn strs(v: &[&str]) -> Vec<String> {
+	v.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	flush().unwrap();
+	kv::flushall();
+	assert_eq!(header("return 1"), Err(ERRMSG_NOMETA));
+	assert_eq!(header("#!js name=x"), Err(ERRMSG_BADENGINE));
+	assert_eq!(header("#!lua name=x foo=y"), Err(ERRMSG_BADMETA));
+	assert_eq!(header("#!lua name=a-b"), Err(ERRMSG_BADNAME));
+	assert_eq!(load(LIB, false), Ok(DataType::bulkStr("mylib")));
+	assert_eq!(
+		load(LIB, false),
+		Ok(DataType::err("ERR Library 'mylib' already exists"))
+	);
+	assert_eq!(load(LIB, true), Ok(DataType::bulkStr("mylib")));
+	assert_eq!(
+		load("#!lua name=empty\nlocal x = 1", false),
+		Ok(DataType::err(ERRMSG_NOFUNCS))
+	);
+	assert_eq!(
+		load("#!lua name=other\nredis.register_function('put', \
+			function() end)", false),
+		Ok(DataType::err("ERR Function put already exists in library mylib"))
+	);
+	assert_eq!(
+		fcall("put", &strs(&["1", "k", "v"]), false),
+		Ok(DataType::str("OK"))
+	);
+	assert_eq!(
+		fcall("peek", &strs(&["1", "k"]), true),
+		Ok(DataType::bulkStr("v"))
+	);
+	assert_eq!(
+		fcall("put", &strs(&["1", "k", "v"]), true),
+		Err(ERRMSG_WRITEONRO)
+	);
+	assert_eq!(fcall("nope", &strs(&["0"]), false), Err(ERRMSG_NOFUNCTION));
+	assert_eq!(delete("nope"), Err(ERRMSG_NOLIB));
+	kv::flushall();
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	flush().unwrap();
+	load(LIB, false).unwrap();
+	let payload = match dump() {
+		Ok(DataType::BulkString(s)) => s,
+		other => panic!("unexpected dump {:?}", other)
+	};
+	assert_eq!(
+		restore(&payload, "append"),
+		Ok(DataType::err("ERR Library 'mylib' already exists"))
+	);
+	assert_eq!(restore(&payload, "replace"), Ok(DataType::str("OK")));
+	assert_eq!(delete("mylib"), Ok(DataType::str("OK")));
+	assert_eq!(codes(), Vec::<String>::new());
+	assert_eq!(restore("junk", "flush"), Err(ERRMSG_BADPAYLOAD));
+	assert_eq!(restore(&payload, "flush"), Ok(DataType::str("OK")));
+	assert_eq!(codes(), vec![LIB.to_string()]);
+	match list(Some("my*"), false) {
+		Ok(DataType::List(l)) => assert_eq!(1, l.len()),
+		other => panic!("unexpected list {:?}", other)
+	}
+	assert_eq!(list(Some("x*"), true), Ok(DataType::List(vec![])));
+	flush().unwrap();
+}
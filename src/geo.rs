@@ -0,0 +1,385 @@
+// 52 bit geohashes as Redis builds them: 26 bits of longitude interleaved
+// with 26 bits of latitude, the latter limited to what Web Mercator covers
+
+use std::collections::HashSet;
+
+use super::datatype::DataType;
+use super::zset::SortedSet;
+
+pub const ERRMSG_NOMEMBER: &str = "ERR could not decode requested zset member";
+const ERRMSG_ANYCOUNT: &str = "ERR the ANY argument requires COUNT argument";
+const ERRMSG_BADUNIT: &str =
+	"ERR unsupported unit provided. please use M, KM, FT, MI";
+const ERRMSG_BYONE: &str =
+	"ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH";
+const ERRMSG_COUNT: &str = "ERR COUNT must be > 0";
+const ERRMSG_FROMONE: &str = "ERR exactly one of FROMMEMBER or FROMLONLAT \
+	can be specified for GEOSEARCH";
+const ERRMSG_NEGATIVE: &str = "ERR radius cannot be negative";
+const ERRMSG_NOTFLOAT: &str = "ERR value is not a valid float";
+const ERRMSG_NUMNAI: &str = "ERR value is not an integer or out of range";
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+
+const STEP: u32 = 26;
+const LAT_MIN: f64 = -85.051_128_78;
+const LAT_MAX: f64 = 85.051_128_78;
+const LON_MIN: f64 = -180.0;
+const LON_MAX: f64 = 180.0;
+const EARTH_RADIUS: f64 = 6_372_797.560_856;
+const MERCATOR_MAX: f64 = 20_037_726.37;
+const ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+pub enum Origin {
+	Member(String),
+	LonLat(f64, f64)
+}
+
+pub enum Shape {
+	Radius(f64),
+	Box(f64, f64)
+}
+
+pub struct Search {
+	pub origin: Origin,
+	pub shape: Shape,
+	// meters per unit
+	pub unit: f64,
+	pub desc: Option<bool>,
+	pub count: Option<usize>,
+	pub any: bool,
+	pub withcoord: bool,
+	pub withdist: bool,
+	pub withhash: bool,
+	pub storedist: bool
+}
+
+pub struct Hit {
+	pub member: String,
+	pub score: f64,
+	// in meters
+	pub dist: f64
+}
+
+pub fn bad_pair(lon: f64, lat: f64) -> String {
+	format!("ERR invalid longitude,latitude pair {:.6},{:.6}", lon, lat)
+}
+
+pub fn valid(lon: f64, lat: f64) -> bool {
+	(LON_MIN..=LON_MAX).contains(&lon) && (LAT_MIN..=LAT_MAX).contains(&lat)
+}
+
+pub fn parse_coord(s: &str) -> Result<f64, &'static str> {
+	match s.parse::<f64>() {
+		Ok(n) if n.is_finite() => Ok(n),
+		_ => Err(ERRMSG_NOTFLOAT)
+	}
+}
+
+pub fn parse_unit(s: &str) -> Result<f64, &'static str> {
+	match s.to_ascii_lowercase().as_str() {
+		"m" => Ok(1.0),
+		"km" => Ok(1000.0),
+		"ft" => Ok(0.3048),
+		"mi" => Ok(1609.34),
+		_ => Err(ERRMSG_BADUNIT)
+	}
+}
+
+// spread the low 32 bits of n over the even bits
+fn spread(n: u32) -> u64 {
+	let mut x = n as u64;
+	x = (x | (x << 16)) & 0x0000_ffff_0000_ffff;
+	x = (x | (x << 8)) & 0x00ff_00ff_00ff_00ff;
+	x = (x | (x << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+	x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+	(x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+fn squash(n: u64) -> u32 {
+	let mut x = n & 0x5555_5555_5555_5555;
+	x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+	x = (x | (x >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+	x = (x | (x >> 4)) & 0x00ff_00ff_00ff_00ff;
+	x = (x | (x >> 8)) & 0x0000_ffff_0000_ffff;
+	((x | (x >> 16)) & 0x0000_0000_ffff_ffff) as u32
+}
+
+fn encode_in(lon: f64, lat: f64, lat_min: f64, lat_max: f64, step: u32)
+	-> u64 {
+	let cells = (1u64 << step) as f64;
+	let last = cells - 1.0;
+	let x = ((lon - LON_MIN) / (LON_MAX - LON_MIN) * cells).min(last) as u32;
+	let y = ((lat - lat_min) / (lat_max - lat_min) * cells).min(last) as u32;
+	spread(y) | (spread(x) << 1)
+}
+
+// the cell of hash at step, as (lon min, lon max, lat min, lat max)
+fn area(hash: u64, step: u32) -> (f64, f64, f64, f64) {
+	let cells = (1u64 << step) as f64;
+	let x = squash(hash >> 1) as f64;
+	let y = squash(hash) as f64;
+	let lon = |i: f64| LON_MIN + i / cells * (LON_MAX - LON_MIN);
+	let lat = |i: f64| LAT_MIN + i / cells * (LAT_MAX - LAT_MIN);
+	(lon(x), lon(x + 1.0), lat(y), lat(y + 1.0))
+}
+
+pub fn encode(lon: f64, lat: f64) -> u64 {
+	encode_in(lon, lat, LAT_MIN, LAT_MAX, STEP)
+}
+
+// the center of the cell of a 52 bit hash
+pub fn decode(hash: u64) -> (f64, f64) {
+	let (x0, x1, y0, y1) = area(hash, STEP);
+	(
+		((x0 + x1) / 2.0).clamp(LON_MIN, LON_MAX),
+		((y0 + y1) / 2.0).clamp(LAT_MIN, LAT_MAX)
+	)
+}
+
+// the standard 11 character geohash, with latitudes over -90 to 90
+pub fn hash_string(hash: u64) -> String {
+	let (lon, lat) = decode(hash);
+	let h = encode_in(lon, lat, -90.0, 90.0, STEP);
+	(0..11)
+		.map(|i| match i {
+			10 => '0',
+			_ => ALPHABET[((h >> (52 - (i + 1) * 5)) & 31) as usize] as char
+		})
+		.collect()
+}
+
+// the great-circle distance in meters, by the haversine formula
+pub fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+	let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+	let u = ((lat2 - lat1) / 2.0).sin();
+	let v = ((lon2.to_radians() - lon1.to_radians()) / 2.0).sin();
+	let a = u * u + lat1.cos() * lat2.cos() * v * v;
+	2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+pub fn format_dist(meters: f64, unit: f64) -> String {
+	format!("{:.4}", meters / unit)
+}
+
+// FROMMEMBER member | FROMLONLAT lon lat, BYRADIUS r unit | BYBOX w h unit,
+// [ASC | DESC] [COUNT n [ANY]] and either WITHCOORD WITHDIST WITHHASH or,
+// when storing, STOREDIST
+pub fn parse_search(prms: &[String], store: bool)
+	-> Result<Search, &'static str> {
+	let mut origin = None;
+	let mut shape = None;
+	let mut s = Search {
+		origin: Origin::LonLat(0.0, 0.0),
+		shape: Shape::Radius(0.0),
+		unit: 1.0,
+		desc: None,
+		count: None,
+		any: false,
+		withcoord: false,
+		withdist: false,
+		withhash: false,
+		storedist: false
+	};
+	let mut i = 0;
+	while i < prms.len() {
+		let arg = |n: usize| prms.get(i + n).ok_or(ERRMSG_SYNERR);
+		match prms[i].to_ascii_lowercase().as_str() {
+			"frommember" if origin.is_none() => {
+				origin = Some(Origin::Member(arg(1)?.clone()));
+				i += 2;
+			},
+			"fromlonlat" if origin.is_none() => {
+				let lon = parse_coord(arg(1)?)?;
+				origin = Some(Origin::LonLat(lon, parse_coord(arg(2)?)?));
+				i += 3;
+			},
+			"frommember" | "fromlonlat" => return Err(ERRMSG_FROMONE),
+			"byradius" if shape.is_none() => {
+				let r = parse_coord(arg(1)?)?;
+				if 0.0 > r {
+					return Err(ERRMSG_NEGATIVE);
+				}
+				s.unit = parse_unit(arg(2)?)?;
+				shape = Some(Shape::Radius(r * s.unit));
+				i += 3;
+			},
+			"bybox" if shape.is_none() => {
+				let (w, h) = (parse_coord(arg(1)?)?, parse_coord(arg(2)?)?);
+				if 0.0 > w || 0.0 > h {
+					return Err("ERR height or width cannot be negative");
+				}
+				s.unit = parse_unit(arg(3)?)?;
+				shape = Some(Shape::Box(w * s.unit, h * s.unit));
+				i += 4;
+			},
+			"byradius" | "bybox" => return Err(ERRMSG_BYONE),
+			"asc" => {
+				s.desc = Some(false);
+				i += 1;
+			},
+			"desc" => {
+				s.desc = Some(true);
+				i += 1;
+			},
+			"count" => {
+				s.count = match arg(1)?.parse::<i64>() {
+					Ok(n) if 0 < n => Some(n as usize),
+					Ok(_) => return Err(ERRMSG_COUNT),
+					Err(_) => return Err(ERRMSG_NUMNAI)
+				};
+				i += 2;
+				if let Some(true) = prms.get(i)
+					.map(|a| a.eq_ignore_ascii_case("any")) {
+					s.any = true;
+					i += 1;
+				}
+			},
+			"any" => return Err(ERRMSG_ANYCOUNT),
+			"withcoord" if !store => {
+				s.withcoord = true;
+				i += 1;
+			},
+			"withdist" if !store => {
+				s.withdist = true;
+				i += 1;
+			},
+			"withhash" if !store => {
+				s.withhash = true;
+				i += 1;
+			},
+			"storedist" if store => {
+				s.storedist = true;
+				i += 1;
+			},
+			_ => return Err(ERRMSG_SYNERR)
+		}
+	}
+	s.origin = origin.ok_or(ERRMSG_FROMONE)?;
+	s.shape = shape.ok_or(ERRMSG_BYONE)?;
+	// a COUNT alone still returns the nearest
+	if s.count.is_some() && !s.any && s.desc.is_none() {
+		s.desc = Some(false);
+	}
+	Ok(s)
+}
+
+// how many steps a cell may have while a 3x3 block of cells still covers
+// radius meters around lat
+fn steps(radius: f64, lat: f64) -> u32 {
+	if 0.0 == radius {
+		return STEP;
+	}
+	let mut r = radius;
+	let mut step: i32 = 1;
+	while r < MERCATOR_MAX {
+		r *= 2.0;
+		step += 1;
+	}
+	step -= 2;
+	if !(-66.0..=66.0).contains(&lat) {
+		step -= 1;
+		if !(-80.0..=80.0).contains(&lat) {
+			step -= 1;
+		}
+	}
+	step.clamp(1, STEP as i32) as u32
+}
+
+// the score ranges of the cell holding lon, lat and of its neighbors
+fn ranges(lon: f64, lat: f64, radius: f64) -> Vec<(f64, f64)> {
+	let step = steps(radius, lat);
+	let (x0, x1, y0, y1) = area(encode_in(lon, lat, LAT_MIN, LAT_MAX, step),
+		step);
+	let (w, h) = (x1 - x0, y1 - y0);
+	let shift = 2 * (STEP - step);
+	let mut cells = HashSet::new();
+	for dy in [-1.0, 0.0, 1.0] {
+		let y = (y0 + y1) / 2.0 + dy * h;
+		if !(LAT_MIN..=LAT_MAX).contains(&y) {
+			continue;
+		}
+		for dx in [-1.0, 0.0, 1.0] {
+			let mut x = (x0 + x1) / 2.0 + dx * w;
+			if LON_MAX < x {
+				x -= 360.0;
+			} else if LON_MIN > x {
+				x += 360.0;
+			}
+			cells.insert(encode_in(x, y, LAT_MIN, LAT_MAX, step));
+		}
+	}
+	cells.into_iter()
+		.map(|c| ((c << shift) as f64, ((c + 1) << shift) as f64))
+		.collect()
+}
+
+// the members within shape around lon, lat, sorted or cut short as asked
+pub fn search(z: &SortedSet, lon: f64, lat: f64, s: &Search) -> Vec<Hit> {
+	let radius = match s.shape {
+		Shape::Radius(r) => r,
+		Shape::Box(w, h) => (w * w + h * h).sqrt() / 2.0
+	};
+	let mut hits = Vec::new();
+	'cells: for (min, max) in ranges(lon, lat, radius) {
+		for (member, score) in z.range(min, max) {
+			let (x, y) = decode(score as u64);
+			let inside = match s.shape {
+				Shape::Radius(r) => Some(distance(lon, lat, x, y))
+					.filter(|d| *d <= r),
+				Shape::Box(w, h) => Some(distance(lon, lat, x, y)).filter(|_|
+					EARTH_RADIUS * (y - lat).to_radians().abs() <= h / 2.0
+						&& distance(lon, y, x, y) <= w / 2.0
+				)
+			};
+			if let Some(dist) = inside {
+				hits.push(Hit {member: member.to_string(), score, dist});
+				if s.any && Some(hits.len()) == s.count {
+					break 'cells;
+				}
+			}
+		}
+	}
+	if let Some(desc) = s.desc {
+		hits.sort_by(|a, b| a.dist.total_cmp(&b.dist));
+		if desc {
+			hits.reverse();
+		}
+	}
+	if let Some(n) = s.count {
+		hits.truncate(n);
+	}
+	hits
+}
+
+pub fn coord(score: f64) -> DataType {
+	let (lon, lat) = decode(score as u64);
+	DataType::List(vec![
+		DataType::bulkStr(&lon.to_string()),
+		DataType::bulkStr(&lat.to_string())
+	])
+}
+
+// the reply to GEOSEARCH: names, or lists with the details asked for
+pub fn reply(hits: &[Hit], s: &Search) -> DataType {
+	DataType::List(hits.iter()
+		.map(|h| {
+			if !s.withdist && !s.withhash && !s.withcoord {
+				return DataType::bulkStr(&h.member);
+			}
+			let mut l = vec![DataType::bulkStr(&h.member)];
+			if s.withdist {
+				l.push(DataType::bulkStr(&format_dist(h.dist, s.unit)));
+			}
+			if s.withhash {
+				l.push(DataType::Integer(h.score as i64));
+			}
+			if s.withcoord {
+				l.push(coord(h.score));
+			}
+			DataType::List(l)
+		})
+		.collect())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,75 @@
+use serial_test::serial;
+use super::*;
+
+fn strs(v: &[&str]) -> Vec<String> {
+	v.iter().map(|s| s.to_string()).collect()
+}
+
+fn sicily() -> SortedSet {
+	let mut z = SortedSet::default();
+	z.insert("Palermo", encode(13.361389, 38.115556) as f64);
+	z.insert("Catania", encode(15.087269, 37.502669) as f64);
+	z
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	assert_eq!(encode(13.361389, 38.115556), 3479099956230698);
+	assert_eq!(encode(15.087269, 37.502669), 3479447370796909);
+	let (lon, lat) = decode(3479099956230698);
+	assert!((lon - 13.361389).abs() < 1e-5 && (lat - 38.115556).abs() < 1e-5);
+	assert_eq!(hash_string(3479099956230698), "sqc8b49rny0");
+	assert_eq!(hash_string(3479447370796909), "sqdtr74hyu0");
+	let (x1, y1) = decode(3479099956230698);
+	let (x2, y2) = decode(3479447370796909);
+	let d = distance(x1, y1, x2, y2);
+	assert_eq!(format_dist(d, 1.0), "166274.1516");
+	assert_eq!(format_dist(d, parse_unit("KM").unwrap()), "166.2742");
+	assert_eq!(format_dist(d, parse_unit("mi").unwrap()), "103.3182");
+	assert_eq!(parse_unit("yd").err(), Some(ERRMSG_BADUNIT));
+	assert!(!valid(181.0, 0.0) && !valid(0.0, 86.0));
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	let z = sicily();
+	let prms = strs(&[
+		"fromlonlat", "15", "37", "byradius", "200", "km", "asc", "withdist"
+	]);
+	let s = parse_search(&prms, false).unwrap();
+	let hits = search(&z, 15.0, 37.0, &s);
+	let found = hits.iter()
+		.map(|h| (h.member.as_str(), format_dist(h.dist, s.unit)))
+		.collect::<Vec<_>>();
+	assert_eq!(found, vec![
+		("Catania", "56.4413".to_string()),
+		("Palermo", "190.4424".to_string())
+	]);
+	let prms = strs(&["fromlonlat", "15", "37", "byradius", "100", "km"]);
+	let s = parse_search(&prms, false).unwrap();
+	assert_eq!(search(&z, 15.0, 37.0, &s).len(), 1);
+	let prms = strs(&[
+		"frommember", "Palermo", "bybox", "400", "400", "km", "desc",
+		"count", "1"
+	]);
+	let s = parse_search(&prms, false).unwrap();
+	let (lon, lat) = decode(z.score("Palermo").unwrap() as u64);
+	let hits = search(&z, lon, lat, &s);
+	assert_eq!(hits.len(), 1);
+	assert_eq!(hits[0].member, "Catania");
+	let bad = |p: &[&str]| parse_search(&strs(p), false).err();
+	assert_eq!(bad(&["byradius", "1", "m"]), Some(ERRMSG_FROMONE));
+	assert_eq!(bad(&["frommember", "a"]), Some(ERRMSG_BYONE));
+	assert_eq!(
+		bad(&["frommember", "a", "byradius", "1", "m", "any"]),
+		Some(ERRMSG_ANYCOUNT)
+	);
+	assert_eq!(
+		bad(&["frommember", "a", "byradius", "1", "m", "count", "0"]),
+		Some(ERRMSG_COUNT)
+	);
+	assert!(parse_search(&strs(&["frommember", "a", "byradius", "1", "m",
+		"storedist"]), false).is_err());
+}
@@ -0,0 +1,73 @@
+use super::bytes;
+
+// glob-style matching as understood by CONFIG GET, PSUBSCRIBE and friends:
+// `*`, `?`, `[abc]`, `[^abc]`, `[a-z]` and `\` to escape the next character
+pub fn matches(pattern: &str, s: &str) -> bool {
+	match_bytes(&bytes::encode(pattern), &bytes::encode(s))
+}
+
+fn match_bytes(p: &[u8], s: &[u8]) -> bool {
+	let (mut pi, mut si) = (0usize, 0usize);
+	// where to resume when the most recent `*` has to swallow one more byte
+	let mut star: Option<(usize, usize)> = None;
+	while si < s.len() {
+		let step = if pi < p.len() {
+			match p[pi] {
+				b'*' => {
+					star = Some((pi, si));
+					pi += 1;
+					continue;
+				},
+				b'?' => Some(pi + 1),
+				b'[' => match_class(p, pi, s[si]),
+				b'\\' if pi + 1 < p.len() =>
+					if p[pi + 1] == s[si] {Some(pi + 2)} else {None},
+				c => if c == s[si] {Some(pi + 1)} else {None}
+			}
+		} else {
+			None
+		};
+		match (step, star) {
+			(Some(next), _) => {
+				pi = next;
+				si += 1;
+			},
+			(None, Some((sp, ss))) => {
+				pi = sp + 1;
+				si = ss + 1;
+				star = Some((sp, ss + 1));
+			},
+			(None, None) => return false
+		}
+	}
+	p[pi..].iter().all(|&c| b'*' == c)
+}
+
+// match one byte against the class opening at p[start], returning the index
+// right after the class on success
+fn match_class(p: &[u8], start: usize, c: u8) -> Option<usize> {
+	let mut i = start + 1;
+	let negate = i < p.len() && b'^' == p[i];
+	if negate {
+		i += 1;
+	}
+	let mut hit = false;
+	while i < p.len() && b']' != p[i] {
+		if b'\\' == p[i] && i + 1 < p.len() {
+			hit |= p[i + 1] == c;
+			i += 2;
+		} else if i + 2 < p.len() && b'-' == p[i + 1] && b']' != p[i + 2] {
+			let (lo, hi) = if p[i] <= p[i + 2] {
+				(p[i], p[i + 2])
+			} else {
+				(p[i + 2], p[i])
+			};
+			hit |= lo <= c && c <= hi;
+			i += 3;
+		} else {
+			hit |= p[i] == c;
+			i += 1;
+		}
+	}
+	if hit != negate {Some((i + 1).min(p.len()))} else {None}
+}
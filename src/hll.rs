@@ -0,0 +1,290 @@
+// HyperLogLog with the layout of Redis: a 16 byte header followed by either
+// 16384 packed 6 bit registers (dense) or run length opcodes (sparse)
+
+pub const ERRMSG_CORRUPT: &str = "INVALIDOBJ Corrupted HLL object detected";
+pub const ERRMSG_NOTHLL: &str =
+	"WRONGTYPE Key is not a valid HyperLogLog string value.";
+
+const MAGIC: &[u8] = b"HYLL";
+const HDR_SIZE: usize = 16;
+const P: u32 = 14;
+const Q: u32 = 64 - P;
+const REGISTERS: usize = 1 << P;
+const BITS: usize = 6;
+const DENSE_SIZE: usize = HDR_SIZE + (REGISTERS * BITS).div_ceil(8);
+const SPARSE_MAX_BYTES: usize = 3000;
+// the largest register value the sparse VAL opcode can hold
+const SPARSE_MAX_VAL: u8 = 32;
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+const SEED: u64 = 0xadc8_3b19;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hll {
+	dense: bool,
+	card: Option<u64>,
+	regs: Vec<u8>
+}
+
+impl Default for Hll {
+	fn default() -> Self {
+		Hll {dense: false, card: Some(0), regs: vec![0; REGISTERS]}
+	}
+}
+
+impl Hll {
+	pub fn parse(v: &[u8]) -> Result<Self, &'static str> {
+		if HDR_SIZE > v.len() || MAGIC != &v[..4] {
+			return Err(ERRMSG_NOTHLL);
+		}
+		let dense = match v[4] {
+			0 if DENSE_SIZE == v.len() => true,
+			1 => false,
+			_ => return Err(ERRMSG_NOTHLL)
+		};
+		let card = match 0 == v[15] & 0x80 {
+			true => {
+				let mut b = [0u8; 8];
+				b.copy_from_slice(&v[8..16]);
+				Some(u64::from_le_bytes(b))
+			},
+			false => None
+		};
+		let regs = match dense {
+			true => (0..REGISTERS).map(|i| dense_get(&v[HDR_SIZE..], i))
+				.collect(),
+			false => sparse_decode(&v[HDR_SIZE..])?
+		};
+		Ok(Hll {dense, card, regs})
+	}
+
+	// count el in, returning whether any register changed
+	pub fn add(&mut self, el: &[u8]) -> bool {
+		let (i, n) = position(el);
+		if n <= self.regs[i] {
+			return false;
+		}
+		self.regs[i] = n;
+		self.card = None;
+		true
+	}
+
+	// keep the larger of each pair of registers
+	pub fn merge(&mut self, o: &Hll) {
+		self.regs.iter_mut().zip(o.regs.iter()).for_each(|(a, b)| {
+			*a = (*a).max(*b);
+		});
+		self.dense |= o.dense;
+		self.card = None;
+	}
+
+	// the estimated cardinality, and whether it had to be worked out anew
+	pub fn count(&mut self) -> (u64, bool) {
+		match self.card {
+			Some(n) => (n, false),
+			None => {
+				let n = estimate(&self.regs);
+				self.card = Some(n);
+				(n, true)
+			}
+		}
+	}
+
+	// sparse while the registers fit, dense from then on
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let sparse = match self.dense {
+			true => None,
+			false => sparse_encode(&self.regs)
+				.filter(|s| HDR_SIZE + s.len() <= SPARSE_MAX_BYTES)
+		};
+		let mut v = MAGIC.to_vec();
+		v.push(if sparse.is_some() {1} else {0});
+		v.extend_from_slice(&[0; 3]);
+		match self.card {
+			Some(n) => v.extend_from_slice(&n.to_le_bytes()),
+			None => v.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0x80])
+		}
+		match sparse {
+			Some(s) => v.extend(s),
+			None => {
+				v.resize(DENSE_SIZE, 0);
+				for (i, n) in self.regs.iter().enumerate() {
+					dense_set(&mut v[HDR_SIZE..], i, *n);
+				}
+			}
+		}
+		v
+	}
+}
+
+// MurmurHash64A, the hash function Redis uses for its HyperLogLogs
+pub fn murmur64a(key: &[u8], seed: u64) -> u64 {
+	const M: u64 = 0xc6a4_a793_5bd1_e995;
+	const R: u32 = 47;
+	let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+	let mut chunks = key.chunks_exact(8);
+	for c in &mut chunks {
+		let mut b = [0u8; 8];
+		b.copy_from_slice(c);
+		let mut k = u64::from_le_bytes(b).wrapping_mul(M);
+		k ^= k >> R;
+		h = (h ^ k.wrapping_mul(M)).wrapping_mul(M);
+	}
+	let tail = chunks.remainder();
+	if !tail.is_empty() {
+		for (i, b) in tail.iter().enumerate() {
+			h ^= (*b as u64) << (8 * i);
+		}
+		h = h.wrapping_mul(M);
+	}
+	h ^= h >> R;
+	h = h.wrapping_mul(M);
+	h ^ (h >> R)
+}
+
+// the register el falls in, and the length of its run of zeros plus one
+fn position(el: &[u8]) -> (usize, u8) {
+	let h = murmur64a(el, SEED);
+	let i = (h & (REGISTERS as u64 - 1)) as usize;
+	let rest = (h >> P) | (1 << Q);
+	(i, rest.trailing_zeros() as u8 + 1)
+}
+
+fn dense_get(regs: &[u8], i: usize) -> u8 {
+	let byte = i * BITS / 8;
+	let fb = i * BITS % 8;
+	let lo = (regs[byte] >> fb) as u16;
+	let hi = regs.get(byte + 1).copied().unwrap_or(0) as u16;
+	((lo | (hi << (8 - fb))) & 63) as u8
+}
+
+fn dense_set(regs: &mut [u8], i: usize, n: u8) {
+	let byte = i * BITS / 8;
+	let fb = i * BITS % 8;
+	regs[byte] &= !(63u8 << fb);
+	regs[byte] |= n << fb;
+	if 2 < fb {
+		regs[byte + 1] &= !(63u8 >> (8 - fb));
+		regs[byte + 1] |= n >> (8 - fb);
+	}
+}
+
+// ZERO 00xxxxxx, XZERO 01xxxxxx xxxxxxxx and VAL 1vvvvvxx opcodes
+fn sparse_decode(ops: &[u8]) -> Result<Vec<u8>, &'static str> {
+	let mut regs = Vec::with_capacity(REGISTERS);
+	let mut i = 0;
+	while i < ops.len() {
+		let op = ops[i];
+		let (n, len) = match op >> 6 {
+			0 => (0, (op & 63) as usize + 1),
+			1 => match ops.get(i + 1) {
+				Some(b) => {
+					i += 1;
+					(0, (((op & 63) as usize) << 8 | *b as usize) + 1)
+				},
+				None => return Err(ERRMSG_CORRUPT)
+			},
+			_ => (((op >> 2) & 31) + 1, (op & 3) as usize + 1)
+		};
+		if REGISTERS < regs.len() + len {
+			return Err(ERRMSG_CORRUPT);
+		}
+		regs.resize(regs.len() + len, n);
+		i += 1;
+	}
+	match REGISTERS == regs.len() {
+		true => Ok(regs),
+		false => Err(ERRMSG_CORRUPT)
+	}
+}
+
+// None when a register is too large for the sparse encoding
+fn sparse_encode(regs: &[u8]) -> Option<Vec<u8>> {
+	let mut ops = Vec::new();
+	let mut i = 0;
+	while i < regs.len() {
+		let n = regs[i];
+		if SPARSE_MAX_VAL < n {
+			return None;
+		}
+		let mut len = regs[i..].iter().take_while(|r| n == **r).count();
+		i += len;
+		while 0 < len {
+			let run = match n {
+				0 if 64 < len => {
+					let run = len.min(REGISTERS);
+					ops.push(0x40 | ((run - 1) >> 8) as u8);
+					ops.push(((run - 1) & 0xff) as u8);
+					run
+				},
+				0 => {
+					ops.push((len - 1) as u8);
+					len
+				},
+				_ => {
+					let run = len.min(4);
+					ops.push(0x80 | ((n - 1) << 2) | (run - 1) as u8);
+					run
+				}
+			};
+			len -= run;
+		}
+	}
+	Some(ops)
+}
+
+fn sigma(x: f64) -> f64 {
+	if 1.0 == x {
+		return f64::INFINITY;
+	}
+	let (mut x, mut y, mut z) = (x, 1.0, x);
+	loop {
+		x *= x;
+		let prev = z;
+		z += x * y;
+		y += y;
+		if prev == z {
+			return z;
+		}
+	}
+}
+
+fn tau(x: f64) -> f64 {
+	if 0.0 == x || 1.0 == x {
+		return 0.0;
+	}
+	let (mut x, mut y, mut z) = (x, 1.0, 1.0 - x);
+	loop {
+		x = x.sqrt();
+		let prev = z;
+		y *= 0.5;
+		z -= (1.0 - x).powi(2) * y;
+		if prev == z {
+			return z / 3.0;
+		}
+	}
+}
+
+// the improved estimator of Otmar Ertl, as Redis uses
+fn estimate(regs: &[u8]) -> u64 {
+	let mut histo = [0u32; 64];
+	regs.iter().for_each(|n| histo[*n as usize] += 1);
+	let m = REGISTERS as f64;
+	let q = Q as usize;
+	let mut z = m * tau((m - histo[q + 1] as f64) / m);
+	for j in (1..=q).rev() {
+		z += histo[j] as f64;
+		z *= 0.5;
+	}
+	z += m * sigma(histo[0] as f64 / m);
+	(ALPHA_INF * m * m / z).round() as u64
+}
+
+// the union of every register set
+pub fn union(hlls: &[Hll]) -> u64 {
+	let mut all = Hll::default();
+	hlls.iter().for_each(|h| all.merge(h));
+	estimate(&all.regs)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,58 @@
+use serial_test::serial;
+use super::*;
+
+fn filled(prefix: &str, n: usize) -> Hll {
+	let mut h = Hll::default();
+	for i in 0..n {
+		h.add(format!("{}{}", prefix, i).as_bytes());
+	}
+	h
+}
+
+fn close(n: u64, expected: u64) -> bool {
+	(n as f64 - expected as f64).abs() / (expected as f64) < 0.03
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	let empty = Hll::default().to_bytes();
+	assert_eq!(&empty[..5], b"HYLL\x01");
+	assert_eq!(&empty[5..], &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x7f, 0xff]);
+	assert_eq!(Hll::parse(&empty), Ok(Hll::default()));
+	assert_eq!(Hll::parse(b"HYLL"), Err(ERRMSG_NOTHLL));
+	let short = b"HYLL\x00\0\0\0\0\0\0\0\0\0\0\0";
+	assert_eq!(Hll::parse(short), Err(ERRMSG_NOTHLL));
+	let mut bad = empty.clone();
+	bad.pop();
+	assert_eq!(Hll::parse(&bad), Err(ERRMSG_CORRUPT));
+	let mut h = Hll::default();
+	assert!(h.add(b"a"));
+	assert!(!h.add(b"a"));
+	assert_eq!(h.count(), (1, true));
+	assert_eq!(h.count(), (1, false));
+	let mut h = filled("a", 7);
+	assert_eq!(h.count().0, 7);
+	let v = h.to_bytes();
+	assert_eq!(v[4], 1);
+	let mut copy = Hll::parse(&v).unwrap();
+	assert_eq!(copy.count().0, 7);
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	let mut h = filled("x", 20000);
+	let v = h.to_bytes();
+	assert_eq!((v[4], v.len()), (0, DENSE_SIZE));
+	assert_eq!(v[15] & 0x80, 0x80);
+	let mut copy = Hll::parse(&v).unwrap();
+	assert_eq!(copy.regs, h.regs);
+	assert!(close(h.count().0, 20000));
+	let other = filled("y", 20000);
+	assert!(close(union(&[h.clone(), other.clone()]), 40000));
+	assert!(close(union(&[h.clone(), h.clone()]), 20000));
+	h.merge(&other);
+	assert!(close(h.count().0, 40000));
+	assert!(close(copy.count().0, 20000));
+}
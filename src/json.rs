@@ -0,0 +1,752 @@
+// JSON documents and the paths into them: JSONPath starting with $, which
+// selects every match, and the legacy dotted paths, which select one
+
+use std::fmt::Write;
+
+use super::bytes;
+use super::datatype::DataType;
+
+pub const ERRMSG_BADJSON: &str = "ERR invalid JSON";
+pub const ERRMSG_BADPATH: &str = "ERR invalid JSON path";
+pub const ERRMSG_NEWATROOT: &str =
+	"ERR new objects must be created at the root";
+pub const ERRMSG_NOKEY: &str =
+	"ERR could not perform this operation on a key that doesn't exist";
+const ERRMSG_INDEX: &str = "ERR index out of bounds";
+const ERRMSG_NOTNUM: &str = "ERR value is not a number";
+const ERRMSG_NOTSTR: &str = "ERR value is not a string";
+
+// as deep as documents may nest
+const MAX_DEPTH: usize = 128;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+	Null,
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	Str(String),
+	Array(Vec<Json>),
+	// members in the order they were added
+	Object(Vec<(String, Json)>)
+}
+
+// never holding a NaN, a document equals itself
+impl Eq for Json {}
+
+// a step from a value to one of its children
+#[derive(Clone, Debug, PartialEq)]
+pub enum Loc {
+	Key(String),
+	Index(usize)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Seg {
+	Key(String),
+	Index(i64),
+	Slice(Option<i64>, Option<i64>),
+	Wild,
+	// the following segment, applied at any depth
+	Descend
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+	pub text: String,
+	pub legacy: bool,
+	segs: Vec<Seg>
+}
+
+#[derive(Default)]
+pub struct Format {
+	pub indent: String,
+	pub newline: String,
+	pub space: String
+}
+
+// the text of a byte string, or None if it is not valid UTF-8
+fn text(s: &str) -> Option<String> {
+	String::from_utf8(bytes::encode(s)).ok()
+}
+
+// text as a byte string, as sent over the wire
+fn wire(s: &str) -> String {
+	bytes::decode(s.as_bytes())
+}
+
+struct Parser<'a> {
+	s: &'a [u8],
+	i: usize
+}
+
+impl Parser<'_> {
+	fn skip_ws(&mut self) {
+		while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.s.get(self.i) {
+			self.i += 1;
+		}
+	}
+
+	fn eat(&mut self, lit: &str) -> Option<()> {
+		match self.s[self.i..].starts_with(lit.as_bytes()) {
+			true => {
+				self.i += lit.len();
+				Some(())
+			},
+			false => None
+		}
+	}
+
+	fn value(&mut self, depth: usize) -> Option<Json> {
+		if MAX_DEPTH < depth {
+			return None;
+		}
+		self.skip_ws();
+		match self.s.get(self.i)? {
+			b'n' => self.eat("null").map(|_| Json::Null),
+			b't' => self.eat("true").map(|_| Json::Bool(true)),
+			b'f' => self.eat("false").map(|_| Json::Bool(false)),
+			b'"' => self.string().map(Json::Str),
+			b'[' => {
+				self.i += 1;
+				let mut l = Vec::new();
+				self.skip_ws();
+				if self.eat("]").is_some() {
+					return Some(Json::Array(l));
+				}
+				loop {
+					l.push(self.value(depth + 1)?);
+					self.skip_ws();
+					if self.eat("]").is_some() {
+						return Some(Json::Array(l));
+					}
+					self.eat(",")?;
+				}
+			},
+			b'{' => {
+				self.i += 1;
+				let mut o: Vec<(String, Json)> = Vec::new();
+				self.skip_ws();
+				if self.eat("}").is_some() {
+					return Some(Json::Object(o));
+				}
+				loop {
+					self.skip_ws();
+					let k = self.string()?;
+					self.skip_ws();
+					self.eat(":")?;
+					let v = self.value(depth + 1)?;
+					match o.iter_mut().find(|(k2, _)| *k2 == k) {
+						Some(m) => m.1 = v,
+						None => o.push((k, v))
+					}
+					self.skip_ws();
+					if self.eat("}").is_some() {
+						return Some(Json::Object(o));
+					}
+					self.eat(",")?;
+				}
+			},
+			_ => self.number()
+		}
+	}
+
+	fn digits(&mut self) -> usize {
+		let start = self.i;
+		while self.s.get(self.i).is_some_and(|b| b.is_ascii_digit()) {
+			self.i += 1;
+		}
+		self.i - start
+	}
+
+	fn number(&mut self) -> Option<Json> {
+		let start = self.i;
+		let _ = self.eat("-");
+		let lead = self.s.get(self.i).copied();
+		match self.digits() {
+			0 => return None,
+			n if 1 < n && Some(b'0') == lead => return None,
+			_ => {}
+		}
+		let mut float = false;
+		if self.eat(".").is_some() {
+			float = true;
+			if 0 == self.digits() {
+				return None;
+			}
+		}
+		if let Some(b'e' | b'E') = self.s.get(self.i) {
+			float = true;
+			self.i += 1;
+			if let Some(b'+' | b'-') = self.s.get(self.i) {
+				self.i += 1;
+			}
+			if 0 == self.digits() {
+				return None;
+			}
+		}
+		let n = std::str::from_utf8(&self.s[start..self.i]).ok()?;
+		match float {
+			false => match n.parse::<i64>() {
+				Ok(n) => Some(Json::Int(n)),
+				Err(_) => n.parse::<f64>().ok().map(Json::Float)
+			},
+			true => n.parse::<f64>().ok()
+				.filter(|f| f.is_finite())
+				.map(Json::Float)
+		}
+	}
+
+	fn hex4(&mut self) -> Option<u32> {
+		let h = std::str::from_utf8(self.s.get(self.i..self.i + 4)?).ok()?;
+		self.i += 4;
+		u32::from_str_radix(h, 16).ok()
+	}
+
+	fn string(&mut self) -> Option<String> {
+		self.eat("\"")?;
+		let mut out: Vec<u8> = Vec::new();
+		loop {
+			let b = *self.s.get(self.i)?;
+			self.i += 1;
+			match b {
+				b'"' => return String::from_utf8(out).ok(),
+				b'\\' => {
+					let e = *self.s.get(self.i)?;
+					self.i += 1;
+					let c = match e {
+						b'"' => '"',
+						b'\\' => '\\',
+						b'/' => '/',
+						b'b' => '\u{8}',
+						b'f' => '\u{c}',
+						b'n' => '\n',
+						b'r' => '\r',
+						b't' => '\t',
+						b'u' => {
+							let hi = self.hex4()?;
+							let cp = match hi {
+								0xd800..=0xdbff => {
+									self.eat("\\u")?;
+									let lo = self.hex4()?;
+									if !(0xdc00..=0xdfff).contains(&lo) {
+										return None;
+									}
+									0x10000 + ((hi - 0xd800) << 10)
+										+ (lo - 0xdc00)
+								},
+								_ => hi
+							};
+							char::from_u32(cp)?
+						},
+						_ => return None
+					};
+					let mut buf = [0u8; 4];
+					out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+				},
+				0..=0x1f => return None,
+				_ => out.push(b)
+			}
+		}
+	}
+}
+
+fn quote(out: &mut String, s: &str) {
+	out.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			'\u{8}' => out.push_str("\\b"),
+			'\u{c}' => out.push_str("\\f"),
+			c if (c as u32) < 0x20 => {
+				let _ = write!(out, "\\u{:04x}", c as u32);
+			},
+			c => out.push(c)
+		}
+	}
+	out.push('"');
+}
+
+impl Json {
+	// a document from its text, given as a byte string
+	pub fn parse(s: &str) -> Result<Json, &'static str> {
+		let s = text(s).ok_or(ERRMSG_BADJSON)?;
+		let mut p = Parser {s: s.as_bytes(), i: 0};
+		let v = p.value(0).ok_or(ERRMSG_BADJSON)?;
+		p.skip_ws();
+		match p.i == s.len() {
+			true => Ok(v),
+			false => Err(ERRMSG_BADJSON)
+		}
+	}
+
+	pub fn type_name(&self) -> &'static str {
+		match self {
+			Json::Null => "null",
+			Json::Bool(_) => "boolean",
+			Json::Int(_) => "integer",
+			Json::Float(_) => "number",
+			Json::Str(_) => "string",
+			Json::Array(_) => "array",
+			Json::Object(_) => "object"
+		}
+	}
+
+	pub fn capacity(&self) -> usize {
+		match self {
+			Json::Str(s) => 24 + s.capacity(),
+			Json::Array(l) =>
+				24 + l.iter().map(|v| v.capacity()).sum::<usize>(),
+			Json::Object(o) => 24 + o.iter()
+				.map(|(k, v)| k.capacity() + v.capacity())
+				.sum::<usize>(),
+			_ => 16
+		}
+	}
+
+	fn write(&self, out: &mut String, f: &Format, depth: usize) {
+		let pad = |out: &mut String, d: usize| {
+			out.push_str(&f.newline);
+			(0..d).for_each(|_| out.push_str(&f.indent));
+		};
+		match self {
+			Json::Null => out.push_str("null"),
+			Json::Bool(b) => out.push_str(if *b {"true"} else {"false"}),
+			Json::Int(n) => {
+				let _ = write!(out, "{}", n);
+			},
+			Json::Float(n) => {
+				let s = n.to_string();
+				out.push_str(&s);
+				if !s.contains(['.', 'e', 'i', 'N']) {
+					out.push_str(".0");
+				}
+			},
+			Json::Str(s) => quote(out, s),
+			Json::Array(l) => {
+				out.push('[');
+				for (i, v) in l.iter().enumerate() {
+					if 0 < i {
+						out.push(',');
+					}
+					pad(out, depth + 1);
+					v.write(out, f, depth + 1);
+				}
+				if !l.is_empty() {
+					pad(out, depth);
+				}
+				out.push(']');
+			},
+			Json::Object(o) => {
+				out.push('{');
+				for (i, (k, v)) in o.iter().enumerate() {
+					if 0 < i {
+						out.push(',');
+					}
+					pad(out, depth + 1);
+					quote(out, k);
+					out.push(':');
+					out.push_str(&f.space);
+					v.write(out, f, depth + 1);
+				}
+				if !o.is_empty() {
+					pad(out, depth);
+				}
+				out.push('}');
+			}
+		}
+	}
+
+	// the text of the document as a byte string
+	pub fn format(&self, f: &Format) -> String {
+		let mut out = String::new();
+		self.write(&mut out, f, 0);
+		wire(&out)
+	}
+
+	pub fn to_text(&self) -> String {
+		self.format(&Format::default())
+	}
+
+	pub fn at(&self, loc: &[Loc]) -> Option<&Json> {
+		loc.iter().try_fold(self, |v, l| match (v, l) {
+			(Json::Object(o), Loc::Key(k)) =>
+				o.iter().find(|(k2, _)| k2 == k).map(|(_, v)| v),
+			(Json::Array(a), Loc::Index(i)) => a.get(*i),
+			_ => None
+		})
+	}
+
+	pub fn at_mut(&mut self, loc: &[Loc]) -> Option<&mut Json> {
+		loc.iter().try_fold(self, |v, l| match (v, l) {
+			(Json::Object(o), Loc::Key(k)) =>
+				o.iter_mut().find(|(k2, _)| k2 == k).map(|(_, v)| v),
+			(Json::Array(a), Loc::Index(i)) => a.get_mut(*i),
+			_ => None
+		})
+	}
+
+	fn walk(&self, segs: &[Seg], at: &mut Vec<Loc>, out: &mut Vec<Vec<Loc>>) {
+		fn visit(v: &Json, l: Loc, segs: &[Seg], at: &mut Vec<Loc>,
+			out: &mut Vec<Vec<Loc>>) {
+			at.push(l);
+			v.walk(segs, at, out);
+			at.pop();
+		}
+		let seg = match segs.first() {
+			Some(seg) => seg,
+			None => {
+				out.push(at.clone());
+				return;
+			}
+		};
+		let rest = &segs[1..];
+		match (seg, self) {
+			(Seg::Key(k), Json::Object(o)) => {
+				if let Some((k, v)) = o.iter().find(|(k2, _)| k2 == k) {
+					visit(v, Loc::Key(k.clone()), rest, at, out);
+				}
+			},
+			(Seg::Index(i), Json::Array(a)) => {
+				let i = if 0 > *i {*i + a.len() as i64} else {*i};
+				if 0 <= i && i < a.len() as i64 {
+					let i = i as usize;
+					visit(&a[i], Loc::Index(i), rest, at, out);
+				}
+			},
+			(Seg::Slice(start, end), Json::Array(a)) => {
+				let len = a.len() as i64;
+				let bound =
+					|n: i64| if 0 > n {(n + len).max(0)} else {n.min(len)};
+				let start = bound(start.unwrap_or(0));
+				let end = bound(end.unwrap_or(len));
+				let (start, end) = (start as usize, end.max(start) as usize);
+				for (i, v) in a.iter().enumerate().take(end).skip(start) {
+					visit(v, Loc::Index(i), rest, at, out);
+				}
+			},
+			(Seg::Wild, Json::Array(a)) => for (i, v) in a.iter().enumerate() {
+				visit(v, Loc::Index(i), rest, at, out);
+			},
+			(Seg::Wild, Json::Object(o)) => for (k, v) in o.iter() {
+				visit(v, Loc::Key(k.clone()), rest, at, out);
+			},
+			(Seg::Descend, _) => {
+				self.walk(rest, at, out);
+				match self {
+					Json::Array(a) => for (i, v) in a.iter().enumerate() {
+						visit(v, Loc::Index(i), segs, at, out);
+					},
+					Json::Object(o) => for (k, v) in o.iter() {
+						visit(v, Loc::Key(k.clone()), segs, at, out);
+					},
+					_ => {}
+				}
+			},
+			_ => {}
+		}
+	}
+
+	// where the path leads, the first match only for legacy paths
+	pub fn select(&self, p: &Path) -> Vec<Vec<Loc>> {
+		let mut out = Vec::new();
+		self.walk(&p.segs, &mut Vec::new(), &mut out);
+		if p.legacy {
+			out.truncate(1);
+		}
+		out
+	}
+}
+
+fn parse_segs(s: &str) -> Option<Vec<Seg>> {
+	let c: Vec<char> = s.chars().collect();
+	let mut segs = Vec::new();
+	let mut i = 0;
+	let name = |i: &mut usize| {
+		let start = *i;
+		while *i < c.len() && !matches!(c[*i], '.' | '[') {
+			*i += 1;
+		}
+		c[start..*i].iter().collect::<String>()
+	};
+	while i < c.len() {
+		match c[i] {
+			'.' => {
+				i += 1;
+				if Some(&'.') == c.get(i) {
+					i += 1;
+					segs.push(Seg::Descend);
+					if Some(&'[') == c.get(i) {
+						continue;
+					}
+				}
+				match name(&mut i).as_str() {
+					"" => return None,
+					"*" => segs.push(Seg::Wild),
+					n => segs.push(Seg::Key(n.to_string()))
+				}
+			},
+			'[' => {
+				let end = i + c[i..].iter().position(|ch| ']' == *ch)?;
+				let inner = c[i + 1..end].iter().collect::<String>();
+				let inner = inner.trim();
+				let quoted = ['\'', '"'].iter().find_map(|q| inner
+					.strip_prefix(*q)
+					.and_then(|s| s.strip_suffix(*q)));
+				segs.push(match (quoted, inner) {
+					(Some(k), _) => Seg::Key(k.to_string()),
+					(None, "*") => Seg::Wild,
+					(None, _) => match inner.split_once(':') {
+						Some((a, b)) => {
+							let n = |s: &str| match s.trim() {
+								"" => Some(None),
+								s => s.parse::<i64>().ok().map(Some)
+							};
+							Seg::Slice(n(a)?, n(b)?)
+						},
+						None => Seg::Index(inner.parse::<i64>().ok()?)
+					}
+				});
+				i = end + 1;
+			},
+			_ => return None
+		}
+	}
+	Some(segs)
+}
+
+// $... as JSONPath, anything else as a legacy path where . is the root
+pub fn parse_path(s: &str) -> Result<Path, &'static str> {
+	let t = text(s).ok_or(ERRMSG_BADPATH)?;
+	let (legacy, rest) = match t.strip_prefix('$') {
+		Some(rest) => (false, rest.to_string()),
+		None => (true, match t.as_str() {
+			"." => String::new(),
+			_ if t.starts_with(['.', '[']) => t.clone(),
+			_ => format!(".{}", t)
+		})
+	};
+	match parse_segs(&rest) {
+		Some(segs) => Ok(Path {text: s.to_string(), legacy, segs}),
+		None => Err(ERRMSG_BADPATH)
+	}
+}
+
+impl Path {
+	pub fn is_root(&self) -> bool {
+		self.segs.is_empty()
+	}
+}
+
+pub fn root() -> Path {
+	Path {text: ".".to_string(), legacy: true, segs: vec![]}
+}
+
+pub fn no_path(p: &Path) -> String {
+	format!("ERR Path '{}' does not exist", p.text)
+}
+
+// the reply to a command run at each match of p: a list for JSONPath, the
+// one result for a legacy path, failing when there is none
+pub fn reply(p: &Path, rs: Vec<Option<DataType>>, expected: &str)
+	-> Result<DataType, String> {
+	if !p.legacy {
+		return Ok(DataType::List(rs.into_iter()
+			.map(|r| r.unwrap_or(DataType::Null))
+			.collect()));
+	}
+	match rs.into_iter().next() {
+		Some(Some(r)) => Ok(r),
+		Some(None) => Err(format!(
+			"ERR WRONGTYPE wrong type of path value - expected {}",
+			expected
+		)),
+		None => Err(no_path(p))
+	}
+}
+
+// the values at p: the first one for a legacy path, or else an array
+fn value(doc: &Json, p: &Path, legacy: bool) -> Result<Json, String> {
+	let mut vs = doc.select(p).iter()
+		.filter_map(|l| doc.at(l).cloned())
+		.collect::<Vec<_>>();
+	match legacy {
+		true if vs.is_empty() => Err(no_path(p)),
+		true => Ok(vs.swap_remove(0)),
+		false => Ok(Json::Array(vs))
+	}
+}
+
+// JSON.GET of paths, several of them gathered in an object
+pub fn get(doc: &Json, paths: &[Path], f: &Format) -> Result<String, String> {
+	let legacy = paths.iter().all(|p| p.legacy);
+	if 1 == paths.len() {
+		return Ok(value(doc, &paths[0], legacy)?.format(f));
+	}
+	let mut o = Vec::with_capacity(paths.len());
+	for p in paths.iter() {
+		let k = text(&p.text).unwrap_or_default();
+		if !o.iter().any(|(k2, _)| *k2 == k) {
+			o.push((k, value(doc, p, legacy)?));
+		}
+	}
+	Ok(Json::Object(o).format(f))
+}
+
+// JSON.SET at p, false when NX or XX says not to
+pub fn set(doc: &mut Json, p: &Path, v: &Json, nx: bool, xx: bool)
+	-> Result<bool, String> {
+	let locs = doc.select(p);
+	if !locs.is_empty() {
+		if nx {
+			return Ok(false);
+		}
+		for l in locs.iter() {
+			if let Some(slot) = doc.at_mut(l) {
+				*slot = v.clone();
+			}
+		}
+		return Ok(true);
+	}
+	let k = match p.segs.last() {
+		Some(Seg::Key(k)) if !xx => k.clone(),
+		_ if xx => return Ok(false),
+		_ => return Err(no_path(p))
+	};
+	let parent = Path {segs: p.segs[..p.segs.len() - 1].to_vec(), ..p.clone()};
+	let mut added = false;
+	for l in doc.select(&parent) {
+		if let Some(Json::Object(o)) = doc.at_mut(&l) {
+			o.push((k.clone(), v.clone()));
+			added = true;
+		}
+	}
+	match added {
+		true => Ok(true),
+		false => Err(no_path(p))
+	}
+}
+
+// JSON.DEL at p, returning how many values went away
+pub fn delete(doc: &mut Json, p: &Path) -> usize {
+	let mut locs = doc.select(p);
+	// the last indexes and the deepest values first
+	locs.sort_by(|a, b| {
+		let key = |l: &Vec<Loc>| l.iter()
+			.map(|s| match s {
+				Loc::Index(i) => (*i, String::new()),
+				Loc::Key(k) => (0, k.clone())
+			})
+			.collect::<Vec<_>>();
+		key(b).cmp(&key(a))
+	});
+	locs.iter()
+		.filter(|l| match l.split_last() {
+			Some((last, parent)) => match (doc.at_mut(parent), last) {
+				(Some(Json::Object(o)), Loc::Key(k)) => {
+					let n = o.len();
+					o.retain(|(k2, _)| k2 != k);
+					n != o.len()
+				},
+				(Some(Json::Array(a)), Loc::Index(i)) if *i < a.len() => {
+					a.remove(*i);
+					true
+				},
+				_ => false
+			},
+			None => true
+		})
+		.count()
+}
+
+// f run on every value p leads to
+pub fn each<F>(doc: &mut Json, p: &Path, mut f: F) -> Vec<Option<DataType>>
+	where F: FnMut(&mut Json) -> Option<DataType> {
+	doc.select(p).iter()
+		.map(|l| doc.at_mut(l).and_then(&mut f))
+		.collect()
+}
+
+pub fn arr_insert(a: &mut Json, at: Option<i64>, vs: &[Json])
+	-> Result<Option<DataType>, &'static str> {
+	let a = match a {
+		Json::Array(a) => a,
+		_ => return Ok(None)
+	};
+	let len = a.len() as i64;
+	let i = match at {
+		None => len,
+		Some(i) if 0 > i && 0 <= i + len => i + len,
+		Some(i) if 0 <= i && i <= len => i,
+		_ => return Err(ERRMSG_INDEX)
+	} as usize;
+	a.splice(i..i, vs.iter().cloned());
+	Ok(Some(DataType::Integer(a.len() as i64)))
+}
+
+pub fn arr_pop(a: &mut Json, at: i64) -> Option<DataType> {
+	match a {
+		Json::Array(a) if !a.is_empty() => {
+			let len = a.len() as i64;
+			let i = if 0 > at {(at + len).max(0)} else {at.min(len - 1)};
+			Some(DataType::bulkStr(&a.remove(i as usize).to_text()))
+		},
+		Json::Array(_) => Some(DataType::Null),
+		_ => None
+	}
+}
+
+pub fn num_incr(v: &mut Json, by: &Json) -> Option<Json> {
+	let n = match (&*v, by) {
+		(Json::Int(a), Json::Int(b)) => match a.checked_add(*b) {
+			Some(n) => Json::Int(n),
+			None => Json::Float(*a as f64 + *b as f64)
+		},
+		(Json::Int(a), Json::Float(b)) => Json::Float(*a as f64 + b),
+		(Json::Float(a), Json::Int(b)) => Json::Float(a + *b as f64),
+		(Json::Float(a), Json::Float(b)) => Json::Float(a + b),
+		_ => return None
+	};
+	*v = n.clone();
+	Some(n)
+}
+
+pub fn parse_number(s: &str) -> Result<Json, &'static str> {
+	match Json::parse(s) {
+		Ok(n @ (Json::Int(_) | Json::Float(_))) => Ok(n),
+		_ => Err(ERRMSG_NOTNUM)
+	}
+}
+
+pub fn parse_string(s: &str) -> Result<String, &'static str> {
+	match Json::parse(s) {
+		Ok(Json::Str(s)) => Ok(s),
+		_ => Err(ERRMSG_NOTSTR)
+	}
+}
+
+pub fn str_append(v: &mut Json, tail: &str) -> Option<DataType> {
+	match v {
+		Json::Str(s) => {
+			s.push_str(tail);
+			Some(DataType::Integer(s.len() as i64))
+		},
+		_ => None
+	}
+}
+
+pub fn obj_keys(v: &Json) -> Option<DataType> {
+	match v {
+		Json::Object(o) => Some(DataType::List(o.iter()
+			.map(|(k, _)| DataType::bulkStr(&wire(k)))
+			.collect())),
+		_ => None
+	}
+}
+
+#[cfg(test)]
+mod tests;
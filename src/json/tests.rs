@@ -0,0 +1,81 @@
+use serial_test::serial;
+use super::*;
+
+fn doc(s: &str) -> Json {
+	Json::parse(s).unwrap()
+}
+
+fn path(s: &str) -> Path {
+	parse_path(s).unwrap()
+}
+
+fn texts(d: &Json, p: &str) -> Vec<String> {
+	d.select(&path(p)).iter()
+		.map(|l| d.at(l).unwrap().to_text())
+		.collect()
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	let d = doc(" {\"a\": [1, 2.5, -3e2, \"x\\u00e9\\n\"], \"b\": {}, \
+		\"c\": null, \"d\": true} ");
+	assert_eq!(
+		d.to_text(),
+		"{\"a\":[1,2.5,-300.0,\"x\u{c3}\u{a9}\\n\"],\"b\":{},\"c\":null,\
+			\"d\":true}"
+	);
+	for bad in ["", "[1,]", "{\"a\" 1}", "01", "[1] 2", "\"\t\"", "nul"] {
+		assert_eq!(Json::parse(bad), Err(ERRMSG_BADJSON));
+	}
+	assert_eq!(Json::parse(&"[".repeat(200)), Err(ERRMSG_BADJSON));
+	let f = Format {
+		indent: "  ".to_string(),
+		newline: "\n".to_string(),
+		space: " ".to_string()
+	};
+	assert_eq!(doc("{\"a\":[1],\"b\":[]}").format(&f),
+		"{\n  \"a\": [\n    1\n  ],\n  \"b\": []\n}");
+	let d = doc("{\"a\":{\"b\":[10,20,{\"b\":30}]},\"b\":1}");
+	assert_eq!(texts(&d, "$.a.b[0]"), vec!["10"]);
+	assert_eq!(texts(&d, "$.a.b[-1].b"), vec!["30"]);
+	assert_eq!(texts(&d, "$['a'][\"b\"][0:2]"), vec!["10", "20"]);
+	assert_eq!(texts(&d, "$.*").len(), 2);
+	assert_eq!(texts(&d, "$..b").len(), 3);
+	assert_eq!(texts(&d, "$.a.b[*]").len(), 3);
+	assert_eq!(texts(&d, "a.b[1]"), vec!["20"]);
+	assert_eq!(texts(&d, "..b"), vec!["1"]);
+	assert_eq!(texts(&d, "."), vec![d.to_text()]);
+	assert!(parse_path("$.a[").is_err() && parse_path("$a").is_err());
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	let mut d = doc("{\"a\":[1],\"n\":{\"a\":\"s\"}}");
+	let b = path("$.b");
+	assert_eq!(set(&mut d, &b, &Json::Int(2), false, false), Ok(true));
+	assert_eq!(set(&mut d, &b, &Json::Int(3), true, false), Ok(false));
+	assert!(set(&mut d, &path("$.x.y"), &Json::Null, false, false).is_err());
+	let rs = each(&mut d, &path("$..a"), |v| arr_insert(v, None, &[Json::Null])
+		.unwrap());
+	assert_eq!(rs, vec![Some(DataType::Integer(2)), None]);
+	assert_eq!(
+		reply(&path("$..a"), rs.clone(), "array"),
+		Ok(DataType::List(vec![DataType::Integer(2), DataType::Null]))
+	);
+	assert!(reply(&path(".n.a"), vec![None], "array").is_err());
+	assert_eq!(arr_insert(&mut doc("[1]"), Some(5), &[]), Err(ERRMSG_INDEX));
+	let mut a = doc("[1,2,3]");
+	assert_eq!(arr_pop(&mut a, 0), Some(DataType::bulkStr("1")));
+	assert_eq!(a, doc("[2,3]"));
+	let mut n = doc("1");
+	assert_eq!(num_incr(&mut n, &Json::Float(0.5)), Some(Json::Float(1.5)));
+	assert_eq!(get(&d, &[path("$.b"), path(".b")], &Format::default()),
+		Ok("{\"$.b\":[2],\".b\":[2]}".to_string()));
+	assert_eq!(delete(&mut d, &path("$..a")), 2);
+	assert_eq!(d, doc("{\"n\":{},\"b\":2}"));
+	assert_eq!(obj_keys(&d), Some(DataType::List(vec![
+		DataType::bulkStr("n"), DataType::bulkStr("b")
+	])));
+}
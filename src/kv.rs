@@ -1,31 +1,102 @@
 use rand::Rng;
-use std::collections::{HashMap, HashSet};
-use std::io::Write;
-use std::sync::Mutex;
+use rand::seq::IteratorRandom;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io::{BufReader, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
 
 use regex::Regex;
 
+use super::bitmap;
+use super::bloom::{self, Bloom};
+use super::bytes;
+use super::cms;
+use super::cuckoo::{self, Cuckoo};
 use super::datatype::DataType;
+use super::expiry;
+use super::function;
+use super::geo::{self, Hit, Origin, Search};
+use super::hll::{self, Hll};
+use super::json::{self, Json};
+use super::parser::parse;
+use super::pubsub;
+use super::search;
+use super::set::Set;
+use super::stream::{self, Stream, StreamId};
+use super::tdigest::{self, Digest};
+use super::timeseries::{self, Options, Policy, Series};
+use super::topk::{self, TopK};
+use super::tracking;
+use super::zset::{self, AddOptions, SortedSet};
 
 use lazy_static::lazy_static;
 
+const ERRMSG_BADEXPIRE: &str = "ERR invalid expire time in 'getex' command";
+const ERRMSG_BADPAYLOAD: &str =
+	"ERR DUMP payload version or checksum are wrong";
+const ERRMSG_BITOPNOT: &str =
+	"ERR BITOP NOT must be called with a single source key.";
+const ERRMSG_BUSYKEY: &str = "BUSYKEY Target key name already exists.";
+const ERRMSG_FIELDSARG: &str =
+	"ERR Mandatory argument FIELDS is missing or not at the right position";
+const ERRMSG_GEOADDARGS: &str = "ERR syntax error. Try GEOADD key [x1] [y1] \
+	[name1] [x2] [y2] [name2] ... ";
 const ERRMSG_CNTNAI: &str = "ERR Count is not an integer";
+const ERRMSG_CNTNEG: &str = "ERR COUNT can't be negative";
+const ERRMSG_CNTNPOS: &str = "ERR count should be greater than 0";
 const ERRMSG_IDXNAI: &str = "ERR Index is not an integer";
 const ERRMSG_IDXOOR: &str = "ERR Index out of range";
+const ERRMSG_HBADEXPIRE: &str = "ERR invalid expire time in 'hgetex' command";
+const ERRMSG_HVALNAF: &str = "ERR Hash value is not a float";
+const ERRMSG_HVALNAI: &str = "ERR Hash value is not an integer";
+const ERRMSG_IDXANDLEN: &str =
+	"ERR If you want both the length and indexes, please just use IDX.";
+const ERRMSG_ITEMEXISTS: &str = "ERR item exists";
+const ERRMSG_ITERNAI: &str = "ERR Iterator is not an integer";
+const ERRMSG_LCSTOOBIG: &str = "ERR Insufficient memory, transient memory \
+	for LCS exceeds proto-max-bulk-len";
+const ERRMSG_LIMITNEG: &str = "ERR LIMIT can't be negative";
+const ERRMSG_MAXLENNEG: &str = "ERR MAXLEN can't be negative";
+const ERRMSG_NANINF: &str = "ERR Increment would produce NaN or Infinity";
 const ERRMSG_NOENX2: &str = "ERR Number of elements is not multiple of 2";
 const ERRMSG_NOSKEY: &str = "ERR No such key";
+const ERRMSG_NOSTREAM: &str = "ERR The XGROUP subcommand requires the key \
+	to exist. Note that for CREATE you may want to use the MKSTREAM option to \
+	create an empty stream automatically.";
+const ERRMSG_NOTFOUND: &str = "ERR not found";
+const ERRMSG_NUMFIELDS: &str =
+	"ERR Parameter `numFields` should be greater than 0";
+const ERRMSG_NUMFIELDSARGS: &str =
+	"ERR The `numfields` parameter must match the number of arguments";
+const ERRMSG_NUMKEYS: &str = "ERR numkeys should be greater than 0";
+const ERRMSG_NUMKEYSARGS: &str =
+	"ERR Number of keys can't be greater than number of args";
 const ERRMSG_NUMNAI: &str = "ERR Number is not an integer";
 const ERRMSG_NUMNPI: &str = "ERR Number is not a positive integer";
+const ERRMSG_OFFOOR: &str = "ERR offset is out of range";
+const ERRMSG_OVERFLOW: &str = "ERR Increment or decrement would overflow";
+const ERRMSG_RANKZERO: &str = "ERR RANK can't be zero: use 1 to start from \
+	the first match, 2 from the second ... or use negative to start from the \
+	end of the list";
 const ERRMSG_STANAI: &str = "ERR Start index is not an integer";
 const ERRMSG_STONAI: &str = "ERR Stop index is not an integer";
+const ERRMSG_STRTOOBIG: &str =
+	"ERR string exceeds maximum allowed size (proto-max-bulk-len)";
 const ERRMSG_SYNERR: &str = "ERR Syntax error";
 const ERRMSG_VALNAI: &str = "ERR Value is not an integer";
 const ERRMSG_VALNAIOOR: &str =
 	"ERR Value is not an integer or out of range";
+const ERRMSG_VALNAF: &str = "ERR Value is not a valid float";
 const ERRMSG_WRITEFAIL: &str = "Write failure";
 const ERRMSG_WRONGTYPE: &str =
 	"WRONGTYPE Operation against a key holding the wrong kind of value";
 
+// strings, and what LCS may allocate, stay within 512MB
+const MAX_STRLEN: usize = 512 << 20;
+// the latest deadline of a hash field, in ms since the epoch
+const MAX_FIELD_DEADLINE: u64 = (1 << 48) - 1;
+
 lazy_static! {
 	static ref M: Mutex<HashMap<DataType, DataType>> =
 		Mutex::new(HashMap::new());
@@ -33,42 +104,301 @@ lazy_static! {
 
 pub fn append<'a>(k: &'a str, v: &'a str) -> Result<DataType, &'a str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	match m.get(&bstr_k) {
-		Some(DataType::BulkString(s)) => {
-			let a = s.to_string() + v;
+		Some(v0 @ (DataType::BulkString(_) | DataType::Integer(_))) => {
+			let a = plain(v0) + v;
 			m.insert(bstr_k.clone(), DataType::bulkStr(&a));
-			Ok(DataType::Integer(a.len().try_into().unwrap()))
+			modified('$', "append", k);
+			Ok(DataType::Integer(bytes::len(&a) as i64))
 		},
 		Some(_) => Err(ERRMSG_WRONGTYPE),
 		None => {
 			m.insert(bstr_k.clone(), DataType::bulkStr(v));
-			Ok(DataType::Integer(v.len().try_into().unwrap()))
+			modified('$', "append", k);
+			Ok(DataType::Integer(bytes::len(v) as i64))
 		}
 	}
 }
 
-pub fn decr(k: &str) -> Result<DataType, &str> {
+// BF.MADD, or BF.ADD replying for its one item unless multi
+pub fn bf_add<'a>(k: &'a str, els: &'a [String], multi: bool)
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	let bf = bloom_at(&mut m, k, true)?.unwrap();
+	let mut rs: Vec<DataType> = els.iter()
+		.map(|el| match bf.add(&bytes::encode(el)) {
+			Ok(added) => DataType::Integer(added as i64),
+			Err(e) => DataType::err(e)
+		})
+		.collect();
+	if rs.contains(&DataType::Integer(1)) {
+		modified('d', "bf.add", k);
+	}
+	match multi {
+		true => Ok(DataType::List(rs)),
+		false => Ok(rs.swap_remove(0))
+	}
+}
+
+pub fn bf_card(k: &str) -> Result<DataType, &str> {
+	let mut m = lock();
+	match bloom_at(&mut m, k, false)? {
+		Some(bf) => Ok(DataType::Integer(bf.items() as i64)),
+		None => Ok(DataType::Integer(0))
+	}
+}
+
+// BF.MEXISTS, or BF.EXISTS replying for its one item unless multi
+pub fn bf_exists<'a>(k: &'a str, els: &'a [String], multi: bool)
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	let bf = bloom_at(&mut m, k, false)?;
+	let mut rs: Vec<DataType> = els.iter()
+		.map(|el| bf.as_ref().is_some_and(|b| b.contains(&bytes::encode(el))))
+		.map(|found| DataType::Integer(found as i64))
+		.collect();
+	match multi {
+		true => Ok(DataType::List(rs)),
+		false => Ok(rs.swap_remove(0))
+	}
+}
+
+pub fn bf_info<'a>(k: &'a str, field: Option<&'a String>)
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	let bf = match bloom_at(&mut m, k, false)? {
+		Some(bf) => bf,
+		None => return Err(ERRMSG_NOTFOUND)
+	};
+	let expansion = match bf.expansion() {
+		Some(n) => DataType::Integer(n as i64),
+		None => DataType::Null
+	};
+	let info = [
+		("Capacity", DataType::Integer(bf.capacity() as i64)),
+		("Size", DataType::Integer(bf.size() as i64)),
+		("Number of filters", DataType::Integer(bf.filters() as i64)),
+		("Number of items inserted", DataType::Integer(bf.items() as i64)),
+		("Expansion rate", expansion)
+	];
+	let field = match field {
+		Some(f) => f.to_ascii_lowercase(),
+		None => return Ok(DataType::List(info.into_iter()
+			.flat_map(|(n, v)| [DataType::str(n), v])
+			.collect()))
+	};
+	let i = match field.as_str() {
+		"capacity" => 0,
+		"size" => 1,
+		"filters" => 2,
+		"items" => 3,
+		"expansion" => 4,
+		_ => return Err(ERRMSG_SYNERR)
+	};
+	Ok(DataType::List(vec![info[i].1.clone()]))
+}
+
+pub fn bf_reserve<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let bf = bloom::parse_reserve(prms)?;
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
-	match m.get(&bstr_k) {
-		Some(DataType::BulkString(s)) => match s.parse::<i64>() {
-			Ok(i) => {
-				let x: i64 = i - 1;
-				m.insert(
-					bstr_k.clone(),
-					DataType::BulkString(x.to_string())
-				);
-				Ok(DataType::Integer(x))
-			},
-			Err(_) => Err(ERRMSG_WRONGTYPE)
-		},
-		Some(_) => Err(ERRMSG_WRONGTYPE),
-		None => {
-			m.insert(bstr_k.clone(), DataType::bulkStr("-1"));
-			Ok(DataType::Integer(-1))
+	let mut m = lock();
+	if m.contains_key(&bstr_k) {
+		return Err(ERRMSG_ITEMEXISTS);
+	}
+	m.insert(bstr_k, DataType::Bloom(Box::new(bf)));
+	modified('d', "bf.reserve", k);
+	Ok(DataType::str("OK"))
+}
+
+pub fn bitcount<'a>(k: &'a str, range: &'a [String])
+	-> Result<DataType, &'a str> {
+	let m = lock();
+	let v = bytes_at(&m, k)?.unwrap_or_default();
+	let span = match range.is_empty() {
+		true => bitmap::whole(&v),
+		false => bitmap::bit_span(&v, range)?
+	};
+	Ok(DataType::Integer(bitmap::count(&v, span) as i64))
+}
+
+pub fn bitfield<'a>(k: &'a str, prms: &'a [String], read_only: bool)
+	-> Result<DataType, &'a str> {
+	let fields = bitmap::parse_fields(prms, read_only)?;
+	let mut m = lock();
+	let mut v = bytes_at(&m, k)?.unwrap_or_default();
+	let (replies, changed) = bitmap::apply(&mut v, &fields);
+	if changed {
+		m.insert(DataType::bulkStr(k), DataType::BulkString(bytes::decode(&v)));
+		modified('$', "setbit", k);
+	}
+	Ok(DataType::List(replies))
+}
+
+// BITOP AND | OR | XOR | NOT, storing the result at dst
+pub fn bitop<'a>(op: &'a str, dst: &'a str, ks: &'a [String])
+	-> Result<DataType, &'a str> {
+	let op = op.to_ascii_lowercase();
+	match op.as_str() {
+		"and" | "or" | "xor" => {},
+		"not" if 1 == ks.len() => {},
+		"not" => return Err(ERRMSG_BITOPNOT),
+		_ => return Err(ERRMSG_SYNERR)
+	}
+	let mut m = lock();
+	let mut srcs: Vec<Vec<u8>> = Vec::with_capacity(ks.len());
+	for k in ks.iter() {
+		srcs.push(bytes_at(&m, k)?.unwrap_or_default());
+	}
+	let v = bitmap::op(&op, &srcs);
+	let bstr_dst: DataType = DataType::bulkStr(dst);
+	if v.is_empty() {
+		if m.remove(&bstr_dst).is_some() {
+			modified('g', "del", dst);
 		}
+	} else {
+		m.insert(bstr_dst, DataType::BulkString(bytes::decode(&v)));
+		modified('$', "set", dst);
+	}
+	Ok(DataType::Integer(v.len() as i64))
+}
+
+pub fn bitpos<'a>(k: &'a str, bit: &'a str, range: &'a [String])
+	-> Result<DataType, &'a str> {
+	let bit: u8 = match bit {
+		"0" => 0,
+		"1" => 1,
+		_ => return Err(bitmap::ERRMSG_BADPOSBIT)
+	};
+	let m = lock();
+	let v = match bytes_at(&m, k)? {
+		Some(v) => v,
+		None => return Ok(DataType::Integer(if 1 == bit {-1} else {0}))
+	};
+	let span = match range.is_empty() {
+		true => bitmap::whole(&v),
+		false => bitmap::bit_span(&v, range)?
+	};
+	Ok(DataType::Integer(bitmap::pos(&v, bit, span, 1 < range.len())))
+}
+
+pub fn cf_add<'a>(k: &'a str, el: &'a str, nx: bool)
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	let cf = cuckoo_at(&mut m, k, true)?.unwrap();
+	let el = bytes::encode(el);
+	if nx && cf.contains(&el) {
+		return Ok(DataType::Integer(0));
+	}
+	cf.add(&el)?;
+	modified('d', "cf.add", k);
+	Ok(DataType::Integer(1))
+}
+
+pub fn cf_count<'a>(k: &'a str, el: &'a str) -> Result<DataType, &'a str> {
+	let mut m = lock();
+	match cuckoo_at(&mut m, k, false)? {
+		Some(cf) => Ok(DataType::Integer(cf.count(&bytes::encode(el)) as i64)),
+		None => Ok(DataType::Integer(0))
+	}
+}
+
+pub fn cf_del<'a>(k: &'a str, el: &'a str) -> Result<DataType, &'a str> {
+	let mut m = lock();
+	let cf = match cuckoo_at(&mut m, k, false)? {
+		Some(cf) => cf,
+		None => return Err(ERRMSG_NOTFOUND)
+	};
+	let deleted = cf.del(&bytes::encode(el));
+	if deleted {
+		modified('d', "cf.del", k);
+	}
+	Ok(DataType::Integer(deleted as i64))
+}
+
+pub fn cf_exists<'a>(k: &'a str, el: &'a str) -> Result<DataType, &'a str> {
+	let mut m = lock();
+	let found = cuckoo_at(&mut m, k, false)?
+		.is_some_and(|cf| cf.contains(&bytes::encode(el)));
+	Ok(DataType::Integer(found as i64))
+}
+
+pub fn cf_reserve<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let cf = cuckoo::parse_reserve(prms)?;
+	let bstr_k: DataType = DataType::bulkStr(k);
+	let mut m = lock();
+	if m.contains_key(&bstr_k) {
+		return Err(ERRMSG_ITEMEXISTS);
+	}
+	m.insert(bstr_k, DataType::Cuckoo(Box::new(cf)));
+	modified('d', "cf.reserve", k);
+	Ok(DataType::str("OK"))
+}
+
+pub fn cms_incrby<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let incrs = cms::parse_incrs(prms)?;
+	let mut m = lock();
+	let s = cms_at(&mut m, k)?.ok_or(cms::ERRMSG_NOKEY)?;
+	let rs: Vec<DataType> = incrs.iter()
+		.map(|(item, n)| match s.incr(item, *n) {
+			Ok(c) => DataType::Integer(c as i64),
+			Err(e) => DataType::err(e)
+		})
+		.collect();
+	modified('d', "cms.incrby", k);
+	Ok(DataType::List(rs))
+}
+
+// CMS.INITBYPROB, or CMS.INITBYDIM unless by_prob
+pub fn cms_init<'a>(k: &'a str, prms: &'a [String], by_prob: bool)
+	-> Result<DataType, &'a str> {
+	let s = match by_prob {
+		true => cms::parse_prob(prms)?,
+		false => cms::parse_dims(prms)?
+	};
+	let bstr_k: DataType = DataType::bulkStr(k);
+	let mut m = lock();
+	if m.contains_key(&bstr_k) {
+		return Err(cms::ERRMSG_EXISTS);
 	}
+	m.insert(bstr_k, DataType::CountMin(Box::new(s)));
+	modified('d', "cms.init", k);
+	Ok(DataType::str("OK"))
+}
+
+pub fn cms_merge<'a>(dst: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let srcs = cms::parse_merge(prms)?;
+	let mut m = lock();
+	let mut sketches = Vec::with_capacity(srcs.len());
+	for (k, w) in srcs.iter() {
+		let s = cms_at(&mut m, k)?.ok_or(cms::ERRMSG_NOKEY)?;
+		sketches.push((s.clone(), *w));
+	}
+	let s = cms_at(&mut m, dst)?.ok_or(cms::ERRMSG_NOKEY)?;
+	s.merge(&sketches.iter().map(|(s, w)| (s, *w)).collect::<Vec<_>>())?;
+	modified('d', "cms.merge", dst);
+	Ok(DataType::str("OK"))
+}
+
+pub fn cms_query<'a>(k: &'a str, items: &'a [String])
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	let s = cms_at(&mut m, k)?.ok_or(cms::ERRMSG_NOKEY)?;
+	Ok(DataType::List(items.iter()
+		.map(|item| DataType::Integer(s.query(&bytes::encode(item)) as i64))
+		.collect()))
+}
+
+pub fn contains(k: &str) -> bool {
+	lock().contains_key(&DataType::bulkStr(k))
+}
+
+pub fn decr(k: &str) -> Result<DataType, &str> {
+	incr_int(k, -1)
 }
 
 pub fn decrby<'a>(k: &'a str, v: &'a str) -> Result<DataType, &'a str> {
@@ -76,91 +406,308 @@ pub fn decrby<'a>(k: &'a str, v: &'a str) -> Result<DataType, &'a str> {
 		Ok(someint) => someint,
 		Err(_) => return Err(ERRMSG_VALNAI)
 	};
-	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
-	match m.get(&bstr_k) {
-		Some(DataType::BulkString(s)) => match s.parse::<i64>() {
-			Ok(i) => {
-				let x: i64 = i - n;
-				m.insert(
-					bstr_k.clone(),
-					DataType::BulkString(x.to_string())
-				);
-				Ok(DataType::Integer(x))
-			},
-			Err(_) => Err(ERRMSG_WRONGTYPE)
-		},
-		Some(_) => Err(ERRMSG_WRONGTYPE),
-		None => {
-			let x: i64 = 0 - n;
-			m.insert(bstr_k.clone(), DataType::BulkString(x.to_string()));
-			Ok(DataType::Integer(x))
-		}
-	}
+	incr_int(k, n.checked_neg().ok_or(ERRMSG_OVERFLOW)?)
 }
 
 pub fn del(ks: &Vec<String>) -> Result<DataType, &str> {
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	let cnt: i64 = ks.iter().map(|k| {
 		match m.remove(&DataType::bulkStr(k)) {
-			Some(_) => 1i64,
+			Some(_) => {
+				modified('g', "del", k);
+				1i64
+			},
 			None => 0i64
 		}
 	}).sum::<i64>();
 	Ok(DataType::Integer(cnt))
 }
 
+// serialized form of the value at k, as consumed by restore
+pub fn dump(k: &str) -> Result<DataType, &str> {
+	let bstr_k: DataType = DataType::bulkStr(k);
+	let m = lock();
+	match m.get(&bstr_k).and_then(|v| rebuild(&bstr_k, v)) {
+		Some(l) => Ok(DataType::BulkString(l)),
+		None => Ok(DataType::Null)
+	}
+}
+
+pub fn flushall() {
+	lock().clear();
+	expiry::clear_all();
+	search::flushed();
+	tracking::invalidate_all();
+}
+
+pub fn ft_create<'a>(name: &'a str, args: &'a [String])
+	-> Result<DataType, &'a str> {
+	let m = lock();
+	search::create(name, args, m.keys().filter_map(|k| match k {
+		DataType::BulkString(k) => Some(k.as_str()),
+		_ => None
+	}))
+}
+
+// drop the index, along with the hashes it covered when dd is set
+pub fn ft_dropindex(name: &str, dd: bool) -> Result<DataType, &str> {
+	let mut m = lock();
+	let ks = search::drop(&m, name)?;
+	if dd {
+		for k in ks.iter() {
+			if m.remove(&DataType::bulkStr(k)).is_some() {
+				modified('g', "del", k);
+			}
+		}
+	}
+	Ok(DataType::str("OK"))
+}
+
+pub fn ft_info(name: &str) -> Result<DataType, &str> {
+	search::info(&lock(), name)
+}
+
+pub fn ft_search<'a>(name: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	search::search(&lock(), name, prms)
+}
+
+pub fn geoadd<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let (opts, i) = zset::parse_options(prms)?;
+	let rest = &prms[i..];
+	if rest.is_empty() || !rest.len().is_multiple_of(3) {
+		return Err(ERRMSG_GEOADDARGS);
+	}
+	let mut pairs = Vec::with_capacity(rest.len() / 3);
+	for t in rest.chunks(3) {
+		let (lon, lat) = (geo::parse_coord(&t[0])?, geo::parse_coord(&t[1])?);
+		if !geo::valid(lon, lat) {
+			return Ok(DataType::err(&geo::bad_pair(lon, lat)));
+		}
+		pairs.push((geo::encode(lon, lat) as f64, t[2].clone()));
+	}
+	sorted_add(k, &opts, &pairs)
+}
+
+pub fn geodist<'a>(k: &'a str, m1: &'a str, m2: &'a str,
+	unit: Option<&'a String>) -> Result<DataType, &'a str> {
+	let unit = match unit {
+		Some(u) => geo::parse_unit(u)?,
+		None => 1.0
+	};
+	let mut m = lock();
+	let z = match zset_at(&mut m, k, false)? {
+		Some(z) => z,
+		None => return Ok(DataType::Null)
+	};
+	match (z.score(m1), z.score(m2)) {
+		(Some(s1), Some(s2)) => {
+			let (x1, y1) = geo::decode(s1 as u64);
+			let (x2, y2) = geo::decode(s2 as u64);
+			let d = geo::distance(x1, y1, x2, y2);
+			Ok(DataType::bulkStr(&geo::format_dist(d, unit)))
+		},
+		_ => Ok(DataType::Null)
+	}
+}
+
+pub fn geohash<'a>(k: &'a str, ms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	let z = zset_at(&mut m, k, false)?;
+	Ok(DataType::List(ms.iter()
+		.map(|e| match z.as_ref().and_then(|z| z.score(e)) {
+			Some(s) => DataType::bulkStr(&geo::hash_string(s as u64)),
+			None => DataType::Null
+		})
+		.collect()))
+}
+
+pub fn geopos<'a>(k: &'a str, ms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	let z = zset_at(&mut m, k, false)?;
+	Ok(DataType::List(ms.iter()
+		.map(|e| match z.as_ref().and_then(|z| z.score(e)) {
+			Some(s) => geo::coord(s),
+			None => DataType::Null
+		})
+		.collect()))
+}
+
+pub fn geosearch<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let s = geo::parse_search(prms, false)?;
+	if let Origin::LonLat(lon, lat) = s.origin {
+		if !geo::valid(lon, lat) {
+			return Ok(DataType::err(&geo::bad_pair(lon, lat)));
+		}
+	}
+	let mut m = lock();
+	let hits = geo_hits(&mut m, k, &s)?;
+	Ok(geo::reply(&hits, &s))
+}
+
+pub fn geosearchstore<'a>(dst: &'a str, k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let s = geo::parse_search(prms, true)?;
+	if let Origin::LonLat(lon, lat) = s.origin {
+		if !geo::valid(lon, lat) {
+			return Ok(DataType::err(&geo::bad_pair(lon, lat)));
+		}
+	}
+	let mut m = lock();
+	let hits = geo_hits(&mut m, k, &s)?;
+	let bstr_dst: DataType = DataType::bulkStr(dst);
+	if hits.is_empty() {
+		if m.remove(&bstr_dst).is_some() {
+			modified('g', "del", dst);
+		}
+		return Ok(DataType::Integer(0));
+	}
+	let mut z = SortedSet::default();
+	for h in hits.iter() {
+		z.insert(&h.member, match s.storedist {
+			true => h.dist / s.unit,
+			false => h.score
+		});
+	}
+	m.insert(bstr_dst, DataType::SortedSet(Box::new(z)));
+	modified('z', "geosearchstore", dst);
+	Ok(DataType::Integer(hits.len() as i64))
+}
+
 pub fn get(k: &str) -> Result<DataType, &str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let m = M.lock().unwrap();
+	let m = lock();
 	let data = m.get(&bstr_k);
 	match data {
-		Some(DataType::BulkString(_)) => Ok(data.unwrap().clone()),
+		Some(v @ (DataType::BulkString(_) | DataType::Integer(_))) =>
+			Ok(DataType::BulkString(plain(v))),
 		Some(_) => Err(ERRMSG_WRONGTYPE),
 		None => Ok(DataType::Null)
 	}
 }
 
+pub fn getbit<'a>(k: &'a str, off: &'a str) -> Result<DataType, &'a str> {
+	let off = bitmap::parse_offset(off)?;
+	let m = lock();
+	let v = bytes_at(&m, k)?.unwrap_or_default();
+	Ok(DataType::Integer(bitmap::getbit(&v, off) as i64))
+}
+
 pub fn getdel(k: &str) -> Result<DataType, &str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	let data = m.get(&bstr_k);
 	let output = match data {
-		Some(DataType::BulkString(_)) => Ok(data.unwrap().clone()),
+		Some(v @ (DataType::BulkString(_) | DataType::Integer(_))) =>
+			Ok(DataType::BulkString(plain(v))),
 		Some(_) => Err(ERRMSG_WRONGTYPE),
 		None => Ok(DataType::Null)
 	};
 	if let Ok(DataType::BulkString(_)) = output {
 		let _ = m.remove(&bstr_k);
+		modified('g', "del", k);
 	};
 	output
 }
 
+// GET, then EX, PX, EXAT or PXAT set a deadline for k, PERSIST drops it
+pub fn getex<'a>(k: &'a str, opts: &'a [String]) -> Result<DataType, &'a str> {
+	let now = stream::now_ms();
+	let at = match opts {
+		[] => None,
+		[o] if o.eq_ignore_ascii_case("persist") => Some(None),
+		[o, n] => {
+			let n = match n.parse::<u64>() {
+				Ok(n) if 0 < n => n,
+				_ => return Err(ERRMSG_BADEXPIRE)
+			};
+			let at = deadline(&o.to_ascii_lowercase(), n, now)?;
+			Some(Some(at.ok_or(ERRMSG_BADEXPIRE)?))
+		},
+		_ => return Err(ERRMSG_SYNERR)
+	};
+	let bstr_k: DataType = DataType::bulkStr(k);
+	let mut m = lock();
+	let v = match m.get(&bstr_k) {
+		Some(v @ (DataType::BulkString(_) | DataType::Integer(_))) =>
+			DataType::BulkString(plain(v)),
+		Some(_) => return Err(ERRMSG_WRONGTYPE),
+		None => return Ok(DataType::Null)
+	};
+	match at {
+		Some(Some(at)) if at <= now => {
+			m.remove(&bstr_k);
+			modified('g', "del", k);
+		},
+		Some(Some(at)) => {
+			expiry::set(k, at);
+			modified('g', "expire", k);
+		},
+		Some(None) if expiry::clear(k) => modified('g', "persist", k),
+		_ => {}
+	}
+	Ok(v)
+}
+
+// the bytes from start to end, both included and counted from the end
+// when negative
+pub fn getrange<'a>(k: &'a str, start: &'a str, end: &'a str)
+	-> Result<DataType, &'a str> {
+	let (start, end) = match (start.parse::<i64>(), end.parse::<i64>()) {
+		(Ok(start), Ok(end)) => (start, end),
+		_ => return Err(ERRMSG_VALNAIOOR)
+	};
+	let v = bytes_at(&lock(), k)?.unwrap_or_default();
+	let n = v.len() as i64;
+	if 0 == n || (start < 0 && end < 0 && start > end) {
+		return Ok(DataType::bulkStr(""));
+	}
+	let start = if start < 0 {(n + start).max(0)} else {start};
+	let end = if end < 0 {(n + end).max(0)} else {end.min(n - 1)};
+	if start > end {
+		return Ok(DataType::bulkStr(""));
+	}
+	Ok(DataType::BulkString(bytes::decode(&v[start as usize..=end as usize])))
+}
+
 pub fn getset<'a>(k: &'a str, v: &'a str) -> Result<DataType, &'a str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	let data = m.get_mut(&bstr_k);
 	let output = match data {
-		Some(DataType::BulkString(_)) => Ok(data.unwrap().clone()),
+		Some(v @ (DataType::BulkString(_) | DataType::Integer(_))) =>
+			Ok(DataType::BulkString(plain(v))),
 		Some(_) => Err(ERRMSG_WRONGTYPE),
 		None => Ok(DataType::Null)
 	};
 	m.insert(bstr_k.clone(), DataType::bulkStr(v));
+	modified('$', "set", k);
 	output
 }
 
 pub fn hdel(k: &str, fs: Vec<String>) -> Result<DataType, &str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	match m.get_mut(&bstr_k) {
 		Some(DataType::HashMap(hmap)) => {
 			let cnt = fs.iter().map(|f| {
 				match hmap.remove(&DataType::bulkStr(&f)) {
-					Some(_) => 1i64,
+					Some(_) => {
+						expiry::clear_field(k, f);
+						1i64
+					},
 					None => 0i64
 				}
 			}).sum::<i64>();
-			if 0 == hmap.len() {m.remove(&bstr_k);}
+			if 0 < cnt {modified('h', "hdel", k);}
+			if 0 == hmap.len() {
+				m.remove(&bstr_k);
+				modified('g', "del", k);
+			}
 			Ok(DataType::Integer(cnt))
 		},
 		Some(_) => Err(ERRMSG_WRONGTYPE),
@@ -170,7 +717,7 @@ pub fn hdel(k: &str, fs: Vec<String>) -> Result<DataType, &str> {
 
 pub fn hexists<'a>(k: &'a str, f: &'a str) -> Result<DataType, &'a str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
+	match lock().get(&bstr_k) {
 		Some(DataType::HashMap(hmap)) => Ok(DataType::Integer(
 			if hmap.contains_key(&DataType::bulkStr(f)) {
 				1i64
@@ -183,9 +730,84 @@ pub fn hexists<'a>(k: &'a str, f: &'a str) -> Result<DataType, &'a str> {
 	}
 }
 
+// HEXPIRE, HPEXPIRE, HEXPIREAT and HPEXPIREAT, as cmd: set a deadline
+// for fields of the hash, per field -2 without it, 0 when the NX, XX, GT
+// or LT condition fails, 1 when set, 2 when deleted as already due
+pub fn hexpire<'a>(cmd: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let k = &prms[0];
+	let n = prms[1].parse::<i64>().map_err(|_| ERRMSG_VALNAIOOR)?;
+	let cond = prms[2].to_ascii_lowercase();
+	let (cond, fs) = match cond.as_str() {
+		"nx" | "xx" | "gt" | "lt" => (cond.as_str(), fields_arg(&prms[3..])?),
+		_ => ("", fields_arg(&prms[2..])?)
+	};
+	let o = match cmd {
+		"hexpire" => "ex",
+		"hpexpire" => "px",
+		"hexpireat" => "exat",
+		_ => "pxat"
+	};
+	let now = stream::now_ms();
+	let at = match u64::try_from(n) {
+		Ok(n) => deadline(o, n, now)?.filter(|at| *at <= MAX_FIELD_DEADLINE),
+		Err(_) => None
+	};
+	let at = match at {
+		Some(at) => at,
+		None => return Ok(DataType::err(
+			&format!("ERR invalid expire time in '{}' command", cmd)
+		))
+	};
+	let bstr_k: DataType = DataType::bulkStr(k);
+	let mut m = lock();
+	let h = match m.get_mut(&bstr_k) {
+		Some(DataType::HashMap(h)) => h,
+		Some(_) => return Err(ERRMSG_WRONGTYPE),
+		None => return Ok(DataType::List(
+			fs.iter().map(|_| DataType::Integer(-2)).collect()
+		))
+	};
+	let (mut set, mut gone) = (false, false);
+	let rs = fs.iter().map(|f| {
+		let bstr_f: DataType = DataType::bulkStr(f);
+		if !h.contains_key(&bstr_f) {
+			return DataType::Integer(-2);
+		}
+		let cur = expiry::field(k, f);
+		let ok = match cond {
+			"nx" => cur.is_none(),
+			"xx" => cur.is_some(),
+			"gt" => cur.is_some_and(|c| c < at),
+			"lt" => cur.is_none_or(|c| at < c),
+			_ => true
+		};
+		DataType::Integer(if !ok {
+			0
+		} else if at <= now {
+			h.remove(&bstr_f);
+			expiry::clear_field(k, f);
+			gone = true;
+			2
+		} else {
+			expiry::set_field(k, f, at);
+			set = true;
+			1
+		})
+	}).collect();
+	let empty = h.is_empty();
+	if set {modified('h', "hexpire", k);}
+	if gone {modified('h', "hdel", k);}
+	if empty {
+		m.remove(&bstr_k);
+		modified('g', "del", k);
+	}
+	Ok(DataType::List(rs))
+}
+
 pub fn hget<'a>(k: &'a str, f: &'a str) -> Result<DataType, &'a str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
+	match lock().get(&bstr_k) {
 		Some(DataType::HashMap(h)) => match h.get(&DataType::bulkStr(f)) {
 			Some(v) => Ok(v.clone()),
 			None => Ok(DataType::Null)
@@ -197,7 +819,7 @@ pub fn hget<'a>(k: &'a str, f: &'a str) -> Result<DataType, &'a str> {
 
 pub fn hgetall(k: &str) -> Result<DataType, &str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let m = M.lock().unwrap();
+	let m = lock();
 	let data = m.get(&bstr_k);
 	match data {
 		Some(DataType::HashMap(_)) => Ok(data.unwrap().clone()),
@@ -206,6 +828,94 @@ pub fn hgetall(k: &str) -> Result<DataType, &str> {
 	}
 }
 
+// the values of fields of the hash, which are deleted
+pub fn hgetdel(prms: &[String]) -> Result<DataType, &str> {
+	let k = &prms[0];
+	let fs = fields_arg(&prms[1..])?;
+	let bstr_k: DataType = DataType::bulkStr(k);
+	let mut m = lock();
+	let h = match m.get_mut(&bstr_k) {
+		Some(DataType::HashMap(h)) => h,
+		Some(_) => return Err(ERRMSG_WRONGTYPE),
+		None => return Ok(DataType::List(
+			fs.iter().map(|_| DataType::Null).collect()
+		))
+	};
+	let vs: Vec<DataType> = fs.iter().map(|f| {
+		expiry::clear_field(k, f);
+		h.remove(&DataType::bulkStr(f)).unwrap_or(DataType::Null)
+	}).collect();
+	let empty = h.is_empty();
+	if vs.iter().any(|v| DataType::Null != *v) {
+		modified('h', "hdel", k);
+	}
+	if empty {
+		m.remove(&bstr_k);
+		modified('g', "del", k);
+	}
+	Ok(DataType::List(vs))
+}
+
+// the values of fields of the hash, then EX, PX, EXAT or PXAT set a
+// deadline for them, PERSIST drops it
+pub fn hgetex(prms: &[String]) -> Result<DataType, &str> {
+	let k = &prms[0];
+	let now = stream::now_ms();
+	let o = prms[1].to_ascii_lowercase();
+	let (at, fs) = match o.as_str() {
+		"persist" => (Some(None), fields_arg(&prms[2..])?),
+		"ex" | "px" | "exat" | "pxat" if 2 < prms.len() => {
+			let at = match prms[2].parse::<u64>() {
+				Ok(n) if 0 < n => deadline(&o, n, now)?
+					.filter(|at| *at <= MAX_FIELD_DEADLINE),
+				_ => None
+			};
+			(Some(Some(at.ok_or(ERRMSG_HBADEXPIRE)?)), fields_arg(&prms[3..])?)
+		},
+		_ => (None, fields_arg(&prms[1..])?)
+	};
+	let bstr_k: DataType = DataType::bulkStr(k);
+	let mut m = lock();
+	let h = match m.get_mut(&bstr_k) {
+		Some(DataType::HashMap(h)) => h,
+		Some(_) => return Err(ERRMSG_WRONGTYPE),
+		None => return Ok(DataType::List(
+			fs.iter().map(|_| DataType::Null).collect()
+		))
+	};
+	let mut event = None;
+	let vs = fs.iter().map(|f| {
+		let bstr_f: DataType = DataType::bulkStr(f);
+		let v = h.get(&bstr_f).cloned().unwrap_or(DataType::Null);
+		if DataType::Null == v {
+			return v;
+		}
+		match at {
+			Some(Some(at)) if at <= now => {
+				h.remove(&bstr_f);
+				expiry::clear_field(k, f);
+				event = Some("hdel");
+			},
+			Some(Some(at)) => {
+				expiry::set_field(k, f, at);
+				event = Some("hexpire");
+			},
+			Some(None) if expiry::clear_field(k, f) => event = Some("hpersist"),
+			_ => {}
+		}
+		v
+	}).collect();
+	let empty = h.is_empty();
+	if let Some(event) = event {
+		modified('h', event, k);
+	}
+	if empty {
+		m.remove(&bstr_k);
+		modified('g', "del", k);
+	}
+	Ok(DataType::List(vs))
+}
+
 pub fn hincrby<'a>(k: &'a str, f: &'a str, n: &'a str)
 	-> Result<DataType, &'a str> {
 	let someint: i64 = match n.parse::<i64>() {
@@ -213,21 +923,23 @@ pub fn hincrby<'a>(k: &'a str, f: &'a str, n: &'a str)
 		Err(_) => return Err(ERRMSG_VALNAI)
 	};
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	match m.get_mut(&bstr_k) {
 		Some(DataType::HashMap(hmap)) => {
 			match hmap.get(&DataType::bulkStr(f)) {
 				Some(DataType::BulkString(somestr)) => {
 					match somestr.parse::<i64>() {
 						Ok(i) => {
-							let x: i64 = i + someint;
+							let x: i64 = i.checked_add(someint)
+								.ok_or(ERRMSG_OVERFLOW)?;
 							hmap.insert(
 								DataType::bulkStr(f),
 								DataType::BulkString(x.to_string())
 							);
+							modified('h', "hincrby", k);
 							Ok(DataType::Integer(x))
 						},
-						Err(_) => Err(ERRMSG_VALNAI)
+						Err(_) => Err(ERRMSG_HVALNAI)
 					}
 				},
 				None => {
@@ -235,6 +947,7 @@ pub fn hincrby<'a>(k: &'a str, f: &'a str, n: &'a str)
 						DataType::bulkStr(f),
 						DataType::BulkString(someint.to_string())
 					);
+					modified('h', "hincrby", k);
 					Ok(DataType::Integer(someint))
 				},
 				Some(_) => todo!() // this should never happen since we
@@ -250,14 +963,34 @@ pub fn hincrby<'a>(k: &'a str, f: &'a str, n: &'a str)
 				DataType::BulkString(someint.to_string())
 			);
 			m.insert(bstr_k.clone(), DataType::hmap(&somehmap));
+			modified('h', "hincrby", k);
 			Ok(DataType::Integer(someint))
 		}
 	}
 }
 
+pub fn hincrbyfloat<'a>(k: &'a str, f: &'a str, n: &'a str)
+	-> Result<DataType, &'a str> {
+	let n = parse_float(n).ok_or(ERRMSG_VALNAF)?;
+	let mut m = lock();
+	let h = match m.entry(DataType::bulkStr(k))
+		.or_insert_with(|| DataType::hmap(&HashMap::new())) {
+		DataType::HashMap(h) => h,
+		_ => return Err(ERRMSG_WRONGTYPE)
+	};
+	let x = match h.get(&DataType::bulkStr(f)) {
+		Some(DataType::BulkString(s)) => parse_float(s).ok_or(ERRMSG_HVALNAF)?,
+		_ => 0.0
+	};
+	let x = float_str(x + n).ok_or(ERRMSG_NANINF)?;
+	h.insert(DataType::bulkStr(f), DataType::bulkStr(&x));
+	modified('h', "hincrbyfloat", k);
+	Ok(DataType::BulkString(x))
+}
+
 pub fn hkeys(k: &str) -> Result<DataType, &str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
+	match lock().get(&bstr_k) {
 		Some(DataType::HashMap(hmap)) => Ok(DataType::List(
 			hmap.keys().cloned().collect::<Vec<_>>()
 		)),
@@ -268,7 +1001,7 @@ pub fn hkeys(k: &str) -> Result<DataType, &str> {
 
 pub fn hlen(k: &str) -> Result<DataType, &str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
+	match lock().get(&bstr_k) {
 		Some(DataType::HashMap(hmap)) =>
 			Ok(DataType::Integer(hmap.len().try_into().unwrap())),
 		Some(_) => Err(ERRMSG_WRONGTYPE),
@@ -278,7 +1011,7 @@ pub fn hlen(k: &str) -> Result<DataType, &str> {
 
 pub fn hmget(k: &str, fs: Vec<String>) -> Result<DataType, &str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
+	match lock().get(&bstr_k) {
 		Some(DataType::HashMap(hmap)) => Ok(DataType::List(
 			fs.iter().map(|f| {
 				match hmap.get(&DataType::bulkStr(&f)) {
@@ -294,13 +1027,79 @@ pub fn hmget(k: &str, fs: Vec<String>) -> Result<DataType, &str> {
 	}
 }
 
+// drop the deadline of fields of the hash, per field -2 without it, -1
+// when it has none, 1 when dropped
+pub fn hpersist(prms: &[String]) -> Result<DataType, &str> {
+	let k = &prms[0];
+	let fs = fields_arg(&prms[1..])?;
+	let m = lock();
+	let h = match m.get(&DataType::bulkStr(k)) {
+		Some(DataType::HashMap(h)) => h,
+		Some(_) => return Err(ERRMSG_WRONGTYPE),
+		None => return Ok(DataType::List(
+			fs.iter().map(|_| DataType::Integer(-2)).collect()
+		))
+	};
+	let rs: Vec<DataType> = fs.iter().map(|f| DataType::Integer(
+		match h.contains_key(&DataType::bulkStr(f)) {
+			false => -2,
+			true if expiry::clear_field(k, f) => 1,
+			true => -1
+		}
+	)).collect();
+	if rs.contains(&DataType::Integer(1)) {
+		modified('h', "hpersist", k);
+	}
+	Ok(DataType::List(rs))
+}
+
+// a random field of the hash, or count distinct ones, or -count ones that
+// may repeat, with their values if withvalues
+pub fn hrandfield<'a>(k: &'a str, opts: &'a [String])
+	-> Result<DataType, &'a str> {
+	let count = match opts.first() {
+		Some(c) => Some(c.parse::<i64>().map_err(|_| ERRMSG_VALNAIOOR)?),
+		None => None
+	};
+	let withvalues = match &opts[opts.len().min(1)..] {
+		[] => false,
+		[w] if w.eq_ignore_ascii_case("withvalues") => true,
+		_ => return Err(ERRMSG_SYNERR)
+	};
+	let m = lock();
+	let h = match m.get(&DataType::bulkStr(k)) {
+		Some(DataType::HashMap(h)) => h,
+		Some(_) => return Err(ERRMSG_WRONGTYPE),
+		None if count.is_some() => return Ok(DataType::List(vec![])),
+		None => return Ok(DataType::Null)
+	};
+	let mut rng = rand::rng();
+	let picked: Vec<(&DataType, &DataType)> = match count {
+		None => return Ok(h.keys().choose(&mut rng).cloned()
+			.unwrap_or(DataType::Null)),
+		Some(c) if 0 <= c => h.iter().choose_multiple(&mut rng, c as usize),
+		Some(c) => {
+			let all: Vec<_> = h.iter().collect();
+			(0..c.unsigned_abs())
+				.map(|_| all[rng.random_range(0..all.len())])
+				.collect()
+		}
+	};
+	Ok(DataType::List(picked.into_iter()
+		.flat_map(|(f, v)| match withvalues {
+			true => vec![f.clone(), v.clone()],
+			false => vec![f.clone()]
+		})
+		.collect()))
+}
+
 pub fn hset<'a>(k: &'a str, nvs: Vec<String>, nx: &'a bool)
 	-> Result<DataType, &'a str> {
 	if 0 != nvs.len() % 2 {
 		return Err(ERRMSG_NOENX2);
 	}
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	match m.get_mut(&bstr_k) {
 		Some(DataType::HashMap(hmap)) => {
 			let mut cnt: i64 = 0;
@@ -324,10 +1123,12 @@ pub fn hset<'a>(k: &'a str, nvs: Vec<String>, nx: &'a bool)
 							DataType::bulkStr(&x[0]),
 							DataType::bulkStr(&x[1])
 						);
+						expiry::clear_field(k, &x[0]);
 						cnt += 1;
 					});
 				}
 			}
+			if 0 < cnt {modified('h', "hset", k);}
 			Ok(DataType::Integer(cnt))
 		},
 		Some(_) => Err(ERRMSG_WRONGTYPE),
@@ -339,79 +1140,316 @@ pub fn hset<'a>(k: &'a str, nvs: Vec<String>, nx: &'a bool)
 			);});
 			let hmap2save = DataType::hmap(&somehmap);
 			m.insert(bstr_k.clone(), hmap2save);
+			modified('h', "hset", k);
 			Ok(DataType::Integer(somehmap.len().try_into().unwrap()))
 		}
 	}
 }
 
-pub fn hvals(k: &str) -> Result<DataType, &str> {
-	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
-		Some(DataType::HashMap(hmap)) => Ok(DataType::List(
-			hmap.values().cloned().collect::<Vec<_>>()
-		)),
-		Some(_) => Err(ERRMSG_WRONGTYPE),
-		None => Ok(DataType::List(vec![]))
-	}
-}
-
-pub fn incr(k: &str) -> Result<DataType, &str> {
-	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
-	match m.get_mut(&bstr_k) {
-		Some(DataType::BulkString(s)) => {
-			match s.parse::<i64>() {
-				Ok(i) => {
-					let x: i64 = i + 1;
-					m.insert(
-						bstr_k.clone(),
-						DataType::BulkString(x.to_string())
-					);
-					Ok(DataType::Integer(x))
-				},
-				Err(_) => Err(ERRMSG_VALNAIOOR)
-			}
+pub fn hstrlen<'a>(k: &'a str, f: &'a str) -> Result<DataType, &'a str> {
+	let n = match lock().get(&DataType::bulkStr(k)) {
+		Some(DataType::HashMap(h)) => match h.get(&DataType::bulkStr(f)) {
+			Some(DataType::BulkString(v)) => bytes::len(v),
+			_ => 0
 		},
-		Some(_) => Err(ERRMSG_WRONGTYPE),
-		None => {
-			m.insert(bstr_k.clone(), DataType::bulkStr("1"));
-			Ok(DataType::Integer(1))
+		Some(_) => return Err(ERRMSG_WRONGTYPE),
+		None => 0
+	};
+	Ok(DataType::Integer(n as i64))
+}
+
+// HTTL, HPTTL, HEXPIRETIME and HPEXPIRETIME, as cmd: the time left before
+// fields of the hash expire, or their deadline, in seconds or in ms, per
+// field -2 without it, -1 when it does not expire
+pub fn httl<'a>(cmd: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let k = &prms[0];
+	let fs = fields_arg(&prms[1..])?;
+	let m = lock();
+	let h = match m.get(&DataType::bulkStr(k)) {
+		Some(DataType::HashMap(h)) => h,
+		Some(_) => return Err(ERRMSG_WRONGTYPE),
+		None => return Ok(DataType::List(
+			fs.iter().map(|_| DataType::Integer(-2)).collect()
+		))
+	};
+	let now = stream::now_ms();
+	Ok(DataType::List(fs.iter().map(|f| DataType::Integer(
+		match (h.contains_key(&DataType::bulkStr(f)), expiry::field(k, f)) {
+			(false, _) => -2,
+			(true, None) => -1,
+			(true, Some(at)) => match cmd {
+				"httl" => (at.saturating_sub(now) as i64 + 500) / 1000,
+				"hpttl" => at.saturating_sub(now) as i64,
+				"hexpiretime" => (at / 1000) as i64,
+				_ => at as i64
+			}
 		}
+	)).collect()))
+}
+
+pub fn hvals(k: &str) -> Result<DataType, &str> {
+	let bstr_k: DataType = DataType::bulkStr(k);
+	match lock().get(&bstr_k) {
+		Some(DataType::HashMap(hmap)) => Ok(DataType::List(
+			hmap.values().cloned().collect::<Vec<_>>()
+		)),
+		Some(_) => Err(ERRMSG_WRONGTYPE),
+		None => Ok(DataType::List(vec![]))
 	}
 }
 
+pub fn incr(k: &str) -> Result<DataType, &str> {
+	incr_int(k, 1)
+}
+
 pub fn incrby<'a>(k: &'a str, v: &'a str) -> Result<DataType, &'a str> {
 	let n: i64 = match v.parse::<i64>() {
 		Ok(someint) => someint,
 		Err(_) => return Err(ERRMSG_VALNAI)
 	};
+	incr_int(k, n)
+}
+
+pub fn incrbyfloat<'a>(k: &'a str, v: &'a str) -> Result<DataType, &'a str> {
+	let n = parse_float(v).ok_or(ERRMSG_VALNAF)?;
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
-	match m.get(&bstr_k) {
-		Some(DataType::BulkString(s)) => match s.parse::<i64>() {
-			Ok(i) => {
-				let x: i64 = i + n;
-				m.insert(
-					bstr_k.clone(),
-					DataType::BulkString(x.to_string())
-				);
-				Ok(DataType::Integer(x))
-			},
-			Err(_) => Err(ERRMSG_VALNAIOOR)
+	let mut m = lock();
+	let x = match m.get(&bstr_k) {
+		Some(DataType::BulkString(s)) => parse_float(s).ok_or(ERRMSG_VALNAF)?,
+		Some(DataType::Integer(i)) => *i as f64,
+		Some(_) => return Err(ERRMSG_WRONGTYPE),
+		None => 0.0
+	};
+	let x = float_str(x + n).ok_or(ERRMSG_NANINF)?;
+	m.insert(bstr_k, DataType::bulkStr(&x));
+	modified('$', "incrbyfloat", k);
+	Ok(DataType::BulkString(x))
+}
+
+pub fn json_arrappend<'a>(k: &'a str, path: &'a str, vs: &'a [String])
+	-> Result<DataType, &'a str> {
+	json_arrinsert(k, path, None, vs)
+}
+
+pub fn json_arrinsert<'a>(k: &'a str, path: &'a str, at: Option<&'a str>,
+	vs: &'a [String]) -> Result<DataType, &'a str> {
+	let p = json::parse_path(path)?;
+	let at = match at {
+		Some(i) => Some(i.parse::<i64>().map_err(|_| ERRMSG_IDXNAI)?),
+		None => None
+	};
+	let vs = vs.iter().map(|v| Json::parse(v)).collect::<Result<Vec<_>, _>>()?;
+	let mut m = lock();
+	let doc = json_at(&mut m, k)?.ok_or(json::ERRMSG_NOKEY)?;
+	let mut failed = None;
+	let rs = json::each(doc, &p, |a| match json::arr_insert(a, at, &vs) {
+		Ok(r) => r,
+		Err(e) => {
+			failed = Some(e);
+			None
+		}
+	});
+	if rs.iter().any(|r| r.is_some()) {
+		let event = if at.is_some() {"json.arrinsert"} else {"json.arrappend"};
+		modified('d', event, k);
+	}
+	if let Some(e) = failed {
+		return Err(e);
+	}
+	Ok(json::reply(&p, rs, "array").unwrap_or_else(|e| DataType::err(&e)))
+}
+
+pub fn json_arrlen<'a>(k: &'a str, path: Option<&'a String>)
+	-> Result<DataType, &'a str> {
+	json_read(k, path, "array", |v| match v {
+		Json::Array(a) => Some(DataType::Integer(a.len() as i64)),
+		_ => None
+	})
+}
+
+pub fn json_arrpop<'a>(k: &'a str, path: Option<&'a String>,
+	at: Option<&'a String>) -> Result<DataType, &'a str> {
+	let p = match path {
+		Some(path) => json::parse_path(path)?,
+		None => json::root()
+	};
+	let at = match at {
+		Some(i) => i.parse::<i64>().map_err(|_| ERRMSG_IDXNAI)?,
+		None => -1
+	};
+	let mut m = lock();
+	let doc = json_at(&mut m, k)?.ok_or(json::ERRMSG_NOKEY)?;
+	let rs = json::each(doc, &p, |a| json::arr_pop(a, at));
+	if rs.iter().any(|r| matches!(r, Some(DataType::BulkString(_)))) {
+		modified('d', "json.arrpop", k);
+	}
+	Ok(json::reply(&p, rs, "array").unwrap_or_else(|e| DataType::err(&e)))
+}
+
+pub fn json_del<'a>(k: &'a str, path: Option<&'a String>)
+	-> Result<DataType, &'a str> {
+	let p = match path {
+		Some(path) => json::parse_path(path)?,
+		None => json::root()
+	};
+	let mut m = lock();
+	let n = match json_at(&mut m, k)? {
+		Some(_) if p.is_root() => {
+			m.remove(&DataType::bulkStr(k));
+			expiry::clear(k);
+			1
 		},
-		Some(_) => Err(ERRMSG_WRONGTYPE),
+		Some(doc) => json::delete(doc, &p),
+		None => 0
+	};
+	if 0 < n {
+		modified('d', "json.del", k);
+	}
+	Ok(DataType::Integer(n as i64))
+}
+
+// JSON.GET KEY [INDENT s] [NEWLINE s] [SPACE s] [PATH ...]
+pub fn json_get<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let mut f = json::Format::default();
+	let mut paths = Vec::new();
+	let mut i = 0;
+	while i < prms.len() {
+		let opt = match prms[i].to_ascii_lowercase().as_str() {
+			"indent" => &mut f.indent,
+			"newline" => &mut f.newline,
+			"space" => &mut f.space,
+			_ => {
+				paths.push(json::parse_path(&prms[i])?);
+				i += 1;
+				continue;
+			}
+		};
+		*opt = prms.get(i + 1).ok_or(ERRMSG_SYNERR)?.clone();
+		i += 2;
+	}
+	if paths.is_empty() {
+		paths.push(json::root());
+	}
+	let mut m = lock();
+	match json_at(&mut m, k)? {
+		Some(doc) => Ok(match json::get(doc, &paths, &f) {
+			Ok(s) => DataType::BulkString(s),
+			Err(e) => DataType::err(&e)
+		}),
+		None => Ok(DataType::Null)
+	}
+}
+
+pub fn json_mget<'a>(ks: &'a [String], path: &'a str)
+	-> Result<DataType, &'a str> {
+	let p = json::parse_path(path)?;
+	let f = json::Format::default();
+	let m = lock();
+	Ok(DataType::List(ks.iter()
+		.map(|k| match m.get(&DataType::bulkStr(k)) {
+			Some(DataType::Json(doc)) =>
+				match json::get(doc, std::slice::from_ref(&p), &f) {
+					Ok(s) => DataType::BulkString(s),
+					Err(_) => DataType::Null
+				},
+			_ => DataType::Null
+		})
+		.collect()))
+}
+
+pub fn json_numincrby<'a>(k: &'a str, path: &'a str, n: &'a str)
+	-> Result<DataType, &'a str> {
+	let p = json::parse_path(path)?;
+	let by = json::parse_number(n)?;
+	let mut m = lock();
+	let doc = json_at(&mut m, k)?.ok_or(json::ERRMSG_NOKEY)?;
+	let mut vs = Vec::new();
+	let rs = json::each(doc, &p, |v| {
+		let n = json::num_incr(v, &by);
+		vs.push(n.clone().unwrap_or(Json::Null));
+		n.map(|n| DataType::BulkString(n.to_text()))
+	});
+	if rs.iter().any(|r| r.is_some()) {
+		modified('d', "json.numincrby", k);
+	}
+	Ok(match p.legacy {
+		true => json::reply(&p, rs, "number")
+			.unwrap_or_else(|e| DataType::err(&e)),
+		false => DataType::BulkString(Json::Array(vs).to_text())
+	})
+}
+
+pub fn json_objkeys<'a>(k: &'a str, path: Option<&'a String>)
+	-> Result<DataType, &'a str> {
+	json_read(k, path, "object", json::obj_keys)
+}
+
+pub fn json_set<'a>(k: &'a str, path: &'a str, v: &'a str,
+	opts: &'a [String]) -> Result<DataType, &'a str> {
+	let p = json::parse_path(path)?;
+	let v = Json::parse(v)?;
+	let (mut nx, mut xx) = (false, false);
+	for o in opts.iter() {
+		match o.to_ascii_lowercase().as_str() {
+			"nx" => nx = true,
+			"xx" => xx = true,
+			_ => return Err(ERRMSG_SYNERR)
+		}
+	}
+	if nx && xx {
+		return Err(ERRMSG_SYNERR);
+	}
+	let mut m = lock();
+	let done = match json_at(&mut m, k)? {
+		Some(doc) => match json::set(doc, &p, &v, nx, xx) {
+			Ok(done) => done,
+			Err(e) => return Ok(DataType::err(&e))
+		},
+		None if xx => false,
+		None if !p.is_root() => return Err(json::ERRMSG_NEWATROOT),
 		None => {
-			let x: i64 = 0 + n;
-			m.insert(bstr_k.clone(), DataType::BulkString(x.to_string()));
-			Ok(DataType::Integer(x))
+			m.insert(DataType::bulkStr(k), DataType::Json(Box::new(v)));
+			true
 		}
+	};
+	match done {
+		true => {
+			modified('d', "json.set", k);
+			Ok(DataType::str("OK"))
+		},
+		false => Ok(DataType::Null)
+	}
+}
+
+// JSON.STRAPPEND KEY [PATH] VALUE
+pub fn json_strappend<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let (p, v) = match prms {
+		[v] => (json::root(), v),
+		[path, v] => (json::parse_path(path)?, v),
+		_ => return Err(ERRMSG_SYNERR)
+	};
+	let tail = json::parse_string(v)?;
+	let mut m = lock();
+	let doc = json_at(&mut m, k)?.ok_or(json::ERRMSG_NOKEY)?;
+	let rs = json::each(doc, &p, |v| json::str_append(v, &tail));
+	if rs.iter().any(|r| r.is_some()) {
+		modified('d', "json.strappend", k);
 	}
+	Ok(json::reply(&p, rs, "string").unwrap_or_else(|e| DataType::err(&e)))
+}
+
+pub fn json_type<'a>(k: &'a str, path: Option<&'a String>)
+	-> Result<DataType, &'a str> {
+	json_read(k, path, "any", |v| Some(DataType::str(v.type_name())))
 }
 
 pub fn keys(p: &str) -> Result<DataType, &str> {
 	match Regex::new(p) {
 		Ok(re) => Ok(DataType::List(
-			M.lock().unwrap().keys()
+			lock().keys()
 				.filter(|d| {
 					match d {
 						DataType::BulkString(s) => re.is_match(s),
@@ -427,14 +1465,122 @@ pub fn keys(p: &str) -> Result<DataType, &str> {
 	}
 }
 
+// names of the keys for which f holds, at most limit of them
+pub fn keys_where<F>(f: F, limit: usize) -> Vec<String>
+	where F: Fn(&str) -> bool {
+	lock().keys()
+		.filter_map(|d| match d {
+			DataType::BulkString(s) if f(s) => Some(s.clone()),
+			_ => None
+		})
+		.take(limit)
+		.collect()
+}
+
+// the longest common subsequence of the strings at k1 and k2, or its
+// length, or the ranges it matches in each with IDX
+pub fn lcs<'a>(k1: &'a str, k2: &'a str, opts: &'a [String])
+	-> Result<DataType, &'a str> {
+	let (mut len, mut idx, mut minlen, mut withlen) = (false, false, 0, false);
+	let mut i = 0;
+	while i < opts.len() {
+		match opts[i].to_ascii_lowercase().as_str() {
+			"len" => len = true,
+			"idx" => idx = true,
+			"withmatchlen" => withlen = true,
+			"minmatchlen" if i + 1 < opts.len() => {
+				i += 1;
+				minlen = opts[i].parse::<i64>()
+					.map_err(|_| ERRMSG_VALNAIOOR)?.max(0) as usize;
+			},
+			_ => return Err(ERRMSG_SYNERR)
+		}
+		i += 1;
+	}
+	if len && idx {
+		return Err(ERRMSG_IDXANDLEN);
+	}
+	let (a, b) = {
+		let m = lock();
+		(bytes_at(&m, k1)?.unwrap_or_default(),
+			bytes_at(&m, k2)?.unwrap_or_default())
+	};
+	let w = b.len() + 1;
+	match (a.len() + 1).checked_mul(w) {
+		Some(n) if n <= MAX_STRLEN / 4 => {},
+		_ => return Err(ERRMSG_LCSTOOBIG)
+	}
+	// t[i * w + j]: the length of the LCS of a[..i] and b[..j]
+	let mut t = vec![0u32; (a.len() + 1) * w];
+	for i in 1..=a.len() {
+		for j in 1..=b.len() {
+			t[i * w + j] = match a[i - 1] == b[j - 1] {
+				true => t[(i - 1) * w + j - 1] + 1,
+				false => t[(i - 1) * w + j].max(t[i * w + j - 1])
+			};
+		}
+	}
+	let n = t[a.len() * w + b.len()];
+	if len {
+		return Ok(DataType::Integer(n as i64));
+	}
+	// walk back, gathering the common bytes and the runs they make, as
+	// their last index in a, in b, and their length
+	let (mut i, mut j) = (a.len(), b.len());
+	let mut common = vec![];
+	let mut runs: Vec<(usize, usize, usize)> = vec![];
+	let mut run: Option<(usize, usize, usize)> = None;
+	while 0 < i && 0 < j {
+		if a[i - 1] == b[j - 1] {
+			common.push(a[i - 1]);
+			run = Some(match run {
+				Some((ae, be, l)) => (ae, be, l + 1),
+				None => (i - 1, j - 1, 1)
+			});
+			i -= 1;
+			j -= 1;
+			continue;
+		}
+		runs.extend(run.take());
+		if t[(i - 1) * w + j] > t[i * w + j - 1] {
+			i -= 1;
+		} else {
+			j -= 1;
+		}
+	}
+	runs.extend(run);
+	if !idx {
+		common.reverse();
+		return Ok(DataType::BulkString(bytes::decode(&common)));
+	}
+	let span = |end: usize, l: usize| DataType::List(vec![
+		DataType::Integer((end + 1 - l) as i64), DataType::Integer(end as i64)
+	]);
+	let matches = runs.into_iter().filter(|r| minlen <= r.2)
+		.map(|(ae, be, l)| {
+			let mut m = vec![span(ae, l), span(be, l)];
+			if withlen {
+				m.push(DataType::Integer(l as i64));
+			}
+			DataType::List(m)
+		})
+		.collect();
+	Ok(DataType::List(vec![
+		DataType::bulkStr("matches"),
+		DataType::List(matches),
+		DataType::bulkStr("len"),
+		DataType::Integer(n as i64)
+	]))
+}
+
 pub fn lindex<'a>(k: &'a str, i: &'a str) -> Result<DataType, &'a str> {
 	let idx: i64 = match i.parse::<i64>() {
 		Ok(v) => v,
 		Err(_) => return Err(ERRMSG_IDXNAI)
 	};
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
-		Some(DataType::List(somevec)) => {
+	match lock().get(&bstr_k) {
+		Some(DataType::Deque(somevec)) => {
 			let u: usize = if idx < 0 {
 				((somevec.len() as i64) + idx) as usize
 			} else {
@@ -453,8 +1599,8 @@ pub fn lindex<'a>(k: &'a str, i: &'a str) -> Result<DataType, &'a str> {
 pub fn linsert<'a>(k: &'a str, o: &'a str, p: &'a str, e: &'a str)
 	-> Result<DataType, &'a str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get_mut(&bstr_k) {
-		Some(DataType::List(l)) => {
+	match lock().get_mut(&bstr_k) {
+		Some(DataType::Deque(l)) => {
 			match l.iter().position(|v| {*v == DataType::bulkStr(p)}) {
 				Some(i) => {
 					let idx = match o.to_ascii_lowercase().as_str() {
@@ -463,6 +1609,7 @@ pub fn linsert<'a>(k: &'a str, o: &'a str, p: &'a str, e: &'a str)
 						_ => return Err(ERRMSG_SYNERR)
 					};
 					l.insert(idx, DataType::bulkStr(e));
+					modified('l', "linsert", k);
 					Ok(DataType::Integer(l.len() as i64))
 				},
 				None => return Ok(DataType::Integer(-1))
@@ -475,8 +1622,8 @@ pub fn linsert<'a>(k: &'a str, o: &'a str, p: &'a str, e: &'a str)
 
 pub fn llen(k: &str) -> Result<DataType, &str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
-		Some(DataType::List(l)) => Ok(DataType::Integer(
+	match lock().get(&bstr_k) {
+		Some(DataType::Deque(l)) => Ok(DataType::Integer(
 			l.len().try_into().unwrap()
 		)),
 		Some(_) => Err(ERRMSG_WRONGTYPE),
@@ -484,12 +1631,93 @@ pub fn llen(k: &str) -> Result<DataType, &str> {
 	}
 }
 
+// the LOADCHUNK command cmd, restoring what SCANDUMP gave at k
+pub fn loadchunk<'a>(cmd: &'a str, k: &'a str, data: &'a str)
+	-> Result<DataType, &'a str> {
+	let v = unchunk(cmd, data)?;
+	let mut m = lock();
+	m.insert(DataType::bulkStr(k), v);
+	modified('d', cmd, k);
+	Ok(DataType::str("OK"))
+}
+
+// LPOP or RPOP of up to count elements of the first non-empty list among
+// the numkeys keys
+pub fn lmpop(prms: &[String]) -> Result<DataType, &str> {
+	let n = match prms[0].parse::<usize>() {
+		Ok(n) if 0 < n => n,
+		_ => return Err(ERRMSG_NUMKEYS)
+	};
+	if prms.len() < n + 2 {
+		return Err(ERRMSG_SYNERR);
+	}
+	let left = side(&prms[n + 1])?;
+	let count = match &prms[n + 2..] {
+		[] => 1,
+		[o, c] if o.eq_ignore_ascii_case("count") => match c.parse::<usize>() {
+			Ok(c) if 0 < c => c,
+			_ => return Err(ERRMSG_CNTNPOS)
+		},
+		_ => return Err(ERRMSG_SYNERR)
+	};
+	let mut m = lock();
+	for k in &prms[1..=n] {
+		let l = match list_at(&mut m, k, false)? {
+			Some(l) if !l.is_empty() => l,
+			_ => continue
+		};
+		let c = count.min(l.len());
+		let es: Vec<DataType> = match left {
+			true => l.drain(..c).collect(),
+			false => l.drain(l.len() - c..).rev().collect()
+		};
+		let empty = l.is_empty();
+		modified('l', if left {"lpop"} else {"rpop"}, k);
+		if empty {
+			m.remove(&DataType::bulkStr(k));
+			modified('g', "del", k);
+		}
+		return Ok(DataType::List(vec![
+			DataType::bulkStr(k), DataType::List(es)
+		]));
+	}
+	Ok(DataType::Null)
+}
+
+// pop an element off the from side of the list at src, and push it on the
+// to side of the list at dst
+pub fn lmove<'a>(src: &'a str, dst: &'a str, from: &'a str, to: &'a str)
+	-> Result<DataType, &'a str> {
+	let (from, to) = (side(from)?, side(to)?);
+	let mut m = lock();
+	list_at(&mut m, dst, false)?;
+	let e = match list_at(&mut m, src, false)?
+		.and_then(|l| if from {l.pop_front()} else {l.pop_back()}) {
+		Some(e) => e,
+		None => return Ok(DataType::Null)
+	};
+	modified('l', if from {"lpop"} else {"rpop"}, src);
+	let empty = list_at(&mut m, src, false)?.is_some_and(|l| l.is_empty());
+	if src != dst && empty {
+		m.remove(&DataType::bulkStr(src));
+		modified('g', "del", src);
+	}
+	let l = list_at(&mut m, dst, true)?.unwrap();
+	match to {
+		true => l.push_front(e.clone()),
+		false => l.push_back(e.clone())
+	}
+	modified('l', if to {"lpush"} else {"rpush"}, dst);
+	Ok(e)
+}
+
 pub fn lpush(k: &str, vs: Vec<String>, x: bool) -> Result<DataType, &str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	match m.get_mut(&bstr_k) {
-		Some(DataType::List(l)) => {
-			vs.iter().for_each(|v| {l.insert(0, DataType::bulkStr(&v));});
+		Some(DataType::Deque(l)) => {
+			vs.iter().for_each(|v| {l.push_front(DataType::bulkStr(&v));});
+			modified('l', "lpush", k);
 			Ok(DataType::Integer(l.len().try_into().unwrap()))
 		},
 		Some(_) => Err(ERRMSG_WRONGTYPE),
@@ -498,11 +1726,12 @@ pub fn lpush(k: &str, vs: Vec<String>, x: bool) -> Result<DataType, &str> {
 				Ok(DataType::Integer(0))
 			},
 			false => {
-				let mut l: Vec<DataType> = Vec::new();
+				let mut l: VecDeque<DataType> = VecDeque::new();
 				vs.iter().for_each(|v| {
-					l.insert(0, DataType::bulkStr(&v));
+					l.push_front(DataType::bulkStr(&v));
 				});
-				m.insert(bstr_k.clone(), DataType::List(l.clone()));
+				m.insert(bstr_k.clone(), DataType::Deque(l.clone()));
+				modified('l', "lpush", k);
 				Ok(DataType::Integer(l.len().try_into().unwrap()))
 			}
 		}
@@ -515,16 +1744,20 @@ pub fn lpop<'a>(k: &'a str, n: &'a str) -> Result<DataType, &'a str> {
 		Err(_) => return Err(ERRMSG_NUMNPI)
 	};
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	match m.get_mut(&bstr_k) {
-		Some(DataType::List(somevec)) => {
+		Some(DataType::Deque(somevec)) => {
 			let mut l: Vec<DataType> = Vec::new();
 			for _ in 0usize..popsize {
 				if 0 < somevec.len() {
-					l.push(somevec.remove(0));
+					l.push(somevec.pop_front().unwrap());
 				}
 			}
-			if 0 == somevec.len() {m.remove(&bstr_k);}
+			if !l.is_empty() {modified('l', "lpop", k);}
+			if 0 == somevec.len() {
+				m.remove(&bstr_k);
+				modified('g', "del", k);
+			}
 			Ok(DataType::List(l))
 		},
 		Some(_) => Err(ERRMSG_WRONGTYPE),
@@ -532,6 +1765,50 @@ pub fn lpop<'a>(k: &'a str, n: &'a str) -> Result<DataType, &'a str> {
 	}
 }
 
+// the index of the rank-th match of e, counted from the tail when rank is
+// negative, or the indexes of count of them, among maxlen elements at most
+pub fn lpos<'a>(k: &'a str, e: &'a str, opts: &'a [String])
+	-> Result<DataType, &'a str> {
+	let (mut rank, mut count, mut maxlen) = (1i64, None, 0usize);
+	for o in opts.chunks(2) {
+		let (name, v) = match o {
+			[name, v] => (name.to_ascii_lowercase(), v),
+			_ => return Err(ERRMSG_SYNERR)
+		};
+		if !matches!(name.as_str(), "rank" | "count" | "maxlen") {
+			return Err(ERRMSG_SYNERR);
+		}
+		let v = v.parse::<i64>().map_err(|_| ERRMSG_VALNAIOOR)?;
+		match name.as_str() {
+			"rank" if 0 == v || i64::MIN == v => return Err(ERRMSG_RANKZERO),
+			"rank" => rank = v,
+			"count" if v < 0 => return Err(ERRMSG_CNTNEG),
+			"count" => count = Some(v as usize),
+			_ if v < 0 => return Err(ERRMSG_MAXLENNEG),
+			_ => maxlen = v as usize
+		}
+	}
+	let m = lock();
+	let l = match m.get(&DataType::bulkStr(k)) {
+		Some(DataType::Deque(l)) => l,
+		Some(_) => return Err(ERRMSG_WRONGTYPE),
+		None if count.is_some() => return Ok(DataType::List(vec![])),
+		None => return Ok(DataType::Null)
+	};
+	let dte = DataType::bulkStr(e);
+	let n = if 0 == maxlen {l.len()} else {maxlen.min(l.len())};
+	let at = |j: usize| if 0 < rank {j} else {l.len() - 1 - j};
+	let mut found = (0..n).map(at)
+		.filter(|i| l[*i] == dte)
+		.skip(rank.unsigned_abs() as usize - 1)
+		.map(|i| DataType::Integer(i as i64));
+	Ok(match count {
+		Some(0) => DataType::List(found.collect()),
+		Some(c) => DataType::List(found.take(c).collect()),
+		None => found.next().unwrap_or(DataType::Null)
+	})
+}
+
 // the Redis' LRANGE specs is soooooo weird :(
 pub fn lrange<'a>(k: &'a str, i: &'a str, j: &'a str)
 	-> Result<DataType, &'a str> {
@@ -544,8 +1821,8 @@ pub fn lrange<'a>(k: &'a str, i: &'a str, j: &'a str)
 		Err(_) => return Err(ERRMSG_STONAI)
 	};
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
-		Some(DataType::List(somevec)) => {
+	match lock().get(&bstr_k) {
+		Some(DataType::Deque(somevec)) => {
 			let veclen: i64 = somevec.len() as i64;
 			// adjust -ve start and stop indexes
 			if istart < 0 {
@@ -575,7 +1852,7 @@ pub fn lrange<'a>(k: &'a str, i: &'a str, j: &'a str)
 				};
 				Ok(DataType::List(
 					// always add 1 to stop index for inclusiveness
-					somevec[ustart..(ustop + 1usize)].to_vec()
+					somevec.range(ustart..(ustop + 1usize)).cloned().collect()
 				))
 			}
 		},
@@ -592,9 +1869,9 @@ pub fn lrem<'a>(k: &'a str, n: &'a str, e: &'a str)
 	};
 	let bstr_k: DataType = DataType::bulkStr(k);
 	let dte = DataType::bulkStr(e);
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	match m.get_mut(&bstr_k) {
-		Some(DataType::List(l)) => {
+		Some(DataType::Deque(l)) => {
 			let mut idxs: Vec<usize> = Vec::new();
 			if cnt > 0 {
 				for i in 0..l.len() {
@@ -625,7 +1902,11 @@ pub fn lrem<'a>(k: &'a str, n: &'a str, e: &'a str)
 			for i in (&idxs).iter().rev() {
 				let _ = l.remove(*i);
 			};
-			if 0 == l.len() {m.remove(&bstr_k);}
+			if !idxs.is_empty() {modified('l', "lrem", k);}
+			if 0 == l.len() {
+				m.remove(&bstr_k);
+				modified('g', "del", k);
+			}
 			Ok(DataType::Integer(idxs.len() as i64))
 		},
 		Some(_) => Err(ERRMSG_WRONGTYPE),
@@ -640,8 +1921,8 @@ pub fn lset<'a>(k: &'a str, i: &'a str, e: &'a str)
 		Err(_) => return Err(ERRMSG_IDXNAI)
 	};
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get_mut(&bstr_k) {
-		Some(DataType::List(l)) => {
+	match lock().get_mut(&bstr_k) {
+		Some(DataType::Deque(l)) => {
 			let veclen: i64 = l.len() as i64;
 			let realidx: i64 = if 0 > idx {
 				(l.len() as i64) + idx
@@ -653,6 +1934,7 @@ pub fn lset<'a>(k: &'a str, i: &'a str, e: &'a str)
 					realidx as usize
 				).unwrap();
 				*element = DataType::bulkStr(e);
+				modified('l', "lset", k);
 				Ok(DataType::bulkStr("OK"))
 			} else {
 				Err(ERRMSG_IDXOOR)
@@ -674,8 +1956,8 @@ pub fn ltrim<'a>(k: &'a str, i: &'a str, j: &'a str)
 		Err(_) => return Err(ERRMSG_STONAI)
 	};
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get_mut(&bstr_k) {
-		Some(DataType::List(somevec)) => {
+	match lock().get_mut(&bstr_k) {
+		Some(DataType::Deque(somevec)) => {
 			let veclen: i64 = somevec.len() as i64;
 			// adjust -ve start and stop indexes
 			if istart < 0 {
@@ -702,6 +1984,7 @@ pub fn ltrim<'a>(k: &'a str, i: &'a str, j: &'a str)
 				somevec.drain(0..ustart);
 				somevec.drain(ustop..somevec.len());
 			};
+			modified('l', "ltrim", k);
 			Ok(DataType::bulkStr("OK"))
 		},
 		Some(_) => Err(ERRMSG_WRONGTYPE),
@@ -710,17 +1993,18 @@ pub fn ltrim<'a>(k: &'a str, i: &'a str, j: &'a str)
 }
 
 pub fn memsize() -> usize {
-	M.lock().unwrap().iter().map(|(k, v)| k.capacity() + v.capacity()).sum()
+	lock().iter().map(|(k, v)| k.capacity() + v.capacity()).sum()
 }
 
 pub fn mget(ks: &Vec<String>) -> Result<DataType, &str> {
 	Ok(DataType::List(
 		ks.iter().map(|k| {
-			let m = M.lock().unwrap();
+			let m = lock();
 			let bstr_k: DataType = DataType::bulkStr(k);
 			let data = m.get(&bstr_k);
 			match data {
-				Some(DataType::BulkString(_)) => data.unwrap().clone(),
+				Some(v @ (DataType::BulkString(_) | DataType::Integer(_))) =>
+					DataType::BulkString(plain(v)),
 				_ => DataType::Null,
 			}
 		}).collect::<Vec<_>>().to_vec()
@@ -731,29 +2015,185 @@ pub fn mset(nvs: &Vec<String>) -> Result<DataType, &str> {
 	if 0 != nvs.len() % 2 {
 		return Err(ERRMSG_NOENX2);
 	}
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	nvs.chunks(2).for_each(|x| {
 		m.insert(DataType::bulkStr(&x[0]), DataType::bulkStr(&x[1]));
+		modified('$', "set", &x[0]);
 	});
 	Ok(DataType::str("OK"))
 }
 
+// MSET, unless any of the keys exists
+pub fn msetnx(nvs: &[String]) -> Result<DataType, &str> {
+	if !nvs.len().is_multiple_of(2) {
+		return Err(ERRMSG_NOENX2);
+	}
+	let mut m = lock();
+	if nvs.chunks(2).any(|x| m.contains_key(&DataType::bulkStr(&x[0]))) {
+		return Ok(DataType::Integer(0));
+	}
+	for x in nvs.chunks(2) {
+		m.insert(DataType::bulkStr(&x[0]), DataType::bulkStr(&x[1]));
+		modified('$', "set", &x[0]);
+	}
+	Ok(DataType::Integer(1))
+}
+
+// PFADD, creating an empty HyperLogLog if there is none at k
+pub fn pfadd(k: &str, els: &[String]) -> Result<DataType, &'static str> {
+	let mut m = lock();
+	let (mut h, mut changed) = match bytes_at(&m, k)? {
+		Some(v) => (Hll::parse(&v)?, false),
+		None => (Hll::default(), true)
+	};
+	for el in els.iter() {
+		changed |= h.add(&bytes::encode(el));
+	}
+	if changed {
+		let v = bytes::decode(&h.to_bytes());
+		m.insert(DataType::bulkStr(k), DataType::BulkString(v));
+		modified('$', "pfadd", k);
+	}
+	Ok(DataType::Integer(changed as i64))
+}
+
+// PFCOUNT of one key caches its estimate, of several estimates their union
+pub fn pfcount(ks: &[String]) -> Result<DataType, &'static str> {
+	let mut m = lock();
+	if 1 == ks.len() {
+		let mut h = match bytes_at(&m, &ks[0])? {
+			Some(v) => Hll::parse(&v)?,
+			None => return Ok(DataType::Integer(0))
+		};
+		let (n, fresh) = h.count();
+		if fresh {
+			let v = bytes::decode(&h.to_bytes());
+			m.insert(DataType::bulkStr(&ks[0]), DataType::BulkString(v));
+		}
+		return Ok(DataType::Integer(n as i64));
+	}
+	let mut hlls = Vec::with_capacity(ks.len());
+	for k in ks.iter() {
+		if let Some(v) = bytes_at(&m, k)? {
+			hlls.push(Hll::parse(&v)?);
+		}
+	}
+	Ok(DataType::Integer(hll::union(&hlls) as i64))
+}
+
+pub fn pfmerge(dst: &str, ks: &[String]) -> Result<DataType, &'static str> {
+	let mut m = lock();
+	let mut h = match bytes_at(&m, dst)? {
+		Some(v) => Hll::parse(&v)?,
+		None => Hll::default()
+	};
+	for k in ks.iter() {
+		if let Some(v) = bytes_at(&m, k)? {
+			h.merge(&Hll::parse(&v)?);
+		}
+	}
+	let v = bytes::decode(&h.to_bytes());
+	m.insert(DataType::bulkStr(dst), DataType::BulkString(v));
+	modified('$', "pfadd", dst);
+	Ok(DataType::str("OK"))
+}
+
+pub fn rename<'a>(k: &'a str, dst: &'a str, nx: bool)
+	-> Result<DataType, &'a str> {
+	let bstr_k: DataType = DataType::bulkStr(k);
+	let bstr_dst: DataType = DataType::bulkStr(dst);
+	let mut m = lock();
+	if !m.contains_key(&bstr_k) {
+		return Err(ERRMSG_NOSKEY);
+	}
+	if nx && m.contains_key(&bstr_dst) {
+		return Ok(DataType::Integer(0));
+	}
+	let v = m.remove(&bstr_k).unwrap();
+	m.insert(bstr_dst, v);
+	let (at, fs) = (expiry::get(k), expiry::fields(k));
+	modified('g', "rename_from", k);
+	modified('g', "rename_to", dst);
+	if let Some(at) = at {
+		expiry::set(dst, at);
+	}
+	for (f, at) in fs {
+		expiry::set_field(dst, &f, at);
+	}
+	match nx {
+		true => Ok(DataType::Integer(1)),
+		false => Ok(DataType::str("OK"))
+	}
+}
+
+pub fn restore<'a>(k: &'a str, payload: &'a str, replace: bool)
+	-> Result<DataType, &'a str> {
+	let req = match parse(&mut BufReader::new(&bytes::encode(payload)[..])) {
+		Ok(req) if !req.parameters.is_empty() => req,
+		_ => return Err(ERRMSG_BADPAYLOAD)
+	};
+	let vs = &req.parameters[1..];
+	let v = match req.command.as_str() {
+		"xadd" | "xsetid" => match Stream::restore(payload) {
+			Some(s) => DataType::Stream(Box::new(s)),
+			None => return Err(ERRMSG_BADPAYLOAD)
+		},
+		"hset" if vs.len().is_multiple_of(2) => DataType::HashMap(
+			vs.chunks(2)
+				.map(|x| (DataType::bulkStr(&x[0]), DataType::bulkStr(&x[1])))
+				.collect()
+		),
+		"sadd" => DataType::hset(vs.iter().map(|e| DataType::bulkStr(e))
+			.collect()),
+		"rpush" => DataType::Deque(vs.iter().map(|e| DataType::bulkStr(e))
+			.collect()),
+		"json.set" if 2 == vs.len() => match Json::parse(&vs[1]) {
+			Ok(doc) => DataType::Json(Box::new(doc)),
+			Err(_) => return Err(ERRMSG_BADPAYLOAD)
+		},
+		"set" if 1 == vs.len() => DataType::bulkStr(&vs[0]),
+		"zadd" => match zset::parse_pairs(vs) {
+			Ok(pairs) => {
+				let mut z = SortedSet::default();
+				pairs.iter().for_each(|(s, e)| {z.insert(e, *s);});
+				DataType::SortedSet(Box::new(z))
+			},
+			Err(_) => return Err(ERRMSG_BADPAYLOAD)
+		},
+		cmd if cmd.ends_with(".loadchunk") && 2 == vs.len() =>
+			unchunk(cmd, &vs[1]).map_err(|_| ERRMSG_BADPAYLOAD)?,
+		_ => return Err(ERRMSG_BADPAYLOAD)
+	};
+	let bstr_k: DataType = DataType::bulkStr(k);
+	let mut m = lock();
+	if !replace && m.contains_key(&bstr_k) {
+		return Err(ERRMSG_BUSYKEY);
+	}
+	m.insert(bstr_k, v);
+	modified('g', "restore", k);
+	Ok(DataType::str("OK"))
+}
+
 pub fn rpop<'a>(k: &'a str, n: &'a str) -> Result<DataType, &'a str> {
 	let popsize: usize = match n.parse::<usize>() {
 		Ok(v) => v,
 		Err(_) => return Err(ERRMSG_NUMNPI)
 	};
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	match m.get_mut(&bstr_k) {
-		Some(DataType::List(somevec)) => {
+		Some(DataType::Deque(somevec)) => {
 			let mut l: Vec<DataType> = Vec::new();
 			for _ in 0usize..popsize {
 				if 0 < somevec.len() {
-					l.push(somevec.pop().unwrap());
+					l.push(somevec.pop_back().unwrap());
 				}
 			}
-			if 0 == somevec.len() {m.remove(&bstr_k);}
+			if !l.is_empty() {modified('l', "rpop", k);}
+			if 0 == somevec.len() {
+				m.remove(&bstr_k);
+				modified('g', "del", k);
+			}
 			Ok(DataType::List(l))
 		},
 		Some(_) => Err(ERRMSG_WRONGTYPE),
@@ -764,10 +2204,11 @@ pub fn rpop<'a>(k: &'a str, n: &'a str) -> Result<DataType, &'a str> {
 pub fn rpush<'a>(k: &'a str, vs: Vec<String>, x: &'a bool)
 	-> Result<DataType, &'a str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	match m.get_mut(&bstr_k) {
-		Some(DataType::List(l)) => {
-			vs.iter().for_each(|v| {l.push(DataType::bulkStr(&v));});
+		Some(DataType::Deque(l)) => {
+			vs.iter().for_each(|v| {l.push_back(DataType::bulkStr(&v));});
+			modified('l', "rpush", k);
 			Ok(DataType::Integer(l.len().try_into().unwrap()))
 		},
 		Some(_) => Err(ERRMSG_WRONGTYPE),
@@ -776,11 +2217,12 @@ pub fn rpush<'a>(k: &'a str, vs: Vec<String>, x: &'a bool)
 				Ok(DataType::Integer(0))
 			},
 			false => {
-				let mut l: Vec<DataType> = Vec::new();
+				let mut l: VecDeque<DataType> = VecDeque::new();
 				vs.iter().for_each(|v| {
-					l.push(DataType::bulkStr(&v));
+					l.push_back(DataType::bulkStr(&v));
 				});
-				m.insert(bstr_k.clone(), DataType::List(l.clone()));
+				m.insert(bstr_k.clone(), DataType::Deque(l.clone()));
+				modified('l', "rpush", k);
 				Ok(DataType::Integer(l.len().try_into().unwrap()))
 			}
 		}
@@ -788,29 +2230,40 @@ pub fn rpush<'a>(k: &'a str, vs: Vec<String>, x: &'a bool)
 }
 
 pub fn sadd(k: &str, vs: Vec<String>) -> Result<DataType, &str> {
-	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
-	match m.get_mut(&bstr_k) {
-		Some(DataType::HashSet(s)) => Ok(DataType::Integer(
-			vs.iter().map(|v|{
-				if s.insert(DataType::bulkStr(v)){1}else{0}
-			}).sum()
-		)),
-		Some(_) => Err(ERRMSG_WRONGTYPE),
-		None => {
-			let mut s: HashSet<DataType> = HashSet::new();
-			let i = vs.iter().map(|v|{
-				if s.insert(DataType::bulkStr(v)){1}else{0}
-			}).sum();
-			m.insert(bstr_k.clone(), DataType::HashSet(s.clone()));
-			Ok(DataType::Integer(i))
-		}
+	let mut m = lock();
+	let s = set_at(&mut m, k, true)?.unwrap();
+	let i = vs.iter().map(|v|{
+		if s.insert(DataType::bulkStr(v)){1}else{0}
+	}).sum();
+	if 0 < i {modified('s', "sadd", k);}
+	Ok(DataType::Integer(i))
+}
+
+// the SCANDUMP command cmd, the whole value at k going out as the one
+// chunk following iterator 0
+pub fn scandump<'a>(cmd: &'a str, k: &'a str, iter: &'a str)
+	-> Result<DataType, &'a str> {
+	let iter = iter.parse::<i64>().map_err(|_| ERRMSG_ITERNAI)?;
+	let m = lock();
+	let (load, data) = match m.get(&DataType::bulkStr(k)) {
+		Some(v) => chunk(v).ok_or(ERRMSG_WRONGTYPE)?,
+		None => return Err(ERRMSG_NOTFOUND)
+	};
+	if load.split('.').next() != cmd.split('.').next() {
+		return Err(ERRMSG_WRONGTYPE);
 	}
+	Ok(DataType::List(match iter {
+		0 => vec![
+			DataType::Integer(1),
+			DataType::BulkString(bytes::decode(&data))
+		],
+		_ => vec![DataType::Integer(0), DataType::bulkStr("")]
+	}))
 }
 
 pub fn scard(k: &str) -> Result<DataType, &str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
+	match lock().get(&bstr_k) {
 		Some(DataType::HashSet(hset)) => Ok(DataType::Integer(
 			hset.len() as i64
 		)),
@@ -821,7 +2274,7 @@ pub fn scard(k: &str) -> Result<DataType, &str> {
 
 pub fn sdiff(k: &str, ks: Vec<String>) -> Result<DataType, &str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let m = M.lock().unwrap();
+	let m = lock();
 	match m.get(&bstr_k) {
 		Some(DataType::HashSet(hset)) => {
 			let mut vs = hset.iter().cloned().collect::<Vec<_>>();
@@ -843,18 +2296,19 @@ pub fn sdiff(k: &str, ks: Vec<String>) -> Result<DataType, &str> {
 pub fn sdiffstore<'a>(dst: &'a str, k: &'a str, ks: Vec<String>)
 	-> Result<DataType, &'a str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
+	let mut m = lock();
 	match m.get(&bstr_k) {
 		Some(DataType::HashSet(hset)) => {
-			let mut vs = hset.iter().cloned()
-				.collect::<HashSet<_>>();
+			let mut vs = hset.iter().cloned().collect::<Set>();
 			ks.iter().for_each(|k2| {match m.get(&DataType::bulkStr(k2)) {
 				Some(DataType::HashSet(hset2)) =>
-					vs.retain(|e| {!hset2.contains(&e)}),
+					vs.retain(|e| {!hset2.contains(e)}),
 				_ => {}
 			}});
-			m.insert(DataType::bulkStr(dst), DataType::hset(&vs));
-			Ok(DataType::Integer(vs.len() as i64))
+			let n = vs.len();
+			m.insert(DataType::bulkStr(dst), DataType::hset(vs));
+			modified('s', "sdiffstore", dst);
+			Ok(DataType::Integer(n as i64))
 		},
 		Some(_) => Err(ERRMSG_WRONGTYPE),
 		None => Ok(DataType::Integer(0))
@@ -862,55 +2316,106 @@ pub fn sdiffstore<'a>(dst: &'a str, k: &'a str, ks: Vec<String>)
 }
 
 pub fn set<'a>(k: &'a str, v: &'a str) -> Result<DataType, &'a str> {
-	let _ = M.lock().unwrap().insert(
+	let _ = lock().insert(
 		DataType::bulkStr(k),
 		DataType::bulkStr(v)
 	);
+	modified('$', "set", k);
 	Ok(DataType::str("OK"))
 }
 
+pub fn setbit<'a>(k: &'a str, off: &'a str, bit: &'a str)
+	-> Result<DataType, &'a str> {
+	let off = bitmap::parse_offset(off)?;
+	let bit: u8 = match bit {
+		"0" => 0,
+		"1" => 1,
+		_ => return Err(bitmap::ERRMSG_BADBIT)
+	};
+	let mut m = lock();
+	let mut v = bytes_at(&m, k)?.unwrap_or_default();
+	let old = bitmap::setbit(&mut v, off, bit);
+	m.insert(DataType::bulkStr(k), DataType::BulkString(bytes::decode(&v)));
+	modified('$', "setbit", k);
+	Ok(DataType::Integer(old as i64))
+}
+
+// overwrite the string at k from offset off with v, padding it with zero
+// bytes up to off
+pub fn setrange<'a>(k: &'a str, off: &'a str, v: &'a str)
+	-> Result<DataType, &'a str> {
+	let off = match off.parse::<i64>() {
+		Ok(off) if 0 <= off => off as usize,
+		Ok(_) => return Err(ERRMSG_OFFOOR),
+		Err(_) => return Err(ERRMSG_VALNAIOOR)
+	};
+	let v = bytes::encode(v);
+	let mut m = lock();
+	let mut s = bytes_at(&m, k)?.unwrap_or_default();
+	if v.is_empty() {
+		return Ok(DataType::Integer(s.len() as i64));
+	}
+	if MAX_STRLEN < off + v.len() {
+		return Err(ERRMSG_STRTOOBIG);
+	}
+	if s.len() < off + v.len() {
+		s.resize(off + v.len(), 0);
+	}
+	s[off..off + v.len()].copy_from_slice(&v);
+	m.insert(DataType::bulkStr(k), DataType::BulkString(bytes::decode(&s)));
+	modified('$', "setrange", k);
+	Ok(DataType::Integer(s.len() as i64))
+}
+
 pub fn sinter(k: &str, ks: Vec<String>) -> Result<DataType, &str> {
-	let bstr_k: DataType = DataType::bulkStr(k);
-	let m = M.lock().unwrap();
-	match m.get(&bstr_k) {
-		Some(DataType::HashSet(hset)) => {
-			let mut vs = hset.iter().cloned().collect::<Vec<_>>();
-			ks.iter().for_each(|k2| {match m.get(&DataType::bulkStr(k2)) {
-				Some(DataType::HashSet(hset2)) =>
-					vs.retain(|e| {hset2.contains(&e)}),
-				_ => {}
-			}});
-			Ok(DataType::List(vs))
-		},
-		Some(_) => Err(ERRMSG_WRONGTYPE),
-		None => Ok(DataType::EmptyList)
+	let ks = [vec![k.to_string()], ks].concat();
+	let sets = sets_at(&lock(), &ks)?;
+	Ok(DataType::List(inter(&sets, 0)))
+}
+
+// the size of the intersection of the numkeys sets, counting no further
+// than LIMIT when it is given and not 0
+pub fn sintercard(prms: &[String]) -> Result<DataType, &str> {
+	let n = match prms[0].parse::<usize>() {
+		Ok(n) if 0 < n => n,
+		_ => return Err(ERRMSG_NUMKEYS)
+	};
+	if prms.len() <= n {
+		return Err(ERRMSG_NUMKEYSARGS);
 	}
+	let limit = match &prms[n + 1..] {
+		[] => 0,
+		[o, l] if o.eq_ignore_ascii_case("limit") => match l.parse::<i64>() {
+			Ok(l) if 0 <= l => l as usize,
+			Ok(_) => return Err(ERRMSG_LIMITNEG),
+			Err(_) => return Err(ERRMSG_NUMNAI)
+		},
+		_ => return Err(ERRMSG_SYNERR)
+	};
+	let sets = sets_at(&lock(), &prms[1..=n])?;
+	Ok(DataType::Integer(inter(&sets, limit).len() as i64))
 }
 
 pub fn sinterstore<'a>(dst: &'a str, k: &'a str, ks: Vec<String>)
 	-> Result<DataType, &'a str> {
-	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
-	match m.get(&bstr_k) {
-		Some(DataType::HashSet(hset)) => {
-			let mut vs = hset.iter().cloned()
-				.collect::<HashSet<_>>();
-			ks.iter().for_each(|k2| {match m.get(&DataType::bulkStr(k2)) {
-				Some(DataType::HashSet(hset2)) =>
-					vs.retain(|e| {hset2.contains(&e)}),
-				_ => {}
-			}});
-			m.insert(DataType::bulkStr(dst), DataType::hset(&vs));
-			Ok(DataType::Integer(vs.len() as i64))
-		},
-		Some(_) => Err(ERRMSG_WRONGTYPE),
-		None => Ok(DataType::Integer(0))
+	let ks = [vec![k.to_string()], ks].concat();
+	let mut m = lock();
+	let vs = inter(&sets_at(&m, &ks)?, 0);
+	if vs.is_empty() {
+		if m.remove(&DataType::bulkStr(dst)).is_some() {
+			modified('g', "del", dst);
+		}
+		return Ok(DataType::Integer(0));
 	}
+	let n = vs.len();
+	m.insert(DataType::bulkStr(dst), DataType::hset(vs.into_iter().collect()));
+	modified('s', "sinterstore", dst);
+	Ok(DataType::Integer(n as i64))
 }
 
 pub fn sismember<'a>(k: &'a str, v: &'a str) -> Result<DataType, &'a str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
+	match lock().get(&bstr_k) {
 		Some(DataType::HashSet(hset)) => Ok(DataType::Integer(
 			if hset.contains(&DataType::bulkStr(v)) {1} else {0}
 		)),
@@ -921,7 +2426,7 @@ pub fn sismember<'a>(k: &'a str, v: &'a str) -> Result<DataType, &'a str> {
 
 pub fn smembers(k: &str) -> Result<DataType, &str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
+	match lock().get(&bstr_k) {
 		Some(DataType::HashSet(hset)) =>
 			Ok(DataType::HashSet(hset.clone())),
 		Some(_) => Err(ERRMSG_WRONGTYPE),
@@ -931,42 +2436,30 @@ pub fn smembers(k: &str) -> Result<DataType, &str> {
 
 pub fn smove<'a>(src: &'a str, dst: &'a str, v: &'a str)
 	-> Result<DataType, &'a str> {
-	let bstr_src: DataType = DataType::bulkStr(src);
-	let mut m = M.lock().unwrap();
-	let item = match m.get_mut(&bstr_src) {
-		Some(DataType::HashSet(hset)) => {
-			let e = hset.take(&DataType::bulkStr(v));
-			if 0 == hset.len() {m.remove(&bstr_src);}
-			e
-		},
-		Some(_) => return Err(ERRMSG_WRONGTYPE),
+	let mut m = lock();
+	set_at(&mut m, dst, false)?;
+	let item = match set_at(&mut m, src, false)? {
+		Some(hset) => hset.take(&DataType::bulkStr(v)),
 		None => return Ok(DataType::Integer(0))
 	};
 	match item {
-		Some(DataType::BulkString(_)) => {
-			let bstr_dst: DataType = DataType::bulkStr(dst);
-			match m.get_mut(&bstr_dst) {
-				Some(DataType::HashSet(hset2)) => {
-					hset2.insert(item.unwrap());
-					Ok(DataType::Integer(1))
-				},
-				Some(_) => Err(ERRMSG_WRONGTYPE),
-				None => {
-					let mut hset2: HashSet<DataType> = HashSet::new();
-					hset2.insert(item.unwrap());
-					m.insert(bstr_dst.clone(), DataType::hset(&hset2));
-					Ok(DataType::Integer(1))
-				}
+		Some(e) => {
+			modified('s', "srem", src);
+			if set_at(&mut m, src, false)?.is_some_and(|s| s.is_empty()) {
+				m.remove(&DataType::bulkStr(src));
+				modified('g', "del", src);
 			}
+			set_at(&mut m, dst, true)?.unwrap().insert(e);
+			modified('s', "sadd", dst);
+			Ok(DataType::Integer(1))
 		},
-		Some(_) => Err(ERRMSG_WRONGTYPE),
 		None => Ok(DataType::Integer(0))
 	}
 }
 
 pub fn smismember(k: &str, vs: Vec<String>) -> Result<DataType, &str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
+	match lock().get(&bstr_k) {
 		Some(DataType::HashSet(hset)) => Ok(DataType::List(
 			vs.iter().map(|v| {DataType::Integer(
 				if hset.contains(&DataType::bulkStr(v)) {1} else {0}
@@ -985,163 +2478,1192 @@ pub fn spop<'a>(k: &'a str, n: &'a str, single_item: bool)
 		Ok(v) => v,
 		Err(_) => return Err(ERRMSG_NUMNPI)
 	};
-	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
-	match m.get_mut(&bstr_k) {
-		Some(DataType::HashSet(hset)) => {
-			let h: Vec<DataType> = hset.iter().cloned().collect();
-			let mut idxs: Vec<usize> = Vec::new();
-			let mut rng = rand::rng();
-			while idxs.len() < popsize {
-				idxs.push(rng.random_range(0..h.len()));
-				idxs.sort();
-				idxs.dedup();
-			}
-			let vs = idxs.iter().map(|&idx|{h.get(idx).unwrap().clone()})
-				.collect::<Vec<_>>();
-			hset.retain(|e| {!vs.contains(e)});
-			if 0 == hset.len() {m.remove(&bstr_k);}
-			if single_item && 1 == vs.len() {
-				Ok(vs.first().unwrap().clone())
-			} else {
-				Ok(DataType::List(vs))
-			}
-		},
-		Some(_) => Err(ERRMSG_WRONGTYPE),
-		None => Ok(DataType::EmptyList)
+	let mut m = lock();
+	let hset = match set_at(&mut m, k, false)? {
+		Some(hset) => hset,
+		None if single_item => return Ok(DataType::Null),
+		None => return Ok(DataType::EmptyList)
+	};
+	let vs = hset.pop(popsize);
+	let empty = hset.is_empty();
+	if !vs.is_empty() {modified('s', "spop", k);}
+	if empty {
+		m.remove(&DataType::bulkStr(k));
+		modified('g', "del", k);
+	}
+	if single_item && 1 == vs.len() {
+		Ok(vs.into_iter().next().unwrap())
+	} else {
+		Ok(DataType::List(vs))
 	}
 }
 
-pub fn srandmember<'a>(k: &'a str, c: &'a str)
+pub fn srandmember<'a>(k: &'a str, c: &'a str, single_item: bool)
 	-> Result<DataType, &'a str> {
 	let i = match c.parse::<i64>() {
 		Ok(n) => n,
 		Err(_) => return Err(ERRMSG_NUMNAI)
 	};
+	let hset = match sets_at(&lock(), &[k.to_string()])?.pop() {
+		Some(hset) if !hset.is_empty() => hset,
+		_ => return Ok(DataType::Null)
+	};
+	// a negative count may give the same member more than once
+	let vs = if 0 <= i {
+		hset.sample(i as usize)
+	} else {
+		hset.pick(i.unsigned_abs() as usize)
+	};
+	if single_item {
+		Ok(vs.into_iter().next().unwrap())
+	} else {
+		Ok(DataType::List(vs))
+	}
+}
+
+pub fn srem(k: &str, vs: Vec<String>) -> Result<DataType, &str> {
+	let mut m = lock();
+	let hset = match set_at(&mut m, k, false)? {
+		Some(hset) => hset,
+		None => return Ok(DataType::Integer(0))
+	};
+	let cnt = vs.iter().filter(|s| hset.remove(&DataType::bulkStr(s)))
+		.count() as i64;
+	let empty = hset.is_empty();
+	if 0 < cnt {modified('s', "srem", k);}
+	if empty {
+		m.remove(&DataType::bulkStr(k));
+		modified('g', "del", k);
+	}
+	Ok(DataType::Integer(cnt))
+}
+
+// drop the keys and hash fields past their deadline every 100ms, even
+// while no command comes
+pub fn start_expiry() {
+	thread::spawn(|| loop {
+		thread::sleep(Duration::from_millis(100));
+		drop(lock());
+	});
+}
+
+pub fn strlen(k: &str) -> Result<DataType, &str> {
+	let n = bytes_at(&lock(), k)?.map_or(0, |v| v.len());
+	Ok(DataType::Integer(n as i64))
+}
+
+pub fn sunion(ks: Vec<String>) -> Result<DataType, &'static str> {
+	let sets = sets_at(&lock(), &ks)?;
+	Ok(DataType::List(union(&sets).iter().cloned().collect()))
+}
+
+pub fn sunionstore(dst: &str, ks: Vec<String>) -> Result<DataType, &str> {
+	let mut m = lock();
+	let wk = union(&sets_at(&m, &ks)?);
+	let n = wk.len();
+	if 0 == n {
+		if m.remove(&DataType::bulkStr(dst)).is_some() {
+			modified('g', "del", dst);
+		}
+		return Ok(DataType::Integer(0));
+	}
+	m.insert(DataType::bulkStr(dst), DataType::hset(wk));
+	modified('s', "sunionstore", dst);
+	Ok(DataType::Integer(n as i64))
+}
+
+pub fn tdigest_add<'a>(k: &'a str, vs: &'a [String])
+	-> Result<DataType, &'a str> {
+	let vs = tdigest::parse_values(vs)?;
+	let mut m = lock();
+	let d = tdigest_at(&mut m, k)?.ok_or(tdigest::ERRMSG_NOKEY)?;
+	vs.iter().for_each(|v| d.add(*v));
+	modified('d', "tdigest.add", k);
+	Ok(DataType::str("OK"))
+}
+
+pub fn tdigest_cdf<'a>(k: &'a str, vs: &'a [String])
+	-> Result<DataType, &'a str> {
+	let vs = tdigest::parse_values(vs)?;
+	let mut m = lock();
+	let d = tdigest_at(&mut m, k)?.ok_or(tdigest::ERRMSG_NOKEY)?;
+	Ok(DataType::List(vs.iter().map(|v| tdigest::float(d.cdf(*v))).collect()))
+}
+
+pub fn tdigest_create<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let d = tdigest::parse_create(prms)?;
+	let bstr_k: DataType = DataType::bulkStr(k);
+	let mut m = lock();
+	if m.contains_key(&bstr_k) {
+		return Err(tdigest::ERRMSG_EXISTS);
+	}
+	m.insert(bstr_k, DataType::TDigest(Box::new(d)));
+	modified('d', "tdigest.create", k);
+	Ok(DataType::str("OK"))
+}
+
+pub fn tdigest_max(k: &str) -> Result<DataType, &str> {
+	let mut m = lock();
+	let d = tdigest_at(&mut m, k)?.ok_or(tdigest::ERRMSG_NOKEY)?;
+	Ok(tdigest::float(d.max()))
+}
+
+// TDIGEST.MERGE into dst, on top of what it holds unless overriding
+pub fn tdigest_merge<'a>(dst: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let (srcs, compression, overriding) = tdigest::parse_merge(prms)?;
+	let mut m = lock();
+	let mut digests = Vec::with_capacity(srcs.len());
+	for k in srcs.iter() {
+		let d = tdigest_at(&mut m, k)?.ok_or(tdigest::ERRMSG_NOKEY)?;
+		digests.push(d.clone());
+	}
+	let old = match overriding {
+		true => None,
+		false => tdigest_at(&mut m, dst)?.map(|d| d.clone())
+	};
+	let compression = compression
+		.or(old.as_ref().map(|d| d.compression()))
+		.unwrap_or_else(|| digests.iter().map(|d| d.compression()).max()
+			.unwrap_or_default());
+	let mut d = Digest::new(compression);
+	old.iter().chain(digests.iter()).for_each(|s| d.merge(s));
+	d.compress();
+	m.insert(DataType::bulkStr(dst), DataType::TDigest(Box::new(d)));
+	modified('d', "tdigest.merge", dst);
+	Ok(DataType::str("OK"))
+}
+
+pub fn tdigest_min(k: &str) -> Result<DataType, &str> {
+	let mut m = lock();
+	let d = tdigest_at(&mut m, k)?.ok_or(tdigest::ERRMSG_NOKEY)?;
+	Ok(tdigest::float(d.min()))
+}
+
+pub fn tdigest_quantile<'a>(k: &'a str, qs: &'a [String])
+	-> Result<DataType, &'a str> {
+	let qs = tdigest::parse_quantiles(qs)?;
+	let mut m = lock();
+	let d = tdigest_at(&mut m, k)?.ok_or(tdigest::ERRMSG_NOKEY)?;
+	Ok(DataType::List(qs.iter()
+		.map(|q| tdigest::float(d.quantile(*q)))
+		.collect()))
+}
+
+pub fn tdigest_rank<'a>(k: &'a str, vs: &'a [String])
+	-> Result<DataType, &'a str> {
+	let vs = tdigest::parse_values(vs)?;
+	let mut m = lock();
+	let d = tdigest_at(&mut m, k)?.ok_or(tdigest::ERRMSG_NOKEY)?;
+	Ok(DataType::List(vs.iter().map(|v| DataType::Integer(d.rank(*v)))
+		.collect()))
+}
+
+// TOPK.INCRBY, or TOPK.ADD counting every item once unless by
+pub fn topk_add<'a>(k: &'a str, prms: &'a [String], by: bool)
+	-> Result<DataType, &'a str> {
+	let incrs = match by {
+		true => topk::parse_incrs(prms)?,
+		false => prms.iter().map(|item| (item.as_str(), 1)).collect()
+	};
+	let mut m = lock();
+	let t = topk_at(&mut m, k)?.ok_or(topk::ERRMSG_NOKEY)?;
+	let rs: Vec<DataType> = incrs.iter()
+		.map(|(item, n)| match t.incr(item, *n) {
+			Some(out) => DataType::BulkString(out),
+			None => DataType::Null
+		})
+		.collect();
+	modified('d', if by {"topk.incrby"} else {"topk.add"}, k);
+	Ok(DataType::List(rs))
+}
+
+pub fn topk_list<'a>(k: &'a str, withcount: Option<&'a String>)
+	-> Result<DataType, &'a str> {
+	let withcount = match withcount {
+		Some(o) if o.eq_ignore_ascii_case("withcount") => true,
+		Some(_) => return Err(ERRMSG_SYNERR),
+		None => false
+	};
+	let mut m = lock();
+	let t = topk_at(&mut m, k)?.ok_or(topk::ERRMSG_NOKEY)?;
+	Ok(DataType::List(t.list()
+		.flat_map(|(item, n)| match withcount {
+			true => vec![DataType::bulkStr(item), DataType::Integer(n as i64)],
+			false => vec![DataType::bulkStr(item)]
+		})
+		.collect()))
+}
+
+pub fn topk_query<'a>(k: &'a str, items: &'a [String])
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	let t = topk_at(&mut m, k)?.ok_or(topk::ERRMSG_NOKEY)?;
+	Ok(DataType::List(items.iter()
+		.map(|item| DataType::Integer(t.contains(item) as i64))
+		.collect()))
+}
+
+pub fn topk_reserve<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let t = topk::parse_reserve(prms)?;
 	let bstr_k: DataType = DataType::bulkStr(k);
-	match M.lock().unwrap().get(&bstr_k) {
-		Some(DataType::HashSet(hset)) => {
-			let h: Vec<DataType> = hset.iter().cloned().collect();
-			let cnt: usize = if (h.len() as i64) < i {
-				h.len()
-			} else if 0 <= i {
-				i as usize
-			} else {
-				(-1 * i) as usize
-			};
-			let mut idxs: Vec<usize> = Vec::new();
-			let mut rng = rand::rng();
-			loop {
-				(0..cnt).for_each(|_| {idxs.push(
-					rng.random_range(0..h.len())
-				);});
-				if 0 < i {
-					idxs.sort();
-					idxs.dedup();
-				};
-				if cnt <= idxs.len() {
-					if cnt < idxs.len() {
-						idxs.truncate(cnt);
-					}
-					break;
-				}
+	let mut m = lock();
+	if m.contains_key(&bstr_k) {
+		return Err(topk::ERRMSG_EXISTS);
+	}
+	m.insert(bstr_k, DataType::TopK(Box::new(t)));
+	modified('d', "topk.reserve", k);
+	Ok(DataType::str("OK"))
+}
+
+// TS.ADD, creating the series out of the options when missing
+pub fn ts_add<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let now = stream::now_ms();
+	let ts = timeseries::parse_timestamp(&prms[0], now)?;
+	let v = timeseries::parse_value(&prms[1])?;
+	let o = timeseries::parse_options(&prms[2..], now)?;
+	if o.timestamp.is_some() {
+		return Err(ERRMSG_SYNERR);
+	}
+	let mut m = lock();
+	series_add(&mut m, k, ts, v, Some(&o))?;
+	modified('d', "ts.add", k);
+	Ok(DataType::Integer(ts as i64))
+}
+
+pub fn ts_create<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let o = timeseries::parse_options(prms, 0)?;
+	if o.on_duplicate.is_some() || o.timestamp.is_some() {
+		return Err(ERRMSG_SYNERR);
+	}
+	let bstr_k: DataType = DataType::bulkStr(k);
+	let mut m = lock();
+	if m.contains_key(&bstr_k) {
+		return Err(timeseries::ERRMSG_EXISTS);
+	}
+	m.insert(bstr_k, DataType::TimeSeries(Box::new(o.series)));
+	modified('d', "ts.create", k);
+	Ok(DataType::str("OK"))
+}
+
+// TS.CREATERULE, downsampling the series at src into the one at dst
+pub fn ts_createrule<'a>(src: &'a str, dst: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let rule = timeseries::parse_rule(prms)?;
+	if src == dst {
+		return Err(timeseries::ERRMSG_RULESAME);
+	}
+	let mut m = lock();
+	let s = series_at(&mut m, src)?.ok_or(timeseries::ERRMSG_NOKEY)?;
+	if s.source().is_some() {
+		return Err(timeseries::ERRMSG_SRCSRC);
+	}
+	let d = series_at(&mut m, dst)?.ok_or(timeseries::ERRMSG_NOKEY)?;
+	if d.source().is_some() {
+		return Err(timeseries::ERRMSG_RULESRC);
+	} else if d.has_rules() {
+		return Err(timeseries::ERRMSG_RULEDST);
+	}
+	d.set_source(Some(src));
+	if let Some(s) = series_at(&mut m, src)? {
+		s.add_rule(dst, rule);
+	}
+	modified('d', "ts.createrule:src", src);
+	modified('d', "ts.createrule:dest", dst);
+	Ok(DataType::str("OK"))
+}
+
+pub fn ts_deleterule<'a>(src: &'a str, dst: &'a str)
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	let s = series_at(&mut m, src)?.ok_or(timeseries::ERRMSG_NOKEY)?;
+	if !s.del_rule(dst) {
+		return Err(timeseries::ERRMSG_NORULE);
+	}
+	if let Ok(Some(d)) = series_at(&mut m, dst) {
+		d.set_source(None);
+	}
+	modified('d', "ts.deleterule:src", src);
+	modified('d', "ts.deleterule:dest", dst);
+	Ok(DataType::str("OK"))
+}
+
+pub fn ts_get(k: &str) -> Result<DataType, &str> {
+	let mut m = lock();
+	let s = series_at(&mut m, k)?.ok_or(timeseries::ERRMSG_NOKEY)?;
+	Ok(DataType::List(match s.last() {
+		Some((ts, v)) =>
+			vec![DataType::Integer(ts as i64), timeseries::float(v)],
+		None => vec![]
+	}))
+}
+
+// TS.INCRBY, or TS.DECRBY if decr, on the last value of the series
+pub fn ts_incrby<'a>(k: &'a str, prms: &'a [String], decr: bool)
+	-> Result<DataType, &'a str> {
+	let now = stream::now_ms();
+	let n = timeseries::parse_value(&prms[0])?;
+	let o = timeseries::parse_options(&prms[1..], now)?;
+	if o.on_duplicate.is_some() {
+		return Err(ERRMSG_SYNERR);
+	}
+	let ts = o.timestamp.unwrap_or(now);
+	let mut m = lock();
+	let v = match series_at(&mut m, k)?.and_then(|s| s.last()) {
+		Some((last, _)) if ts < last => return Err(timeseries::ERRMSG_OLDINCR),
+		Some((_, v)) => v,
+		None => 0.0
+	};
+	let v = if decr {v - n} else {v + n};
+	let o = Options {on_duplicate: Some(Policy::Last), ..o};
+	series_add(&mut m, k, ts, v, Some(&o))?;
+	modified('d', if decr {"ts.decrby"} else {"ts.incrby"}, k);
+	Ok(DataType::Integer(ts as i64))
+}
+
+pub fn ts_info(k: &str) -> Result<DataType, &str> {
+	let mut m = lock();
+	let s = series_at(&mut m, k)?.ok_or(timeseries::ERRMSG_NOKEY)?;
+	Ok(s.info())
+}
+
+// TS.MADD, the timestamp of every sample added or the error it ran into
+pub fn ts_madd(prms: &[String]) -> Result<DataType, &str> {
+	let now = stream::now_ms();
+	let mut m = lock();
+	let mut rs = Vec::with_capacity(prms.len() / 3);
+	for p in prms.chunks(3) {
+		let added = timeseries::parse_timestamp(&p[1], now)
+			.and_then(|ts| Ok((ts, timeseries::parse_value(&p[2])?)))
+			.and_then(|(ts, v)| series_add(&mut m, &p[0], ts, v, None)
+				.map(|_| ts));
+		rs.push(match added {
+			Ok(ts) => {
+				modified('d', "ts.add", &p[0]);
+				DataType::Integer(ts as i64)
+			},
+			Err(e) => DataType::err(e)
+		});
+	}
+	Ok(DataType::List(rs))
+}
+
+// TS.MRANGE, or TS.MREVRANGE if rev, over the series the filter picks
+pub fn ts_mrange(prms: &[String], rev: bool) -> Result<DataType, &str> {
+	let r = timeseries::parse_range(prms, rev, true)?;
+	let m = lock();
+	let mut found: Vec<(&String, &Series)> = m.iter()
+		.filter_map(|kv| match kv {
+			(DataType::BulkString(k), DataType::TimeSeries(s))
+				if r.matches(s) => Some((k, s.as_ref())),
+			_ => None
+		})
+		.collect();
+	found.sort_unstable_by_key(|(k, _)| *k);
+	Ok(DataType::List(found.iter()
+		.map(|(k, s)| DataType::List(vec![
+			DataType::bulkStr(k),
+			r.labels_of(s),
+			timeseries::reply(&s.query(&r))
+		]))
+		.collect()))
+}
+
+pub fn ts_queryindex(prms: &[String]) -> Result<DataType, &str> {
+	let filter = timeseries::parse_filter(prms)?;
+	let m = lock();
+	let mut ks: Vec<&String> = m.iter()
+		.filter_map(|kv| match kv {
+			(DataType::BulkString(k), DataType::TimeSeries(s))
+				if filter.iter().all(|f| f.matches(s)) => Some(k),
+			_ => None
+		})
+		.collect();
+	ks.sort_unstable();
+	Ok(DataType::List(ks.iter().map(|k| DataType::bulkStr(k)).collect()))
+}
+
+// TS.RANGE, or TS.REVRANGE if rev
+pub fn ts_range<'a>(k: &'a str, prms: &'a [String], rev: bool)
+	-> Result<DataType, &'a str> {
+	let r = timeseries::parse_range(prms, rev, false)?;
+	let mut m = lock();
+	let s = series_at(&mut m, k)?.ok_or(timeseries::ERRMSG_NOKEY)?;
+	Ok(timeseries::reply(&s.query(&r)))
+}
+
+// the time left before k expires, in ms or rounded to seconds, -1 for
+// keys that do not, -2 without key
+pub fn ttl(k: &str, ms: bool) -> Result<DataType, &str> {
+	if !lock().contains_key(&DataType::bulkStr(k)) {
+		return Ok(DataType::Integer(-2));
+	}
+	let left = match expiry::get(k) {
+		Some(at) => at.saturating_sub(stream::now_ms()) as i64,
+		None => return Ok(DataType::Integer(-1))
+	};
+	Ok(DataType::Integer(if ms {left} else {(left + 500) / 1000}))
+}
+
+pub fn write_data<'a, W>(w: &'a mut W) -> Result<(), &'a str>
+	where W: Write {
+	// libraries first, so that loading them never waits on the data
+	for code in function::codes() {
+		let l = DataType::List(vec![
+			DataType::bulkStr("function"),
+			DataType::bulkStr("load"),
+			DataType::bulkStr("replace"),
+			DataType::bulkStr(&code)
+		]);
+		if let Err(_) = w.write_all(&l.to_bytes()) {
+			return Err(ERRMSG_WRITEFAIL);
+		}
+	}
+	for (name, args) in search::definitions() {
+		let l = DataType::List(
+			["ft.create", &name].into_iter()
+				.chain(args.iter().map(|a| a.as_str()))
+				.map(DataType::bulkStr)
+				.collect()
+		);
+		if w.write_all(&l.to_bytes()).is_err() {
+			return Err(ERRMSG_WRITEFAIL);
+		}
+	}
+	let m = lock();
+	for t in m.iter() {
+		let mut l = match rebuild(t.0, t.1) {
+			Some(l) => l,
+			None => return Err(ERRMSG_WRONGTYPE)
+		};
+		let due = match t.0 {
+			DataType::BulkString(k) => expiry::get(k).map(|at| (k, at)),
+			_ => None
+		};
+		if let Some((k, at)) = due {
+			l += &DataType::List(
+				["getex", k, "pxat", &at.to_string()].into_iter()
+					.map(DataType::bulkStr)
+					.collect()
+			).to_string();
+		}
+		if let DataType::BulkString(k) = t.0 {
+			for (f, at) in expiry::fields(k) {
+				l += &DataType::List(
+					["hpexpireat", k, &at.to_string(), "fields", "1", &f]
+						.into_iter()
+						.map(DataType::bulkStr)
+						.collect()
+				).to_string();
+			}
+		}
+		if let Err(_) = w.write_all(&bytes::encode(&l)) {
+			return Err(ERRMSG_WRITEFAIL);
+		}
+	}
+	Ok(())
+}
+
+pub fn xack<'a>(k: &'a str, g: &'a str, ids: &'a [String])
+	-> Result<DataType, &'a str> {
+	let ids = parse_ids(ids)?;
+	let mut m = lock();
+	match stream_at(&mut m, k, false)? {
+		Some(s) => Ok(DataType::Integer(s.ack(g, &ids) as i64)),
+		None => Ok(DataType::Integer(0))
+	}
+}
+
+pub fn xadd<'a>(k: &'a str, prms: &'a [String]) -> Result<DataType, &'a str> {
+	let a = stream::parse_add(prms)?;
+	let mut m = lock();
+	let fresh = !m.contains_key(&DataType::bulkStr(k));
+	if fresh && a.nomkstream {
+		return Ok(DataType::Null);
+	}
+	let added = stream_at(&mut m, k, true)?.unwrap().add(&a, &prms[a.id_at]);
+	match added {
+		Ok((id, trimmed)) => {
+			modified('t', "xadd", k);
+			if 0 < trimmed {
+				modified('t', "xtrim", k);
 			}
-			Ok(DataType::List(
-				idxs.iter().map(|&idx|{h.get(idx).unwrap().clone()})
-					.collect::<Vec<_>>()
-			))
+			stream::changed();
+			Ok(id)
 		},
+		Err(e) => {
+			if fresh {
+				m.remove(&DataType::bulkStr(k));
+			}
+			Err(e)
+		}
+	}
+}
+
+// XAUTOCLAIM past the key, group and consumer
+pub fn xautoclaim<'a>(k: &'a str, g: &'a str, c: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	match stream_at(&mut m, k, false)? {
+		Some(s) => Ok(s.autoclaim(k, g, c, prms)
+			.unwrap_or_else(|e| DataType::err(&e))),
+		None => Ok(DataType::err(&stream::nogroup(k, g)))
+	}
+}
+
+// XCLAIM past the key, group and consumer
+pub fn xclaim<'a>(k: &'a str, g: &'a str, c: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let claim = stream::parse_claim(prms)?;
+	let mut m = lock();
+	match stream_at(&mut m, k, false)? {
+		Some(s) => Ok(s.claim(k, g, c, &claim)
+			.unwrap_or_else(|e| DataType::err(&e))),
+		None => Ok(DataType::err(&stream::nogroup(k, g)))
+	}
+}
+
+pub fn xdel<'a>(k: &'a str, ids: &'a [String]) -> Result<DataType, &'a str> {
+	let ids = parse_ids(ids)?;
+	let mut m = lock();
+	let n = match stream_at(&mut m, k, false)? {
+		Some(s) => s.delete(&ids),
+		None => 0
+	};
+	if 0 < n {
+		modified('t', "xdel", k);
+	}
+	Ok(DataType::Integer(n as i64))
+}
+
+// XGROUP subcommands, past the subcommand and the key
+pub fn xgroup<'a>(sub: &str, k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let mkstream = "create" == sub
+		&& prms.iter().skip(2).any(|p| p.eq_ignore_ascii_case("mkstream"));
+	let mut m = lock();
+	let fresh = !m.contains_key(&DataType::bulkStr(k));
+	let s = match stream_at(&mut m, k, mkstream)? {
+		Some(s) => s,
+		None => return Err(ERRMSG_NOSTREAM)
+	};
+	match s.group(k, sub, prms) {
+		Ok(DataType::Integer(0)) => Ok(DataType::Integer(0)),
+		Ok(reply) => {
+			modified('t', &format!("xgroup-{}", sub), k);
+			Ok(reply)
+		},
+		Err(e) => {
+			if fresh {
+				m.remove(&DataType::bulkStr(k));
+			}
+			Ok(DataType::err(&e))
+		}
+	}
+}
+
+// XINFO subcommands, past the subcommand and the key
+pub fn xinfo<'a>(sub: &str, k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	match stream_at(&mut m, k, false)? {
+		Some(s) => Ok(s.info(k, sub, prms)
+			.unwrap_or_else(|e| DataType::err(&e))),
+		None => Err(ERRMSG_NOSKEY)
+	}
+}
+
+pub fn xlen(k: &str) -> Result<DataType, &str> {
+	let mut m = lock();
+	match stream_at(&mut m, k, false)? {
+		Some(s) => Ok(DataType::Integer(s.len() as i64)),
+		None => Ok(DataType::Integer(0))
+	}
+}
+
+// XPENDING past the key and group
+pub fn xpending<'a>(k: &'a str, g: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	match stream_at(&mut m, k, false)? {
+		Some(s) => Ok(s.pending(k, g, prms)
+			.unwrap_or_else(|e| DataType::err(&e))),
+		None => Ok(DataType::err(&stream::nogroup(k, g)))
+	}
+}
+
+// the XREAD or XREADGROUP parameters with every "$" ID replaced by the last
+// ID of its stream, so that a blocked read waits for entries added later
+pub fn xpin(prms: &[String]) -> Vec<String> {
+	let mut prms = prms.to_vec();
+	let r = match stream::parse_read(&prms) {
+		Ok(r) => r,
+		Err(_) => return prms
+	};
+	let first = prms.len() - r.ids.len();
+	let mut m = lock();
+	for (i, k) in r.keys.iter().enumerate() {
+		if "$" == prms[first + i] {
+			prms[first + i] = match stream_at(&mut m, k, false) {
+				Ok(Some(s)) => s.last_id().to_string(),
+				_ => String::from("0-0")
+			};
+		}
+	}
+	prms
+}
+
+pub fn xrange<'a>(k: &'a str, start: &'a str, end: &'a str,
+	count: Option<&'a str>, rev: bool) -> Result<DataType, &'a str> {
+	let count = match count.map(|n| n.parse::<i64>()) {
+		Some(Ok(n)) => Some(n.max(0) as usize),
+		Some(Err(_)) => return Err(ERRMSG_VALNAIOOR),
+		None => None
+	};
+	let mut m = lock();
+	match stream_at(&mut m, k, false)? {
+		Some(s) => s.range(start, end, count, rev),
+		None => Ok(DataType::List(vec![]))
+	}
+}
+
+// XREAD and XREADGROUP, None when no stream had anything to read
+pub fn xread(prms: &[String]) -> Result<DataType, &str> {
+	let r = stream::parse_read(prms)?;
+	let mut m = lock();
+	let mut l: Vec<DataType> = Vec::new();
+	for (k, id) in r.keys.iter().zip(r.ids.iter()) {
+		let s = stream_at(&mut m, k, false)?;
+		let entries = match (&r.group, s) {
+			(Some(_), Some(s)) => match s.read_group(k, &r, id) {
+				Ok(entries) => entries,
+				Err(e) => return Ok(DataType::err(&e))
+			},
+			(Some((g, _)), None) => return Ok(DataType::err(&format!(
+				"{} in XREADGROUP with GROUP option",
+				stream::nogroup(k, g)
+			))),
+			(None, Some(s)) => {
+				let from = match id.as_str() {
+					"$" => s.last_id(),
+					_ => StreamId::parse(id, 0)?
+				};
+				Some(s.after(from, r.count)).filter(|e| !e.is_empty())
+			},
+			(None, None) => None
+		};
+		if let Some(entries) = entries {
+			l.push(DataType::List(vec![
+				DataType::bulkStr(k),
+				DataType::List(entries)
+			]));
+		}
+	}
+	match l.is_empty() {
+		true => Ok(DataType::Null),
+		false => Ok(DataType::List(l))
+	}
+}
+
+// XSETID past the key
+pub fn xsetid<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	let reply = match stream_at(&mut m, k, false)? {
+		Some(s) => s.set_id(prms)?,
+		None => return Err(ERRMSG_NOSKEY)
+	};
+	modified('t', "xsetid", k);
+	Ok(reply)
+}
+
+// XTRIM past the key
+pub fn xtrim<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let t = stream::parse_trim(prms)?;
+	let mut m = lock();
+	let n = match stream_at(&mut m, k, false)? {
+		Some(s) => s.trim(&t),
+		None => 0
+	};
+	if 0 < n {
+		modified('t', "xtrim", k);
+	}
+	Ok(DataType::Integer(n as i64))
+}
+
+pub fn zadd<'a>(k: &'a str, prms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let (opts, i) = zset::parse_options(prms)?;
+	let pairs = zset::parse_pairs(&prms[i..])?;
+	sorted_add(k, &opts, &pairs)
+}
+
+pub fn zrem<'a>(k: &'a str, ms: &'a [String])
+	-> Result<DataType, &'a str> {
+	let mut m = lock();
+	let (n, empty) = match zset_at(&mut m, k, false)? {
+		Some(z) => (
+			ms.iter().filter(|e| z.remove(e).is_some()).count(),
+			z.is_empty()
+		),
+		None => (0, false)
+	};
+	if 0 < n {
+		modified('z', "zrem", k);
+	}
+	if empty {
+		m.remove(&DataType::bulkStr(k));
+		modified('g', "del", k);
+	}
+	Ok(DataType::Integer(n as i64))
+}
+
+// the Bloom filter stored at k, created when missing if create is set
+fn bloom_at<'a>(m: &'a mut HashMap<DataType, DataType>, k: &str,
+	create: bool) -> Result<Option<&'a mut Bloom>, &'static str> {
+	let bstr_k: DataType = DataType::bulkStr(k);
+	if create && !m.contains_key(&bstr_k) {
+		m.insert(bstr_k.clone(), DataType::Bloom(Box::default()));
+	}
+	match m.get_mut(&bstr_k) {
+		Some(DataType::Bloom(bf)) => Ok(Some(bf)),
 		Some(_) => Err(ERRMSG_WRONGTYPE),
-		None => Ok(DataType::Null)
+		None => Ok(None)
 	}
 }
 
-pub fn srem(k: &str, vs: Vec<String>) -> Result<DataType, &str> {
+// the bytes of the string stored at k
+fn bytes_at(m: &HashMap<DataType, DataType>, k: &str)
+	-> Result<Option<Vec<u8>>, &'static str> {
+	match m.get(&DataType::bulkStr(k)) {
+		Some(DataType::BulkString(s)) => Ok(Some(bytes::encode(s))),
+		Some(DataType::Integer(i)) => Ok(Some(i.to_string().into_bytes())),
+		Some(_) => Err(ERRMSG_WRONGTYPE),
+		None => Ok(None)
+	}
+}
+
+// the LOADCHUNK command restoring v, and what SCANDUMP gives of it
+fn chunk(v: &DataType) -> Option<(&'static str, Vec<u8>)> {
+	match v {
+		DataType::Bloom(bf) => Some(("bf.loadchunk", bf.to_bytes())),
+		DataType::CountMin(s) => Some(("cms.loadchunk", s.to_bytes())),
+		DataType::Cuckoo(cf) => Some(("cf.loadchunk", cf.to_bytes())),
+		DataType::TDigest(d) => Some(("tdigest.loadchunk", d.to_bytes())),
+		DataType::TimeSeries(s) => Some(("ts.loadchunk", s.to_bytes())),
+		DataType::TopK(t) => Some(("topk.loadchunk", t.to_bytes())),
+		_ => None
+	}
+}
+
+// the Count-Min sketch stored at k
+fn cms_at<'a>(m: &'a mut HashMap<DataType, DataType>, k: &str)
+	-> Result<Option<&'a mut cms::Sketch>, &'static str> {
+	match m.get_mut(&DataType::bulkStr(k)) {
+		Some(DataType::CountMin(s)) => Ok(Some(s)),
+		Some(_) => Err(ERRMSG_WRONGTYPE),
+		None => Ok(None)
+	}
+}
+
+// the cuckoo filter stored at k, created when missing if create is set
+fn cuckoo_at<'a>(m: &'a mut HashMap<DataType, DataType>, k: &str,
+	create: bool) -> Result<Option<&'a mut Cuckoo>, &'static str> {
 	let bstr_k: DataType = DataType::bulkStr(k);
-	let mut m = M.lock().unwrap();
+	if create && !m.contains_key(&bstr_k) {
+		m.insert(bstr_k.clone(), DataType::Cuckoo(Box::default()));
+	}
 	match m.get_mut(&bstr_k) {
-		Some(DataType::HashSet(hset)) => {
-			let cnt = vs.iter().map(|s| {
-				if hset.remove(&DataType::bulkStr(&s)) {
-					1i64
-				} else {
-					0i64
-				}
-			}).sum::<i64>();
-			if 0 == hset.len() {m.remove(&bstr_k);}
-			Ok(DataType::Integer(cnt))
-		},
+		Some(DataType::Cuckoo(cf)) => Ok(Some(cf)),
 		Some(_) => Err(ERRMSG_WRONGTYPE),
-		None => Ok(DataType::Integer(0))
+		None => Ok(None)
 	}
 }
 
-pub fn sunion(ks: Vec<String>) -> Result<DataType, &'static str> {
-	let m = M.lock().unwrap();
-	let mut wk: HashSet<DataType> = HashSet::new();
-	for k in ks {
-		let bstr_k: DataType = DataType::bulkStr(&k);
-		if let Some(DataType::HashSet(hset)) = m.get(&bstr_k) {
-			wk = wk.union(hset).cloned().collect();
-		} else {
-			return Err(ERRMSG_WRONGTYPE);
+// the deadline, in ms since the epoch, that the EX, PX, EXAT or PXAT
+// option o with n sets, None when out of range
+fn deadline(o: &str, n: u64, now: u64) -> Result<Option<u64>, &'static str> {
+	Ok(match o {
+		"ex" => n.checked_mul(1000).and_then(|n| n.checked_add(now)),
+		"px" => n.checked_add(now),
+		"exat" => n.checked_mul(1000),
+		"pxat" => Some(n),
+		_ => return Err(ERRMSG_SYNERR)
+	})
+}
+
+// the fields out of FIELDS numfields field [field ...]
+fn fields_arg(prms: &[String]) -> Result<&[String], &'static str> {
+	if prms.len() < 2 || !prms[0].eq_ignore_ascii_case("fields") {
+		return Err(ERRMSG_FIELDSARG);
+	}
+	match prms[1].parse::<usize>() {
+		Ok(0) | Err(_) => Err(ERRMSG_NUMFIELDS),
+		Ok(n) if n == prms.len() - 2 => Ok(&prms[2..]),
+		Ok(_) => Err(ERRMSG_NUMFIELDSARGS)
+	}
+}
+
+// x as the shortest text giving it back exactly; None for NaN and the
+// infinities
+fn float_str(x: f64) -> Option<String> {
+	if !x.is_finite() {
+		return None;
+	}
+	Some(if 0.0 == x {"0".to_string()} else {x.to_string()})
+}
+
+// the members found by a GEOSEARCH on the sorted set at k
+fn geo_hits(m: &mut HashMap<DataType, DataType>, k: &str, s: &Search)
+	-> Result<Vec<Hit>, &'static str> {
+	let z = match zset_at(m, k, false)? {
+		Some(z) => z,
+		None => return Ok(vec![])
+	};
+	let (lon, lat) = match &s.origin {
+		Origin::LonLat(lon, lat) => (*lon, *lat),
+		Origin::Member(e) => match z.score(e) {
+			Some(score) => geo::decode(score as u64),
+			None => return Err(geo::ERRMSG_NOMEMBER)
 		}
+	};
+	Ok(geo::search(z, lon, lat, s))
+}
+
+// add n to the integer stored at k, kept in its integer encoding
+fn incr_int(k: &str, n: i64) -> Result<DataType, &str> {
+	let bstr_k: DataType = DataType::bulkStr(k);
+	let mut m = lock();
+	let i = match m.get(&bstr_k) {
+		Some(DataType::Integer(i)) => *i,
+		Some(DataType::BulkString(s)) =>
+			s.parse::<i64>().map_err(|_| ERRMSG_VALNAIOOR)?,
+		Some(_) => return Err(ERRMSG_WRONGTYPE),
+		None => 0
+	};
+	let x = i.checked_add(n).ok_or(ERRMSG_OVERFLOW)?;
+	m.insert(bstr_k, DataType::Integer(x));
+	modified('$', "incrby", k);
+	Ok(DataType::Integer(x))
+}
+
+// the members found in all of the sets, no more than limit of them unless
+// it is 0
+fn inter(sets: &[Arc<Set>], limit: usize) -> Vec<DataType> {
+	let smallest = match sets.iter().min_by_key(|s| s.len()) {
+		Some(s) => s,
+		None => return vec![]
+	};
+	let found = smallest.iter()
+		.filter(|e| sets.iter().all(|s| s.contains(e)))
+		.cloned();
+	if 0 == limit {found.collect()} else {found.take(limit).collect()}
+}
+
+// the JSON document stored at k
+fn json_at<'a>(m: &'a mut HashMap<DataType, DataType>, k: &str)
+	-> Result<Option<&'a mut Json>, &'static str> {
+	match m.get_mut(&DataType::bulkStr(k)) {
+		Some(DataType::Json(doc)) => Ok(Some(doc)),
+		Some(_) => Err(ERRMSG_WRONGTYPE),
+		None => Ok(None)
 	}
-	Ok(DataType::List(wk.iter().cloned().collect::<Vec<_>>()))
 }
 
-pub fn sunionstore(dst: &str, ks: Vec<String>) -> Result<DataType, &str> {
+// f on the values at path in the document at k, Null without a document
+fn json_read<'a, F>(k: &str, path: Option<&'a String>, expected: &str, f: F)
+	-> Result<DataType, &'a str> where F: Fn(&Json) -> Option<DataType> {
+	let p = match path {
+		Some(path) => json::parse_path(path)?,
+		None => json::root()
+	};
+	let mut m = lock();
+	let doc = match json_at(&mut m, k)? {
+		Some(doc) => doc,
+		None => return Ok(DataType::Null)
+	};
+	let rs = doc.select(&p).iter()
+		.map(|l| doc.at(l).and_then(&f))
+		.collect();
+	Ok(json::reply(&p, rs, expected).unwrap_or_else(|e| DataType::err(&e)))
+}
+
+// the list stored at k, created when missing if create is set
+fn list_at<'a>(m: &'a mut HashMap<DataType, DataType>, k: &str,
+	create: bool) -> Result<Option<&'a mut VecDeque<DataType>>, &'static str> {
+	let bstr_k: DataType = DataType::bulkStr(k);
+	if create && !m.contains_key(&bstr_k) {
+		m.insert(bstr_k.clone(), DataType::Deque(VecDeque::new()));
+	}
+	match m.get_mut(&bstr_k) {
+		Some(DataType::Deque(l)) => Ok(Some(l)),
+		Some(_) => Err(ERRMSG_WRONGTYPE),
+		None => Ok(None)
+	}
+}
+
+// the keyspace, rid of the keys past their deadline
+fn lock() -> MutexGuard<'static, HashMap<DataType, DataType>> {
 	let mut m = M.lock().unwrap();
-	let mut wk: HashSet<DataType> = HashSet::new();
-	for k in ks {
+	let now = stream::now_ms();
+	for k in expiry::take_due(now) {
+		if m.remove(&DataType::bulkStr(&k)).is_some() {
+			modified('x', "expired", &k);
+		}
+	}
+	let mut hs = BTreeSet::new();
+	for (k, f) in expiry::take_due_fields(now) {
+		if let Some(DataType::HashMap(h)) = m.get_mut(&DataType::bulkStr(&k)) {
+			h.remove(&DataType::bulkStr(&f));
+			hs.insert(k);
+		}
+	}
+	for k in hs {
+		modified('h', "hexpired", &k);
 		let bstr_k: DataType = DataType::bulkStr(&k);
-		if let Some(DataType::HashSet(hset)) = m.get(&bstr_k) {
-			wk = wk.union(hset).cloned().collect();
-		} else {
-			return Err(ERRMSG_WRONGTYPE);
+		let empty = matches!(m.get(&bstr_k),
+			Some(DataType::HashMap(h)) if h.is_empty());
+		if empty {
+			m.remove(&bstr_k);
+			modified('g', "del", &k);
 		}
 	}
-	m.insert(DataType::bulkStr(dst), DataType::hset(&wk));
-	Ok(DataType::Integer(wk.len() as i64))
+	m
 }
 
-pub fn write_data<'a, W>(w: &'a mut W) -> Result<(), &'a str>
-	where W: Write {
-	let m = M.lock().unwrap();
-	for t in m.iter() {
-		match t.0 {
-			DataType::BulkString(_) => {},
-			_ => return Err(ERRMSG_WRONGTYPE)
-		}
-		let l = DataType::List(match t.1 {
-			DataType::HashMap(hm) => {
-				let mut v = vec![DataType::bulkStr("hset"), t.0.clone()];
-				v.extend(hm.iter().flat_map(|x|
-					vec![x.0.clone(), x.1.clone()]
-				));
-				v
-			},
-			DataType::HashSet(hs) => {
-				let mut v = vec![DataType::bulkStr("sadd"), t.0.clone()];
-				v.extend(hs.iter().cloned());
-				v
-			},
-			DataType::List(l) => {
-				let mut v = vec![DataType::bulkStr("rpush"), t.0.clone()];
-				v.extend(l.iter().cloned());
-				v
-			},
-			DataType::BulkString(_) => {
-				vec![DataType::bulkStr("set"), t.0.clone(), t.1.clone()]
-			},
-			_ => return Err(ERRMSG_WRONGTYPE)
-		});
-		if let Err(_) = write!(w, "{}", l) {
-			return Err(ERRMSG_WRITEFAIL);
+// key k was changed by event, of the given keyspace notification class
+fn modified(class: char, event: &str, k: &str) {
+	// a value replaced as a whole loses its deadline
+	if matches!(event, "del" | "set" | "restore" | "rename_from" | "rename_to"
+		| "sdiffstore" | "sinterstore" | "sunionstore" | "geosearchstore") {
+		expiry::clear(k);
+		expiry::clear_fields(k);
+	}
+	pubsub::notify(class, event, k);
+	search::touch(k);
+	tracking::invalidate(k);
+}
+
+fn parse_ids(ids: &[String]) -> Result<Vec<StreamId>, &'static str> {
+	ids.iter().map(|id| StreamId::parse(id, 0)).collect()
+}
+
+// a float as INCRBYFLOAT reads it: no NaN, infinities or spaces
+fn parse_float(s: &str) -> Option<f64> {
+	match s.parse::<f64>() {
+		Ok(x) if x.is_finite() && s.trim() == s => Some(x),
+		_ => None
+	}
+}
+
+// the text of a string value, whatever its encoding
+fn plain(v: &DataType) -> String {
+	match v {
+		DataType::Integer(i) => i.to_string(),
+		DataType::BulkString(s) => s.clone(),
+		_ => String::new()
+	}
+}
+
+// the commands recreating the value v stored at key k, serialized
+fn rebuild(k: &DataType, v: &DataType) -> Option<String> {
+	match k {
+		DataType::BulkString(_) => {},
+		_ => return None
+	}
+	if let Some((load, data)) = chunk(v) {
+		return Some(DataType::List(vec![
+			DataType::bulkStr(load),
+			k.clone(),
+			DataType::bulkStr("1"),
+			DataType::BulkString(bytes::decode(&data))
+		]).to_string());
+	}
+	Some(DataType::List(match v {
+		DataType::Stream(s) => return Some(
+			s.rebuild(k).iter().map(|l| l.to_string()).collect()
+		),
+		DataType::HashMap(hm) => {
+			let mut l = vec![DataType::bulkStr("hset"), k.clone()];
+			l.extend(hm.iter().flat_map(|x| vec![x.0.clone(), x.1.clone()]));
+			l
+		},
+		DataType::HashSet(hs) => {
+			let mut l = vec![DataType::bulkStr("sadd"), k.clone()];
+			l.extend(hs.iter().cloned());
+			l
+		},
+		DataType::Json(doc) => vec![
+			DataType::bulkStr("json.set"),
+			k.clone(),
+			DataType::bulkStr("$"),
+			DataType::BulkString(doc.to_text())
+		],
+		DataType::SortedSet(z) => {
+			let mut l = vec![DataType::bulkStr("zadd"), k.clone()];
+			l.extend(z.iter().flat_map(|(e, s)|
+				[DataType::bulkStr(&s.to_string()), DataType::bulkStr(e)]
+			));
+			l
+		},
+		DataType::Deque(vs) => {
+			let mut l = vec![DataType::bulkStr("rpush"), k.clone()];
+			l.extend(vs.iter().cloned());
+			l
+		},
+		DataType::BulkString(_) | DataType::Integer(_) => vec![
+			DataType::bulkStr("set"), k.clone(), DataType::BulkString(plain(v))
+		],
+		_ => return None
+	}).to_string())
+}
+
+// add a sample to the series at k, created out of o when missing, and
+// the samples its rules downsample to their destinations
+fn series_add(m: &mut HashMap<DataType, DataType>, k: &str, ts: u64,
+	v: f64, o: Option<&Options>) -> Result<(), &'static str> {
+	let bstr_k: DataType = DataType::bulkStr(k);
+	if let (Some(o), false) = (o, m.contains_key(&bstr_k)) {
+		m.insert(bstr_k, DataType::TimeSeries(Box::new(o.series.clone())));
+	}
+	let s = series_at(m, k)?.ok_or(timeseries::ERRMSG_NOKEY)?;
+	for (dst, ts, v) in s.add(ts, v, o.and_then(|o| o.on_duplicate))? {
+		if let Ok(Some(d)) = series_at(m, &dst) {
+			let _ = d.add(ts, v, Some(Policy::Last));
+			modified('d', "ts.add:dest", &dst);
 		}
 	}
 	Ok(())
 }
 
+// the time series stored at k
+fn series_at<'a>(m: &'a mut HashMap<DataType, DataType>, k: &str)
+	-> Result<Option<&'a mut Series>, &'static str> {
+	match m.get_mut(&DataType::bulkStr(k)) {
+		Some(DataType::TimeSeries(s)) => Ok(Some(s)),
+		Some(_) => Err(ERRMSG_WRONGTYPE),
+		None => Ok(None)
+	}
+}
+
+fn set_at<'a>(m: &'a mut HashMap<DataType, DataType>, k: &str,
+	create: bool) -> Result<Option<&'a mut Set>, &'static str> {
+	let bstr_k: DataType = DataType::bulkStr(k);
+	if create && !m.contains_key(&bstr_k) {
+		m.insert(bstr_k.clone(), DataType::hset(Set::default()));
+	}
+	match m.get_mut(&bstr_k) {
+		Some(DataType::HashSet(s)) => Ok(Some(Arc::make_mut(s))),
+		Some(_) => Err(ERRMSG_WRONGTYPE),
+		None => Ok(None)
+	}
+}
+
+// the sets at ks as they are now, a missing key being an empty set, for
+// going through them once the store is let go of
+fn sets_at(m: &HashMap<DataType, DataType>, ks: &[String])
+	-> Result<Vec<Arc<Set>>, &'static str> {
+	ks.iter().map(|k| match m.get(&DataType::bulkStr(k)) {
+		Some(DataType::HashSet(s)) => Ok(s.clone()),
+		Some(_) => Err(ERRMSG_WRONGTYPE),
+		None => Ok(Arc::new(Set::default()))
+	}).collect()
+}
+
+// whether s names the head of a list, LEFT, rather than its tail, RIGHT
+fn side(s: &str) -> Result<bool, &'static str> {
+	match s.to_ascii_lowercase().as_str() {
+		"left" => Ok(true),
+		"right" => Ok(false),
+		_ => Err(ERRMSG_SYNERR)
+	}
+}
+
+// ZADD, also behind GEOADD, creating the sorted set unless opts.xx
+fn sorted_add(k: &str, opts: &AddOptions, pairs: &[(f64, String)])
+	-> Result<DataType, &'static str> {
+	let mut m = lock();
+	let (added, updated) = match zset_at(&mut m, k, !opts.xx)? {
+		Some(z) => z.add(opts, pairs),
+		None => (0, 0)
+	};
+	if 0 < added + updated {
+		modified('z', "zadd", k);
+	}
+	Ok(DataType::Integer((added + if opts.ch {updated} else {0}) as i64))
+}
+
+// the stream stored at k, created when missing if create is set
+fn stream_at<'a>(m: &'a mut HashMap<DataType, DataType>, k: &str,
+	create: bool) -> Result<Option<&'a mut Stream>, &'static str> {
+	let bstr_k: DataType = DataType::bulkStr(k);
+	if create && !m.contains_key(&bstr_k) {
+		m.insert(bstr_k.clone(), DataType::Stream(Box::default()));
+	}
+	match m.get_mut(&bstr_k) {
+		Some(DataType::Stream(s)) => Ok(Some(s)),
+		Some(_) => Err(ERRMSG_WRONGTYPE),
+		None => Ok(None)
+	}
+}
+
+// the t-digest stored at k
+fn tdigest_at<'a>(m: &'a mut HashMap<DataType, DataType>, k: &str)
+	-> Result<Option<&'a mut Digest>, &'static str> {
+	match m.get_mut(&DataType::bulkStr(k)) {
+		Some(DataType::TDigest(d)) => Ok(Some(d)),
+		Some(_) => Err(ERRMSG_WRONGTYPE),
+		None => Ok(None)
+	}
+}
+
+// the Top-K sketch stored at k
+fn topk_at<'a>(m: &'a mut HashMap<DataType, DataType>, k: &str)
+	-> Result<Option<&'a mut TopK>, &'static str> {
+	match m.get_mut(&DataType::bulkStr(k)) {
+		Some(DataType::TopK(t)) => Ok(Some(t)),
+		Some(_) => Err(ERRMSG_WRONGTYPE),
+		None => Ok(None)
+	}
+}
+
+// the value the LOADCHUNK command cmd makes out of data
+fn union(sets: &[Arc<Set>]) -> Set {
+	sets.iter().flat_map(|s| s.iter().cloned()).collect()
+}
+
+fn unchunk(cmd: &str, data: &str) -> Result<DataType, &'static str> {
+	let b = bytes::encode(data);
+	Ok(match cmd {
+		"bf.loadchunk" => DataType::Bloom(Box::new(Bloom::parse(&b)?)),
+		"cf.loadchunk" => DataType::Cuckoo(Box::new(Cuckoo::parse(&b)?)),
+		"cms.loadchunk" =>
+			DataType::CountMin(Box::new(cms::Sketch::parse(&b)?)),
+		"tdigest.loadchunk" => DataType::TDigest(Box::new(Digest::parse(&b)?)),
+		"topk.loadchunk" => DataType::TopK(Box::new(TopK::parse(&b)?)),
+		"ts.loadchunk" => DataType::TimeSeries(Box::new(Series::parse(&b)?)),
+		_ => return Err(ERRMSG_BADPAYLOAD)
+	})
+}
+
+// the sorted set stored at k, created when missing if create is set
+fn zset_at<'a>(m: &'a mut HashMap<DataType, DataType>, k: &str,
+	create: bool) -> Result<Option<&'a mut SortedSet>, &'static str> {
+	let bstr_k: DataType = DataType::bulkStr(k);
+	if create && !m.contains_key(&bstr_k) {
+		m.insert(bstr_k.clone(), DataType::SortedSet(Box::default()));
+	}
+	match m.get_mut(&bstr_k) {
+		Some(DataType::SortedSet(z)) => Ok(Some(z)),
+		Some(_) => Err(ERRMSG_WRONGTYPE),
+		None => Ok(None)
+	}
+}
+
 #[cfg(test)]
 mod tests;
@@ -288,7 +288,7 @@ fn plan6() {
 	);
 	assert_eq!(
 		hincrby("fieldvalues", "field2", "64"),
-		Err("ERR Value is not an integer")
+		Err("ERR Hash value is not an integer")
 	);
 	assert_eq!(
 		del(&vec!["fieldvalues".to_string()]),
@@ -791,8 +791,9 @@ fn plan8() {
 		sismember("someset", "two"),
 		Ok(DataType::Integer(1))
 	);
-	assert!(matches!(srandmember("someset", "5"), Ok(DataType::List(_))));
-	if let Ok(DataType::List(l)) = srandmember("someset", "5") {
+	assert!(matches!(srandmember("someset", "5", false),
+		Ok(DataType::List(_))));
+	if let Ok(DataType::List(l)) = srandmember("someset", "5", false) {
 		let v = vec![
 			DataType::bulkStr("one"),
 			DataType::bulkStr("two"),
@@ -803,8 +804,9 @@ fn plan8() {
 		assert_eq!(l.len(), 5usize);
 		assert_eq!(v.iter().all(|e|{l.contains(e)}), true);
 	}
-	assert!(matches!(srandmember("someset", "6"), Ok(DataType::List(_))));
-	if let Ok(DataType::List(l)) = srandmember("someset", "6") {
+	assert!(matches!(srandmember("someset", "6", false),
+		Ok(DataType::List(_))));
+	if let Ok(DataType::List(l)) = srandmember("someset", "6", false) {
 		let v = vec![
 			DataType::bulkStr("one"),
 			DataType::bulkStr("two"),
@@ -815,8 +817,9 @@ fn plan8() {
 		assert_eq!(l.len(), 5usize);
 		assert_eq!(v.iter().all(|e|{l.contains(e)}), true);
 	}
-	assert!(matches!(srandmember("someset", "-4"), Ok(DataType::List(_))));
-	if let Ok(DataType::List(l)) = srandmember("someset", "-4") {
+	assert!(matches!(srandmember("someset", "-4", false),
+		Ok(DataType::List(_))));
+	if let Ok(DataType::List(l)) = srandmember("someset", "-4", false) {
 		let v = vec![
 			DataType::bulkStr("one"),
 			DataType::bulkStr("two"),
@@ -827,8 +830,9 @@ fn plan8() {
 		assert_eq!(l.len(), 4usize);
 		assert_eq!(l.iter().all(|e|{v.contains(e)}), true);
 	}
-	assert!(matches!(srandmember("someset", "-6"), Ok(DataType::List(_))));
-	if let Ok(DataType::List(l)) = srandmember("someset", "-6") {
+	assert!(matches!(srandmember("someset", "-6", false),
+		Ok(DataType::List(_))));
+	if let Ok(DataType::List(l)) = srandmember("someset", "-6", false) {
 		let v = vec![
 			DataType::bulkStr("one"),
 			DataType::bulkStr("two"),
@@ -839,11 +843,12 @@ fn plan8() {
 		assert_eq!(l.len(), 6usize);
 		assert_eq!(l.iter().all(|e|{v.contains(e)}), true);
 	}
-	assert!(matches!(srandmember("someset", "0"), Ok(DataType::List(_))));
-	if let Ok(DataType::List(l)) = srandmember("someset", "0") {
+	assert!(matches!(srandmember("someset", "0", false),
+		Ok(DataType::List(_))));
+	if let Ok(DataType::List(l)) = srandmember("someset", "0", false) {
 		assert_eq!(l.len(), 0usize);
 	}
-	assert_eq!(srandmember("nonexists", "5"), Ok(DataType::Null));
+	assert_eq!(srandmember("nonexists", "5", false), Ok(DataType::Null));
 	assert_eq!(
 		sadd(
 			"anotherset",
@@ -1158,3 +1163,334 @@ fn plan9() {
 		Ok(DataType::Integer(3))
 	);
 }
+
+#[test]
+#[serial]
+fn plan10() {
+	assert_eq!(
+		rpush("letters", vec!["a".to_string(), "b".to_string()], &false),
+		Ok(DataType::Integer(2))
+	);
+	let payload = match dump("letters") {
+		Ok(DataType::BulkString(p)) => p,
+		other => panic!("unexpected dump: {:?}", other)
+	};
+	assert_eq!(dump("nosuchkey"), Ok(DataType::Null));
+	assert_eq!(
+		restore("letters", &payload, false),
+		Err(ERRMSG_BUSYKEY)
+	);
+	assert_eq!(restore("copy", &payload, false), Ok(DataType::str("OK")));
+	assert_eq!(
+		lrange("copy", "0", "-1"),
+		Ok(DataType::List(vec![
+			DataType::bulkStr("a"),
+			DataType::bulkStr("b")
+		]))
+	);
+	assert_eq!(restore("copy", "junk", true), Err(ERRMSG_BADPAYLOAD));
+	assert!(contains("copy"));
+	assert_eq!(keys_where(|k| k.starts_with("co"), 10), vec!["copy"]);
+	assert_eq!(
+		del(&vec!["letters".to_string(), "copy".to_string()]),
+		Ok(DataType::Integer(2))
+	);
+}
+
+#[test]
+#[serial]
+fn plan11() {
+	assert_eq!(set("old", "v"), Ok(DataType::str("OK")));
+	assert_eq!(set("taken", "w"), Ok(DataType::str("OK")));
+	assert_eq!(rename("nosuchkey", "new", false), Err(ERRMSG_NOSKEY));
+	assert_eq!(rename("old", "taken", true), Ok(DataType::Integer(0)));
+	assert_eq!(rename("old", "new", true), Ok(DataType::Integer(1)));
+	assert_eq!(rename("new", "taken", false), Ok(DataType::str("OK")));
+	assert_eq!(get("taken"), Ok(DataType::bulkStr("v")));
+	assert!(!contains("new"));
+	assert_eq!(
+		del(&vec!["taken".to_string()]),
+		Ok(DataType::Integer(1))
+	);
+}
+
+#[test]
+#[serial]
+fn plan12() {
+	assert_eq!(set("f", "10.5"), Ok(DataType::str("OK")));
+	assert_eq!(incrbyfloat("f", "0.1"), Ok(DataType::bulkStr("10.6")));
+	assert_eq!(incrbyfloat("f", "-5"), Ok(DataType::bulkStr("5.6")));
+	assert_eq!(incrbyfloat("f", "5e3"), Ok(DataType::bulkStr("5005.6")));
+	assert_eq!(incrbyfloat("f", "-5005.6"), Ok(DataType::bulkStr("0")));
+	assert_eq!(incrbyfloat("g", "0.1"), Ok(DataType::bulkStr("0.1")));
+	assert_eq!(incrbyfloat("g", "0.2"),
+		Ok(DataType::bulkStr("0.30000000000000004")));
+	assert_eq!(incrbyfloat("g", "nan"), Err(ERRMSG_VALNAF));
+	assert_eq!(set("g", "1.7e308"), Ok(DataType::str("OK")));
+	assert_eq!(incrbyfloat("g", "1.7e308"), Err(ERRMSG_NANINF));
+	assert_eq!(incr("g"), Err(ERRMSG_VALNAIOOR));
+	assert_eq!(decrby("h", "-9223372036854775808"), Err(ERRMSG_OVERFLOW));
+	assert_eq!(incrby("h", "9223372036854775807"),
+		Ok(DataType::Integer(i64::MAX)));
+	assert_eq!(incr("h"), Err(ERRMSG_OVERFLOW));
+	assert_eq!(incrbyfloat("h", "1"),
+		Ok(DataType::bulkStr("9223372036854776000")));
+	// no digit gets rounded away
+	assert_eq!(set("j", "1234567890.123456"), Ok(DataType::str("OK")));
+	assert_eq!(incrbyfloat("j", "0"),
+		Ok(DataType::bulkStr("1234567890.123456")));
+	assert_eq!(hincrbyfloat("hf", "d", "1234567890.123456"),
+		Ok(DataType::bulkStr("1234567890.123456")));
+	assert_eq!(incr("i"), Ok(DataType::Integer(1)));
+	assert_eq!(append("i", "0"), Ok(DataType::Integer(2)));
+	assert_eq!(incrby("i", "-20"), Ok(DataType::Integer(-10)));
+	assert_eq!(mget(&vec!["i".to_string()]),
+		Ok(DataType::List(vec![DataType::bulkStr("-10")])));
+	assert_eq!(hincrbyfloat("hf", "a", "1.5"), Ok(DataType::bulkStr("1.5")));
+	assert_eq!(hincrby("hf", "a", "1"), Err(ERRMSG_HVALNAI));
+	assert_eq!(hincrby("hf", "b", "9223372036854775807"),
+		Ok(DataType::Integer(i64::MAX)));
+	assert_eq!(hincrby("hf", "b", "1"), Err(ERRMSG_OVERFLOW));
+	assert_eq!(hset("hf", vec!["c".to_string(), "x".to_string()], &false),
+		Ok(DataType::Integer(1)));
+	assert_eq!(hincrbyfloat("hf", "c", "1"), Err(ERRMSG_HVALNAF));
+	assert_eq!(
+		del(&["f", "g", "h", "i", "j", "hf"].map(|k| k.to_string()).to_vec()),
+		Ok(DataType::Integer(6))
+	);
+}
+
+#[test]
+#[serial]
+fn plan13() {
+	let prms = |ps: &[&str]|
+		ps.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+	assert_eq!(set("s", "This is a string"), Ok(DataType::str("OK")));
+	assert_eq!(getrange("s", "0", "3"), Ok(DataType::bulkStr("This")));
+	assert_eq!(getrange("s", "-3", "-1"), Ok(DataType::bulkStr("ing")));
+	assert_eq!(getrange("s", "10", "100"), Ok(DataType::bulkStr("string")));
+	assert_eq!(getrange("s", "-1", "-5"), Ok(DataType::bulkStr("")));
+	assert_eq!(getrange("s", "x", "1"), Err(ERRMSG_VALNAIOOR));
+	assert_eq!(getrange("nokey", "0", "-1"), Ok(DataType::bulkStr("")));
+	assert_eq!(set("empty", ""), Ok(DataType::str("OK")));
+	assert_eq!(getrange("empty", "-3", "-1"), Ok(DataType::bulkStr("")));
+	assert_eq!(setrange("s", "10", "thing"), Ok(DataType::Integer(16)));
+	assert_eq!(get("s"), Ok(DataType::bulkStr("This is a thingg")));
+	assert_eq!(setrange("p", "3", "ab"), Ok(DataType::Integer(5)));
+	assert_eq!(get("p"), Ok(DataType::bulkStr("\0\0\0ab")));
+	assert_eq!(setrange("p", "-1", "ab"), Err(ERRMSG_OFFOOR));
+	assert_eq!(setrange("p", "536870911", "ab"), Err(ERRMSG_STRTOOBIG));
+	assert_eq!(strlen("p"), Ok(DataType::Integer(5)));
+	assert_eq!(strlen("nokey"), Ok(DataType::Integer(0)));
+	assert_eq!(msetnx(&prms(&["a", "ohmytext", "p", "x"])),
+		Ok(DataType::Integer(0)));
+	assert!(!contains("a"));
+	assert_eq!(msetnx(&prms(&["a", "ohmytext", "b", "mynewtext"])),
+		Ok(DataType::Integer(1)));
+	assert_eq!(lcs("a", "b", &[]), Ok(DataType::bulkStr("mytext")));
+	assert_eq!(lcs("a", "b", &prms(&["LEN"])), Ok(DataType::Integer(6)));
+	let span = |a: i64, b: i64|
+		DataType::List(vec![DataType::Integer(a), DataType::Integer(b)]);
+	assert_eq!(lcs("a", "b", &prms(&["IDX", "MINMATCHLEN", "4",
+		"WITHMATCHLEN"])), Ok(DataType::List(vec![
+		DataType::bulkStr("matches"),
+		DataType::List(vec![DataType::List(vec![
+			span(4, 7), span(5, 8), DataType::Integer(4)
+		])]),
+		DataType::bulkStr("len"),
+		DataType::Integer(6)
+	])));
+	assert_eq!(lcs("a", "b", &prms(&["IDX"])).map(|r| match r {
+		DataType::List(l) => l[1].clone(),
+		_ => DataType::Null
+	}), Ok(DataType::List(vec![
+		DataType::List(vec![span(4, 7), span(5, 8)]),
+		DataType::List(vec![span(2, 3), span(0, 1)])
+	])));
+	assert_eq!(lcs("a", "b", &prms(&["LEN", "IDX"])), Err(ERRMSG_IDXANDLEN));
+	assert_eq!(ttl("a", false), Ok(DataType::Integer(-1)));
+	assert_eq!(ttl("nokey", false), Ok(DataType::Integer(-2)));
+	assert_eq!(getex("a", &prms(&["EX", "100"])),
+		Ok(DataType::bulkStr("ohmytext")));
+	assert_eq!(ttl("a", false), Ok(DataType::Integer(100)));
+	assert_eq!(getex("a", &prms(&["EX", "0"])), Err(ERRMSG_BADEXPIRE));
+	assert_eq!(getex("a", &prms(&["PERSIST"])),
+		Ok(DataType::bulkStr("ohmytext")));
+	assert_eq!(ttl("a", true), Ok(DataType::Integer(-1)));
+	assert_eq!(getex("b", &prms(&["PX", "20"])),
+		Ok(DataType::bulkStr("mynewtext")));
+	assert_eq!(rename("b", "c", false), Ok(DataType::str("OK")));
+	assert!(matches!(ttl("c", true), Ok(DataType::Integer(1..=20))));
+	std::thread::sleep(std::time::Duration::from_millis(40));
+	assert_eq!(get("c"), Ok(DataType::Null));
+	assert_eq!(getex("a", &prms(&["PXAT", "1"])),
+		Ok(DataType::bulkStr("ohmytext")));
+	assert!(!contains("a"));
+	assert_eq!(
+		del(&["s", "p", "empty"].map(|k| k.to_string()).to_vec()),
+		Ok(DataType::Integer(3))
+	);
+}
+
+#[test]
+#[serial]
+fn plan14() {
+	let prms = |ps: &[&str]|
+		ps.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+	let items = |ps: &[&str]|
+		DataType::List(ps.iter().map(|p| DataType::bulkStr(p)).collect());
+	let ints = |is: &[i64]|
+		DataType::List(is.iter().map(|i| DataType::Integer(*i)).collect());
+	assert_eq!(rpush("l", prms(&["a", "b", "c", "b", "d", "b"]), &false),
+		Ok(DataType::Integer(6)));
+	assert_eq!(lpos("l", "b", &[]), Ok(DataType::Integer(1)));
+	assert_eq!(lpos("l", "b", &prms(&["RANK", "2"])), Ok(DataType::Integer(3)));
+	assert_eq!(lpos("l", "b", &prms(&["RANK", "-1"])),
+		Ok(DataType::Integer(5)));
+	assert_eq!(lpos("l", "b", &prms(&["COUNT", "0"])), Ok(ints(&[1, 3, 5])));
+	assert_eq!(lpos("l", "b", &prms(&["RANK", "-2", "COUNT", "5"])),
+		Ok(ints(&[3, 1])));
+	assert_eq!(lpos("l", "b", &prms(&["COUNT", "0", "MAXLEN", "4"])),
+		Ok(ints(&[1, 3])));
+	assert_eq!(lpos("l", "x", &[]), Ok(DataType::Null));
+	assert_eq!(lpos("l", "b", &prms(&["RANK", "0"])), Err(ERRMSG_RANKZERO));
+	assert_eq!(lpos("l", "b", &prms(&["COUNT", "-1"])), Err(ERRMSG_CNTNEG));
+	assert_eq!(lmove("l", "m", "LEFT", "RIGHT"), Ok(DataType::bulkStr("a")));
+	assert_eq!(lmove("l", "m", "right", "left"), Ok(DataType::bulkStr("b")));
+	assert_eq!(lmove("l", "l", "left", "right"), Ok(DataType::bulkStr("b")));
+	assert_eq!(lmove("l", "m", "up", "left"), Err(ERRMSG_SYNERR));
+	assert_eq!(lmove("nokey", "m", "left", "left"), Ok(DataType::Null));
+	assert_eq!(lrange("l", "0", "-1"), Ok(items(&["c", "b", "d", "b"])));
+	assert_eq!(lrange("m", "0", "-1"), Ok(items(&["b", "a"])));
+	assert_eq!(lmpop(&prms(&["2", "nokey", "m", "RIGHT", "COUNT", "5"])),
+		Ok(DataType::List(vec![DataType::bulkStr("m"), items(&["a", "b"])])));
+	assert!(!contains("m"));
+	assert_eq!(lmpop(&prms(&["2", "m", "l", "LEFT"])),
+		Ok(DataType::List(vec![DataType::bulkStr("l"), items(&["c"])])));
+	assert_eq!(lmpop(&prms(&["1", "m", "LEFT"])), Ok(DataType::Null));
+	assert_eq!(lmpop(&prms(&["0", "l", "LEFT"])), Err(ERRMSG_NUMKEYS));
+	assert_eq!(lmpop(&prms(&["1", "l", "LEFT", "COUNT", "0"])),
+		Err(ERRMSG_CNTNPOS));
+	assert_eq!(set("s", "v"), Ok(DataType::str("OK")));
+	assert_eq!(lmove("l", "s", "left", "left"), Err(ERRMSG_WRONGTYPE));
+	assert_eq!(llen("l"), Ok(DataType::Integer(3)));
+	assert_eq!(
+		del(&["l", "s"].map(|k| k.to_string()).to_vec()),
+		Ok(DataType::Integer(2))
+	);
+}
+
+#[test]
+#[serial]
+fn plan15() {
+	let prms = |ps: &[&str]|
+		ps.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+	let ints = |is: &[i64]|
+		DataType::List(is.iter().map(|i| DataType::Integer(*i)).collect());
+	assert_eq!(hset("h", prms(&["a", "1", "b", "22", "c", "333"]), &false),
+		Ok(DataType::Integer(3)));
+	assert_eq!(hstrlen("h", "c"), Ok(DataType::Integer(3)));
+	assert_eq!(hstrlen("h", "x"), Ok(DataType::Integer(0)));
+	assert_eq!(hexpire("hexpire", &prms(&["h", "100", "FIELDS", "2", "a",
+		"x"])), Ok(ints(&[1, -2])));
+	assert_eq!(hexpire("hexpire", &prms(&["h", "200", "NX", "FIELDS", "2",
+		"a", "b"])), Ok(ints(&[0, 1])));
+	assert_eq!(hexpire("hpexpire", &prms(&["h", "50000", "GT", "FIELDS",
+		"1", "a"])), Ok(ints(&[0])));
+	assert_eq!(httl("httl", &prms(&["h", "FIELDS", "3", "a", "b", "c"])),
+		Ok(ints(&[100, 200, -1])));
+	assert_eq!(hpersist(&prms(&["h", "FIELDS", "3", "a", "c", "x"])),
+		Ok(ints(&[1, -1, -2])));
+	assert_eq!(hexpire("hexpire", &prms(&["h", "1", "FIELDS", "2", "a"])),
+		Err(ERRMSG_NUMFIELDSARGS));
+	assert_eq!(hexpire("hexpire", &prms(&["h", "1", "FIELD", "1", "a"])),
+		Err(ERRMSG_FIELDSARG));
+	assert_eq!(hexpire("hexpireat", &prms(&["h", "1", "FIELDS", "1", "a"])),
+		Ok(ints(&[2])));
+	assert_eq!(hgetex(&prms(&["h", "PX", "20", "FIELDS", "2", "c", "a"])),
+		Ok(DataType::List(vec![DataType::bulkStr("333"), DataType::Null])));
+	assert!(matches!(httl("hpttl", &prms(&["h", "FIELDS", "1", "c"])),
+		Ok(DataType::List(l)) if matches!(l[0], DataType::Integer(1..=20))));
+	std::thread::sleep(std::time::Duration::from_millis(40));
+	assert_eq!(hgetall("h").map(|h| match h {
+		DataType::HashMap(h) => h.len(),
+		_ => 0
+	}), Ok(1));
+	// renaming keeps the deadlines, HSET drops them
+	assert_eq!(rename("h", "g", false), Ok(DataType::str("OK")));
+	let at = expiry::field("g", "b").map(|at| (at / 1000) as i64);
+	assert_eq!(httl("hexpiretime", &prms(&["g", "FIELDS", "1", "b"])),
+		Ok(ints(&[at.unwrap_or(0)])));
+	assert_eq!(hset("g", prms(&["b", "2"]), &false), Ok(DataType::Integer(1)));
+	assert_eq!(expiry::field("g", "b"), None);
+	assert_eq!(hset("g", prms(&["d", "4", "e", "5"]), &false),
+		Ok(DataType::Integer(2)));
+	assert_eq!(hrandfield("g", &prms(&["-5"])).map(|r| match r {
+		DataType::List(l) => l.len(),
+		_ => 0
+	}), Ok(5));
+	assert_eq!(hrandfield("g", &prms(&["5", "WITHVALUES"])).map(|r| match r {
+		DataType::List(l) => l.len(),
+		_ => 0
+	}), Ok(6));
+	assert_eq!(hrandfield("g", &prms(&["1", "x"])), Err(ERRMSG_SYNERR));
+	assert_eq!(hrandfield("nokey", &[]), Ok(DataType::Null));
+	assert_eq!(hgetdel(&prms(&["g", "FIELDS", "2", "b", "x"])),
+		Ok(DataType::List(vec![DataType::bulkStr("2"), DataType::Null])));
+	assert_eq!(hgetdel(&prms(&["g", "FIELDS", "2", "d", "e"])),
+		Ok(DataType::List(vec![
+			DataType::bulkStr("4"), DataType::bulkStr("5")
+		])));
+	assert!(!contains("g"));
+}
+
+#[test]
+#[serial]
+fn plan16() {
+	let prms = |ps: &[&str]|
+		ps.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+	let len = |r: Result<DataType, &str>| match r {
+		Ok(DataType::List(l)) => l.len(),
+		_ => 0
+	};
+	assert_eq!(sadd("s1", prms(&["a", "b", "c", "d"])),
+		Ok(DataType::Integer(4)));
+	assert_eq!(sadd("s2", prms(&["b", "c", "d", "e"])),
+		Ok(DataType::Integer(4)));
+	assert_eq!(sadd("s3", prms(&["c", "d", "f"])), Ok(DataType::Integer(3)));
+	// without a count, a single member or nil
+	assert!(matches!(srandmember("s1", "1", true),
+		Ok(DataType::BulkString(_))));
+	assert_eq!(srandmember("nokey", "1", true), Ok(DataType::Null));
+	// a count past the size of the set gives all of it, unless repeats are
+	// allowed by a negative one
+	assert_eq!(len(srandmember("s1", "100", false)), 4);
+	assert_eq!(len(srandmember("s1", "-100", false)), 100);
+	assert_eq!(len(spop("s1", "100", false)), 4);
+	assert!(!contains("s1"));
+	assert_eq!(spop("s1", "1", true), Ok(DataType::Null));
+	assert_eq!(sintercard(&prms(&["2", "s2", "s3"])), Ok(DataType::Integer(2)));
+	assert_eq!(sintercard(&prms(&["2", "s2", "s3", "LIMIT", "1"])),
+		Ok(DataType::Integer(1)));
+	assert_eq!(sintercard(&prms(&["2", "s2", "s3", "limit", "0"])),
+		Ok(DataType::Integer(2)));
+	assert_eq!(sintercard(&prms(&["2", "s2", "nokey"])),
+		Ok(DataType::Integer(0)));
+	assert_eq!(sintercard(&prms(&["0", "s2"])), Err(ERRMSG_NUMKEYS));
+	assert_eq!(sintercard(&prms(&["3", "s2", "s3"])), Err(ERRMSG_NUMKEYSARGS));
+	assert_eq!(sintercard(&prms(&["1", "s2", "LIMIT", "-1"])),
+		Err(ERRMSG_LIMITNEG));
+	assert_eq!(sintercard(&prms(&["1", "s2", "LIMIT"])), Err(ERRMSG_SYNERR));
+	assert_eq!(set("str", "x"), Ok(DataType::str("OK")));
+	assert_eq!(sintercard(&prms(&["2", "s2", "str"])), Err(ERRMSG_WRONGTYPE));
+	// a member list taken from SMEMBERS stays as it was after later writes
+	let snapshot = smembers("s2");
+	assert_eq!(srem("s2", prms(&["b", "e"])), Ok(DataType::Integer(2)));
+	assert!(matches!(snapshot, Ok(DataType::HashSet(s)) if 4 == s.len()));
+	assert_eq!(scard("s2"), Ok(DataType::Integer(2)));
+	assert_eq!(len(sunion(prms(&["s2", "nokey", "s3"]))), 3);
+	assert_eq!(sinterstore("s4", "s2", prms(&["nokey"])),
+		Ok(DataType::Integer(0)));
+	assert!(!contains("s4"));
+	assert_eq!(del(&prms(&["s2", "s3", "str"])), Ok(DataType::Integer(3)));
+}
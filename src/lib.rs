@@ -1,7 +1,35 @@
+pub mod bitmap;
+pub mod bloom;
+pub mod bytes;
 pub mod cli;
+pub mod client;
+pub mod cluster;
+pub mod cms;
 pub mod comm;
 pub mod command;
+pub mod config;
+pub mod cuckoo;
 pub mod datatype;
+pub mod expiry;
+pub mod function;
+pub mod geo;
+pub mod glob;
+pub mod hll;
+pub mod json;
 pub mod kv;
+pub mod logger;
+pub mod monitor;
 pub mod parser;
+pub mod pubsub;
+pub mod replication;
 pub mod request;
+pub mod script;
+pub mod search;
+pub mod set;
+pub mod stats;
+pub mod stream;
+pub mod tdigest;
+pub mod timeseries;
+pub mod topk;
+pub mod tracking;
+pub mod zset;
@@ -0,0 +1,34 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use super::config;
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Level {
+	Debug,
+	Verbose,
+	Notice,
+	Warning
+}
+
+pub fn log(level: Level, msg: &str) {
+	let threshold = match config::get("loglevel").as_str() {
+		"debug" => Level::Debug,
+		"verbose" => Level::Verbose,
+		"notice" => Level::Notice,
+		"warning" => Level::Warning,
+		_ => return
+	};
+	if level < threshold {
+		return;
+	}
+	let path = config::get("logfile");
+	if path.is_empty() {
+		println!("{}", msg);
+	} else if let Ok(mut f) = OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(&path) {
+		let _ = writeln!(f, "{}", msg);
+	}
+}
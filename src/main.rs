@@ -1,18 +1,19 @@
 use signal_hook::consts::{SIGINT, SIGTERM};
 use signal_hook::flag::register;
-use std::fs::File;
+use std::fs::{self, File};
 use std::process::exit;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use threadpool::ThreadPool;
 
-use kyvi::{cli, comm, command, kv};
+use kyvi::logger::{self, Level};
+use kyvi::{cli, cluster, comm, command, config, kv, replication};
 
 fn main() {
 	match cli::do_args() {
-		Ok((to_quit, datafilepath, bindaddr, thpoolsize)) => {
+		Ok(to_quit) => {
 			let is_stopped = Arc::new(AtomicBool::new(false));
 			for sig in vec![SIGINT, SIGTERM] {
 				if let Err(e) = register(sig, Arc::clone(&is_stopped)) {
@@ -20,49 +21,48 @@ fn main() {
 					return;
 				}
 			}
-			let pool = ThreadPool::new(thpoolsize);
+			let pool = ThreadPool::new(config::get_usize("thpool"));
 			if !to_quit {
+				let datafilepath = config::get("datapath");
 				if 0 < datafilepath.len() {
-					// this Vec is used to discard output from the parser
-					let mut buf: Vec<u8> = Vec::new();
+					// set loglevel to debug to see entries failing to load
 					match File::open(datafilepath.clone()) {
-						Ok(f) => command::process(&f, &mut buf),
+						Ok(f) => command::replay(&f),
 						Err(e) => eprintln!("{:?}", e.to_string())
 					}
-					// uncomment the 3 next lines for debugging as needed:
-					// String::from_utf8(buf).unwrap().lines()
-					// 	.filter(|s| {s.starts_with("-")})
-					// 	.for_each(|s| {println!("{}", s);});
+				}
+				replication::start_heartbeat();
+				kv::start_expiry();
+				if cluster::enabled() {
+					if let Err(e) = cluster::init() {
+						eprintln!("{}", e);
+						return;
+					}
+					cluster::start_bus();
 				}
 
 				thread::spawn(move || {
+					let mut last_save = Instant::now();
 					while !is_stopped.load(Ordering::Relaxed) {
 						thread::sleep(Duration::from_secs(2));
-					}
-					exit(
-						if 0 < datafilepath.len() {
-							match File::options()
-								.create(true)
-								.truncate(true)
-								.write(true)
-								.open(datafilepath.clone()) {
-								Ok(mut f) => {
-									if let Err(e) = kv::write_data(&mut f) {
-										eprintln!("{:?}", e);
-									};
-									0i32
-								},
-								Err(e) => {
-									eprintln!("{:?}", e.to_string());
-									1i32
-								}
-							}
-						} else {
-							0i32
+						let interval = config::get_u64("save");
+						if 0 < interval
+							&& last_save.elapsed().as_secs() >= interval {
+							save();
+							last_save = Instant::now();
 						}
-					);
+					}
+					exit(save());
 				});
-				println!("Listening on \"{bindaddr}\"...");
+				let bindaddr = format!(
+					"{}:{}",
+					config::get("bind"),
+					config::get("port")
+				);
+				logger::log(
+					Level::Notice,
+					&format!("Listening on \"{bindaddr}\"...")
+				);
 				if let Err(e) = comm::listen_to(&pool, &bindaddr) {
 					eprintln!("{}", e.to_string());
 				}
@@ -71,3 +71,30 @@ fn main() {
 		Err(e) => eprintln!("{e}")
 	}
 }
+
+// write all data to the configured data file, returning an exit status
+fn save() -> i32 {
+	let datafilepath = config::get("datapath");
+	if datafilepath.is_empty() {
+		return 0;
+	}
+	// the previous snapshot stays in place until the new one is complete
+	let tmppath = format!("{}.tmp", datafilepath);
+	let written = File::create(&tmppath)
+		.map_err(|e| e.to_string())
+		.and_then(|mut f| {
+			kv::write_data(&mut f).map_err(|e| e.to_string())?;
+			f.sync_all().map_err(|e| e.to_string())
+		})
+		.and_then(|_| {
+			fs::rename(&tmppath, &datafilepath).map_err(|e| e.to_string())
+		});
+	match written {
+		Ok(_) => 0,
+		Err(e) => {
+			let _ = fs::remove_file(&tmppath);
+			eprintln!("{:?}", e);
+			1
+		}
+	}
+}
@@ -0,0 +1,92 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+
+use super::bytes;
+use super::request::Request;
+
+// number of feed lines a monitor may lag behind before lines get dropped
+const BACKLOG: usize = 1024;
+
+const REDACTED: &str = "\"(redacted)\"";
+
+lazy_static! {
+	static ref MONITORS: Mutex<Vec<SyncSender<String>>> =
+		Mutex::new(Vec::new());
+}
+
+// cheap check on the dispatch path so that nothing gets formatted when
+// nobody is watching
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn register() -> Receiver<String> {
+	let (tx, rx) = sync_channel(BACKLOG);
+	let mut monitors = MONITORS.lock().unwrap();
+	monitors.push(tx);
+	COUNT.store(monitors.len(), Ordering::Relaxed);
+	rx
+}
+
+pub fn feed(db: usize, addr: &str, req: &Request) {
+	// as with Redis, monitors never see MONITOR itself
+	if 0 == COUNT.load(Ordering::Relaxed) || "monitor" == req.command {
+		return;
+	}
+	let line = format_line(db, addr, req);
+	let mut monitors = MONITORS.lock().unwrap();
+	// a slow monitor only loses lines, it never blocks the dispatcher
+	monitors.retain(|tx| {
+		!matches!(tx.try_send(line.clone()), Err(TrySendError::Disconnected(_)))
+	});
+	COUNT.store(monitors.len(), Ordering::Relaxed);
+}
+
+fn format_line(db: usize, addr: &str, req: &Request) -> String {
+	let ts = match SystemTime::now().duration_since(UNIX_EPOCH) {
+		Ok(d) => format!("{}.{:06}", d.as_secs(), d.subsec_micros()),
+		Err(_) => String::from("0.000000")
+	};
+	let mut line = format!("+{} [{} {}] {}", ts, db, addr, quote(&req.command));
+	let mut redact = matches!(req.command.as_str(), "auth");
+	for prm in req.parameters.iter() {
+		line.push(' ');
+		if redact {
+			line.push_str(REDACTED);
+		} else {
+			line.push_str(&quote(prm));
+		}
+		// HELLO and MIGRATE carry credentials after the AUTH token
+		if matches!(req.command.as_str(), "hello" | "migrate")
+			&& prm.eq_ignore_ascii_case("auth") {
+			redact = true;
+		}
+	}
+	line.push_str("\r\n");
+	line
+}
+
+pub fn quote(s: &str) -> String {
+	let mut q = String::with_capacity(s.len() + 2);
+	q.push('"');
+	for b in bytes::encode(s) {
+		match b {
+			b'\\' => q.push_str("\\\\"),
+			b'"' => q.push_str("\\\""),
+			b'\n' => q.push_str("\\n"),
+			b'\r' => q.push_str("\\r"),
+			b'\t' => q.push_str("\\t"),
+			0x07 => q.push_str("\\a"),
+			0x08 => q.push_str("\\b"),
+			0x20..=0x7e => q.push(b as char),
+			_ => q.push_str(&format!("\\x{:02x}", b))
+		}
+	}
+	q.push('"');
+	q
+}
+
+#[cfg(test)]
+mod tests;
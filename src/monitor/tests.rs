@@ -0,0 +1,47 @@
+use super::*;
+
+fn req(cmd: &str, prms: &[&str]) -> Request {
+	Request {
+		command: cmd.to_string(),
+		parameters: prms.iter().map(|s| s.to_string()).collect()
+	}
+}
+
+#[test]
+fn plan1() {
+	assert_eq!(quote("plain"), "\"plain\"");
+	assert_eq!(quote("a \"b\"\\c"), "\"a \\\"b\\\"\\\\c\"");
+	assert_eq!(quote("\r\n\t"), "\"\\r\\n\\t\"");
+	assert_eq!(quote("\u{1}\u{c3}\u{a9}"), "\"\\x01\\xc3\\xa9\"");
+}
+
+#[test]
+fn plan2() {
+	let line = format_line(0, "127.0.0.1:50000", &req("set", &["k", "v"]));
+	assert!(line.starts_with("+"));
+	assert!(line.ends_with(" [0 127.0.0.1:50000] \"set\" \"k\" \"v\"\r\n"));
+	let line = format_line(0, "127.0.0.1:50000", &req("auth", &["u", "p"]));
+	assert!(line.ends_with(
+		"\"auth\" \"(redacted)\" \"(redacted)\"\r\n"
+	));
+	let line = format_line(
+		0,
+		"127.0.0.1:50000",
+		&req("hello", &["3", "AUTH", "u", "p"])
+	);
+	assert!(line.ends_with(
+		"\"hello\" \"3\" \"AUTH\" \"(redacted)\" \"(redacted)\"\r\n"
+	));
+}
+
+#[test]
+fn plan3() {
+	let rx = register();
+	feed(0, "127.0.0.1:50000", &req("monitor", &[]));
+	assert!(rx.try_recv().is_err());
+	feed(0, "127.0.0.1:50000", &req("get", &["k"]));
+	assert!(rx.recv().unwrap().ends_with(" \"get\" \"k\"\r\n"));
+	drop(rx);
+	feed(0, "127.0.0.1:50000", &req("get", &["k"]));
+	assert_eq!(COUNT.load(Ordering::Relaxed), 0);
+}
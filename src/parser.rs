@@ -1,9 +1,12 @@
 use std::io::{BufRead, BufReader, Read};
 
+use super::bytes;
+use super::config;
 use super::request::Request;
 
 const ERRMSG_BADLISTLEN: &str = "ERR Invalid list length";
 const ERRMSG_BADSTRLEN: &str = "ERR Invalid string length";
+const ERRMSG_BULKTOOBIG: &str = "ERR Protocol error: invalid bulk length";
 const ERRMSG_CNXERR: &str = "ERR Connection error";
 const ERRMSG_EOF: &str = "ERR EOF reached";
 const ERRMSG_LISTLENDIFF: &str = "ERR Contents unmatch list length";
@@ -11,6 +14,8 @@ const ERRMSG_PROTOERR: &str = "ERR Protocol error";
 const ERRMSG_STRLENDIFF: &str = "ERR Contents unmatch string length";
 
 const EMPTY_STRING: String = String::new();
+// most memory set aside for a list or bulk string before its contents come
+const PREALLOC_MAX: usize = 64 * 1024;
 
 pub fn parse<R: Read>(reader: &mut BufReader<R>) -> Result<Request, &str> {
 	let mut prms = get_parameters(reader)?;
@@ -23,66 +28,59 @@ pub fn parse<R: Read>(reader: &mut BufReader<R>) -> Result<Request, &str> {
 }
 
 fn get_parameters<R: Read>(reader: &mut BufReader<R>)
-	-> Result<Vec<String>, &str> {
-	let mut parameters: Vec<String> = Vec::new();
-	let mut llen: usize = 0;
-	let mut slen: usize = 0;
-	let mut lcnt: usize = 0;
-	let mut sln: String = String::new();
-	let mut sbuf: String = String::new();
-	loop {
-		sln.clear();
-		let line = match reader.read_line(&mut sln) {
-			Ok(0) => return Err(ERRMSG_EOF),
-			Ok(_) => sln.trim_end(),
-			Err(_) => return Err(ERRMSG_CNXERR)
-		};
-		if 0 == line.len() && (sbuf.len() < slen || lcnt < llen) {
-			return Err(ERRMSG_PROTOERR);
+	-> Result<Vec<String>, &'static str> {
+	let line = read_header(reader)?;
+	let llen: usize = match line.strip_prefix('*') {
+		Some(n) => match n.parse::<usize>() {
+			Ok(n) => n,
+			Err(_) => return Err(ERRMSG_BADLISTLEN)
+		},
+		None => return Err(ERRMSG_PROTOERR)
+	};
+	let mut parameters: Vec<String> =
+		Vec::with_capacity(llen.min(PREALLOC_MAX));
+	while parameters.len() < llen {
+		let line = match read_header(reader) {
+			Err(ERRMSG_EOF) => return Err(ERRMSG_LISTLENDIFF),
+			other => other?
 		};
-		let c = line.chars().nth(0).unwrap_or('\0');
-		if 0 == llen {
-			if '*' == c {
-				match line[1..].parse::<usize>() {
-					Ok(n) => llen = n,
-					Err(_) => return Err(ERRMSG_BADLISTLEN)
-				};
-			} else {
-				return Err(ERRMSG_PROTOERR);
-			};
-		} else if 0 == slen {
-			if '$' == c {
-				match line[1..].parse::<usize>() {
-					Ok(n) => slen = n,
-					Err(_) => return Err(ERRMSG_BADSTRLEN)
-				};
-			} else {
-				return Err(ERRMSG_PROTOERR);
-			};
-		} else if 0 < slen {
-			if sbuf.len() < slen {
-				sbuf.push_str(&line);
-			};
-			if sbuf.len() == slen {
-				parameters.push(sbuf.clone());
-				sbuf.clear();
-				slen = 0;
-				lcnt += 1;
-			} else if sbuf.len() > slen {
-				return Err(ERRMSG_STRLENDIFF);
-			};
-		} else {
-			return Err(ERRMSG_PROTOERR);
+		let slen: usize = match line.strip_prefix('$') {
+			Some(n) => match n.parse::<usize>() {
+				Ok(n) if n > config::get_usize("proto-max-bulk-len") =>
+					return Err(ERRMSG_BULKTOOBIG),
+				Ok(n) => n,
+				Err(_) => return Err(ERRMSG_BADSTRLEN)
+			},
+			None => return Err(ERRMSG_PROTOERR)
 		};
-		if 0 < llen && lcnt == llen {
-			break;
+		// read the exact number of bytes so that values may hold anything,
+		// CR and LF included, followed by the terminating CRLF, the buffer
+		// growing as they arrive rather than trusting the announced length
+		let mut sbuf: Vec<u8> =
+			Vec::with_capacity((slen + 2).min(PREALLOC_MAX));
+		match reader.by_ref().take(slen as u64 + 2).read_to_end(&mut sbuf) {
+			Ok(n) if n == slen + 2 => {},
+			_ => return Err(ERRMSG_STRLENDIFF)
 		}
-	};
-	if slen != 0 || sbuf.len() != 0 {
-		Err(ERRMSG_STRLENDIFF)
-	} else if lcnt != llen {
-		Err(ERRMSG_LISTLENDIFF)
-	} else {
-		Ok(parameters)
+		if !sbuf.ends_with(b"\r\n") {
+			return Err(ERRMSG_STRLENDIFF);
+		}
+		sbuf.truncate(slen);
+		parameters.push(bytes::decode(&sbuf));
+	}
+	Ok(parameters)
+}
+
+fn read_header<R: Read>(reader: &mut BufReader<R>)
+	-> Result<String, &'static str> {
+	let mut ln: Vec<u8> = Vec::new();
+	match reader.read_until(b'\n', &mut ln) {
+		Ok(0) => Err(ERRMSG_EOF),
+		Ok(_) => match String::from_utf8(ln) {
+			Ok(s) if s.ends_with('\n') => Ok(s.trim_end().to_string()),
+			Ok(_) => Err(ERRMSG_EOF),
+			Err(_) => Err(ERRMSG_PROTOERR)
+		},
+		Err(_) => Err(ERRMSG_CNXERR)
 	}
 }
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+
+use lazy_static::lazy_static;
+
+use super::client;
+use super::config;
+use super::datatype::DataType;
+use super::glob;
+use super::parser::parse;
+use super::request::Request;
+
+// event classes that the A flag stands for
+const ALL_CLASSES: &str = "g$lshzxetd";
+
+struct Subscriber {
+	// None tells the connection that it left subscribed mode
+	tx: Sender<Option<DataType>>,
+	channels: Vec<String>,
+	patterns: Vec<String>
+}
+
+impl Subscriber {
+	fn count(&self) -> usize {
+		self.channels.len() + self.patterns.len()
+	}
+}
+
+#[derive(Default)]
+struct Bus {
+	subscribers: HashMap<u64, Subscriber>,
+	// subscriber ids by channel and by pattern
+	channels: HashMap<String, Vec<u64>>,
+	patterns: HashMap<String, Vec<u64>>
+}
+
+lazy_static! {
+	static ref BUS: Mutex<Bus> = Mutex::new(Bus::default());
+}
+
+// cheap check on the write path so that no notification gets formatted
+// when nobody listens
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn publish(channel: &str, msg: &str) -> usize {
+	let bus = BUS.lock().unwrap();
+	let mut n = 0;
+	let mut send = |id: &u64, l: Vec<DataType>| {
+		if let Some(s) = bus.subscribers.get(id) {
+			let _ = s.tx.send(Some(DataType::List(l)));
+			n += 1;
+		}
+	};
+	for id in bus.channels.get(channel).into_iter().flatten() {
+		send(id, vec![
+			DataType::bulkStr("message"),
+			DataType::bulkStr(channel),
+			DataType::bulkStr(msg)
+		]);
+	}
+	for (p, ids) in bus.patterns.iter() {
+		if !glob::matches(p, channel) {
+			continue;
+		}
+		for id in ids {
+			send(id, vec![
+				DataType::bulkStr("pmessage"),
+				DataType::bulkStr(p),
+				DataType::bulkStr(channel),
+				DataType::bulkStr(msg)
+			]);
+		}
+	}
+	n
+}
+
+// send a message of channel to client id alone, if it subscribed to it
+pub fn deliver(id: u64, channel: &str, msg: DataType) -> bool {
+	let bus = BUS.lock().unwrap();
+	match bus.subscribers.get(&id) {
+		Some(s) if s.channels.iter().any(|c| c == channel) => s.tx.send(Some(
+			DataType::List(vec![
+				DataType::bulkStr("message"),
+				DataType::bulkStr(channel),
+				msg
+			])
+		)).is_ok(),
+		_ => false
+	}
+}
+
+// publish a keyspace notification of the given class about key, as
+// selected by notify-keyspace-events
+pub fn notify(class: char, event: &str, key: &str) {
+	if 0 == COUNT.load(Ordering::Relaxed) {
+		return;
+	}
+	let flags = config::get("notify-keyspace-events");
+	let selected = flags.contains(class)
+		|| (flags.contains('A') && ALL_CLASSES.contains(class));
+	if !selected {
+		return;
+	}
+	if flags.contains('K') {
+		publish(&format!("__keyspace@0__:{}", key), event);
+	}
+	if flags.contains('E') {
+		publish(&format!("__keyevent@0__:{}", event), key);
+	}
+}
+
+pub fn channels(pattern: Option<&str>) -> Result<DataType, &'static str> {
+	let bus = BUS.lock().unwrap();
+	let mut names = bus.channels.keys()
+		.filter(|c| pattern.is_none_or(|p| glob::matches(p, c)))
+		.collect::<Vec<_>>();
+	names.sort();
+	Ok(DataType::List(names.iter().map(|c| DataType::bulkStr(c)).collect()))
+}
+
+pub fn numsub(names: &[String]) -> Result<DataType, &'static str> {
+	let bus = BUS.lock().unwrap();
+	Ok(DataType::List(names.iter()
+		.flat_map(|c| vec![
+			DataType::bulkStr(c),
+			DataType::Integer(bus.channels.get(c).map_or(0, |ids| ids.len())
+				as i64)
+		])
+		.collect()))
+}
+
+pub fn numpat() -> Result<DataType, &'static str> {
+	Ok(DataType::Integer(BUS.lock().unwrap().patterns.len() as i64))
+}
+
+// serve a connection that sent a (P)(UN)SUBSCRIBE request for as long as it
+// stays subscribed, false when the connection is to be closed
+pub fn serve<R, W>(reader: &mut BufReader<R>, writer: &mut BufWriter<W>,
+	id: u64, req: &Request) -> bool
+	where R: Read + Send, W: Write {
+	let (tx, rx) = channel();
+	if 0 == apply(id, &tx, req) {
+		while let Ok(Some(reply)) = rx.try_recv() {
+			if writer.write_all(&reply.to_bytes()).is_err() {
+				return false;
+			}
+		}
+		return true;
+	}
+	client::set_flag(id, 'P');
+	let open = thread::scope(|scope| {
+		let listening = scope.spawn(|| listen(reader, id, &tx));
+		while let Ok(Some(msg)) = rx.recv() {
+			if writer.write_all(&msg.to_bytes()).is_err()
+				|| writer.flush().is_err() {
+				break;
+			}
+		}
+		listening.join().unwrap_or(false)
+	});
+	client::clear_flag(id, 'P');
+	open
+}
+
+// handle the requests of a subscribed connection, until it leaves
+// subscribed mode or goes away
+fn listen<R: Read>(reader: &mut BufReader<R>, id: u64,
+	tx: &Sender<Option<DataType>>) -> bool {
+	loop {
+		let req = match parse(reader) {
+			Ok(req) => req,
+			Err("ERR EOF reached") | Err("ERR Connection error") => {
+				forget(id);
+				let _ = tx.send(None);
+				return false;
+			},
+			Err(e) => {
+				let _ = tx.send(Some(DataType::err(e)));
+				continue;
+			}
+		};
+		client::touch(
+			id,
+			&req.command,
+			&req.parameters,
+			reader.buffer().len(),
+			0
+		);
+		let reply = match req.command.as_str() {
+			"subscribe" | "psubscribe" if req.parameters.is_empty() =>
+				DataType::err(&format!(
+					"ERR wrong number of arguments for '{}' command",
+					req.command
+				)),
+			"subscribe" | "psubscribe" | "unsubscribe" | "punsubscribe" => {
+				if 0 == apply(id, tx, &req) {
+					let _ = tx.send(None);
+					return true;
+				}
+				continue;
+			},
+			"ping" => DataType::List(vec![
+				DataType::bulkStr("pong"),
+				DataType::bulkStr(req.parameters.first().map_or("", |s| s))
+			]),
+			"quit" => {
+				forget(id);
+				let _ = tx.send(Some(DataType::str("OK")));
+				let _ = tx.send(None);
+				return false;
+			},
+			cmd => DataType::err(&format!(
+				"ERR Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE \
+					/ PING / QUIT are allowed in this context",
+				cmd
+			))
+		};
+		let _ = tx.send(Some(reply));
+	}
+}
+
+// run a (P)(UN)SUBSCRIBE request of client id, queueing the replies on tx,
+// and return the number of subscriptions left
+fn apply(id: u64, tx: &Sender<Option<DataType>>, req: &Request) -> usize {
+	let mut guard = BUS.lock().unwrap();
+	let bus = &mut *guard;
+	let sub = bus.subscribers.entry(id).or_insert_with(|| Subscriber {
+		tx: tx.clone(),
+		channels: vec![],
+		patterns: vec![]
+	});
+	let cmd = req.command.as_str();
+	let (other, mine, index) = match cmd {
+		"psubscribe" | "punsubscribe" =>
+			(sub.channels.len(), &mut sub.patterns, &mut bus.patterns),
+		_ => (sub.patterns.len(), &mut sub.channels, &mut bus.channels)
+	};
+	let reply = |name: DataType, n: usize| Some(DataType::List(vec![
+		DataType::bulkStr(cmd),
+		name,
+		DataType::Integer(n as i64)
+	]));
+	if matches!(cmd, "subscribe" | "psubscribe") {
+		for name in req.parameters.iter() {
+			if !mine.contains(name) {
+				mine.push(name.clone());
+				index.entry(name.clone()).or_default().push(id);
+				COUNT.fetch_add(1, Ordering::Relaxed);
+			}
+			let _ = tx.send(reply(DataType::bulkStr(name), other + mine.len()));
+		}
+	} else {
+		let names = match req.parameters.is_empty() {
+			true => mine.clone(),
+			false => req.parameters.clone()
+		};
+		if names.is_empty() {
+			let _ = tx.send(reply(DataType::Null, other));
+		}
+		for name in names.iter() {
+			if let Some(i) = mine.iter().position(|s| s == name) {
+				mine.remove(i);
+				drop_id(index, name, id);
+				COUNT.fetch_sub(1, Ordering::Relaxed);
+			}
+			let _ = tx.send(reply(DataType::bulkStr(name), other + mine.len()));
+		}
+	}
+	let left = sub.count();
+	if 0 == left {
+		bus.subscribers.remove(&id);
+	}
+	left
+}
+
+fn drop_id(index: &mut HashMap<String, Vec<u64>>, name: &str, id: u64) {
+	if let Some(ids) = index.get_mut(name) {
+		ids.retain(|i| *i != id);
+		if ids.is_empty() {
+			index.remove(name);
+		}
+	}
+}
+
+// drop every subscription of client id
+fn forget(id: u64) {
+	let mut guard = BUS.lock().unwrap();
+	let bus = &mut *guard;
+	if let Some(sub) = bus.subscribers.remove(&id) {
+		sub.channels.iter().for_each(|c| drop_id(&mut bus.channels, c, id));
+		sub.patterns.iter().for_each(|p| drop_id(&mut bus.patterns, p, id));
+		COUNT.fetch_sub(sub.count(), Ordering::Relaxed);
+	}
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,88 @@
+use serial_test::serial;
+use super::*;
+use crate::kv;
+
+fn req(cmd: &str, prms: &[&str]) -> Request {
+	Request {
+		command: cmd.to_string(),
+		parameters: prms.iter().map(|s| s.to_string()).collect()
+	}
+}
+
+fn strs(v: &[&str]) -> Vec<DataType> {
+	v.iter().map(|s| DataType::bulkStr(s)).collect()
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	let (tx, rx) = channel();
+	assert_eq!(apply(1, &tx, &req("subscribe", &["a", "b"])), 2);
+	assert_eq!(apply(1, &tx, &req("psubscribe", &["c*"])), 3);
+	assert_eq!(
+		rx.try_iter().flatten().collect::<Vec<_>>(),
+		vec![
+			DataType::List(vec![
+				DataType::bulkStr("subscribe"),
+				DataType::bulkStr("a"),
+				DataType::Integer(1)
+			]),
+			DataType::List(vec![
+				DataType::bulkStr("subscribe"),
+				DataType::bulkStr("b"),
+				DataType::Integer(2)
+			]),
+			DataType::List(vec![
+				DataType::bulkStr("psubscribe"),
+				DataType::bulkStr("c*"),
+				DataType::Integer(3)
+			])
+		]
+	);
+	assert_eq!(publish("a", "hi"), 1);
+	assert_eq!(publish("cat", "meow"), 1);
+	assert_eq!(publish("x", "nobody"), 0);
+	assert_eq!(
+		rx.try_iter().flatten().collect::<Vec<_>>(),
+		vec![
+			DataType::List(strs(&["message", "a", "hi"])),
+			DataType::List(strs(&["pmessage", "c*", "cat", "meow"]))
+		]
+	);
+	assert_eq!(channels(Some("a*")), Ok(DataType::List(strs(&["a"]))));
+	assert_eq!(numpat(), Ok(DataType::Integer(1)));
+	assert_eq!(apply(1, &tx, &req("unsubscribe", &[])), 1);
+	assert_eq!(apply(1, &tx, &req("punsubscribe", &["c*"])), 0);
+	assert_eq!(0, COUNT.load(Ordering::Relaxed));
+	assert!(BUS.lock().unwrap().subscribers.is_empty());
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	let (tx, rx) = channel();
+	apply(2, &tx, &req("psubscribe", &["__key*"]));
+	rx.try_iter().count();
+	let _ = config::set_startup("notify-keyspace-events", "Kl");
+	let _ = kv::set("ks", "v");
+	let _ = kv::lpush("kl", vec!["a".to_string()], false);
+	let _ = config::set_startup("notify-keyspace-events", "EA");
+	let _ = kv::del(&vec!["ks".to_string(), "kl".to_string()]);
+	assert_eq!(
+		rx.try_iter().flatten().collect::<Vec<_>>(),
+		vec![
+			DataType::List(strs(&[
+				"pmessage", "__key*", "__keyspace@0__:kl", "lpush"
+			])),
+			DataType::List(strs(&[
+				"pmessage", "__key*", "__keyevent@0__:del", "ks"
+			])),
+			DataType::List(strs(&[
+				"pmessage", "__key*", "__keyevent@0__:del", "kl"
+			]))
+		]
+	);
+	let _ = config::set_startup("notify-keyspace-events", "");
+	forget(2);
+	assert_eq!(0, COUNT.load(Ordering::Relaxed));
+}
@@ -0,0 +1,641 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use rand::Rng;
+
+use super::client;
+use super::cluster;
+use super::command;
+use super::config;
+use super::datatype::DataType;
+use super::expiry;
+use super::function;
+use super::kv;
+use super::logger::{self, Level};
+use super::parser::parse;
+use super::request::Request;
+use super::stream;
+
+const ERRMSG_BADPORT: &str = "ERR Invalid master port";
+const ERRMSG_NOWAIT: &str = "ERR WAIT cannot be used with replica instances.";
+const ERRMSG_TIMEOUTNAI: &str = "ERR timeout is not an integer or out of range";
+const ERRMSG_READONLY: &str =
+	"READONLY You can't write against a read only replica.";
+
+// number of stream chunks a replica may lag behind before it is dropped
+// and left to catch up through PSYNC
+const REPLICA_QUEUE: usize = 65536;
+
+enum Role {
+	Primary,
+	Replica {host: String, port: u16, connected: bool}
+}
+
+struct Replica {
+	id: u64,
+	addr: String,
+	// last offset the replica acknowledged and when it did so
+	ack: u64,
+	acked: Instant,
+	tx: SyncSender<Arc<Vec<u8>>>
+}
+
+struct State {
+	role: Role,
+	replid: String,
+	// previous history this node can still serve PSYNC for, and up to
+	// which offset, after it got promoted
+	replid2: String,
+	offset2: u64,
+	// total number of bytes ever fed into the replication stream
+	offset: u64,
+	// tail of the replication stream, starting at offset `start`
+	backlog: VecDeque<u8>,
+	start: u64,
+	replicas: Vec<Replica>,
+	// ports announced through REPLCONF listening-port, by client id
+	ports: HashMap<u64, String>,
+	// whether this node ever received a dataset from a primary
+	synced: bool
+}
+
+lazy_static! {
+	static ref STATE: Mutex<State> = Mutex::new(State {
+		role: Role::Primary,
+		replid: new_replid(),
+		replid2: String::from("0000000000000000000000000000000000000000"),
+		offset2: 0,
+		offset: 0,
+		backlog: VecDeque::new(),
+		start: 0,
+		replicas: Vec::new(),
+		ports: HashMap::new(),
+		synced: false
+	});
+	// serializes writes against snapshots so that no write ends up both in
+	// a snapshot and in the stream following it
+	static ref WRITE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+// bumped whenever REPLICAOF changes so that stale links wind down
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+pub fn new_replid() -> String {
+	let mut rng = rand::rng();
+	(0..40).map(|_| {
+		char::from_digit(rng.random_range(0..16), 16).unwrap()
+	}).collect()
+}
+
+pub fn write_guard() -> MutexGuard<'static, ()> {
+	WRITE_LOCK.lock().unwrap()
+}
+
+pub fn is_replica() -> bool {
+	matches!(STATE.lock().unwrap().role, Role::Replica {..})
+}
+
+pub fn check_writable() -> Result<(), &'static str> {
+	if is_replica() && "yes" == config::get("replica-read-only") {
+		Err(ERRMSG_READONLY)
+	} else {
+		Ok(())
+	}
+}
+
+pub fn offset() -> u64 {
+	STATE.lock().unwrap().offset
+}
+
+// feed a write that just got applied to the backlog and the replicas;
+// the caller holds the write guard
+pub fn propagate(req: &Request, reply: &DataType) {
+	// SPOP picks at random, so replicas are told which members went away
+	let req = match (req.command.as_str(), reply) {
+		("spop", DataType::BulkString(s)) => Request {
+			command: String::from("srem"),
+			parameters: vec![req.parameters[0].clone(), s.clone()]
+		},
+		("spop", DataType::List(l)) if !l.is_empty() => Request {
+			command: String::from("srem"),
+			parameters: [req.parameters[0].clone()].into_iter()
+				.chain(l.iter().filter_map(|e| match e {
+					DataType::BulkString(s) => Some(s.clone()),
+					_ => None
+				}))
+				.collect()
+		},
+		("spop", _) => return,
+		// relative deadlines go out as the one the key got here
+		("getex", DataType::Null) => return,
+		("getex", _) if req.parameters[1..].iter().any(|p| matches!(
+			p.to_ascii_lowercase().as_str(), "ex" | "px" | "exat"
+		)) => match expiry::get(&req.parameters[0]) {
+			Some(at) => Request {
+				command: String::from("getex"),
+				parameters: vec![
+					req.parameters[0].clone(),
+					String::from("pxat"),
+					at.to_string()
+				]
+			},
+			None => Request {
+				command: String::from("del"),
+				parameters: vec![req.parameters[0].clone()]
+			}
+		},
+		// the keys moved away, unless they were only copied
+		("migrate", _) if req.parameters.iter()
+			.any(|p| p.eq_ignore_ascii_case("copy")) => return,
+		("migrate", _) => Request {
+			command: String::from("del"),
+			parameters: cluster::keys(req).iter().map(|k| k.to_string())
+				.collect()
+		},
+		// generated stream IDs depend on the clock of the master
+		("xadd", DataType::BulkString(id)) => {
+			let mut req = req.clone();
+			if let Ok(a) = stream::parse_add(&req.parameters[1..]) {
+				req.parameters[1 + a.id_at] = id.clone();
+			}
+			req
+		},
+		("xadd", _) | ("xreadgroup", DataType::Null) => return,
+		_ => req.clone()
+	};
+	let offset = feed(encode(&req));
+	client::set_last_write(client::current(), offset);
+}
+
+pub fn encode(req: &Request) -> Vec<u8> {
+	let mut l = vec![DataType::bulkStr(&req.command)];
+	l.extend(req.parameters.iter().map(|p| DataType::bulkStr(p)));
+	DataType::List(l).to_bytes()
+}
+
+// returns the offset reached once the bytes are in the stream
+fn feed(bytes: Vec<u8>) -> u64 {
+	let mut st = STATE.lock().unwrap();
+	st.offset += bytes.len() as u64;
+	let offset = st.offset;
+	let cap = config::get_usize("repl-backlog-size");
+	st.backlog.extend(bytes.iter());
+	while st.backlog.len() > cap {
+		let excess = st.backlog.len() - cap;
+		st.backlog.drain(0..excess);
+		st.start += excess as u64;
+	}
+	if st.replicas.is_empty() {
+		return offset;
+	}
+	let chunk = Arc::new(bytes);
+	// a replica that cannot keep up is dropped rather than slowing writers
+	st.replicas.retain(|r| {
+		!matches!(
+			r.tx.try_send(Arc::clone(&chunk)),
+			Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_))
+		)
+	});
+	offset
+}
+
+// take over a connection that issued SYNC or PSYNC and keep streaming
+// writes to it, while collecting its acknowledgements, until either side
+// goes away
+pub fn serve_replica<R, W>(reader: &mut BufReader<R>, writer: &mut BufWriter<W>,
+	id: u64, req: &Request)
+	where R: Read + Send, W: Write {
+	let rx = match handshake(writer, id, req) {
+		Ok(rx) => rx,
+		Err(_) => return
+	};
+	client::set_flag(id, 'S');
+	logger::log(
+		Level::Notice,
+		&format!("Replica {} asks for synchronization", client::addr(id))
+	);
+	thread::scope(|scope| {
+		scope.spawn(|| {
+			while let Ok(ack) = parse(reader) {
+				let prms = &ack.parameters;
+				let off = match (prms.first(), prms.get(1)) {
+					(Some(sub), Some(n)) if "replconf" == ack.command
+						&& sub.eq_ignore_ascii_case("ack") => n.parse::<u64>(),
+					_ => continue
+				};
+				let mut st = STATE.lock().unwrap();
+				if let (Some(r), Ok(n)) =
+					(st.replicas.iter_mut().find(|r| r.id == id), off) {
+					r.ack = r.ack.max(n);
+					r.acked = Instant::now();
+				}
+			}
+			// dropping the sender wakes the streaming loop up
+			STATE.lock().unwrap().replicas.retain(|r| r.id != id);
+		});
+		while let Ok(chunk) = rx.recv() {
+			if writer.write_all(&chunk).is_err() || writer.flush().is_err() {
+				break;
+			}
+		}
+		STATE.lock().unwrap().replicas.retain(|r| r.id != id);
+	});
+	STATE.lock().unwrap().ports.remove(&id);
+}
+
+fn handshake<W: Write>(writer: &mut BufWriter<W>, id: u64, req: &Request)
+	-> std::io::Result<Receiver<Arc<Vec<u8>>>> {
+	// no write may slip in between the snapshot and the registration
+	let _guard = write_guard();
+	let (tx, rx) = sync_channel(REPLICA_QUEUE);
+	let mut st = STATE.lock().unwrap();
+	let wanted = match (req.parameters.first(), req.parameters.get(1)) {
+		(Some(rid), Some(off)) => match off.parse::<u64>() {
+			Ok(n) if 0 < n && (*rid == st.replid
+				|| (*rid == st.replid2 && n - 1 <= st.offset2)) =>
+				Some(n - 1),
+			_ => None
+		},
+		_ => None
+	};
+	match wanted {
+		Some(from) if st.start <= from && from <= st.offset => {
+			write!(writer, "+CONTINUE {}\r\n", st.replid)?;
+			let skip = (from - st.start) as usize;
+			let pending = st.backlog.iter().skip(skip).cloned()
+				.collect::<Vec<u8>>();
+			writer.write_all(&pending)?;
+		},
+		_ => {
+			let mut snapshot: Vec<u8> = Vec::new();
+			drop(st);
+			if let Err(e) = kv::write_data(&mut snapshot) {
+				logger::log(Level::Warning, e);
+			}
+			st = STATE.lock().unwrap();
+			if "sync" != req.command.as_str() {
+				write!(writer, "+FULLRESYNC {} {}\r\n", st.replid, st.offset)?;
+			}
+			write!(writer, "${}\r\n", snapshot.len())?;
+			writer.write_all(&snapshot)?;
+		}
+	}
+	writer.flush()?;
+	let addr = match client::addr(id).rsplit_once(':') {
+		Some((ip, port)) => format!(
+			"{}:{}",
+			ip,
+			st.ports.get(&id).map_or(port, |p| p.as_str())
+		),
+		None => String::new()
+	};
+	st.replicas.push(Replica {id, addr, ack: 0, acked: Instant::now(), tx});
+	Ok(rx)
+}
+
+pub fn replicaof(host: &str, port: &str) -> Result<DataType, &'static str> {
+	if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+		let mut st = STATE.lock().unwrap();
+		if let Role::Replica {..} = st.role {
+			GENERATION.fetch_add(1, Ordering::SeqCst);
+			st.role = Role::Primary;
+			// keep serving PSYNC for the history shared with the old primary
+			st.replid2 = std::mem::replace(&mut st.replid, new_replid());
+			st.offset2 = st.offset;
+			logger::log(Level::Notice, "MASTER MODE enabled");
+		}
+		return Ok(DataType::str("OK"));
+	}
+	let port: u16 = match port.parse() {
+		Ok(n) => n,
+		Err(_) => return Err(ERRMSG_BADPORT)
+	};
+	let mut st = STATE.lock().unwrap();
+	if let Role::Replica {host: h, port: p, ..} = &st.role {
+		if h == host && *p == port {
+			return Ok(DataType::str("OK Already connected to specified \
+				master"));
+		}
+	}
+	let gen = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+	st.role = Role::Replica {host: host.to_string(), port, connected: false};
+	// chained replicas resync against the new history
+	st.replicas.clear();
+	let addr = format!("{}:{}", host, port);
+	logger::log(
+		Level::Notice,
+		&format!("Connecting to MASTER {}", addr)
+	);
+	thread::spawn(move || {link(gen, addr);});
+	Ok(DataType::str("OK"))
+}
+
+pub fn replconf(prms: &[String]) -> Result<DataType, &'static str> {
+	let sub = prms.first().map(|s| s.to_ascii_lowercase());
+	match (sub.as_deref(), prms.len()) {
+		(Some("listening-port"), 2) => {
+			if prms[1].parse::<u16>().is_err() {
+				return Err(ERRMSG_BADPORT);
+			}
+			STATE.lock().unwrap().ports.insert(
+				client::current(),
+				prms[1].clone()
+			);
+			Ok(DataType::str("OK"))
+		},
+		(Some("capa"), _) | (Some("ip-address"), 2) => Ok(DataType::str("OK")),
+		_ => Err("ERR Unrecognized REPLCONF option")
+	}
+}
+
+// block until numreplicas replicas acknowledged the last write of the
+// calling connection, or until the timeout (in ms, 0 for none) expires;
+// returns how many did
+pub fn wait(numreplicas: &str, timeout: &str)
+	-> Result<DataType, &'static str> {
+	let wanted = match numreplicas.parse::<usize>() {
+		Ok(n) => n,
+		Err(_) => return Err("ERR value is not an integer or out of range")
+	};
+	let ms = match timeout.parse::<u64>() {
+		Ok(n) => n,
+		Err(_) => return Err(ERRMSG_TIMEOUTNAI)
+	};
+	if is_replica() {
+		return Err(ERRMSG_NOWAIT);
+	}
+	let target = client::last_write(client::current());
+	let acked = || {
+		STATE.lock().unwrap().replicas.iter().filter(|r| r.ack >= target)
+			.count()
+	};
+	if acked() >= wanted {
+		return Ok(DataType::Integer(acked() as i64));
+	}
+	{
+		// ask for fresh acknowledgements instead of waiting for the next
+		// periodic ones
+		let _guard = write_guard();
+		feed(encode(&getack()));
+	}
+	let deadline = Instant::now() + Duration::from_millis(ms);
+	loop {
+		let n = acked();
+		if n >= wanted || (0 < ms && Instant::now() >= deadline) {
+			return Ok(DataType::Integer(n as i64));
+		}
+		thread::sleep(Duration::from_millis(5));
+	}
+}
+
+fn getack() -> Request {
+	Request {
+		command: String::from("replconf"),
+		parameters: vec![String::from("GETACK"), String::from("*")]
+	}
+}
+
+pub fn info() -> String {
+	let st = STATE.lock().unwrap();
+	let mut out = String::from("# Replication\r\n");
+	match &st.role {
+		Role::Primary => {
+			out.push_str(&format!(
+				"role:master\r\nconnected_slaves:{}\r\n",
+				st.replicas.len()
+			));
+			for (i, r) in st.replicas.iter().enumerate() {
+				let (ip, port) = r.addr.rsplit_once(':').unwrap_or(("", ""));
+				out.push_str(&format!(
+					"slave{}:ip={},port={},state=online,offset={},lag={}\r\n",
+					i, ip, port, r.ack, r.acked.elapsed().as_secs()
+				));
+			}
+		},
+		Role::Replica {host, port, connected} => out.push_str(&format!(
+			"role:slave\r\nmaster_host:{}\r\nmaster_port:{}\r\n\
+				master_link_status:{}\r\nslave_repl_offset:{}\r\n\
+				slave_read_only:{}\r\nconnected_slaves:{}\r\n",
+			host,
+			port,
+			if *connected {"up"} else {"down"},
+			st.offset,
+			if "yes" == config::get("replica-read-only") {1} else {0},
+			st.replicas.len()
+		))
+	}
+	out.push_str(&format!(
+		"master_replid:{}\r\nmaster_replid2:{}\r\n\
+			master_repl_offset:{}\r\nsecond_repl_offset:{}\r\n\
+			repl_backlog_size:{}\r\nrepl_backlog_first_byte_offset:{}\r\n\
+			repl_backlog_histlen:{}\r\n",
+		st.replid,
+		st.replid2,
+		st.offset,
+		if 0 < st.offset2 {(st.offset2 + 1) as i64} else {-1},
+		config::get("repl-backlog-size"),
+		st.start + 1,
+		st.backlog.len()
+	));
+	out
+}
+
+// keep a replica in sync with its primary for as long as the generation
+// it was started for is current
+fn link(gen: u64, addr: String) {
+	while gen == GENERATION.load(Ordering::SeqCst) {
+		if let Err(e) = sync_once(gen, &addr) {
+			logger::log(
+				Level::Warning,
+				&format!("Replication with MASTER {} failed: {}", addr, e)
+			);
+		}
+		set_connected(gen, false);
+		thread::sleep(Duration::from_secs(1));
+	}
+}
+
+fn set_connected(gen: u64, flag: bool) {
+	let mut st = STATE.lock().unwrap();
+	if gen != GENERATION.load(Ordering::SeqCst) {
+		return;
+	}
+	if let Role::Replica {connected, ..} = &mut st.role {
+		*connected = flag;
+	}
+}
+
+fn sync_once(gen: u64, addr: &str) -> Result<(), String> {
+	let sockaddr = match addr.to_socket_addrs() {
+		Ok(mut a) => a.next().ok_or("unresolvable address")?,
+		Err(e) => return Err(e.to_string())
+	};
+	let stream = TcpStream::connect_timeout(&sockaddr, Duration::from_secs(5))
+		.map_err(|e| e.to_string())?;
+	let timeout = config::get_u64("repl-timeout").max(1);
+	stream.set_read_timeout(Some(Duration::from_secs(timeout)))
+		.map_err(|e| e.to_string())?;
+	let mut w = &stream;
+	let mut reader = BufReader::new(&stream);
+	let mut call = |args: &[&str]| -> Result<String, String> {
+		let req = Request {
+			command: args[0].to_string(),
+			parameters: args[1..].iter().map(|s| s.to_string()).collect()
+		};
+		w.write_all(&encode(&req)).map_err(|e| e.to_string())?;
+		let mut ln = String::new();
+		match reader.read_line(&mut ln) {
+			Ok(0) => Err(String::from("connection closed")),
+			Ok(_) if ln.starts_with('-') => Err(ln.trim_end().to_string()),
+			Ok(_) => Ok(ln.trim_end().to_string()),
+			Err(e) => Err(e.to_string())
+		}
+	};
+	call(&["ping"])?;
+	call(&["replconf", "listening-port", &config::get("port")])?;
+	let (replid, offset, synced) = {
+		let st = STATE.lock().unwrap();
+		(st.replid.clone(), st.offset, st.synced)
+	};
+	let psync = if synced {
+		call(&["psync", &replid, &(offset + 1).to_string()])?
+	} else {
+		call(&["psync", "?", "-1"])?
+	};
+	let toks = psync.split_whitespace().collect::<Vec<_>>();
+	match toks.first() {
+		Some(&"+FULLRESYNC") if 3 == toks.len() => {
+			let newoff = toks[2].parse::<u64>().map_err(|e| e.to_string())?;
+			full_sync(&mut reader, toks[1], newoff)?;
+		},
+		Some(&"+CONTINUE") => {
+			let mut st = STATE.lock().unwrap();
+			if let Some(id) = toks.get(1) {
+				if **id != st.replid {
+					st.replid2 = std::mem::replace(&mut st.replid,
+						id.to_string());
+					st.offset2 = st.offset;
+				}
+			}
+			logger::log(
+				Level::Notice,
+				"Successful partial resynchronization with master"
+			);
+		},
+		_ => return Err(format!("unexpected reply to PSYNC: {}", psync))
+	}
+	set_connected(gen, true);
+	let done = AtomicBool::new(false);
+	// acknowledgements come from two threads, one whole ACK at a time
+	let out = Mutex::new(&stream);
+	let ack = || {
+		let off = STATE.lock().unwrap().offset;
+		let req = Request {
+			command: String::from("replconf"),
+			parameters: vec![String::from("ACK"), off.to_string()]
+		};
+		let _ = out.lock().unwrap().write_all(&encode(&req));
+	};
+	thread::scope(|scope| {
+		// acknowledge the processed offset every second
+		scope.spawn(|| {
+			while !done.load(Ordering::Relaxed) {
+				ack();
+				for _ in 0..10 {
+					if done.load(Ordering::Relaxed) {
+						return;
+					}
+					thread::sleep(Duration::from_millis(100));
+				}
+			}
+		});
+		let res = stream_loop(gen, &mut reader, &ack);
+		done.store(true, Ordering::Relaxed);
+		res
+	})
+}
+
+fn stream_loop<R: Read>(gen: u64, reader: &mut BufReader<R>, ack: &dyn Fn())
+	-> Result<(), String> {
+	while gen == GENERATION.load(Ordering::SeqCst) {
+		let req = parse(reader).map_err(|e| e.to_string())?;
+		let is_getack = "replconf" == req.command
+			&& req.parameters.first()
+				.is_some_and(|s| s.eq_ignore_ascii_case("getack"));
+		{
+			let _guard = write_guard();
+			if !is_getack {
+				let reply = command::execute(&req);
+				if let DataType::SimpleError(e) = reply {
+					logger::log(Level::Warning, &e);
+				}
+			}
+			feed(encode(&req));
+		}
+		if is_getack {
+			ack();
+		}
+	}
+	Ok(())
+}
+
+fn full_sync<R: Read>(reader: &mut BufReader<R>, replid: &str, offset: u64)
+	-> Result<(), String> {
+	let mut ln = String::new();
+	reader.read_line(&mut ln).map_err(|e| e.to_string())?;
+	let size = match ln.trim_end().strip_prefix('$') {
+		Some(n) => n.parse::<usize>().map_err(|e| e.to_string())?,
+		None => return Err(format!("unexpected snapshot header: {}", ln))
+	};
+	// grown as bytes arrive rather than trusting the announced size
+	let mut snapshot: Vec<u8> = Vec::new();
+	reader.take(size as u64).read_to_end(&mut snapshot)
+		.map_err(|e| e.to_string())?;
+	if snapshot.len() != size {
+		return Err(String::from("snapshot shorter than announced"));
+	}
+	let _guard = write_guard();
+	kv::flushall();
+	let _ = function::flush();
+	command::replay(&snapshot[..]);
+	let mut st = STATE.lock().unwrap();
+	st.replid = replid.to_string();
+	st.offset = offset;
+	st.backlog.clear();
+	st.start = offset;
+	st.synced = true;
+	st.replicas.clear();
+	logger::log(
+		Level::Notice,
+		&format!("MASTER <-> REPLICA sync: loaded {} bytes", size)
+	);
+	Ok(())
+}
+
+// primaries ping their replicas so that they can tell a dead link apart
+// from an idle one
+pub fn start_heartbeat() {
+	thread::spawn(|| loop {
+		thread::sleep(Duration::from_secs(
+			config::get_u64("repl-ping-replica-period").max(1)
+		));
+		if is_replica() || STATE.lock().unwrap().replicas.is_empty() {
+			continue;
+		}
+		let _guard = write_guard();
+		feed(encode(&Request {
+			command: String::from("ping"),
+			parameters: vec![]
+		}));
+	});
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,102 @@
+use serial_test::serial;
+use super::*;
+
+fn req(cmd: &str, prms: &[&str]) -> Request {
+	Request {
+		command: cmd.to_string(),
+		parameters: prms.iter().map(|s| s.to_string()).collect()
+	}
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	assert_eq!(
+		encode(&req("set", &["k", "a\r\nb"])),
+		b"*3\r\n$3\r\nset\r\n$1\r\nk\r\n$4\r\na\r\nb\r\n".to_vec()
+	);
+	let before = offset();
+	propagate(&req("set", &["k", "v"]), &DataType::str("OK"));
+	assert_eq!(offset() - before, 27);
+	// SPOP travels as SREM of whatever got popped
+	propagate(&req("spop", &["s"]), &DataType::bulkStr("m"));
+	assert_eq!(offset() - before, 27 + 28);
+	propagate(&req("spop", &["s"]), &DataType::Null);
+	assert_eq!(offset() - before, 27 + 28);
+	// GETEX travels with the deadline the key got
+	expiry::set("gk", 12345);
+	propagate(&req("getex", &["gk", "EX", "100"]), &DataType::bulkStr("v"));
+	assert_eq!(offset() - before, 27 + 28 + 44);
+	expiry::clear("gk");
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	kv::flushall();
+	let _ = kv::set("snap", "shot");
+	let mut out: Vec<u8> = Vec::new();
+	{
+		let mut w = BufWriter::new(&mut out);
+		let _ = handshake(&mut w, 0, &req("psync", &["?", "-1"]));
+	}
+	let s = String::from_utf8(out).unwrap();
+	assert!(s.starts_with("+FULLRESYNC "));
+	assert!(s.ends_with(
+		"$33\r\n*3\r\n$3\r\nset\r\n$4\r\nsnap\r\n$4\r\nshot\r\n"
+	));
+	// resume from the current offset of the current history
+	let (replid, off) = {
+		let st = STATE.lock().unwrap();
+		(st.replid.clone(), st.offset)
+	};
+	propagate(&req("del", &["snap"]), &DataType::Integer(1));
+	let mut out: Vec<u8> = Vec::new();
+	{
+		let mut w = BufWriter::new(&mut out);
+		let _ = handshake(
+			&mut w,
+			0,
+			&req("psync", &[&replid, &(off + 1).to_string()])
+		);
+	}
+	assert_eq!(
+		String::from_utf8(out).unwrap(),
+		format!("+CONTINUE {}\r\n*2\r\n$3\r\ndel\r\n$4\r\nsnap\r\n", replid)
+	);
+	STATE.lock().unwrap().replicas.clear();
+	kv::flushall();
+}
+
+#[test]
+#[serial]
+fn plan3() {
+	let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+	let id = client::register(&stream);
+	client::set_current(id);
+	propagate(&req("set", &["k", "v"]), &DataType::str("OK"));
+	let target = offset();
+	let (tx, _rx) = sync_channel(1);
+	STATE.lock().unwrap().replicas.push(Replica {
+		id: 0,
+		addr: String::from("127.0.0.1:7000"),
+		ack: target - 1,
+		acked: Instant::now(),
+		tx
+	});
+	// the only replica lags behind the last write
+	assert_eq!(wait("1", "20"), Ok(DataType::Integer(0)));
+	STATE.lock().unwrap().replicas[0].ack = offset();
+	assert_eq!(wait("1", "0"), Ok(DataType::Integer(1)));
+	assert!(info().contains("slave0:ip=127.0.0.1,port=7000,state=online"));
+	assert_eq!(
+		wait("one", "0"),
+		Err("ERR value is not an integer or out of range")
+	);
+	// a client that never wrote has nothing to wait for
+	client::set_current(0);
+	assert_eq!(wait("1", "20"), Ok(DataType::Integer(1)));
+	client::unregister(id);
+	STATE.lock().unwrap().replicas.clear();
+}
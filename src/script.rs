@@ -0,0 +1,591 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock, RwLockReadGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use mlua::{
+	HookTriggers, Lua, LuaOptions, RegistryKey, StdLib, Table, Value, Variadic
+};
+use phf::phf_set;
+use sha1_smol::Sha1;
+
+use super::bytes;
+use super::command;
+use super::config;
+use super::datatype::DataType;
+use super::logger::{self, Level};
+use super::monitor;
+use super::replication;
+use super::request::Request;
+
+const ERRMSG_BADARG: &str =
+	"ERR Lua redis lib command arguments must be strings or integers";
+const ERRMSG_BUSY: &str =
+	"BUSY kyvi is busy running a script. You can only call SCRIPT KILL.";
+const ERRMSG_BADFNNAME: &str = "ERR Function names can only contain \
+	letters, numbers, or underscores(_) and must be at least one character \
+	long";
+const ERRMSG_BADFLAG: &str = "ERR unknown flag given";
+const ERRMSG_BADREGISTER: &str =
+	"ERR wrong arguments given to redis.register_function";
+const ERRMSG_CALLONLOAD: &str =
+	"ERR redis.call can only be called inside a script invocation";
+const ERRMSG_KILLED: &str = "ERR Script killed by user with SCRIPT KILL...";
+const ERRMSG_NEGKEYS: &str = "ERR Number of keys can't be negative";
+const ERRMSG_NOARGS: &str =
+	"ERR Please specify at least one argument for this redis lib call";
+const ERRMSG_NOREGISTER: &str = "ERR redis.register_function can only be \
+	called on FUNCTION LOAD command";
+const ERRMSG_NOSCRIPT: &str = "NOSCRIPT No matching script. Please use EVAL.";
+const ERRMSG_NOTALLOWED: &str = "ERR This command is not allowed from script";
+const ERRMSG_NOTBUSY: &str = "NOTBUSY No scripts in execution right now.";
+const ERRMSG_NUMNAI: &str = "ERR value is not an integer or out of range";
+const ERRMSG_READONLY: &str =
+	"ERR Write commands are not allowed from read-only scripts.";
+const ERRMSG_TOOMANYKEYS: &str =
+	"ERR Number of keys can't be greater than number of args";
+const ERRMSG_UNKILLABLE: &str = "UNKILLABLE Sorry the script already \
+	executed write commands against the dataset. You can either wait the \
+	script termination or kill the server in a hard way.";
+
+// globals are off limits so that scripts cannot keep state around
+const PROTECT_GLOBALS: &str = r#"
+dofile = nil
+loadfile = nil
+setmetatable(_G, {
+	__newindex = function(_, k)
+		error("Script attempted to create global variable '"
+			.. tostring(k) .. "'", 2)
+	end,
+	__index = function(_, k)
+		error("Script attempted to access nonexistent global variable '"
+			.. tostring(k) .. "'", 2)
+	end
+})
+"#;
+
+// flags a registered function may carry
+static FUNCTION_FLAGS: phf::Set<&str> = phf_set! {
+	"allow-cross-slot-keys", "allow-oom", "allow-stale", "no-cluster",
+	"no-writes"
+};
+
+// commands that make no sense or would deadlock inside a script
+static NOSCRIPT_CMDS: phf::Set<&str> = phf_set! {
+	"asking", "client", "eval", "eval_ro", "evalsha", "evalsha_ro", "fcall",
+	"fcall_ro", "function", "monitor", "psubscribe", "psync", "punsubscribe",
+	"quit", "replconf", "replicaof", "script", "slaveof", "subscribe",
+	"sync", "unsubscribe", "wait"
+};
+
+struct Running {
+	since: Instant,
+	// whether the script changed any data, which makes it unkillable
+	wrote: bool
+}
+
+// a function as registered by a library
+#[derive(Clone, Debug, PartialEq)]
+pub struct Registered {
+	pub name: String,
+	pub flags: Vec<String>,
+	pub description: Option<String>
+}
+
+// error raised by redis.call, handed back to the client verbatim
+#[derive(Debug)]
+struct CallError(String);
+
+impl fmt::Display for CallError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.0)
+	}
+}
+
+impl Error for CallError {}
+
+lazy_static! {
+	static ref SCRIPTS: Mutex<HashMap<String, String>> =
+		Mutex::new(HashMap::new());
+	// commands touching the keyspace hold it shared, scripts exclusively
+	static ref EXEC: RwLock<()> = RwLock::new(());
+	static ref RUNNING: Mutex<Option<Running>> = Mutex::new(None);
+}
+
+static KILL: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+	// every connection thread gets its own interpreter
+	static LUA: Lua = new_vm();
+	static READ_ONLY: Cell<bool> = const { Cell::new(false) };
+	// set while library code runs, when only register_function is usable
+	static LOADING: Cell<bool> = const { Cell::new(false) };
+	// libraries loaded into the interpreter of the thread, by name, along
+	// with the digest of their code
+	static LIBS: RefCell<HashMap<String, (String, RegistryKey)>> =
+		RefCell::new(HashMap::new());
+}
+
+pub fn sha1hex(s: &str) -> String {
+	Sha1::from(bytes::encode(s)).digest().to_string()
+}
+
+// wait for any running script to finish before touching the keyspace, or
+// give up with BUSY once it ran for longer than busy-reply-threshold
+pub fn shared() -> Result<RwLockReadGuard<'static, ()>, &'static str> {
+	loop {
+		match EXEC.try_read() {
+			Ok(g) => return Ok(g),
+			Err(TryLockError::Poisoned(e)) => return Ok(e.into_inner()),
+			Err(TryLockError::WouldBlock) if busy() => return Err(ERRMSG_BUSY),
+			Err(TryLockError::WouldBlock) =>
+				thread::sleep(Duration::from_millis(1))
+		}
+	}
+}
+
+fn busy() -> bool {
+	let limit = Duration::from_millis(config::get_u64("busy-reply-threshold"));
+	RUNNING.lock().unwrap().as_ref().is_some_and(|r| r.since.elapsed() >= limit)
+}
+
+pub fn eval(body: &str, prms: &[String], read_only: bool)
+	-> Result<DataType, &'static str> {
+	let sha = sha1hex(body);
+	SCRIPTS.lock().unwrap()
+		.entry(sha.clone())
+		.or_insert_with(|| body.to_string());
+	run(&sha, body, prms, read_only)
+}
+
+pub fn evalsha(sha: &str, prms: &[String], read_only: bool)
+	-> Result<DataType, &'static str> {
+	let sha = sha.to_ascii_lowercase();
+	let body = match SCRIPTS.lock().unwrap().get(&sha) {
+		Some(body) => body.clone(),
+		None => return Err(ERRMSG_NOSCRIPT)
+	};
+	run(&sha, &body, prms, read_only)
+}
+
+pub fn script_exists(shas: &[String]) -> Result<DataType, &'static str> {
+	let scripts = SCRIPTS.lock().unwrap();
+	Ok(DataType::List(shas.iter()
+		.map(|s| DataType::Integer(
+			scripts.contains_key(&s.to_ascii_lowercase()) as i64
+		))
+		.collect()))
+}
+
+pub fn script_flush() -> Result<DataType, &'static str> {
+	SCRIPTS.lock().unwrap().clear();
+	Ok(DataType::str("OK"))
+}
+
+pub fn script_kill() -> Result<DataType, &'static str> {
+	match RUNNING.lock().unwrap().as_ref() {
+		None => Err(ERRMSG_NOTBUSY),
+		Some(r) if r.wrote => Err(ERRMSG_UNKILLABLE),
+		Some(_) => {
+			KILL.store(true, Ordering::Relaxed);
+			Ok(DataType::str("OK"))
+		}
+	}
+}
+
+pub fn script_load(body: &str) -> Result<DataType, &'static str> {
+	let sha = sha1hex(body);
+	let code = bytes::encode(body);
+	let compiled =
+		LUA.with(|lua| lua.load(&code[..]).into_function().map(|_| ()));
+	if let Err(e) = compiled {
+		return Ok(DataType::err(&error_text(&e, &sha)));
+	}
+	SCRIPTS.lock().unwrap().insert(sha.clone(), body.to_string());
+	Ok(DataType::bulkStr(&sha))
+}
+
+// split NUMKEYS KEY... ARG... into its keys and arguments
+pub fn split_keys(prms: &[String])
+	-> Result<(&[String], &[String]), &'static str> {
+	let n = match prms[0].parse::<i64>() {
+		Ok(n) if 0 > n => return Err(ERRMSG_NEGKEYS),
+		Ok(n) if n as usize > prms.len() - 1 => return Err(ERRMSG_TOOMANYKEYS),
+		Ok(n) => n as usize,
+		Err(_) => return Err(ERRMSG_NUMNAI)
+	};
+	Ok(prms[1..].split_at(n))
+}
+
+// run library code in a scratch interpreter, for the functions it registers
+pub fn load_library(lib: &str, code: &str) -> Result<Vec<Registered>, String> {
+	let lua = new_vm();
+	let fns = run_library(&lua, code).map_err(|e| error_text(&e, lib))?;
+	let mut out: Vec<Registered> = Vec::new();
+	for pair in fns.pairs::<String, Table>() {
+		let (name, f) = pair.map_err(|e| error_text(&e, lib))?;
+		let flags: Vec<String> = match f.raw_get::<_, Option<Table>>("flags") {
+			Ok(Some(t)) => t.sequence_values::<String>()
+				.collect::<mlua::Result<_>>()
+				.map_err(|_| ERRMSG_BADFLAG.to_string())?,
+			_ => vec![]
+		};
+		if flags.iter().any(|f| !FUNCTION_FLAGS.contains(f.as_str())) {
+			return Err(ERRMSG_BADFLAG.to_string());
+		}
+		out.push(Registered {
+			name,
+			flags,
+			description: f.raw_get::<_, Option<String>>("description")
+				.unwrap_or(None)
+		});
+	}
+	out.sort_by(|a, b| a.name.cmp(&b.name));
+	Ok(out)
+}
+
+// call function name of library lib, loading the library into the
+// interpreter of this thread first if it changed since
+pub fn fcall(lib: &str, code: &str, name: &str, prms: &[String],
+	read_only: bool) -> Result<DataType, &'static str> {
+	let (keys, argv) = split_keys(prms)?;
+	let _exclusive = EXEC.write().unwrap_or_else(|e| e.into_inner());
+	let _guard = replication::write_guard();
+	*RUNNING.lock().unwrap() = Some(Running {
+		since: Instant::now(),
+		wrote: false
+	});
+	KILL.store(false, Ordering::Relaxed);
+	READ_ONLY.with(|r| r.set(read_only));
+	let reply = LUA.with(|lua| {
+		let res = (|| -> mlua::Result<DataType> {
+			let fns = library(lua, lib, code)?;
+			let f: Table = fns.raw_get(name)?;
+			let callback: mlua::Function = f.raw_get("callback")?;
+			let ks = strings(lua, keys)?;
+			let vs = strings(lua, argv)?;
+			let v: Value = callback.call((ks, vs))?;
+			Ok(from_lua(&v))
+		})();
+		match res {
+			Ok(d) => d,
+			Err(e) => DataType::err(&error_text(&e, name))
+		}
+	});
+	*RUNNING.lock().unwrap() = None;
+	Ok(reply)
+}
+
+fn library<'lua>(lua: &'lua Lua, lib: &str, code: &str)
+	-> mlua::Result<Table<'lua>> {
+	let sha = sha1hex(code);
+	let cached = LIBS.with(|libs| match libs.borrow().get(lib) {
+		Some((s, key)) if *s == sha => Some(lua.registry_value::<Table>(key)),
+		_ => None
+	});
+	if let Some(fns) = cached {
+		return fns;
+	}
+	let fns = run_library(lua, code)?;
+	let key = lua.create_registry_value(fns.clone())?;
+	LIBS.with(|libs| libs.borrow_mut().insert(lib.to_string(), (sha, key)));
+	Ok(fns)
+}
+
+// the functions registered by the code, by name
+fn run_library<'lua>(lua: &'lua Lua, code: &str) -> mlua::Result<Table<'lua>> {
+	let fns = lua.create_table()?;
+	lua.set_named_registry_value("library", fns.clone())?;
+	// the shebang line is no Lua, but keep line numbers right
+	let body = match code.split_once('\n') {
+		Some((_, rest)) => format!("\n{}", rest),
+		None => String::new()
+	};
+	LOADING.with(|l| l.set(true));
+	let res = lua.load(&bytes::encode(&body)[..]).set_name("=user_function")
+		.exec();
+	LOADING.with(|l| l.set(false));
+	lua.unset_named_registry_value("library")?;
+	res.map(|_| fns)
+}
+
+fn run(sha: &str, body: &str, prms: &[String], read_only: bool)
+	-> Result<DataType, &'static str> {
+	let (keys, argv) = split_keys(prms)?;
+	let _exclusive = EXEC.write().unwrap_or_else(|e| e.into_inner());
+	let _guard = replication::write_guard();
+	*RUNNING.lock().unwrap() = Some(Running {
+		since: Instant::now(),
+		wrote: false
+	});
+	KILL.store(false, Ordering::Relaxed);
+	READ_ONLY.with(|r| r.set(read_only));
+	let reply = LUA.with(|lua| execute(lua, sha, body, keys, argv));
+	*RUNNING.lock().unwrap() = None;
+	Ok(reply)
+}
+
+fn execute(lua: &Lua, sha: &str, body: &str, keys: &[String], argv: &[String])
+	-> DataType {
+	let res = (|| -> mlua::Result<DataType> {
+		let globals = lua.globals();
+		globals.raw_set("KEYS", strings(lua, keys)?)?;
+		globals.raw_set("ARGV", strings(lua, argv)?)?;
+		let f = lua.load(&bytes::encode(body)[..]).set_name("=user_script")
+			.into_function()?;
+		let v: Value = f.call(())?;
+		Ok(from_lua(&v))
+	})();
+	match res {
+		Ok(d) => d,
+		Err(e) => DataType::err(&error_text(&e, sha))
+	}
+}
+
+fn new_vm() -> Lua {
+	let libs = StdLib::TABLE | StdLib::STRING | StdLib::MATH | StdLib::UTF8;
+	let lua = Lua::new_with(libs, LuaOptions::default())
+		.expect("the Lua interpreter could not start");
+	lua.set_hook(HookTriggers::new().every_nth_instruction(1000), |_, _| {
+		if KILL.load(Ordering::Relaxed) {
+			return Err(call_error(ERRMSG_KILLED));
+		}
+		Ok(())
+	});
+	install(&lua).expect("the Lua redis library could not be set up");
+	lua
+}
+
+fn install(lua: &Lua) -> mlua::Result<()> {
+	let redis = lua.create_table()?;
+	redis.set("call", lua.create_function(|lua, args: Variadic<Value>| {
+		invoke(lua, args, true)
+	})?)?;
+	redis.set("pcall", lua.create_function(|lua, args: Variadic<Value>| {
+		invoke(lua, args, false)
+	})?)?;
+	redis.set("register_function", lua.create_function(
+		|lua, args: Variadic<Value>| register_function(lua, args)
+	)?)?;
+	redis.set("sha1hex", lua.create_function(|_, s: mlua::String| {
+		Ok(Sha1::from(s.as_bytes()).digest().to_string())
+	})?)?;
+	redis.set("error_reply", lua.create_function(|lua, s: String| {
+		let t = lua.create_table()?;
+		t.set("err", s)?;
+		Ok(t)
+	})?)?;
+	redis.set("status_reply", lua.create_function(|lua, s: String| {
+		let t = lua.create_table()?;
+		t.set("ok", s)?;
+		Ok(t)
+	})?)?;
+	redis.set("log", lua.create_function(|_, (level, msg): (i64, String)| {
+		let level = match level {
+			0 => Level::Debug,
+			1 => Level::Verbose,
+			2 => Level::Notice,
+			_ => Level::Warning
+		};
+		logger::log(level, &msg);
+		Ok(())
+	})?)?;
+	redis.set("LOG_DEBUG", 0)?;
+	redis.set("LOG_VERBOSE", 1)?;
+	redis.set("LOG_NOTICE", 2)?;
+	redis.set("LOG_WARNING", 3)?;
+	let globals = lua.globals();
+	globals.set("redis", redis.clone())?;
+	globals.set("server", redis)?;
+	lua.load(PROTECT_GLOBALS).exec()
+}
+
+// register_function(name, callback) or register_function{function_name =
+// name, callback = callback, flags = {...}, description = ...}
+fn register_function<'lua>(lua: &'lua Lua, args: Variadic<Value<'lua>>)
+	-> mlua::Result<()> {
+	let library = lua.named_registry_value::<Option<Table>>("library")?;
+	let fns: Table = match library {
+		Some(t) => t,
+		None => return Err(call_error(ERRMSG_NOREGISTER))
+	};
+	let f = lua.create_table()?;
+	let name: String = match (args.first(), args.get(1)) {
+		(Some(Value::String(n)), Some(Value::Function(cb))) => {
+			f.raw_set("callback", cb.clone())?;
+			n.to_str()?.to_string()
+		},
+		(Some(Value::Table(t)), None) => {
+			f.raw_set("callback", t.raw_get::<_, mlua::Function>("callback")?)?;
+			f.raw_set("flags", t.raw_get::<_, Option<Table>>("flags")?)?;
+			f.raw_set("description", t.raw_get::<_, Value>("description")?)?;
+			t.raw_get::<_, String>("function_name")?
+		},
+		_ => return Err(call_error(ERRMSG_BADREGISTER))
+	};
+	if name.is_empty()
+		|| !name.bytes().all(|b| b.is_ascii_alphanumeric() || b'_' == b) {
+		return Err(call_error(ERRMSG_BADFNNAME));
+	}
+	if fns.contains_key(name.as_str())? {
+		return Err(call_error(&format!(
+			"ERR Function {} already exists",
+			name
+		)));
+	}
+	fns.raw_set(name, f)
+}
+
+// redis.call raises errors, redis.pcall returns them as {err = ...}
+fn invoke<'lua>(lua: &'lua Lua, args: Variadic<Value<'lua>>, raise: bool)
+	-> mlua::Result<Value<'lua>> {
+	let mut argv: Vec<String> = Vec::new();
+	for a in args.iter() {
+		argv.push(match a {
+			Value::String(s) => bytes::decode(s.as_bytes()),
+			Value::Integer(n) => n.to_string(),
+			Value::Number(n) => n.to_string(),
+			_ => return Err(call_error(ERRMSG_BADARG))
+		});
+	}
+	if argv.is_empty() {
+		return Err(call_error(ERRMSG_NOARGS));
+	}
+	let req = Request {
+		command: argv.remove(0).to_ascii_lowercase(),
+		parameters: argv
+	};
+	match call(&req) {
+		DataType::SimpleError(e) if raise => Err(call_error(&e)),
+		reply => to_lua(lua, &reply)
+	}
+}
+
+fn call_error(msg: &str) -> mlua::Error {
+	mlua::Error::external(CallError(msg.to_string()))
+}
+
+// run a command of the CMDS table on behalf of the script
+fn call(req: &Request) -> DataType {
+	if LOADING.with(|l| l.get()) {
+		return DataType::err(ERRMSG_CALLONLOAD);
+	}
+	if NOSCRIPT_CMDS.contains(req.command.as_str()) {
+		return DataType::err(ERRMSG_NOTALLOWED);
+	}
+	let is_write = command::is_write(req);
+	if is_write {
+		if READ_ONLY.with(|r| r.get()) {
+			return DataType::err(ERRMSG_READONLY);
+		}
+		if let Err(e) = replication::check_writable() {
+			return DataType::err(e);
+		}
+	}
+	monitor::feed(0, "lua", req);
+	let reply = command::execute(req);
+	// replicas get the effects of the script rather than the script
+	if is_write && !matches!(reply, DataType::SimpleError(_)) {
+		if let Some(r) = RUNNING.lock().unwrap().as_mut() {
+			r.wrote = true;
+		}
+		replication::propagate(req, &reply);
+	}
+	reply
+}
+
+fn to_lua<'lua>(lua: &'lua Lua, d: &DataType) -> mlua::Result<Value<'lua>> {
+	let seq = |items: Vec<&DataType>| -> mlua::Result<Value<'lua>> {
+		let t = lua.create_table()?;
+		for (i, e) in items.into_iter().enumerate() {
+			t.raw_set(i + 1, to_lua(lua, e)?)?;
+		}
+		Ok(Value::Table(t))
+	};
+	Ok(match d {
+		DataType::BulkString(s) =>
+			Value::String(lua.create_string(bytes::encode(s))?),
+		DataType::Integer(n) => Value::Integer(*n),
+		DataType::Json(j) =>
+			Value::String(lua.create_string(bytes::encode(&j.to_text()))?),
+		DataType::Null => Value::Boolean(false),
+		DataType::SimpleString(s) | DataType::SimpleError(s) => {
+			let t = lua.create_table()?;
+			let ok = matches!(d, DataType::SimpleString(_));
+			t.raw_set(if ok {"ok"} else {"err"}, s.as_str())?;
+			Value::Table(t)
+		},
+		DataType::EmptyList => seq(vec![])?,
+		DataType::List(l) => seq(l.iter().collect())?,
+		DataType::Deque(l) => seq(l.iter().collect())?,
+		DataType::HashSet(h) => seq(h.iter().collect())?,
+		DataType::HashMap(h) =>
+			seq(h.iter().flat_map(|(k, v)| [k, v]).collect())?,
+		DataType::Bloom(_) | DataType::CountMin(_) | DataType::Cuckoo(_) |
+			DataType::SortedSet(_) | DataType::Stream(_) |
+			DataType::TDigest(_) | DataType::TimeSeries(_) |
+			DataType::TopK(_) => seq(vec![])?
+	})
+}
+
+// a Lua sequence of the given values, as byte strings
+fn strings<'lua>(lua: &'lua Lua, ss: &[String]) -> mlua::Result<Table<'lua>> {
+	lua.create_sequence_from(ss.iter()
+		.map(|s| lua.create_string(bytes::encode(s)))
+		.collect::<mlua::Result<Vec<_>>>()?)
+}
+
+// Lua values map to replies the way they do in Redis
+fn from_lua(v: &Value) -> DataType {
+	match v {
+		Value::Boolean(true) => DataType::Integer(1),
+		Value::Integer(n) => DataType::Integer(*n),
+		Value::Number(n) => DataType::Integer(*n as i64),
+		Value::String(s) => DataType::BulkString(bytes::decode(s.as_bytes())),
+		Value::Table(t) => {
+			if let Ok(Value::String(e)) = t.raw_get::<_, Value>("err") {
+				return DataType::err(&e.to_string_lossy());
+			}
+			if let Ok(Value::String(s)) = t.raw_get::<_, Value>("ok") {
+				return DataType::str(&s.to_string_lossy());
+			}
+			let mut l: Vec<DataType> = Vec::new();
+			// arrays end at the first nil
+			while let Ok(e) = t.raw_get::<_, Value>(l.len() + 1) {
+				if let Value::Nil = e {
+					break;
+				}
+				l.push(from_lua(&e));
+			}
+			DataType::List(l)
+		},
+		_ => DataType::Null
+	}
+}
+
+fn error_text(e: &mlua::Error, sha: &str) -> String {
+	match e {
+		mlua::Error::CallbackError {cause, ..} => error_text(cause, sha),
+		mlua::Error::ExternalError(x) => match x.downcast_ref::<CallError>() {
+			Some(CallError(s)) => s.clone(),
+			None => format!("ERR {} script: {}", x, sha)
+		},
+		mlua::Error::SyntaxError {message, ..} =>
+			format!("ERR Error compiling script (new function): {}", message),
+		// leave the traceback out, replies are single lines
+		mlua::Error::RuntimeError(s) => format!(
+			"ERR {} script: {}",
+			s.lines().next().unwrap_or(""),
+			sha
+		),
+		e => format!("ERR {} script: {}", e, sha).replace(['\r', '\n'], " ")
+	}
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,89 @@
+use serial_test::serial;
+use super::*;
+use crate::kv;
+
+fn strs(v: &[&str]) -> Vec<String> {
+	v.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	assert_eq!(sha1hex(""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+	let prms = strs(&["2", "k1", "k2", "a"]);
+	assert_eq!(
+		split_keys(&prms),
+		Ok((&prms[1..3], &prms[3..]))
+	);
+	assert_eq!(split_keys(&strs(&["-1"])), Err(ERRMSG_NEGKEYS));
+	assert_eq!(split_keys(&strs(&["2", "k"])), Err(ERRMSG_TOOMANYKEYS));
+	assert_eq!(split_keys(&strs(&["x"])), Err(ERRMSG_NUMNAI));
+	assert_eq!(
+		eval("return {1, 2.9, 'x', false, {ok = 'y'}}", &strs(&["0"]), false),
+		Ok(DataType::List(vec![
+			DataType::Integer(1),
+			DataType::Integer(2),
+			DataType::bulkStr("x"),
+			DataType::Null,
+			DataType::str("y")
+		]))
+	);
+	assert_eq!(
+		eval("return redis.error_reply('bad')", &strs(&["0"]), false),
+		Ok(DataType::err("bad"))
+	);
+	assert_eq!(
+		eval("return ARGV[1] .. KEYS[1]", &strs(&["1", "k", "v"]), false),
+		Ok(DataType::bulkStr("vk"))
+	);
+	assert!(matches!(
+		eval("x = 1", &strs(&["0"]), false),
+		Ok(DataType::SimpleError(e)) if e.contains("global variable 'x'")
+	));
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	kv::flushall();
+	let body = "redis.call('set', KEYS[1], ARGV[1]) \
+		return redis.call('get', KEYS[1])";
+	assert_eq!(
+		eval(body, &strs(&["1", "k", "v"]), false),
+		Ok(DataType::bulkStr("v"))
+	);
+	assert_eq!(
+		eval(body, &strs(&["1", "k", "w"]), true),
+		Ok(DataType::err(ERRMSG_READONLY))
+	);
+	// call raises the error of the command, pcall returns it
+	assert_eq!(
+		eval("return redis.call('incr', 'k')", &strs(&["0"]), false),
+		Ok(DataType::err("ERR Value is not an integer or out of range"))
+	);
+	assert_eq!(
+		eval(
+			"return type(redis.pcall('incr', 'k'))",
+			&strs(&["0"]),
+			false
+		),
+		Ok(DataType::bulkStr("table"))
+	);
+	assert_eq!(
+		eval("return redis.call('wait', '0', '0')", &strs(&["0"]), false),
+		Ok(DataType::err(ERRMSG_NOTALLOWED))
+	);
+	let sha = sha1hex(body);
+	assert_eq!(
+		script_exists(&strs(&[&sha, "nosuch"])),
+		Ok(DataType::List(vec![DataType::Integer(1), DataType::Integer(0)]))
+	);
+	assert_eq!(
+		evalsha(&sha.to_uppercase(), &strs(&["1", "k", "x"]), false),
+		Ok(DataType::bulkStr("x"))
+	);
+	assert_eq!(script_flush(), Ok(DataType::str("OK")));
+	assert_eq!(evalsha(&sha, &strs(&["0"]), false), Err(ERRMSG_NOSCRIPT));
+	assert_eq!(script_kill(), Err(ERRMSG_NOTBUSY));
+	kv::flushall();
+}
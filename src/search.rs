@@ -0,0 +1,1562 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use phf::phf_set;
+
+use super::bytes;
+use super::datatype::DataType;
+use super::hll::murmur64a;
+
+pub const ERRMSG_NOINDEX: &str = "ERR Unknown Index name";
+const ERRMSG_BADBLOB: &str = "ERR Error parsing vector similarity query: \
+	query vector blob size does not match index's expected size";
+const ERRMSG_BADFIELD: &str = "ERR Invalid field type";
+const ERRMSG_BADLIMIT: &str = "ERR Bad LIMIT arguments";
+const ERRMSG_BADKNN: &str =
+	"ERR Error parsing vector similarity query: invalid KNN clause";
+const ERRMSG_BADNUMBER: &str = "ERR Bad numeric range in query";
+const ERRMSG_BADQUERY: &str = "ERR Syntax error in query";
+const ERRMSG_BADVECTOR: &str =
+	"ERR Bad arguments for vector similarity index";
+const ERRMSG_DUPFIELD: &str = "ERR Duplicate field in schema";
+const ERRMSG_EXISTS: &str = "ERR Index already exists";
+const ERRMSG_NOFIELD: &str = "ERR Unknown field in query";
+const ERRMSG_NOSORT: &str = "ERR Property to sort by is not in the schema";
+const ERRMSG_NOPARAM: &str = "ERR No such parameter in query";
+const ERRMSG_NOSCHEMA: &str = "ERR Fields arguments are missing";
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+// the most results a search replies with by default
+const LIMIT: usize = 10;
+// BM25 term frequency saturation and length normalization
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+static STOPWORDS: phf::Set<&str> = phf_set! {
+	"a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in",
+	"into", "is", "it", "no", "not", "of", "on", "or", "such", "that", "the",
+	"their", "then", "there", "these", "they", "this", "to", "was", "will",
+	"with"
+};
+
+lazy_static! {
+	// indexes by name, ordered for FT._LIST
+	static ref INDEXES: Mutex<BTreeMap<String, Index>> =
+		Mutex::new(BTreeMap::new());
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Metric {
+	L2,
+	Ip,
+	Cosine
+}
+
+const METRICS: [(Metric, &str); 3] = [
+	(Metric::L2, "L2"),
+	(Metric::Ip, "IP"),
+	(Metric::Cosine, "COSINE")
+];
+
+// a number ordered for heaps and trees, distances and field values alike
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Num(f64);
+
+impl Eq for Num {}
+
+impl PartialOrd for Num {
+	fn partial_cmp(&self, o: &Num) -> Option<Ordering> {
+		Some(self.cmp(o))
+	}
+}
+
+impl Ord for Num {
+	fn cmp(&self, o: &Num) -> Ordering {
+		self.0.total_cmp(&o.0)
+	}
+}
+
+// a vector of a hash, and its links to the others on each layer it is on
+#[derive(Clone, Debug)]
+struct Node {
+	key: String,
+	v: Vec<f32>,
+	links: Vec<Vec<u32>>,
+	deleted: bool
+}
+
+// the vectors of one field, searched by brute force, or when hnsw is set
+// through a hierarchical navigable small world graph of m links a node per
+// layer, twice as many on the bottom one; replaced vectors stay in the
+// graph as stepping stones until they outnumber the live ones
+#[derive(Clone, Debug)]
+struct Vectors {
+	dim: usize,
+	metric: Metric,
+	wide: bool,
+	hnsw: bool,
+	m: usize,
+	ef_construction: usize,
+	ef_runtime: usize,
+	nodes: Vec<Node>,
+	live: HashMap<String, u32>,
+	entry: Option<u32>
+}
+
+impl Vectors {
+	// FLAT | HNSW NARGS TYPE type DIM dim DISTANCE_METRIC metric
+	// [M m] [EF_CONSTRUCTION n] [EF_RUNTIME n] ..., and the number of
+	// parameters taken
+	fn parse(prms: &[String]) -> Result<(Vectors, usize), &'static str> {
+		let hnsw = match prms.first().map(|a| a.to_ascii_lowercase()) {
+			Some(a) if "flat" == a => false,
+			Some(a) if "hnsw" == a => true,
+			_ => return Err(ERRMSG_BADVECTOR)
+		};
+		let n = match prms.get(1).map(|n| n.parse::<usize>()) {
+			Some(Ok(n)) if 0 == n % 2 && n + 2 <= prms.len() => n,
+			_ => return Err(ERRMSG_BADVECTOR)
+		};
+		let mut vs = Vectors {
+			dim: 0, metric: Metric::L2, wide: false, hnsw, m: 16,
+			ef_construction: 200, ef_runtime: 10, nodes: vec![],
+			live: HashMap::new(), entry: None
+		};
+		let (mut typed, mut measured) = (false, false);
+		for a in prms[2..n + 2].chunks(2) {
+			let num = a[1].parse::<usize>().ok().filter(|n| 0 < *n);
+			match (a[0].to_ascii_lowercase().as_str(), num) {
+				("type", _) => {
+					vs.wide = match a[1].to_ascii_lowercase().as_str() {
+						"float32" => false,
+						"float64" => true,
+						_ => return Err(ERRMSG_BADVECTOR)
+					};
+					typed = true;
+				},
+				("dim", Some(n)) => vs.dim = n,
+				("distance_metric", _) => {
+					vs.metric = METRICS.iter()
+						.find(|(_, s)| s.eq_ignore_ascii_case(&a[1]))
+						.ok_or(ERRMSG_BADVECTOR)?.0;
+					measured = true;
+				},
+				("initial_cap" | "block_size", Some(_)) => {},
+				("m", Some(n)) if hnsw => vs.m = n.max(2),
+				("ef_construction", Some(n)) if hnsw => vs.ef_construction = n,
+				("ef_runtime", Some(n)) if hnsw => vs.ef_runtime = n,
+				("epsilon", _) if hnsw => {},
+				_ => return Err(ERRMSG_BADVECTOR)
+			}
+		}
+		match typed && measured && 0 < vs.dim {
+			true => Ok((vs, n + 2)),
+			false => Err(ERRMSG_BADVECTOR)
+		}
+	}
+
+	// the vector held by little endian floats b
+	fn decode(&self, b: &[u8]) -> Option<Vec<f32>> {
+		let w = if self.wide {8} else {4};
+		if b.len() != w * self.dim {
+			return None;
+		}
+		Some(b.chunks(w).map(|f| match self.wide {
+			true => f64::from_le_bytes(f.try_into().unwrap()) as f32,
+			false => f32::from_le_bytes(f.try_into().unwrap())
+		}).collect())
+	}
+
+	fn dist(&self, a: &[f32], b: &[f32]) -> f64 {
+		let dot = || a.iter().zip(b.iter()).map(|(x, y)| x * y).sum::<f32>();
+		(match self.metric {
+			Metric::L2 => a.iter().zip(b.iter())
+				.map(|(x, y)| (x - y) * (x - y))
+				.sum(),
+			Metric::Ip => 1.0 - dot(),
+			Metric::Cosine => {
+				let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>()
+					.sqrt();
+				match norm(a) * norm(b) {
+					0.0 => 1.0,
+					n => 1.0 - dot() / n
+				}
+			}
+		}) as f64
+	}
+
+	// the top layer of key, drawn from its hash so that a rebuilt graph
+	// comes out the same
+	fn level(&self, key: &str) -> usize {
+		let h = murmur64a(&bytes::encode(key), SEED);
+		let u = ((h >> 11) as f64 / (1u64 << 53) as f64).max(f64::MIN_POSITIVE);
+		((-u.ln() / (self.m as f64).ln()) as usize).min(16)
+	}
+
+	// the ef nodes nearest q found on a layer from entry, nearest first
+	fn search_layer(&self, q: &[f32], entry: u32, ef: usize, layer: usize)
+		-> Vec<(Num, u32)> {
+		let d = Num(self.dist(q, &self.nodes[entry as usize].v));
+		let mut seen = HashSet::from([entry]);
+		let mut cands = BinaryHeap::from([Reverse((d, entry))]);
+		let mut found = BinaryHeap::from([(d, entry)]);
+		while let Some(Reverse((d, c))) = cands.pop() {
+			if ef <= found.len() && found.peek().is_some_and(|f| f.0 < d) {
+				break;
+			}
+			for n in self.nodes[c as usize].links[layer].iter() {
+				if !seen.insert(*n) {
+					continue;
+				}
+				let d = Num(self.dist(q, &self.nodes[*n as usize].v));
+				if found.len() < ef || found.peek().is_some_and(|f| d < f.0) {
+					cands.push(Reverse((d, *n)));
+					found.push((d, *n));
+					if ef < found.len() {
+						found.pop();
+					}
+				}
+			}
+		}
+		found.into_sorted_vec()
+	}
+
+	// the entry point on layer, walking greedily down from the top
+	fn descend(&self, q: &[f32], layer: usize) -> Option<u32> {
+		let mut ep = self.entry?;
+		let top = self.nodes[ep as usize].links.len() - 1;
+		for l in (layer + 1..=top).rev() {
+			ep = self.search_layer(q, ep, 1, l)[0].1;
+		}
+		Some(ep)
+	}
+
+	fn insert(&mut self, key: &str, v: Vec<f32>) {
+		self.remove(key);
+		let id = self.nodes.len() as u32;
+		let level = if self.hnsw {self.level(key)} else {0};
+		let ep = self.descend(&v, level);
+		self.nodes.push(Node {
+			key: key.to_string(),
+			v,
+			links: vec![vec![]; level + 1],
+			deleted: false
+		});
+		self.live.insert(key.to_string(), id);
+		let mut ep = match (self.hnsw, ep) {
+			(true, Some(ep)) => ep,
+			(true, None) => {
+				self.entry = Some(id);
+				return;
+			},
+			(false, _) => return
+		};
+		let q = self.nodes[id as usize].v.clone();
+		let top = self.nodes[ep as usize].links.len() - 1;
+		for l in (0..=level.min(top)).rev() {
+			let found = self.search_layer(&q, ep, self.ef_construction, l);
+			let max = if 0 == l {2 * self.m} else {self.m};
+			let links: Vec<u32> =
+				found.iter().take(self.m).map(|(_, n)| *n).collect();
+			for n in links.iter() {
+				self.link(*n, id, l, max);
+			}
+			self.nodes[id as usize].links[l] = links;
+			ep = found[0].1;
+		}
+		if top < level {
+			self.entry = Some(id);
+		}
+	}
+
+	// link node n to id on a layer, dropping its farthest links beyond max
+	fn link(&mut self, n: u32, id: u32, layer: usize, max: usize) {
+		let links = &mut self.nodes[n as usize].links[layer];
+		let mut links = std::mem::take(links);
+		links.push(id);
+		if max < links.len() {
+			let v = &self.nodes[n as usize].v;
+			links.sort_by_cached_key(|x|
+				Num(self.dist(v, &self.nodes[*x as usize].v)));
+			links.truncate(max);
+		}
+		self.nodes[n as usize].links[layer] = links;
+	}
+
+	fn remove(&mut self, key: &str) {
+		if let Some(id) = self.live.remove(key) {
+			self.nodes[id as usize].deleted = true;
+			if self.live.len() < self.nodes.len() / 2 {
+				let nodes = std::mem::take(&mut self.nodes);
+				self.clear();
+				nodes.into_iter().filter(|n| !n.deleted)
+					.for_each(|n| self.insert(&n.key, n.v));
+			}
+		}
+	}
+
+	fn clear(&mut self) {
+		self.nodes.clear();
+		self.live.clear();
+		self.entry = None;
+	}
+
+	// the k vectors nearest q that pass the filter, nearest first, looked
+	// up in the graph and then by brute force when that finds too few
+	fn knn<F>(&self, q: &[f32], k: usize, ef: Option<usize>, f: F)
+		-> Vec<(f32, &str)> where F: Fn(&str) -> bool {
+		let pick = |n: &Node| !n.deleted && f(&n.key);
+		let mut hits: Vec<(Num, &str)> = match self.descend(q, 0) {
+			Some(ep) if self.hnsw => {
+				let ef = ef.unwrap_or(self.ef_runtime).max(k);
+				self.search_layer(q, ep, ef, 0).into_iter()
+					.map(|(d, n)| (d, &self.nodes[n as usize]))
+					.filter(|(_, n)| pick(n))
+					.map(|(d, n)| (d, n.key.as_str()))
+					.collect()
+			},
+			_ => vec![]
+		};
+		if hits.len() < k.min(self.live.len()) {
+			hits = self.live.values()
+				.map(|id| &self.nodes[*id as usize])
+				.filter(|n| pick(n))
+				.map(|n| (Num(self.dist(q, &n.v)), n.key.as_str()))
+				.collect();
+		}
+		hits.sort_unstable();
+		hits.truncate(k);
+		hits.into_iter().map(|(d, k)| (d.0 as f32, k)).collect()
+	}
+}
+
+// Porter's stemmer over a lowercase word, the stem being b[..j] as the
+// suffixes are looked at
+struct Stemmer {
+	b: Vec<u8>,
+	j: usize
+}
+
+impl Stemmer {
+	fn cons(&self, i: usize) -> bool {
+		match self.b[i] {
+			b'a' | b'e' | b'i' | b'o' | b'u' => false,
+			b'y' => 0 == i || !self.cons(i - 1),
+			_ => true
+		}
+	}
+
+	// the number of vowels followed by consonants in the stem
+	fn m(&self) -> usize {
+		let (mut n, mut i) = (0, 0);
+		while i < self.j && self.cons(i) {
+			i += 1;
+		}
+		loop {
+			while i < self.j && !self.cons(i) {
+				i += 1;
+			}
+			if self.j <= i {
+				return n;
+			}
+			while i < self.j && self.cons(i) {
+				i += 1;
+			}
+			n += 1;
+		}
+	}
+
+	fn vowel_in_stem(&self) -> bool {
+		(0..self.j).any(|i| !self.cons(i))
+	}
+
+	fn doublec(&self, i: usize) -> bool {
+		1 <= i && self.b[i] == self.b[i - 1] && self.cons(i)
+	}
+
+	// consonant, vowel, consonant ending at i, the last not w, x or y
+	fn cvc(&self, i: usize) -> bool {
+		2 <= i && self.cons(i) && !self.cons(i - 1) && self.cons(i - 2) &&
+			!matches!(self.b[i], b'w' | b'x' | b'y')
+	}
+
+	fn ends(&mut self, s: &str) -> bool {
+		match self.b.ends_with(s.as_bytes()) {
+			true => {
+				self.j = self.b.len() - s.len();
+				true
+			},
+			false => false
+		}
+	}
+
+	fn set_to(&mut self, s: &str) {
+		self.b.truncate(self.j);
+		self.b.extend_from_slice(s.as_bytes());
+	}
+
+	// replace the first of the suffixes found when the stem measures more
+	// than min
+	fn replace(&mut self, rules: &[(&str, &str)], min: usize) {
+		for (from, to) in rules.iter() {
+			if !self.ends(from) || "ion" == *from &&
+				!(0 < self.j && matches!(self.b[self.j - 1], b's' | b't')) {
+				continue;
+			}
+			if min < self.m() {
+				self.set_to(to);
+			}
+			return;
+		}
+	}
+
+	fn step1(&mut self) {
+		if self.b.ends_with(b"s") {
+			if self.ends("sses") {
+				self.set_to("ss");
+			} else if self.ends("ies") {
+				self.set_to("i");
+			} else if !self.b.ends_with(b"ss") {
+				self.b.pop();
+			}
+		}
+		if self.ends("eed") {
+			if 0 < self.m() {
+				self.set_to("ee");
+			}
+		} else if (self.ends("ed") || self.ends("ing")) &&
+			self.vowel_in_stem() {
+			self.b.truncate(self.j);
+			let last = self.b.len() - 1;
+			if self.ends("at") {
+				self.set_to("ate");
+			} else if self.ends("bl") {
+				self.set_to("ble");
+			} else if self.ends("iz") {
+				self.set_to("ize");
+			} else if self.doublec(last) {
+				if !matches!(self.b[last], b'l' | b's' | b'z') {
+					self.b.pop();
+				}
+			} else if 1 == self.m() && self.cvc(last) {
+				self.b.push(b'e');
+			}
+		}
+		if self.ends("y") && self.vowel_in_stem() {
+			self.set_to("i");
+		}
+	}
+
+	fn step5(&mut self) {
+		self.j = self.b.len();
+		if self.b.ends_with(b"e") {
+			let m = self.m();
+			if 1 < m || 1 == m && !self.cvc(self.j - 2) {
+				self.b.pop();
+			}
+		}
+		self.j = self.b.len();
+		if self.b.ends_with(b"l") && self.doublec(self.j - 1) && 1 < self.m() {
+			self.b.pop();
+		}
+	}
+}
+
+fn stem(w: &str) -> String {
+	if w.len() <= 2 || !w.bytes().all(|c| c.is_ascii_lowercase()) {
+		return w.to_string();
+	}
+	let mut s = Stemmer {b: w.as_bytes().to_vec(), j: w.len()};
+	s.step1();
+	s.replace(&[
+		("ational", "ate"), ("tional", "tion"), ("enci", "ence"),
+		("anci", "ance"), ("izer", "ize"), ("bli", "ble"), ("alli", "al"),
+		("entli", "ent"), ("eli", "e"), ("ousli", "ous"), ("ization", "ize"),
+		("ation", "ate"), ("ator", "ate"), ("alism", "al"),
+		("iveness", "ive"), ("fulness", "ful"), ("ousness", "ous"),
+		("aliti", "al"), ("iviti", "ive"), ("biliti", "ble"), ("logi", "log")
+	], 0);
+	s.replace(&[
+		("icate", "ic"), ("ative", ""), ("alize", "al"), ("iciti", "ic"),
+		("ical", "ic"), ("ful", ""), ("ness", "")
+	], 0);
+	s.replace(&[
+		("al", ""), ("ance", ""), ("ence", ""), ("er", ""), ("ic", ""),
+		("able", ""), ("ible", ""), ("ant", ""), ("ement", ""), ("ment", ""),
+		("ent", ""), ("ion", ""), ("ou", ""), ("ism", ""), ("ate", ""),
+		("iti", ""), ("ous", ""), ("ive", ""), ("ize", "")
+	], 1);
+	s.step5();
+	String::from_utf8(s.b).unwrap()
+}
+
+// the words of s, lowercased, less the stop words
+fn words(s: &str) -> Vec<String> {
+	s.split(|c: char| !c.is_alphanumeric() && '_' != c)
+		.filter(|w| !w.is_empty())
+		.map(|w| w.to_lowercase())
+		.filter(|w| !STOPWORDS.contains(w.as_str()))
+		.collect()
+}
+
+// a word and its stem
+type Word = (String, String);
+
+fn stemmed(s: &str) -> Vec<Word> {
+	words(s).into_iter().map(|w| {
+		let s = stem(&w);
+		(w, s)
+	}).collect()
+}
+
+// the docs by their words, stems being marked with a leading +
+type Postings = BTreeMap<String, BTreeSet<String>>;
+
+#[derive(Clone, Debug)]
+enum Kind {
+	Text {
+		weight: f64,
+		nostem: bool,
+		terms: Postings
+	},
+	Tag {
+		separator: char,
+		case_sensitive: bool,
+		tags: Postings
+	},
+	Numeric(BTreeSet<(Num, String)>),
+	Vector(Box<Vectors>)
+}
+
+// a hash field indexed, known by its alias in queries
+#[derive(Clone, Debug)]
+struct Field {
+	name: String,
+	alias: String,
+	sortable: bool,
+	kind: Kind
+}
+
+impl Field {
+	// the tags in s, as matched
+	fn tags(&self, s: &str) -> Vec<String> {
+		let (separator, case_sensitive) = match self.kind {
+			Kind::Tag {separator, case_sensitive, ..} =>
+				(separator, case_sensitive),
+			_ => return vec![]
+		};
+		s.split(separator)
+			.map(|t| t.trim())
+			.filter(|t| !t.is_empty())
+			.map(|t| match case_sensitive {
+				true => t.to_string(),
+				false => t.to_lowercase()
+			})
+			.collect()
+	}
+
+	fn stems(&self) -> bool {
+		matches!(self.kind, Kind::Text {nostem: false, ..})
+	}
+
+	fn weight(&self) -> f64 {
+		match self.kind {
+			Kind::Text {weight, ..} => weight,
+			_ => 0.0
+		}
+	}
+
+	// index s as the value of the field at key k
+	fn add(&mut self, k: &str, s: &str) -> Option<Value> {
+		let v = match &mut self.kind {
+			Kind::Text {nostem: true, ..} => Value::Text(words(s).into_iter()
+				.map(|w| (w.clone(), w))
+				.collect()),
+			Kind::Text {..} => Value::Text(stemmed(s)),
+			Kind::Tag {..} => Value::Tags(self.tags(s)),
+			Kind::Numeric(_) => Value::Number(
+				s.parse::<f64>().ok().filter(|n| !n.is_nan())?
+			),
+			Kind::Vector(vs) => {
+				if let Some(v) = vs.decode(&bytes::encode(s)) {
+					vs.insert(k, v);
+				}
+				return None;
+			}
+		};
+		self.post(k, &v, true);
+		Some(v)
+	}
+
+	// add key k to the postings of value v, or remove it
+	fn post(&mut self, k: &str, v: &Value, add: bool) {
+		let post = |ps: &mut Postings, t: String| match add {
+			true => {
+				ps.entry(t).or_default().insert(k.to_string());
+			},
+			false => if let Some(ks) = ps.get_mut(&t) {
+				ks.remove(k);
+				if ks.is_empty() {
+					ps.remove(&t);
+				}
+			}
+		};
+		match (&mut self.kind, v) {
+			(Kind::Text {terms, nostem, ..}, Value::Text(ws)) => {
+				for (w, s) in ws.iter() {
+					post(terms, w.clone());
+					if !*nostem {
+						post(terms, format!("+{}", s));
+					}
+				}
+			},
+			(Kind::Tag {tags, ..}, Value::Tags(ts)) => {
+				for t in ts.iter() {
+					post(tags, t.clone());
+				}
+			},
+			(Kind::Numeric(tree), Value::Number(n)) => {
+				let entry = (Num(*n), k.to_string());
+				if add {
+					tree.insert(entry);
+				} else {
+					tree.remove(&entry);
+				}
+			},
+			_ => {}
+		}
+	}
+
+	fn clear(&mut self) {
+		match &mut self.kind {
+			Kind::Text {terms, ..} => terms.clear(),
+			Kind::Tag {tags, ..} => tags.clear(),
+			Kind::Numeric(tree) => tree.clear(),
+			Kind::Vector(vs) => vs.clear()
+		}
+	}
+
+	// the docs holding word w, or a word starting with it when prefix is set
+	fn docs(&self, w: &Word, prefix: bool, verbatim: bool) -> Vec<&str> {
+		let terms = match &self.kind {
+			Kind::Text {terms, ..} => terms,
+			_ => return vec![]
+		};
+		let ks: Vec<&BTreeSet<String>> = match prefix {
+			true => terms.range(w.0.clone()..)
+				.take_while(|(t, _)| t.starts_with(&w.0))
+				.map(|(_, ks)| ks)
+				.collect(),
+			false => terms.get(&w.0).into_iter()
+				.chain((self.stems() && !verbatim)
+					.then(|| terms.get(&format!("+{}", w.1)))
+					.flatten())
+				.collect()
+		};
+		ks.into_iter().flatten().map(|k| k.as_str()).collect()
+	}
+
+	// the docs with a value between the bounds, each left out when set
+	fn range(&self, (lo, lx): (f64, bool), (hi, hx): (f64, bool))
+		-> BTreeSet<&str> {
+		let tree = match &self.kind {
+			Kind::Numeric(tree) => tree,
+			_ => return BTreeSet::new()
+		};
+		tree.range((Num(lo), String::new())..)
+			.take_while(|(n, _)| n.0 < hi || !hx && n.0 == hi)
+			.filter(|(n, _)| !lx || lo < n.0)
+			.map(|(_, k)| k.as_str())
+			.collect()
+	}
+
+	// whether word t of a doc is the word w of a query
+	fn matches(&self, t: &Word, w: &Word, verbatim: bool) -> bool {
+		t.0 == w.0 || !verbatim && self.stems() && t.1 == w.1
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+	Text(Vec<Word>),
+	Tags(Vec<String>),
+	Number(f64)
+}
+
+// the values of the fields of a hash, vectors aside
+type Doc = Vec<Option<Value>>;
+
+// FIELD [AS alias] TEXT [NOSTEM] [WEIGHT weight] | TAG [SEPARATOR sep]
+// [CASESENSITIVE] | NUMERIC | VECTOR algorithm nargs attributes ...
+// [SORTABLE] [FIELD ...]
+fn parse_schema(prms: &[String]) -> Result<Vec<Field>, &'static str> {
+	let mut fields: Vec<Field> = vec![];
+	let mut i = 0;
+	while i < prms.len() {
+		let name = prms[i].clone();
+		let alias = match prms.get(i + 1) {
+			Some(a) if a.eq_ignore_ascii_case("as") => {
+				i += 2;
+				prms.get(i).ok_or(ERRMSG_SYNERR)?.clone()
+			},
+			_ => name.clone()
+		};
+		let mut kind = match prms.get(i + 1).map(|t| t.to_ascii_lowercase()) {
+			Some(t) if "text" == t => Kind::Text {
+				weight: 1.0, nostem: false, terms: BTreeMap::new()
+			},
+			Some(t) if "tag" == t => Kind::Tag {
+				separator: ',', case_sensitive: false, tags: BTreeMap::new()
+			},
+			Some(t) if "numeric" == t => Kind::Numeric(BTreeSet::new()),
+			Some(t) if "vector" == t => {
+				let (vs, n) = Vectors::parse(&prms[i + 2..])?;
+				i += n;
+				Kind::Vector(Box::new(vs))
+			},
+			_ => return Err(ERRMSG_BADFIELD)
+		};
+		i += 2;
+		let mut sortable = false;
+		while let Some(o) = prms.get(i).map(|o| o.to_ascii_lowercase()) {
+			let arg = prms.get(i + 1);
+			match (o.as_str(), &mut kind) {
+				("sortable", _) => sortable = true,
+				("unf", _) if sortable => {},
+				("nostem", Kind::Text {nostem, ..}) => *nostem = true,
+				("weight", Kind::Text {weight, ..}) => {
+					*weight = arg.and_then(|w| w.parse::<f64>().ok())
+						.filter(|w| w.is_finite() && 0.0 <= *w)
+						.ok_or(ERRMSG_SYNERR)?;
+					i += 1;
+				},
+				("separator", Kind::Tag {separator, ..}) => {
+					let mut cs = arg.ok_or(ERRMSG_SYNERR)?.chars();
+					*separator = match (cs.next(), cs.next()) {
+						(Some(c), None) => c,
+						_ => return Err(ERRMSG_SYNERR)
+					};
+					i += 1;
+				},
+				("casesensitive", Kind::Tag {case_sensitive, ..}) =>
+					*case_sensitive = true,
+				_ => break
+			}
+			i += 1;
+		}
+		if fields.iter().any(|f| f.alias == alias) {
+			return Err(ERRMSG_DUPFIELD);
+		}
+		fields.push(Field {name, alias, sortable, kind});
+	}
+	match fields.is_empty() {
+		true => Err(ERRMSG_NOSCHEMA),
+		false => Ok(fields)
+	}
+}
+
+// an index over the hashes whose keys start with one of the prefixes, the
+// keys changed since it was last looked at waiting to be indexed again
+#[derive(Clone, Debug)]
+struct Index {
+	// what FT.CREATE got after the name
+	args: Vec<String>,
+	prefixes: Vec<String>,
+	fields: Vec<Field>,
+	docs: BTreeMap<String, Doc>,
+	// the number of words in the text fields of all the docs
+	words: usize,
+	pending: BTreeSet<String>
+}
+
+impl Index {
+	// [ON HASH] [PREFIX count prefix ...] SCHEMA ...
+	fn parse(args: &[String]) -> Result<Index, &'static str> {
+		let mut prefixes = vec![];
+		let mut i = 0;
+		while i < args.len() {
+			let next = args.get(i + 1).map(|s| s.to_ascii_lowercase());
+			match args[i].to_ascii_lowercase().as_str() {
+				"on" if Some("hash") == next.as_deref() => i += 2,
+				"prefix" => {
+					let n = next.and_then(|n| n.parse::<usize>().ok())
+						.filter(|n| 0 < *n && i + 2 + n <= args.len())
+						.ok_or(ERRMSG_SYNERR)?;
+					prefixes.extend_from_slice(&args[i + 2..i + 2 + n]);
+					i += 2 + n;
+				},
+				"schema" => return Ok(Index {
+					args: args.to_vec(),
+					prefixes: match prefixes.is_empty() {
+						true => vec![String::new()],
+						false => prefixes
+					},
+					fields: parse_schema(&args[i + 1..])?,
+					docs: BTreeMap::new(),
+					words: 0,
+					pending: BTreeSet::new()
+				}),
+				_ => return Err(ERRMSG_SYNERR)
+			}
+		}
+		Err(ERRMSG_NOSCHEMA)
+	}
+
+	fn covers(&self, k: &str) -> bool {
+		self.prefixes.iter().any(|p| k.starts_with(p.as_str()))
+	}
+
+	// index again the keys changed since the last time
+	fn sync(&mut self, m: &HashMap<DataType, DataType>) {
+		for k in std::mem::take(&mut self.pending) {
+			self.unindex(&k);
+			if let Some(DataType::HashMap(h)) = m.get(&DataType::bulkStr(&k)) {
+				self.index(&k, h);
+			}
+		}
+	}
+
+	fn index(&mut self, k: &str, h: &HashMap<DataType, DataType>) {
+		let doc: Doc = self.fields.iter_mut()
+			.map(|f| match h.get(&DataType::bulkStr(&f.name)) {
+				Some(DataType::BulkString(s)) => f.add(k, s),
+				_ => None
+			})
+			.collect();
+		self.words += length(&doc);
+		self.docs.insert(k.to_string(), doc);
+	}
+
+	fn unindex(&mut self, k: &str) {
+		let doc = match self.docs.remove(k) {
+			Some(doc) => doc,
+			None => return
+		};
+		self.words -= length(&doc);
+		for (f, v) in self.fields.iter_mut().zip(doc.iter()) {
+			match (&mut f.kind, v) {
+				(Kind::Vector(vs), _) => vs.remove(k),
+				(_, Some(v)) => f.post(k, v, false),
+				_ => {}
+			}
+		}
+	}
+
+	fn clear(&mut self) {
+		self.docs.clear();
+		self.words = 0;
+		self.pending.clear();
+		self.fields.iter_mut().for_each(|f| f.clear());
+	}
+
+	fn field(&self, alias: &str) -> Option<usize> {
+		self.fields.iter().position(|f| f.alias == alias)
+	}
+
+	// the text fields, or the one given
+	fn texts(&self, scope: Option<usize>)
+		-> impl Iterator<Item = (usize, &Field)> {
+		self.fields.iter().enumerate()
+			.filter(move |(i, f)| matches!(f.kind, Kind::Text {..}) &&
+				scope.is_none_or(|s| s == *i))
+	}
+
+	// whether the words ws follow one another in a text field of doc k
+	fn phrase(&self, k: &str, scope: Option<usize>, ws: &[Word], verbatim: bool)
+		-> bool {
+		let doc = &self.docs[k];
+		self.texts(scope).any(|(i, f)| match &doc[i] {
+			Some(Value::Text(ts)) => ts.windows(ws.len()).any(|run|
+				run.iter().zip(ws.iter())
+					.all(|(t, w)| f.matches(t, w, verbatim))
+			),
+			_ => false
+		})
+	}
+
+	// the BM25 score of doc k for the terms, found in df docs each
+	fn score(&self, k: &str, terms: &[(Option<usize>, &Word, usize)],
+		verbatim: bool) -> f64 {
+		let doc = &self.docs[k];
+		let n = self.docs.len() as f64;
+		let norm = match self.words {
+			0 => 1.0,
+			w => 1.0 - B + B * length(doc) as f64 * n / w as f64
+		};
+		terms.iter().map(|(scope, w, df)| {
+			let tf: f64 = self.texts(*scope)
+				.map(|(i, f)| match &doc[i] {
+					Some(Value::Text(ts)) => f.weight() * ts.iter()
+						.filter(|t| f.matches(t, w, verbatim))
+						.count() as f64,
+					_ => 0.0
+				})
+				.sum();
+			let df = *df as f64;
+			let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+			idf * tf * (K1 + 1.0) / (tf + K1 * norm)
+		}).sum()
+	}
+}
+
+// the number of words in the text fields of doc
+fn length(doc: &Doc) -> usize {
+	doc.iter().map(|v| match v {
+		Some(Value::Text(ws)) => ws.len(),
+		_ => 0
+	}).sum()
+}
+
+// a query filter over the fields, by their positions in the index, words
+// being looked for in all the text fields unless one is given
+#[derive(Clone, Debug, PartialEq)]
+enum Filter {
+	All,
+	Term(Option<usize>, Word),
+	Prefix(Option<usize>, Word),
+	Phrase(Option<usize>, Vec<Word>),
+	Tag(usize, Vec<String>),
+	Range(usize, (f64, bool), (f64, bool)),
+	Not(Box<Filter>),
+	And(Vec<Filter>),
+	Or(Vec<Filter>)
+}
+
+impl Filter {
+	// the keys of the docs matching
+	fn eval<'a>(&self, index: &'a Index, verbatim: bool) -> BTreeSet<&'a str> {
+		match self {
+			Filter::All => index.docs.keys().map(|k| k.as_str()).collect(),
+			Filter::Term(scope, w) | Filter::Prefix(scope, w) => {
+				let prefix = matches!(self, Filter::Prefix(..));
+				index.texts(*scope)
+					.flat_map(|(_, f)| f.docs(w, prefix, verbatim))
+					.collect()
+			},
+			Filter::Phrase(scope, ws) => {
+				let mut ks = Filter::And(ws.iter()
+					.map(|w| Filter::Term(*scope, w.clone()))
+					.collect()
+				).eval(index, verbatim);
+				ks.retain(|k| index.phrase(k, *scope, ws, verbatim));
+				ks
+			},
+			Filter::Tag(f, ts) => match &index.fields[*f].kind {
+				Kind::Tag {tags, ..} => ts.iter()
+					.filter_map(|t| tags.get(t))
+					.flatten()
+					.map(|k| k.as_str())
+					.collect(),
+				_ => BTreeSet::new()
+			},
+			Filter::Range(f, lo, hi) => index.fields[*f].range(*lo, *hi),
+			Filter::Not(q) => {
+				let out = q.eval(index, verbatim);
+				index.docs.keys()
+					.map(|k| k.as_str())
+					.filter(|k| !out.contains(k))
+					.collect()
+			},
+			Filter::And(qs) => {
+				let mut ks = qs[0].eval(index, verbatim);
+				for q in qs[1..].iter() {
+					if ks.is_empty() {
+						break;
+					}
+					let more = q.eval(index, verbatim);
+					ks.retain(|k| more.contains(k));
+				}
+				ks
+			},
+			Filter::Or(qs) => qs.iter()
+				.flat_map(|q| q.eval(index, verbatim))
+				.collect()
+		}
+	}
+
+	// the words the matches are ranked on
+	fn terms<'a>(&'a self, out: &mut Vec<(Option<usize>, &'a Word)>) {
+		match self {
+			Filter::Term(scope, w) => out.push((*scope, w)),
+			Filter::Phrase(scope, ws) =>
+				out.extend(ws.iter().map(|w| (*scope, w))),
+			Filter::And(qs) | Filter::Or(qs) =>
+				qs.iter().for_each(|q| q.terms(out)),
+			_ => {}
+		}
+	}
+}
+
+// chars ending a word of a query
+const BREAKS: &[char] = &[
+	' ', '\t', '\r', '\n', '(', ')', '|', '"', '@', '{', '}', '[', ']', '*'
+];
+
+// a recursive descent over a query, alternatives being split by | and
+// terms juxtaposed having all to match
+struct Parser<'a> {
+	cs: Vec<char>,
+	at: usize,
+	scope: Option<usize>,
+	index: &'a Index,
+	params: &'a HashMap<String, String>
+}
+
+impl Parser<'_> {
+	fn peek(&mut self) -> Option<char> {
+		while self.cs.get(self.at).is_some_and(|c| c.is_whitespace()) {
+			self.at += 1;
+		}
+		self.cs.get(self.at).copied()
+	}
+
+	fn eat(&mut self, c: char) -> bool {
+		match self.peek() == Some(c) {
+			true => {
+				self.at += 1;
+				true
+			},
+			false => false
+		}
+	}
+
+	// the text up to one of the given chars, backslashes escaping
+	fn until(&mut self, stops: &[char]) -> String {
+		let mut s = String::new();
+		while let Some(c) = self.cs.get(self.at).copied() {
+			if stops.contains(&c) {
+				break;
+			}
+			self.at += 1;
+			match ('\\' == c, self.cs.get(self.at)) {
+				(true, Some(e)) => {
+					s.push(*e);
+					self.at += 1;
+				},
+				_ => s.push(c)
+			}
+		}
+		s
+	}
+
+	fn param(&self, s: &str) -> Result<String, &'static str> {
+		match s.strip_prefix('$') {
+			Some(p) => self.params.get(p).cloned().ok_or(ERRMSG_NOPARAM),
+			None => Ok(s.to_string())
+		}
+	}
+
+	fn or(&mut self) -> Result<Filter, &'static str> {
+		let mut qs = vec![self.and()?];
+		while self.eat('|') {
+			qs.push(self.and()?);
+		}
+		Ok(if 1 == qs.len() {qs.pop().unwrap()} else {Filter::Or(qs)})
+	}
+
+	fn and(&mut self) -> Result<Filter, &'static str> {
+		let mut qs = vec![];
+		while !matches!(self.peek(), None | Some('|') | Some(')')) {
+			qs.push(self.unary()?);
+		}
+		match qs.len() {
+			0 => Err(ERRMSG_BADQUERY),
+			1 => Ok(qs.pop().unwrap()),
+			_ => Ok(Filter::And(qs))
+		}
+	}
+
+	fn unary(&mut self) -> Result<Filter, &'static str> {
+		if self.eat('-') {
+			return Ok(Filter::Not(Box::new(self.unary()?)));
+		} else if self.eat('*') {
+			return Ok(Filter::All);
+		} else if !self.eat('@') {
+			return self.text(self.scope);
+		}
+		let alias = self.until(&[':', ' ']);
+		let f = self.index.field(&alias).ok_or(ERRMSG_NOFIELD)?;
+		if !self.eat(':') {
+			return Err(ERRMSG_BADQUERY);
+		}
+		let field = &self.index.fields[f];
+		match (&field.kind, self.peek()) {
+			(Kind::Text {..}, _) => self.text(Some(f)),
+			(Kind::Tag {..}, Some('{')) => {
+				self.at += 1;
+				let mut tags = vec![];
+				loop {
+					let t = self.until(&['|', '}']);
+					tags.extend(field.tags(&self.param(t.trim())?));
+					if self.eat('}') {
+						break;
+					} else if !self.eat('|') {
+						return Err(ERRMSG_BADQUERY);
+					}
+				}
+				Ok(Filter::Tag(f, tags))
+			},
+			(Kind::Numeric(_), Some('[')) => {
+				self.at += 1;
+				let bounds = self.until(&[']']);
+				if !self.eat(']') {
+					return Err(ERRMSG_BADQUERY);
+				}
+				match bounds.split_whitespace().collect::<Vec<&str>>()[..] {
+					[lo, hi] => Ok(Filter::Range(
+						f, self.bound(lo)?, self.bound(hi)?
+					)),
+					_ => Err(ERRMSG_BADNUMBER)
+				}
+			},
+			_ => Err(ERRMSG_BADQUERY)
+		}
+	}
+
+	// a word, a prefix, a phrase or a group of them, in the text field
+	// given or in all of them
+	fn text(&mut self, scope: Option<usize>) -> Result<Filter, &'static str> {
+		if self.eat('(') {
+			let outer = std::mem::replace(&mut self.scope, scope);
+			let q = self.or();
+			self.scope = outer;
+			return match self.eat(')') {
+				true => q,
+				false => Err(ERRMSG_BADQUERY)
+			};
+		} else if self.eat('"') {
+			let s = self.until(&['"']);
+			return match self.eat('"') {
+				true => Ok(phrase(scope, &s)),
+				false => Err(ERRMSG_BADQUERY)
+			};
+		}
+		let w = self.until(BREAKS);
+		if w.is_empty() {
+			return Err(ERRMSG_BADQUERY);
+		}
+		let w = self.param(&w)?;
+		match self.cs.get(self.at) {
+			Some('*') => {
+				self.at += 1;
+				let w = w.to_lowercase();
+				Ok(Filter::Prefix(scope, (w.clone(), w)))
+			},
+			_ => Ok(phrase(scope, &w))
+		}
+	}
+
+	// a range bound, and whether it is left out
+	fn bound(&self, s: &str) -> Result<(f64, bool), &'static str> {
+		let (s, exclusive) = match s.strip_prefix('(') {
+			Some(s) => (s, true),
+			None => (s, false)
+		};
+		match self.param(s)?.parse::<f64>() {
+			Ok(n) if !n.is_nan() => Ok((n, exclusive)),
+			_ => Err(ERRMSG_BADNUMBER)
+		}
+	}
+}
+
+// the words of s one after another, any doc matching a query of stop
+// words alone
+fn phrase(scope: Option<usize>, s: &str) -> Filter {
+	let mut ws = stemmed(s);
+	match ws.len() {
+		0 => Filter::All,
+		1 => Filter::Term(scope, ws.pop().unwrap()),
+		_ => Filter::Phrase(scope, ws)
+	}
+}
+
+// KNN k @field $blob [EF_RUNTIME ef] [AS alias]
+#[derive(Clone, Debug, PartialEq)]
+struct Knn {
+	field: usize,
+	k: usize,
+	v: Vec<f32>,
+	ef: Option<usize>,
+	alias: String
+}
+
+fn parse_knn(s: &str, index: &Index, params: &HashMap<String, String>)
+	-> Result<Knn, &'static str> {
+	let param = |s: &str| match s.strip_prefix('$') {
+		Some(p) => params.get(p).cloned().ok_or(ERRMSG_NOPARAM),
+		None => Ok(s.to_string())
+	};
+	let ws: Vec<&str> = s.split_whitespace().collect();
+	let (k, alias, blob) = match ws[..] {
+		[knn, k, f, blob, ..] if knn.eq_ignore_ascii_case("knn") =>
+			(param(k)?, f.strip_prefix('@').ok_or(ERRMSG_BADKNN)?, blob),
+		_ => return Err(ERRMSG_BADKNN)
+	};
+	let field = index.field(alias).ok_or(ERRMSG_NOFIELD)?;
+	let vs = match &index.fields[field].kind {
+		Kind::Vector(vs) => vs,
+		_ => return Err(ERRMSG_BADKNN)
+	};
+	let mut knn = Knn {
+		field,
+		k: k.parse::<usize>().map_err(|_| ERRMSG_BADKNN)?,
+		v: vs.decode(&bytes::encode(&param(blob)?)).ok_or(ERRMSG_BADBLOB)?,
+		ef: None,
+		alias: format!("__{}_score", alias)
+	};
+	for o in ws[4..].chunks(2) {
+		match (o[0].to_ascii_lowercase().as_str(), o.get(1)) {
+			("ef_runtime", Some(n)) => knn.ef = Some(
+				param(n)?.parse::<usize>().map_err(|_| ERRMSG_BADKNN)?
+			),
+			("as", Some(a)) => knn.alias = a.to_string(),
+			_ => return Err(ERRMSG_BADKNN)
+		}
+	}
+	Ok(knn)
+}
+
+// FILTER [=> [KNN ...]]
+fn parse_query(q: &str, index: &Index, params: &HashMap<String, String>)
+	-> Result<(Filter, Option<Knn>), &'static str> {
+	let (filter, knn) = match q.split_once("=>") {
+		Some((f, knn)) => {
+			let knn = knn.trim()
+				.strip_prefix('[').and_then(|k| k.strip_suffix(']'))
+				.ok_or(ERRMSG_BADKNN)?;
+			(f, Some(parse_knn(knn, index, params)?))
+		},
+		None => (q, None)
+	};
+	let mut p = Parser {
+		cs: filter.chars().collect(), at: 0, scope: None, index, params
+	};
+	let filter = p.or()?;
+	match p.peek() {
+		None => Ok((filter, knn)),
+		Some(_) => Err(ERRMSG_BADQUERY)
+	}
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Options {
+	nocontent: bool,
+	verbatim: bool,
+	withscores: bool,
+	// the fields to reply with, all of them when none
+	fields: Option<Vec<String>>,
+	// the field to sort by, and whether descending
+	sortby: Option<(String, bool)>,
+	offset: usize,
+	limit: usize,
+	params: HashMap<String, String>
+}
+
+// FT.SEARCH options: [NOCONTENT] [VERBATIM] [WITHSCORES]
+// [RETURN count field ...] [SORTBY field [ASC | DESC]]
+// [LIMIT offset num] [PARAMS nargs name value ...] [DIALECT dialect]
+fn parse_options(prms: &[String]) -> Result<Options, &'static str> {
+	let mut opts = Options {limit: LIMIT, ..Options::default()};
+	let mut i = 0;
+	let count = |i: usize| prms.get(i).and_then(|n| n.parse::<usize>().ok());
+	while i < prms.len() {
+		match prms[i].to_ascii_lowercase().as_str() {
+			"nocontent" => opts.nocontent = true,
+			"verbatim" => opts.verbatim = true,
+			"withscores" => opts.withscores = true,
+			"return" => {
+				let n = count(i + 1).filter(|n| i + 2 + n <= prms.len())
+					.ok_or(ERRMSG_SYNERR)?;
+				opts.fields = Some(prms[i + 2..i + 2 + n].to_vec());
+				i += 1 + n;
+			},
+			"sortby" if i + 1 < prms.len() => {
+				let by = prms[i + 1].clone();
+				i += 1;
+				let o = prms.get(i + 1).map(|o| o.to_ascii_lowercase());
+				let desc = match o {
+					Some(o) if "asc" == o || "desc" == o => {
+						i += 1;
+						"desc" == o
+					},
+					_ => false
+				};
+				opts.sortby = Some((by, desc));
+			},
+			"limit" => {
+				match (count(i + 1), count(i + 2)) {
+					(Some(o), Some(n)) => (opts.offset, opts.limit) = (o, n),
+					_ => return Err(ERRMSG_BADLIMIT)
+				}
+				i += 2;
+			},
+			"params" => {
+				let n = count(i + 1)
+					.filter(|n| 0 == n % 2 && i + 2 + n <= prms.len())
+					.ok_or(ERRMSG_SYNERR)?;
+				for p in prms[i + 2..i + 2 + n].chunks(2) {
+					opts.params.insert(p[0].clone(), p[1].clone());
+				}
+				i += 1 + n;
+			},
+			"dialect" if i + 1 < prms.len() => i += 1,
+			_ => return Err(ERRMSG_SYNERR)
+		}
+		i += 1;
+	}
+	Ok(opts)
+}
+
+// FT.CREATE, indexing the existing keys the next time it is used
+pub fn create<'a, I>(name: &str, args: &[String], keys: I)
+	-> Result<DataType, &'static str> where I: Iterator<Item = &'a str> {
+	let mut index = Index::parse(args)?;
+	let mut idxs = INDEXES.lock().unwrap();
+	if idxs.contains_key(name) {
+		return Err(ERRMSG_EXISTS);
+	}
+	index.pending = keys.filter(|k| index.covers(k))
+		.map(|k| k.to_string())
+		.collect();
+	idxs.insert(name.to_string(), index);
+	Ok(DataType::str("OK"))
+}
+
+// the names and arguments of the FT.CREATE commands rebuilding the indexes
+pub fn definitions() -> Vec<(String, Vec<String>)> {
+	INDEXES.lock().unwrap().iter()
+		.map(|(n, idx)| (n.clone(), idx.args.clone()))
+		.collect()
+}
+
+// FT.DROPINDEX, giving the keys that were indexed
+pub fn drop(m: &HashMap<DataType, DataType>, name: &str)
+	-> Result<Vec<String>, &'static str> {
+	let mut index = INDEXES.lock().unwrap().remove(name)
+		.ok_or(ERRMSG_NOINDEX)?;
+	index.sync(m);
+	Ok(index.docs.into_keys().collect())
+}
+
+// every key is gone
+pub fn flushed() {
+	INDEXES.lock().unwrap().values_mut().for_each(|idx| idx.clear());
+}
+
+pub fn info(m: &HashMap<DataType, DataType>, name: &str)
+	-> Result<DataType, &'static str> {
+	let mut idxs = INDEXES.lock().unwrap();
+	let index = idxs.get_mut(name).ok_or(ERRMSG_NOINDEX)?;
+	index.sync(m);
+	let attrs = index.fields.iter()
+		.map(|f| {
+			let mut l = vec![
+				DataType::str("identifier"), DataType::bulkStr(&f.name),
+				DataType::str("attribute"), DataType::bulkStr(&f.alias),
+				DataType::str("type")
+			];
+			match &f.kind {
+				Kind::Text {weight, nostem, ..} => l.extend([
+					DataType::str("TEXT"),
+					DataType::str("WEIGHT"),
+					DataType::bulkStr(&weight.to_string())
+				].into_iter().chain(nostem.then(|| DataType::str("NOSTEM")))),
+				Kind::Tag {separator, case_sensitive, ..} => l.extend([
+					DataType::str("TAG"),
+					DataType::str("SEPARATOR"),
+					DataType::bulkStr(&separator.to_string())
+				].into_iter().chain(case_sensitive.then(||
+					DataType::str("CASESENSITIVE")
+				))),
+				Kind::Numeric(_) => l.push(DataType::str("NUMERIC")),
+				Kind::Vector(vs) => l.extend([
+					DataType::str("VECTOR"),
+					DataType::str("algorithm"),
+					DataType::str(if vs.hnsw {"HNSW"} else {"FLAT"}),
+					DataType::str("data_type"),
+					DataType::str(if vs.wide {"FLOAT64"} else {"FLOAT32"}),
+					DataType::str("dim"),
+					DataType::Integer(vs.dim as i64),
+					DataType::str("distance_metric"),
+					DataType::str(METRICS.iter()
+						.find(|(m, _)| *m == vs.metric)
+						.map_or("", |(_, n)| n))
+				])
+			}
+			if f.sortable {
+				l.push(DataType::str("SORTABLE"));
+			}
+			DataType::List(l)
+		})
+		.collect();
+	let terms: usize = index.fields.iter().map(|f| match &f.kind {
+		Kind::Text {terms, ..} => terms.len(),
+		_ => 0
+	}).sum();
+	Ok(DataType::List(vec![
+		DataType::str("index_name"),
+		DataType::bulkStr(name),
+		DataType::str("index_definition"),
+		DataType::List(vec![
+			DataType::str("key_type"),
+			DataType::str("HASH"),
+			DataType::str("prefixes"),
+			DataType::List(index.prefixes.iter()
+				.map(|p| DataType::bulkStr(p))
+				.collect())
+		]),
+		DataType::str("attributes"),
+		DataType::List(attrs),
+		DataType::str("num_docs"),
+		DataType::Integer(index.docs.len() as i64),
+		DataType::str("num_terms"),
+		DataType::Integer(terms as i64),
+		DataType::str("num_records"),
+		DataType::Integer(index.words as i64)
+	]))
+}
+
+pub fn list() -> DataType {
+	DataType::List(INDEXES.lock().unwrap().keys()
+		.map(|n| DataType::bulkStr(n))
+		.collect())
+}
+
+// the value of field f of the hash at k
+fn value<'a>(m: &'a HashMap<DataType, DataType>, k: &str, f: &str)
+	-> Option<&'a DataType> {
+	match m.get(&DataType::bulkStr(k)) {
+		Some(DataType::HashMap(h)) => h.get(&DataType::bulkStr(f)),
+		_ => None
+	}
+}
+
+// FT.SEARCH over the hashes in m, the query and then the options in prms;
+// hits come nearest first for KNN, best scoring first for words, and in
+// the order of their keys otherwise, unless sorted by a field
+pub fn search(m: &HashMap<DataType, DataType>, name: &str, prms: &[String])
+	-> Result<DataType, &'static str> {
+	let mut idxs = INDEXES.lock().unwrap();
+	let index = idxs.get_mut(name).ok_or(ERRMSG_NOINDEX)?;
+	index.sync(m);
+	let index = &*index;
+	let opts = parse_options(&prms[1..])?;
+	let (filter, knn) = parse_query(&prms[0], index, &opts.params)?;
+	let ks = filter.eval(index, opts.verbatim);
+	let mut terms = vec![];
+	filter.terms(&mut terms);
+	let terms: Vec<(Option<usize>, &Word, usize)> = terms.into_iter()
+		.map(|(scope, w)| {
+			let df = Filter::Term(scope, w.clone()).eval(index, opts.verbatim);
+			(scope, w, df.len())
+		})
+		.collect();
+	let score = |k: &str| index.score(k, &terms, opts.verbatim);
+	let mut hits: Vec<(&str, f64, Option<f32>)> = match &knn {
+		Some(knn) => match &index.fields[knn.field].kind {
+			Kind::Vector(vs) => vs.knn(&knn.v, knn.k, knn.ef, |k|
+				ks.contains(k)
+			).into_iter()
+				.map(|(d, k)| (k, score(k), Some(d)))
+				.collect(),
+			_ => vec![]
+		},
+		None => ks.into_iter().map(|k| (k, score(k), None)).collect()
+	};
+	if knn.is_none() && !terms.is_empty() {
+		hits.sort_by(|a, b| b.1.total_cmp(&a.1));
+	}
+	if let Some((by, desc)) = &opts.sortby {
+		if knn.as_ref().is_some_and(|knn| knn.alias == *by) {
+			hits.sort_by(|a, b| match desc {
+				true => b.2.partial_cmp(&a.2).unwrap(),
+				false => a.2.partial_cmp(&b.2).unwrap()
+			});
+		} else {
+			let f = &index.fields[index.field(by).ok_or(ERRMSG_NOSORT)?];
+			let numeric = matches!(f.kind, Kind::Numeric(_));
+			let key = |k: &str| match value(m, k, &f.name) {
+				Some(DataType::BulkString(s)) if numeric =>
+					s.parse::<f64>().ok().map(|n| (Num(n), String::new())),
+				Some(DataType::BulkString(s)) =>
+					Some((Num(0.0), s.to_lowercase())),
+				_ => None
+			};
+			let mut keyed: Vec<_> = hits.into_iter()
+				.map(|h| (key(h.0), h))
+				.collect();
+			keyed.sort_by(|a, b| match (&a.0, &b.0) {
+				(Some(x), Some(y)) if *desc => y.cmp(x),
+				(Some(x), Some(y)) => x.cmp(y),
+				(x, y) => y.is_some().cmp(&x.is_some())
+			});
+			hits = keyed.into_iter().map(|(_, h)| h).collect();
+		}
+	}
+	let mut l = vec![DataType::Integer(hits.len() as i64)];
+	let content = !opts.nocontent &&
+		opts.fields.as_ref().is_none_or(|fs| !fs.is_empty());
+	for (k, score, d) in hits.into_iter().skip(opts.offset).take(opts.limit) {
+		l.push(DataType::bulkStr(k));
+		if opts.withscores {
+			l.push(DataType::bulkStr(&score.to_string()));
+		}
+		if !content {
+			continue;
+		}
+		let dist = d.zip(knn.as_ref()).map(|(d, knn)| (&knn.alias, d));
+		let mut fields = vec![];
+		match &opts.fields {
+			Some(fs) => for r in fs.iter() {
+				match dist {
+					Some((alias, d)) if alias == r => {
+						fields.push(DataType::bulkStr(r));
+						fields.push(DataType::bulkStr(&d.to_string()));
+						continue;
+					},
+					_ => {}
+				}
+				let name = index.field(r).map_or(r, |f| &index.fields[f].name);
+				if let Some(v) = value(m, k, name) {
+					fields.push(DataType::bulkStr(r));
+					fields.push(v.clone());
+				}
+			},
+			None => {
+				if let Some((alias, d)) = dist {
+					fields.push(DataType::bulkStr(alias));
+					fields.push(DataType::bulkStr(&d.to_string()));
+				}
+				let h = m.get(&DataType::bulkStr(k));
+				if let Some(DataType::HashMap(h)) = h {
+					let mut pairs: Vec<_> = h.iter()
+						.filter_map(|p| match p {
+							(DataType::BulkString(f), v) => Some((f, v)),
+							_ => None
+						})
+						.collect();
+					pairs.sort_unstable_by_key(|(f, _)| *f);
+					fields.extend(pairs.into_iter()
+						.flat_map(|(f, v)| [DataType::bulkStr(f), v.clone()]));
+				}
+			}
+		}
+		l.push(DataType::List(fields));
+	}
+	Ok(DataType::List(l))
+}
+
+// key k changed, to be indexed again by the indexes covering it
+pub fn touch(k: &str) {
+	for idx in INDEXES.lock().unwrap().values_mut() {
+		if idx.covers(k) {
+			idx.pending.insert(k.to_string());
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,163 @@
+use serial_test::serial;
+use super::*;
+
+fn prms(ps: &[&str]) -> Vec<String> {
+	ps.iter().map(|p| p.to_string()).collect()
+}
+
+fn blob(v: &[f32]) -> String {
+	bytes::decode(&v.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<u8>>())
+}
+
+fn hash(fs: &[(&str, &str)]) -> DataType {
+	DataType::HashMap(fs.iter()
+		.map(|(f, v)| (DataType::bulkStr(f), DataType::bulkStr(v)))
+		.collect())
+}
+
+// the keys of a search reply
+fn keys(r: DataType) -> Vec<String> {
+	match r {
+		DataType::List(l) => l.into_iter().skip(1)
+			.filter_map(|k| match k {
+				DataType::BulkString(k) => Some(k),
+				_ => None
+			})
+			.collect(),
+		_ => vec![]
+	}
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	let bad = prms(&["SCHEMA", "v", "VECTOR", "FLAT", "2", "DIM", "2"]);
+	assert_eq!(Index::parse(&bad).err(), Some(ERRMSG_BADVECTOR));
+	let bad = prms(&["PREFIX", "1"]);
+	assert_eq!(Index::parse(&bad).err(), Some(ERRMSG_SYNERR));
+	let mut m = HashMap::new();
+	let point = |i: usize| [(i % 10) as f32, (i / 10) as f32];
+	for i in 0..100 {
+		let (k, v) = (format!("p:{}", i), blob(&point(i)));
+		let tag = if 0 == i % 2 {"even"} else {"odd"};
+		let n = i.to_string();
+		m.insert(DataType::bulkStr(&k),
+			hash(&[("v", &v), ("kind", tag), ("n", &n)]));
+	}
+	m.insert(DataType::bulkStr("q:0"), hash(&[("n", "1")]));
+	let args = prms(&[
+		"ON", "HASH", "PREFIX", "1", "p:", "SCHEMA", "v", "VECTOR", "HNSW",
+		"8", "TYPE", "FLOAT32", "DIM", "2", "DISTANCE_METRIC", "L2", "M",
+		"4", "kind", "TAG", "n", "AS", "num", "NUMERIC"
+	]);
+	let ks = m.keys().filter_map(|k| match k {
+		DataType::BulkString(k) => Some(k.as_str()),
+		_ => None
+	});
+	assert!(create("plan1", &args, ks).is_ok());
+	assert_eq!(create("plan1", &args, [].into_iter()), Err(ERRMSG_EXISTS));
+	let q = blob(&[3.2, 4.1]);
+	let knn = |filter: &str, k: &str| prms(&[
+		&format!("{}=>[KNN {} @v $q]", filter, k), "PARAMS", "2", "q", &q,
+		"NOCONTENT"
+	]);
+	assert_eq!(keys(search(&m, "plan1", &knn("*", "3")).unwrap()),
+		vec!["p:43", "p:44", "p:53"]);
+	assert_eq!(keys(search(&m, "plan1", &knn("@kind:{odd}", "2")).unwrap()),
+		vec!["p:43", "p:53"]);
+	let narrow = knn("@num:[(90 +inf] -@kind:{even}", "3");
+	assert_eq!(keys(search(&m, "plan1", &narrow).unwrap()),
+		vec!["p:93", "p:95", "p:91"]);
+	let tags = prms(&["@kind:{even} (@num:[0 4] | @num:[98 98])"]);
+	assert_eq!(keys(search(&m, "plan1", &tags).unwrap()),
+		vec!["p:0", "p:2", "p:4", "p:98"]);
+	let short = prms(&["*=>[KNN 1 @v $q]", "PARAMS", "2", "q", "ab"]);
+	assert_eq!(search(&m, "plan1", &short), Err(ERRMSG_BADBLOB));
+	assert_eq!(search(&m, "plan1", &prms(&["@x:{a}"])), Err(ERRMSG_NOFIELD));
+	assert_eq!(search(&m, "plan1", &prms(&["(@kind:{odd}"])),
+		Err(ERRMSG_BADQUERY));
+	// replaced and removed hashes come out of the graph
+	for i in 0..60 {
+		let k = format!("p:{}", i);
+		if 43 == i {
+			let v = blob(&[50.0, 50.0]);
+			m.insert(DataType::bulkStr(&k), hash(&[("v", &v)]));
+		} else {
+			m.remove(&DataType::bulkStr(&k));
+		}
+		touch(&k);
+	}
+	assert_eq!(keys(search(&m, "plan1", &knn("*", "2")).unwrap()),
+		vec!["p:63", "p:64"]);
+	let moved = knn("-@num:[-inf +inf]", "5");
+	assert_eq!(keys(search(&m, "plan1", &moved).unwrap()), vec!["p:43"]);
+	assert_eq!(drop(&m, "plan1").map(|ks| ks.len()), Ok(41));
+	assert_eq!(search(&m, "plan1", &prms(&["*"])), Err(ERRMSG_NOINDEX));
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	let stems = ["caresses", "ponies", "running", "hopping", "relational",
+		"generalization", "happiness", "motoring", "filing", "controll"];
+	assert_eq!(stems.map(stem), ["caress", "poni", "run", "hop", "relat",
+		"gener", "happi", "motor", "file", "control"]);
+	let mut m = HashMap::new();
+	for (k, title, body, year) in [
+		("b:1", "Running with the wolves", "A pack runs at night", "1990"),
+		("b:2", "The old man and the sea", "He fished alone", "1952"),
+		("b:3", "Sea wolves", "Wolves by the sea, wolves at sea", "2001"),
+		("b:4", "Night runner", "She ran through the night", "1975")
+	] {
+		m.insert(DataType::bulkStr(k),
+			hash(&[("title", title), ("body", body), ("year", year)]));
+	}
+	let args = prms(&[
+		"PREFIX", "1", "b:", "SCHEMA", "title", "TEXT", "WEIGHT", "2",
+		"SORTABLE", "body", "TEXT", "NOSTEM", "year", "NUMERIC", "SORTABLE"
+	]);
+	let ks = m.keys().filter_map(|k| match k {
+		DataType::BulkString(k) => Some(k.as_str()),
+		_ => None
+	});
+	assert!(create("plan2", &args, ks).is_ok());
+	let find = |q: &[&str]| keys(search(&m, "plan2", &prms(q)).unwrap());
+	assert_eq!(find(&["wolves", "NOCONTENT"]), vec!["b:3", "b:1"]);
+	assert_eq!(find(&["run", "NOCONTENT"]), vec!["b:1"]);
+	assert_eq!(find(&["run*", "NOCONTENT"]), vec!["b:1", "b:4"]);
+	assert_eq!(find(&["runs", "VERBATIM", "NOCONTENT"]), vec!["b:1"]);
+	assert_eq!(find(&["\"sea wolves\"", "NOCONTENT"]), vec!["b:3"]);
+	assert_eq!(find(&["\"night runs\"", "NOCONTENT"]), vec![] as Vec<&str>);
+	assert_eq!(find(&["@title:(night | sea) -old", "NOCONTENT"]),
+		vec!["b:4", "b:3"]);
+	assert_eq!(find(&["@body:night @year:[1980 +inf]", "NOCONTENT"]),
+		vec!["b:1"]);
+	assert_eq!(find(&["the", "SORTBY", "year", "DESC", "LIMIT", "1", "2",
+		"NOCONTENT"]), vec!["b:1", "b:4"]);
+	let r = search(&m, "plan2", &prms(&[
+		"@year:[(1952 2001]", "SORTBY", "year", "RETURN", "1", "title"
+	]));
+	assert_eq!(r, Ok(DataType::List(vec![
+		DataType::Integer(3),
+		DataType::bulkStr("b:4"),
+		DataType::List(vec![
+			DataType::bulkStr("title"), DataType::bulkStr("Night runner")
+		]),
+		DataType::bulkStr("b:1"),
+		DataType::List(vec![
+			DataType::bulkStr("title"),
+			DataType::bulkStr("Running with the wolves")
+		]),
+		DataType::bulkStr("b:3"),
+		DataType::List(vec![
+			DataType::bulkStr("title"), DataType::bulkStr("Sea wolves")
+		])
+	])));
+	m.remove(&DataType::bulkStr("b:3"));
+	touch("b:3");
+	let find = |q: &[&str]| keys(search(&m, "plan2", &prms(q)).unwrap());
+	assert_eq!(find(&["wolves", "NOCONTENT"]), vec!["b:1"]);
+	let none = prms(&["wolves", "SORTBY", "nope"]);
+	assert_eq!(search(&m, "plan2", &none), Err(ERRMSG_NOSORT));
+	assert_eq!(drop(&m, "plan2").map(|ks| ks.len()), Ok(3));
+}
@@ -0,0 +1,113 @@
+use rand::Rng;
+use rand::seq::index;
+use std::collections::HashMap;
+use std::slice::Iter;
+
+use super::datatype::DataType;
+
+// members kept in a vector for indexed access, with their position in it so
+// membership stays O(1) and removal swaps the last member in
+#[derive(Clone, Debug, Default)]
+pub struct Set {
+	members: Vec<DataType>,
+	positions: HashMap<DataType, usize>
+}
+
+impl PartialEq for Set {
+	fn eq(&self, o: &Self) -> bool {
+		self.len() == o.len() && self.iter().all(|e| o.contains(e))
+	}
+}
+
+impl Eq for Set {}
+
+impl FromIterator<DataType> for Set {
+	fn from_iter<I: IntoIterator<Item = DataType>>(it: I) -> Self {
+		let mut s = Set::default();
+		it.into_iter().for_each(|e| {s.insert(e);});
+		s
+	}
+}
+
+impl Set {
+	pub fn contains(&self, e: &DataType) -> bool {
+		self.positions.contains_key(e)
+	}
+
+	pub fn insert(&mut self, e: DataType) -> bool {
+		if self.contains(&e) {
+			return false;
+		}
+		self.positions.insert(e.clone(), self.members.len());
+		self.members.push(e);
+		true
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.members.is_empty()
+	}
+
+	pub fn iter(&self) -> Iter<'_, DataType> {
+		self.members.iter()
+	}
+
+	pub fn len(&self) -> usize {
+		self.members.len()
+	}
+
+	// n members drawn with repeats
+	pub fn pick(&self, n: usize) -> Vec<DataType> {
+		if self.is_empty() {
+			return vec![];
+		}
+		let mut rng = rand::rng();
+		(0..n).map(|_| {
+			self.members[rng.random_range(0..self.len())].clone()
+		}).collect()
+	}
+
+	// up to n distinct members, taken out of the set
+	pub fn pop(&mut self, n: usize) -> Vec<DataType> {
+		let mut rng = rand::rng();
+		(0..n.min(self.len())).map(|_| {
+			self.remove_at(rng.random_range(0..self.len()))
+		}).collect()
+	}
+
+	pub fn remove(&mut self, e: &DataType) -> bool {
+		self.take(e).is_some()
+	}
+
+	pub fn retain<F: FnMut(&DataType) -> bool>(&mut self, mut f: F) {
+		let mut i = 0;
+		while i < self.len() {
+			if f(&self.members[i]) {
+				i += 1;
+			} else {
+				self.remove_at(i);
+			}
+		}
+	}
+
+	// up to n distinct members
+	pub fn sample(&self, n: usize) -> Vec<DataType> {
+		index::sample(&mut rand::rng(), self.len(), n.min(self.len()))
+			.iter().map(|i| self.members[i].clone()).collect()
+	}
+
+	pub fn take(&mut self, e: &DataType) -> Option<DataType> {
+		self.positions.get(e).copied().map(|i| self.remove_at(i))
+	}
+
+	fn remove_at(&mut self, i: usize) -> DataType {
+		let e = self.members.swap_remove(i);
+		self.positions.remove(&e);
+		if let Some(moved) = self.members.get(i) {
+			*self.positions.get_mut(moved).unwrap() = i;
+		}
+		e
+	}
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,43 @@
+use serial_test::serial;
+use super::*;
+
+fn set(v: &[&str]) -> Set {
+	v.iter().map(|s| DataType::bulkStr(s)).collect()
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	let mut s = set(&["a", "b", "c", "a"]);
+	assert_eq!(s.len(), 3);
+	assert!(!s.insert(DataType::bulkStr("b")));
+	assert!(s.remove(&DataType::bulkStr("a")));
+	assert!(!s.remove(&DataType::bulkStr("a")));
+	assert!(s.contains(&DataType::bulkStr("c")));
+	assert_eq!(s, set(&["c", "b"]));
+	assert_eq!(s.take(&DataType::bulkStr("c")), Some(DataType::bulkStr("c")));
+	assert_eq!(s, set(&["b"]));
+	let mut s = set(&["a", "b", "c", "d"]);
+	s.retain(|e| *e != DataType::bulkStr("a") && *e != DataType::bulkStr("c"));
+	assert_eq!(s, set(&["b", "d"]));
+	assert!(s.contains(&DataType::bulkStr("d")));
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	let mut s = set(&["a", "b", "c", "d", "e"]);
+	let picked = s.sample(3);
+	assert_eq!(picked.len(), 3);
+	assert_eq!(picked.iter().cloned().collect::<Set>().len(), 3);
+	assert_eq!(s.sample(9).len(), 5);
+	let picked = s.pick(9);
+	assert_eq!(picked.len(), 9);
+	assert!(picked.iter().all(|e| s.contains(e)));
+	let popped = s.pop(2);
+	assert_eq!(s.len(), 3);
+	assert!(popped.iter().all(|e| !s.contains(e)));
+	assert_eq!(s.pop(9).len(), 3);
+	assert!(s.is_empty());
+	assert_eq!(s.pick(2), vec![]);
+}
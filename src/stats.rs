@@ -0,0 +1,23 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub static COMMANDS_PROCESSED: AtomicU64 = AtomicU64::new(0);
+pub static CONNECTIONS_RECEIVED: AtomicU64 = AtomicU64::new(0);
+pub static REJECTED_CONNECTIONS: AtomicU64 = AtomicU64::new(0);
+
+pub fn incr(counter: &AtomicU64) {
+	counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn get(counter: &AtomicU64) -> u64 {
+	counter.load(Ordering::Relaxed)
+}
+
+pub fn reset() {
+	for c in [
+		&COMMANDS_PROCESSED,
+		&CONNECTIONS_RECEIVED,
+		&REJECTED_CONNECTIONS
+	] {
+		c.store(0, Ordering::Relaxed);
+	}
+}
@@ -0,0 +1,1170 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::BufReader;
+use std::ops::Bound;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+
+use super::bytes;
+use super::datatype::DataType;
+use super::parser::parse;
+use super::request::Request;
+
+pub const ERRMSG_BADID: &str =
+	"ERR Invalid stream ID specified as stream command argument";
+const ERRMSG_ADDED: &str = "ERR The entries_added specified in XSETID is \
+	smaller than the target stream length";
+const ERRMSG_EXHAUSTED: &str =
+	"ERR The stream has exhausted the last possible ID, unable to add more \
+	items";
+const ERRMSG_LIMIT: &str =
+	"ERR syntax error, LIMIT cannot be used without the special ~ option";
+const ERRMSG_MAXDELETED: &str = "ERR The ID specified in XSETID is smaller \
+	than the provided max_deleted_entry_id";
+const ERRMSG_MAXLEN: &str = "ERR The MAXLEN argument must be >= 0.";
+const ERRMSG_NUMNAI: &str = "ERR value is not an integer or out of range";
+const ERRMSG_SETIDSMALL: &str =
+	"ERR The ID specified in XSETID is smaller than the target stream top item";
+const ERRMSG_SMALLID: &str = "ERR The ID specified in XADD is equal or \
+	smaller than the target stream top item";
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+const ERRMSG_TIMEOUT: &str = "ERR timeout is not an integer or out of range";
+const ERRMSG_UNBALANCED: &str = "ERR Unbalanced 'xread' list of streams: for \
+	each stream key an ID or '$' must be specified.";
+const ERRMSG_WRONGARGS: &str =
+	"ERR wrong number of arguments for 'xadd' command";
+const ERRMSG_ZEROID: &str =
+	"ERR The ID specified in XADD must be greater than 0-0";
+
+lazy_static! {
+	// bumped by every XADD, waking up blocked readers
+	static ref VERSION: Mutex<u64> = Mutex::new(0);
+	static ref CHANGED: Condvar = Condvar::new();
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct StreamId {
+	pub ms: u64,
+	pub seq: u64
+}
+
+impl StreamId {
+	const MAX: StreamId = StreamId {ms: u64::MAX, seq: u64::MAX};
+
+	// "ms-seq", or "ms" completed with seq
+	pub fn parse(s: &str, seq: u64) -> Result<StreamId, &'static str> {
+		let (ms, sq) = match s.split_once('-') {
+			Some((ms, sq)) => (ms, sq.parse::<u64>()),
+			None => (s, Ok(seq))
+		};
+		match (ms.parse::<u64>(), sq) {
+			(Ok(ms), Ok(seq)) => Ok(StreamId {ms, seq}),
+			_ => Err(ERRMSG_BADID)
+		}
+	}
+
+	fn next(self) -> Option<StreamId> {
+		match self.seq.checked_add(1) {
+			Some(seq) => Some(StreamId {ms: self.ms, seq}),
+			None => self.ms.checked_add(1).map(|ms| StreamId {ms, seq: 0})
+		}
+	}
+
+	fn prev(self) -> Option<StreamId> {
+		match self.seq.checked_sub(1) {
+			Some(seq) => Some(StreamId {ms: self.ms, seq}),
+			None => self.ms.checked_sub(1)
+				.map(|ms| StreamId {ms, seq: u64::MAX})
+		}
+	}
+
+	fn bulk(&self) -> DataType {
+		DataType::BulkString(self.to_string())
+	}
+}
+
+impl fmt::Display for StreamId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}-{}", self.ms, self.seq)
+	}
+}
+
+// an entry delivered to a consumer but not acknowledged yet
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pending {
+	consumer: String,
+	// unix time in ms of the last delivery
+	delivered: u64,
+	count: u64
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Consumer {
+	// unix times in ms of the last attempted and successful interactions
+	seen: u64,
+	active: Option<u64>
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Group {
+	last_id: StreamId,
+	// number of entries up to last_id, when known
+	entries_read: Option<u64>,
+	pel: BTreeMap<StreamId, Pending>,
+	consumers: BTreeMap<String, Consumer>
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Stream {
+	// flattened field value pairs by ID
+	entries: BTreeMap<StreamId, Vec<String>>,
+	last_id: StreamId,
+	entries_added: u64,
+	max_deleted: StreamId,
+	groups: BTreeMap<String, Group>
+}
+
+pub enum Trim {
+	MaxLen(u64),
+	MinId(StreamId)
+}
+
+pub struct Trimming {
+	trim: Trim,
+	limit: Option<u64>
+}
+
+pub struct Add {
+	pub nomkstream: bool,
+	trimming: Option<Trimming>,
+	// position of the ID among the parameters following the key
+	pub id_at: usize,
+	fvs: Vec<String>
+}
+
+pub struct Read {
+	pub group: Option<(String, String)>,
+	pub count: Option<usize>,
+	pub block: Option<Duration>,
+	noack: bool,
+	pub keys: Vec<String>,
+	pub ids: Vec<String>
+}
+
+pub struct Claim {
+	min_idle: u64,
+	ids: Vec<StreamId>,
+	delivered: Option<u64>,
+	retrycount: Option<u64>,
+	force: bool,
+	justid: bool,
+	last_id: Option<StreamId>
+}
+
+pub fn now_ms() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH)
+		.map_or(0, |d| d.as_millis() as u64)
+}
+
+pub fn version() -> u64 {
+	*VERSION.lock().unwrap()
+}
+
+pub fn changed() {
+	*VERSION.lock().unwrap() += 1;
+	CHANGED.notify_all();
+}
+
+// wait for a change after version seen, false when the deadline passed
+pub fn wait(seen: u64, deadline: Option<Instant>) -> bool {
+	let mut v = VERSION.lock().unwrap();
+	while *v == seen {
+		v = match deadline {
+			Some(d) => {
+				let now = Instant::now();
+				if now >= d {
+					return false;
+				}
+				CHANGED.wait_timeout(v, d - now).unwrap().0
+			},
+			None => CHANGED.wait(v).unwrap()
+		};
+	}
+	true
+}
+
+fn number<T: std::str::FromStr>(s: &str) -> Result<T, &'static str> {
+	s.parse::<T>().map_err(|_| ERRMSG_NUMNAI)
+}
+
+fn entry(id: &StreamId, fvs: &[String]) -> DataType {
+	DataType::List(vec![
+		id.bulk(),
+		DataType::List(fvs.iter().map(|s| DataType::bulkStr(s)).collect())
+	])
+}
+
+pub fn nogroup(k: &str, g: &str) -> String {
+	format!("NOGROUP No such key '{}' or consumer group '{}'", k, g)
+}
+
+// MAXLEN|MINID [=|~] threshold [LIMIT count], starting at prms[*i]
+fn parse_trimming(prms: &[String], i: &mut usize)
+	-> Result<Trimming, &'static str> {
+	let strategy = prms[*i].to_ascii_lowercase();
+	*i += 1;
+	let approx = match prms.get(*i).map(|s| s.as_str()) {
+		Some("~") => true,
+		Some("=") => false,
+		_ => {*i -= 1; false}
+	};
+	*i += 1;
+	let threshold = match prms.get(*i) {
+		Some(t) => t,
+		None => return Err(ERRMSG_SYNERR)
+	};
+	let trim = match strategy.as_str() {
+		"maxlen" => match threshold.parse::<i64>() {
+			Ok(n) if 0 > n => return Err(ERRMSG_MAXLEN),
+			Ok(n) => Trim::MaxLen(n as u64),
+			Err(_) => return Err(ERRMSG_NUMNAI)
+		},
+		_ => Trim::MinId(StreamId::parse(threshold, 0)?)
+	};
+	let mut limit = None;
+	if prms.get(*i + 1).is_some_and(|s| s.eq_ignore_ascii_case("limit")) {
+		if !approx {
+			return Err(ERRMSG_LIMIT);
+		}
+		limit = match prms.get(*i + 2) {
+			Some(n) => Some(number::<u64>(n)?),
+			None => return Err(ERRMSG_SYNERR)
+		};
+		*i += 2;
+	}
+	*i += 1;
+	Ok(Trimming {trim, limit})
+}
+
+// [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]] id field value
+// [field value ...]
+pub fn parse_add(prms: &[String]) -> Result<Add, &'static str> {
+	let mut i = 0;
+	let mut nomkstream = false;
+	let mut trimming = None;
+	while i < prms.len() {
+		match prms[i].to_ascii_lowercase().as_str() {
+			"nomkstream" => {
+				nomkstream = true;
+				i += 1;
+			},
+			"maxlen" | "minid" =>
+				trimming = Some(parse_trimming(prms, &mut i)?),
+			_ => break
+		}
+	}
+	let fvs = prms.get(i + 1..).unwrap_or(&[]);
+	if fvs.is_empty() || !fvs.len().is_multiple_of(2) {
+		return Err(ERRMSG_WRONGARGS);
+	}
+	Ok(Add {nomkstream, trimming, id_at: i, fvs: fvs.to_vec()})
+}
+
+pub fn parse_trim(prms: &[String]) -> Result<Trimming, &'static str> {
+	let mut i = 0;
+	let t = match prms.first().map(|s| s.to_ascii_lowercase()).as_deref() {
+		Some("maxlen") | Some("minid") => parse_trimming(prms, &mut i)?,
+		_ => return Err(ERRMSG_SYNERR)
+	};
+	match i == prms.len() {
+		true => Ok(t),
+		false => Err(ERRMSG_SYNERR)
+	}
+}
+
+// [GROUP group consumer] [COUNT count] [BLOCK ms] [NOACK] STREAMS key ...
+// id ...
+pub fn parse_read(prms: &[String]) -> Result<Read, &'static str> {
+	let mut r = Read {
+		group: None,
+		count: None,
+		block: None,
+		noack: false,
+		keys: vec![],
+		ids: vec![]
+	};
+	let mut i = 0;
+	while i < prms.len() {
+		let arg = |n: usize| prms.get(i + n).ok_or(ERRMSG_SYNERR);
+		match prms[i].to_ascii_lowercase().as_str() {
+			"group" => {
+				r.group = Some((arg(1)?.clone(), arg(2)?.clone()));
+				i += 3;
+			},
+			"count" => {
+				r.count = Some(number::<usize>(arg(1)?)?);
+				i += 2;
+			},
+			"block" => {
+				r.block = match arg(1)?.parse::<i64>() {
+					Ok(ms) if 0 <= ms => Some(Duration::from_millis(ms as u64)),
+					Ok(_) => return Err("ERR timeout is negative"),
+					Err(_) => return Err(ERRMSG_TIMEOUT)
+				};
+				i += 2;
+			},
+			"noack" => {
+				r.noack = true;
+				i += 1;
+			},
+			"streams" => {
+				let rest = &prms[i + 1..];
+				if rest.is_empty() || !rest.len().is_multiple_of(2) {
+					return Err(ERRMSG_UNBALANCED);
+				}
+				let (keys, ids) = rest.split_at(rest.len() / 2);
+				r.keys = keys.to_vec();
+				r.ids = ids.to_vec();
+				return Ok(r);
+			},
+			_ => return Err(ERRMSG_SYNERR)
+		}
+	}
+	Err(ERRMSG_SYNERR)
+}
+
+// key group consumer min-idle-time id [id ...] [IDLE ms] [TIME ms]
+// [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID id], past the key, group and
+// consumer
+pub fn parse_claim(prms: &[String]) -> Result<Claim, &'static str> {
+	let mut c = Claim {
+		min_idle: number::<u64>(&prms[0])?,
+		ids: vec![],
+		delivered: None,
+		retrycount: None,
+		force: false,
+		justid: false,
+		last_id: None
+	};
+	let mut i = 1;
+	while i < prms.len() {
+		match StreamId::parse(&prms[i], 0) {
+			Ok(id) => c.ids.push(id),
+			Err(_) => break
+		}
+		i += 1;
+	}
+	let now = now_ms();
+	while i < prms.len() {
+		let arg = prms.get(i + 1).ok_or(ERRMSG_SYNERR);
+		match prms[i].to_ascii_lowercase().as_str() {
+			"idle" => c.delivered =
+				Some(now.saturating_sub(number::<u64>(arg?)?)),
+			"time" => c.delivered = Some(number::<u64>(arg?)?),
+			"retrycount" => c.retrycount = Some(number::<u64>(arg?)?),
+			"lastid" => c.last_id = Some(StreamId::parse(arg?, 0)?),
+			"force" => {
+				c.force = true;
+				i += 1;
+				continue;
+			},
+			"justid" => {
+				c.justid = true;
+				i += 1;
+				continue;
+			},
+			_ => return Err(ERRMSG_SYNERR)
+		}
+		i += 2;
+	}
+	Ok(c)
+}
+
+// "-", "(id" or id, None when nothing can follow an exclusive start
+fn start_bound(s: &str) -> Result<Option<StreamId>, &'static str> {
+	match s {
+		"-" => Ok(Some(StreamId::default())),
+		"+" => Ok(Some(StreamId::MAX)),
+		_ => match s.strip_prefix('(') {
+			Some(id) => Ok(StreamId::parse(id, 0)?.next()),
+			None => Ok(Some(StreamId::parse(s, 0)?))
+		}
+	}
+}
+
+// "+", "(id" or id, None when nothing can precede an exclusive end
+fn end_bound(s: &str) -> Result<Option<StreamId>, &'static str> {
+	match s {
+		"-" => Ok(Some(StreamId::default())),
+		"+" => Ok(Some(StreamId::MAX)),
+		_ => match s.strip_prefix('(') {
+			Some(id) => Ok(StreamId::parse(id, u64::MAX)?.prev()),
+			None => Ok(Some(StreamId::parse(s, u64::MAX)?))
+		}
+	}
+}
+
+impl Stream {
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	pub fn last_id(&self) -> StreamId {
+		self.last_id
+	}
+
+	pub fn capacity(&self) -> usize {
+		self.entries.values()
+			.map(|fvs| 16 + fvs.iter().map(|s| s.capacity()).sum::<usize>())
+			.sum::<usize>()
+			+ self.groups.values().map(|g| 64 * (1 + g.pel.len()))
+				.sum::<usize>()
+	}
+
+	// the ID an XADD with the given ID argument creates
+	fn next_id(&self, arg: &str) -> Result<StreamId, &'static str> {
+		let last = self.last_id;
+		let (ms, seq) = match arg {
+			"*" => (now_ms().max(last.ms), None),
+			_ => match arg.strip_suffix("-*") {
+				Some(ms) =>
+					(number::<u64>(ms).map_err(|_| ERRMSG_BADID)?, None),
+				None => {
+					let id = StreamId::parse(arg, 0)?;
+					(id.ms, Some(id.seq))
+				}
+			}
+		};
+		let id = match seq {
+			Some(seq) => StreamId {ms, seq},
+			None if ms == last.ms && StreamId::default() != last =>
+				match last.seq.checked_add(1) {
+					Some(seq) => StreamId {ms, seq},
+					None => return Err(ERRMSG_EXHAUSTED)
+				},
+			None if 0 == ms => StreamId {ms, seq: 1},
+			None => StreamId {ms, seq: 0}
+		};
+		if StreamId::default() == id {
+			return Err(ERRMSG_ZEROID);
+		}
+		if id <= last {
+			return Err(ERRMSG_SMALLID);
+		}
+		Ok(id)
+	}
+
+	// add the entry, returning its ID and the number of entries trimmed
+	pub fn add(&mut self, a: &Add, id: &str)
+		-> Result<(DataType, usize), &'static str> {
+		let id = self.next_id(id)?;
+		self.entries.insert(id, a.fvs.clone());
+		self.last_id = id;
+		self.entries_added += 1;
+		let trimmed = match &a.trimming {
+			Some(t) => self.trim(t),
+			None => 0
+		};
+		Ok((id.bulk(), trimmed))
+	}
+
+	// remove the oldest entries, returning how many went away
+	pub fn trim(&mut self, t: &Trimming) -> usize {
+		let mut n = 0;
+		while let Some((&id, _)) = self.entries.first_key_value() {
+			let excess = match t.trim {
+				Trim::MaxLen(max) => self.entries.len() as u64 > max,
+				Trim::MinId(min) => id < min
+			};
+			if !excess || t.limit.is_some_and(|l| n as u64 >= l && 0 < l) {
+				break;
+			}
+			self.entries.remove(&id);
+			n += 1;
+		}
+		n
+	}
+
+	pub fn range(&self, start: &str, end: &str, count: Option<usize>,
+		rev: bool) -> Result<DataType, &'static str> {
+		let (s, e) = match rev {
+			false => (start_bound(start)?, end_bound(end)?),
+			true => (start_bound(end)?, end_bound(start)?)
+		};
+		let (s, e) = match (s, e) {
+			(Some(s), Some(e)) if s <= e => (s, e),
+			_ => return Ok(DataType::List(vec![]))
+		};
+		let it = self.entries.range(s..=e);
+		let count = count.unwrap_or(usize::MAX);
+		let l: Vec<DataType> = match rev {
+			false => it.take(count).map(|(id, fvs)| entry(id, fvs)).collect(),
+			true => it.rev().take(count).map(|(id, fvs)| entry(id, fvs))
+				.collect()
+		};
+		Ok(DataType::List(l))
+	}
+
+	pub fn delete(&mut self, ids: &[StreamId]) -> usize {
+		let mut n = 0;
+		for id in ids {
+			if self.entries.remove(id).is_some() {
+				self.max_deleted = self.max_deleted.max(*id);
+				n += 1;
+			}
+		}
+		n
+	}
+
+	// XSETID last-id [ENTRIESADDED entries-added] [MAXDELETEDID id]
+	pub fn set_id(&mut self, prms: &[String])
+		-> Result<DataType, &'static str> {
+		let id = StreamId::parse(&prms[0], 0)?;
+		let mut added = self.entries_added;
+		let mut max_deleted = self.max_deleted;
+		let mut i = 1;
+		while i + 1 < prms.len() {
+			match prms[i].to_ascii_lowercase().as_str() {
+				"entriesadded" => added = number::<u64>(&prms[i + 1])?,
+				"maxdeletedid" =>
+					max_deleted = StreamId::parse(&prms[i + 1], 0)?,
+				_ => return Err(ERRMSG_SYNERR)
+			}
+			i += 2;
+		}
+		if i != prms.len() {
+			return Err(ERRMSG_SYNERR);
+		}
+		if self.entries.last_key_value().is_some_and(|(top, _)| id < *top) {
+			return Err(ERRMSG_SETIDSMALL);
+		}
+		if added < self.entries.len() as u64 {
+			return Err(ERRMSG_ADDED);
+		}
+		if id < max_deleted {
+			return Err(ERRMSG_MAXDELETED);
+		}
+		self.last_id = id;
+		self.entries_added = added;
+		self.max_deleted = max_deleted;
+		Ok(DataType::str("OK"))
+	}
+
+	// entries after id, for XREAD
+	pub fn after(&self, id: StreamId, count: Option<usize>) -> Vec<DataType> {
+		self.entries.range((Bound::Excluded(id), Bound::Unbounded))
+			.take(count.unwrap_or(usize::MAX))
+			.map(|(id, fvs)| entry(id, fvs))
+			.collect()
+	}
+
+	// the ID meant by a group ID argument, "$" being the last one
+	fn group_id(&self, s: &str) -> Result<StreamId, &'static str> {
+		match s {
+			"$" => Ok(self.last_id),
+			_ => StreamId::parse(s, 0)
+		}
+	}
+
+	// entries read by a group having seen up to id, when that is known
+	fn entries_read_at(&self, id: StreamId) -> Option<u64> {
+		let first = self.entries.first_key_value().map(|(id, _)| *id);
+		if id >= self.last_id {
+			Some(self.entries_added)
+		} else if StreamId::default() == self.max_deleted
+			&& first.is_none_or(|f| id < f) {
+			Some(0)
+		} else {
+			None
+		}
+	}
+
+	// XGROUP CREATE|SETID|DESTROY|CREATECONSUMER|DELCONSUMER, past the key
+	pub fn group(&mut self, k: &str, sub: &str, prms: &[String])
+		-> Result<DataType, String> {
+		let name = &prms[0];
+		match sub {
+			"create" | "setid" => {
+				let id = self.group_id(&prms[1])?;
+				let mut entries_read = self.entries_read_at(id);
+				let mut i = 2;
+				while i < prms.len() {
+					match prms[i].to_ascii_lowercase().as_str() {
+						"mkstream" if "create" == sub => i += 1,
+						"entriesread" if i + 1 < prms.len() => {
+							entries_read = Some(number::<u64>(&prms[i + 1])?);
+							i += 2;
+						},
+						_ => return Err(ERRMSG_SYNERR.to_string())
+					}
+				}
+				match (sub, self.groups.get_mut(name)) {
+					("create", Some(_)) => return Err(String::from(
+						"BUSYGROUP Consumer Group name already exists"
+					)),
+					("create", None) => {
+						self.groups.insert(name.clone(), Group {
+							last_id: id,
+							entries_read,
+							pel: BTreeMap::new(),
+							consumers: BTreeMap::new()
+						});
+					},
+					(_, Some(g)) => {
+						g.last_id = id;
+						g.entries_read = entries_read;
+					},
+					(_, None) => return Err(no_such_group(k, name))
+				}
+				Ok(DataType::str("OK"))
+			},
+			"destroy" => Ok(DataType::Integer(
+				self.groups.remove(name).is_some() as i64
+			)),
+			"createconsumer" | "delconsumer" => {
+				let g = match self.groups.get_mut(name) {
+					Some(g) => g,
+					None => return Err(no_such_group(k, name))
+				};
+				let c = &prms[1];
+				if "createconsumer" == sub {
+					let fresh = !g.consumers.contains_key(c);
+					g.consumers.entry(c.clone()).or_insert(Consumer {
+						seen: now_ms(),
+						active: None
+					});
+					return Ok(DataType::Integer(fresh as i64));
+				}
+				let before = g.pel.len();
+				g.pel.retain(|_, p| p.consumer != *c);
+				g.consumers.remove(c);
+				Ok(DataType::Integer((before - g.pel.len()) as i64))
+			},
+			_ => Err(ERRMSG_SYNERR.to_string())
+		}
+	}
+
+	// XREADGROUP for one stream: new entries for ">", or the history of the
+	// consumer after the given ID; None when no new entries exist
+	pub fn read_group(&mut self, k: &str, r: &Read, id: &str)
+		-> Result<Option<Vec<DataType>>, String> {
+		let (gname, cname) = r.group.as_ref().unwrap();
+		let now = now_ms();
+		let count = r.count.filter(|n| 0 < *n).unwrap_or(usize::MAX);
+		let g = match self.groups.get_mut(gname) {
+			Some(g) => g,
+			None => return Err(format!(
+				"{} in XREADGROUP with GROUP option",
+				nogroup(k, gname)
+			))
+		};
+		let c = g.consumers.entry(cname.clone()).or_insert(Consumer {
+			seen: now,
+			active: None
+		});
+		c.seen = now;
+		if ">" != id {
+			let from = StreamId::parse(id, 0)?;
+			let l = g.pel.range((Bound::Excluded(from), Bound::Unbounded))
+				.filter(|(_, p)| p.consumer == *cname)
+				.take(count)
+				.map(|(id, _)| match self.entries.get(id) {
+					Some(fvs) => entry(id, fvs),
+					None => DataType::List(vec![id.bulk(), DataType::Null])
+				})
+				.collect::<Vec<_>>();
+			if !l.is_empty() {
+				c.active = Some(now);
+			}
+			return Ok(Some(l));
+		}
+		let fresh = self.entries
+			.range((Bound::Excluded(g.last_id), Bound::Unbounded))
+			.take(count)
+			.map(|(id, fvs)| (*id, entry(id, fvs)))
+			.collect::<Vec<_>>();
+		if fresh.is_empty() {
+			return Ok(None);
+		}
+		c.active = Some(now);
+		for (id, _) in fresh.iter() {
+			g.last_id = *id;
+			g.entries_read = g.entries_read.map(|n| n + 1);
+			if !r.noack {
+				g.pel.insert(*id, Pending {
+					consumer: cname.clone(),
+					delivered: now,
+					count: 1
+				});
+			}
+		}
+		if g.last_id == self.last_id {
+			g.entries_read = Some(self.entries_added);
+		}
+		Ok(Some(fresh.into_iter().map(|(_, e)| e).collect()))
+	}
+
+	pub fn ack(&mut self, gname: &str, ids: &[StreamId]) -> usize {
+		match self.groups.get_mut(gname) {
+			Some(g) => ids.iter().filter(|id| g.pel.remove(id).is_some())
+				.count(),
+			None => 0
+		}
+	}
+
+	// XPENDING group [[IDLE min-idle-time] start end count [consumer]]
+	pub fn pending(&self, k: &str, gname: &str, prms: &[String])
+		-> Result<DataType, String> {
+		let g = match self.groups.get(gname) {
+			Some(g) => g,
+			None => return Err(nogroup(k, gname))
+		};
+		if prms.is_empty() {
+			let mut per: BTreeMap<&str, usize> = BTreeMap::new();
+			g.pel.values()
+				.for_each(|p| *per.entry(&p.consumer).or_default() += 1);
+			return Ok(match (g.pel.first_key_value(), g.pel.last_key_value()) {
+				(Some((first, _)), Some((last, _))) => DataType::List(vec![
+					DataType::Integer(g.pel.len() as i64),
+					first.bulk(),
+					last.bulk(),
+					DataType::List(per.iter().map(|(c, n)| DataType::List(vec![
+						DataType::bulkStr(c),
+						DataType::BulkString(n.to_string())
+					])).collect())
+				]),
+				_ => DataType::List(vec![
+					DataType::Integer(0),
+					DataType::Null,
+					DataType::Null,
+					DataType::Null
+				])
+			});
+		}
+		let (min_idle, rest) = match prms[0].eq_ignore_ascii_case("idle") {
+			true if 5 <= prms.len() => (number::<u64>(&prms[1])?, &prms[2..]),
+			true => return Err(ERRMSG_SYNERR.to_string()),
+			false => (0, prms)
+		};
+		if !matches!(rest.len(), 3 | 4) {
+			return Err(ERRMSG_SYNERR.to_string());
+		}
+		let (s, e) = (start_bound(&rest[0])?, end_bound(&rest[1])?);
+		let count = number::<i64>(&rest[2])?.max(0) as usize;
+		let (s, e) = match (s, e) {
+			(Some(s), Some(e)) if s <= e => (s, e),
+			_ => return Ok(DataType::List(vec![]))
+		};
+		let now = now_ms();
+		Ok(DataType::List(g.pel.range(s..=e)
+			.filter(|(_, p)| rest.get(3).is_none_or(|c| p.consumer == *c))
+			.filter(|(_, p)| now.saturating_sub(p.delivered) >= min_idle)
+			.take(count)
+			.map(|(id, p)| DataType::List(vec![
+				id.bulk(),
+				DataType::bulkStr(&p.consumer),
+				DataType::Integer(now.saturating_sub(p.delivered) as i64),
+				DataType::Integer(p.count as i64)
+			]))
+			.collect()))
+	}
+
+	pub fn claim(&mut self, k: &str, gname: &str, cname: &str, c: &Claim)
+		-> Result<DataType, String> {
+		let now = now_ms();
+		let g = match self.groups.get_mut(gname) {
+			Some(g) => g,
+			None => return Err(nogroup(k, gname))
+		};
+		if let Some(id) = c.last_id.filter(|id| *id > g.last_id) {
+			g.last_id = id;
+		}
+		let mut claimed: Vec<DataType> = Vec::new();
+		for id in c.ids.iter() {
+			let fvs = self.entries.get(id);
+			if !g.pel.contains_key(id) {
+				if !c.force || fvs.is_none() {
+					continue;
+				}
+				g.pel.insert(*id, Pending {
+					consumer: cname.to_string(),
+					delivered: now,
+					count: 0
+				});
+			}
+			let p = g.pel.get_mut(id).unwrap();
+			if 0 < c.min_idle && now.saturating_sub(p.delivered) < c.min_idle {
+				continue;
+			}
+			let fvs = match fvs {
+				Some(fvs) => fvs,
+				None => {
+					g.pel.remove(id);
+					continue;
+				}
+			};
+			p.consumer = cname.to_string();
+			p.delivered = c.delivered.unwrap_or(now);
+			match c.retrycount {
+				Some(n) => p.count = n,
+				None if !c.justid => p.count += 1,
+				None => {}
+			}
+			claimed.push(match c.justid {
+				true => id.bulk(),
+				false => entry(id, fvs)
+			});
+		}
+		let consumer = g.consumers.entry(cname.to_string())
+			.or_insert(Consumer {seen: now, active: None});
+		consumer.seen = now;
+		if !claimed.is_empty() {
+			consumer.active = Some(now);
+		}
+		Ok(DataType::List(claimed))
+	}
+
+	// XAUTOCLAIM group consumer min-idle-time start [COUNT count] [JUSTID]
+	pub fn autoclaim(&mut self, k: &str, gname: &str, cname: &str,
+		prms: &[String]) -> Result<DataType, String> {
+		let min_idle = number::<u64>(&prms[0])?;
+		let start = match start_bound(&prms[1])? {
+			Some(id) => id,
+			None => StreamId::MAX
+		};
+		let mut count = 100;
+		let mut justid = false;
+		let mut i = 2;
+		while i < prms.len() {
+			match prms[i].to_ascii_lowercase().as_str() {
+				"count" if i + 1 < prms.len() => {
+					count = match prms[i + 1].parse::<usize>() {
+						Ok(n) if 0 < n => n,
+						_ => return Err(String::from(
+							"ERR COUNT must be > 0"
+						))
+					};
+					i += 2;
+				},
+				"justid" => {
+					justid = true;
+					i += 1;
+				},
+				_ => return Err(ERRMSG_SYNERR.to_string())
+			}
+		}
+		let now = now_ms();
+		let g = match self.groups.get_mut(gname) {
+			Some(g) => g,
+			None => return Err(nogroup(k, gname))
+		};
+		let scanned = g.pel.range(start..).take(count + 1)
+			.map(|(id, p)| (*id, now.saturating_sub(p.delivered) >= min_idle))
+			.collect::<Vec<_>>();
+		let next = match scanned.get(count) {
+			Some((id, _)) => *id,
+			None => StreamId::default()
+		};
+		let mut claimed: Vec<DataType> = Vec::new();
+		let mut deleted: Vec<DataType> = Vec::new();
+		for (id, idle) in scanned.iter().take(count) {
+			if !idle {
+				continue;
+			}
+			let fvs = match self.entries.get(id) {
+				Some(fvs) => fvs,
+				None => {
+					g.pel.remove(id);
+					deleted.push(id.bulk());
+					continue;
+				}
+			};
+			let p = g.pel.get_mut(id).unwrap();
+			p.consumer = cname.to_string();
+			p.delivered = now;
+			if !justid {
+				p.count += 1;
+			}
+			claimed.push(match justid {
+				true => id.bulk(),
+				false => entry(id, fvs)
+			});
+		}
+		let consumer = g.consumers.entry(cname.to_string())
+			.or_insert(Consumer {seen: now, active: None});
+		consumer.seen = now;
+		if !claimed.is_empty() {
+			consumer.active = Some(now);
+		}
+		Ok(DataType::List(vec![
+			next.bulk(),
+			DataType::List(claimed),
+			DataType::List(deleted)
+		]))
+	}
+
+	fn lag(&self, g: &Group) -> DataType {
+		if 0 == self.entries_added || g.last_id >= self.last_id {
+			return DataType::Integer(0);
+		}
+		match g.entries_read {
+			Some(n) if self.max_deleted < g.last_id
+				|| StreamId::default() == self.max_deleted =>
+				DataType::Integer(self.entries_added.saturating_sub(n) as i64),
+			_ => DataType::Null
+		}
+	}
+
+	// XINFO STREAM [FULL [COUNT count]], GROUPS or CONSUMERS group
+	pub fn info(&self, k: &str, sub: &str, prms: &[String])
+		-> Result<DataType, String> {
+		let now = now_ms();
+		match sub {
+			"groups" => Ok(DataType::List(self.groups.iter()
+				.map(|(name, g)| DataType::List(vec![
+					DataType::bulkStr("name"),
+					DataType::bulkStr(name),
+					DataType::bulkStr("consumers"),
+					DataType::Integer(g.consumers.len() as i64),
+					DataType::bulkStr("pending"),
+					DataType::Integer(g.pel.len() as i64),
+					DataType::bulkStr("last-delivered-id"),
+					g.last_id.bulk(),
+					DataType::bulkStr("entries-read"),
+					g.entries_read.map_or(DataType::Null,
+						|n| DataType::Integer(n as i64)),
+					DataType::bulkStr("lag"),
+					self.lag(g)
+				]))
+				.collect())),
+			"consumers" => {
+				let name = prms.first().map_or("", |s| s);
+				let g = match self.groups.get(name) {
+					Some(g) => g,
+					None => return Err(nogroup(k, name))
+				};
+				Ok(DataType::List(g.consumers.iter()
+					.map(|(name, c)| DataType::List(vec![
+						DataType::bulkStr("name"),
+						DataType::bulkStr(name),
+						DataType::bulkStr("pending"),
+						DataType::Integer(g.pel.values()
+							.filter(|p| p.consumer == *name).count() as i64),
+						DataType::bulkStr("idle"),
+						DataType::Integer(now.saturating_sub(c.seen) as i64),
+						DataType::bulkStr("inactive"),
+						DataType::Integer(c.active.map_or(-1,
+							|t| now.saturating_sub(t) as i64))
+					]))
+					.collect()))
+			},
+			_ => self.info_stream(prms)
+		}
+	}
+
+	fn info_stream(&self, prms: &[String]) -> Result<DataType, String> {
+		let full = prms.first().is_some_and(|s| s.eq_ignore_ascii_case("full"));
+		let count = match (prms.get(1), prms.get(2)) {
+			(Some(c), Some(n)) if full && c.eq_ignore_ascii_case("count") =>
+				match number::<usize>(n)? {
+					0 => usize::MAX,
+					n => n
+				},
+			(None, None) if full || prms.is_empty() => 10,
+			_ => return Err(ERRMSG_SYNERR.to_string())
+		};
+		let first = self.entries.first_key_value();
+		let last = self.entries.last_key_value();
+		let mut l = vec![
+			DataType::bulkStr("length"),
+			DataType::Integer(self.entries.len() as i64),
+			DataType::bulkStr("last-generated-id"),
+			self.last_id.bulk(),
+			DataType::bulkStr("max-deleted-entry-id"),
+			self.max_deleted.bulk(),
+			DataType::bulkStr("entries-added"),
+			DataType::Integer(self.entries_added as i64),
+			DataType::bulkStr("recorded-first-entry-id"),
+			first.map_or(StreamId::default(), |(id, _)| *id).bulk()
+		];
+		if !full {
+			l.extend([
+				DataType::bulkStr("groups"),
+				DataType::Integer(self.groups.len() as i64),
+				DataType::bulkStr("first-entry"),
+				first.map_or(DataType::Null, |(id, fvs)| entry(id, fvs)),
+				DataType::bulkStr("last-entry"),
+				last.map_or(DataType::Null, |(id, fvs)| entry(id, fvs))
+			]);
+			return Ok(DataType::List(l));
+		}
+		let pending = |g: &Group, c: Option<&str>| DataType::List(g.pel.iter()
+			.filter(|(_, p)| c.is_none_or(|c| p.consumer == c))
+			.take(count)
+			.map(|(id, p)| {
+				let mut e = vec![id.bulk()];
+				if c.is_none() {
+					e.push(DataType::bulkStr(&p.consumer));
+				}
+				e.push(DataType::Integer(p.delivered as i64));
+				e.push(DataType::Integer(p.count as i64));
+				DataType::List(e)
+			})
+			.collect());
+		l.extend([
+			DataType::bulkStr("entries"),
+			DataType::List(self.entries.iter().take(count)
+				.map(|(id, fvs)| entry(id, fvs)).collect()),
+			DataType::bulkStr("groups"),
+			DataType::List(self.groups.iter()
+				.map(|(name, g)| DataType::List(vec![
+					DataType::bulkStr("name"),
+					DataType::bulkStr(name),
+					DataType::bulkStr("last-delivered-id"),
+					g.last_id.bulk(),
+					DataType::bulkStr("entries-read"),
+					g.entries_read.map_or(DataType::Null,
+						|n| DataType::Integer(n as i64)),
+					DataType::bulkStr("lag"),
+					self.lag(g),
+					DataType::bulkStr("pel-count"),
+					DataType::Integer(g.pel.len() as i64),
+					DataType::bulkStr("pending"),
+					pending(g, None),
+					DataType::bulkStr("consumers"),
+					DataType::List(g.consumers.iter()
+						.map(|(cname, c)| DataType::List(vec![
+							DataType::bulkStr("name"),
+							DataType::bulkStr(cname),
+							DataType::bulkStr("seen-time"),
+							DataType::Integer(c.seen as i64),
+							DataType::bulkStr("active-time"),
+							DataType::Integer(
+								c.active.map_or(-1, |t| t as i64)
+							),
+							DataType::bulkStr("pel-count"),
+							DataType::Integer(g.pel.values()
+								.filter(|p| p.consumer == *cname)
+								.count() as i64),
+							DataType::bulkStr("pending"),
+							pending(g, Some(cname))
+						]))
+						.collect())
+				]))
+				.collect())
+		]);
+		Ok(DataType::List(l))
+	}
+
+	// the commands recreating the stream at key k, groups and pending
+	// entries included
+	pub fn rebuild(&self, k: &DataType) -> Vec<DataType> {
+		let cmd = |args: Vec<String>| DataType::List(
+			args.iter().map(|s| DataType::bulkStr(s)).collect()
+		);
+		let key = match k {
+			DataType::BulkString(s) => s.clone(),
+			_ => return vec![]
+		};
+		let mut out: Vec<DataType> = self.entries.iter()
+			.map(|(id, fvs)| {
+				let mut l =
+					vec!["xadd".to_string(), key.clone(), id.to_string()];
+				l.extend(fvs.iter().cloned());
+				cmd(l)
+			})
+			.collect();
+		out.push(cmd(vec![
+			"xsetid".to_string(),
+			key.clone(),
+			self.last_id.to_string(),
+			"entriesadded".to_string(),
+			self.entries_added.to_string(),
+			"maxdeletedid".to_string(),
+			self.max_deleted.to_string()
+		]));
+		for (name, g) in self.groups.iter() {
+			let mut l = vec![
+				"xgroup".to_string(),
+				"create".to_string(),
+				key.clone(),
+				name.clone(),
+				g.last_id.to_string()
+			];
+			if let Some(n) = g.entries_read {
+				l.extend(["entriesread".to_string(), n.to_string()]);
+			}
+			out.push(cmd(l));
+			for cname in g.consumers.keys() {
+				out.push(cmd(vec![
+					"xgroup".to_string(),
+					"createconsumer".to_string(),
+					key.clone(),
+					name.clone(),
+					cname.clone()
+				]));
+			}
+			for (id, p) in g.pel.iter() {
+				out.push(cmd(vec![
+					"xclaim".to_string(),
+					key.clone(),
+					name.clone(),
+					p.consumer.clone(),
+					"0".to_string(),
+					id.to_string(),
+					"time".to_string(),
+					p.delivered.to_string(),
+					"retrycount".to_string(),
+					p.count.to_string(),
+					"force".to_string(),
+					"justid".to_string()
+				]));
+			}
+		}
+		out
+	}
+
+	// a stream out of the commands produced by rebuild
+	pub fn restore(payload: &str) -> Option<Stream> {
+		let raw = bytes::encode(payload);
+		let mut reader = BufReader::new(&raw[..]);
+		let mut s = Stream::default();
+		while let Ok(req) = parse(&mut reader) {
+			s.apply(&req).ok()?;
+		}
+		Some(s)
+	}
+
+	fn apply(&mut self, req: &Request) -> Result<(), String> {
+		let prms = &req.parameters;
+		match (req.command.as_str(), prms.len()) {
+			("xadd", n) if 3 < n => {
+				let a = parse_add(&prms[1..])?;
+				self.add(&a, &prms[1 + a.id_at])?;
+			},
+			("xsetid", n) if 1 < n => {self.set_id(&prms[1..])?;},
+			("xgroup", n) if 3 < n => {
+				self.group("", &prms[0].to_ascii_lowercase(), &prms[2..])?;
+			},
+			("xclaim", n) if 4 < n => {
+				let c = parse_claim(&prms[3..])?;
+				self.claim("", &prms[1], &prms[2], &c)?;
+			},
+			_ => return Err(ERRMSG_SYNERR.to_string())
+		}
+		Ok(())
+	}
+}
+
+fn no_such_group(k: &str, g: &str) -> String {
+	format!("NOGROUP No such consumer group '{}' for key name '{}'", g, k)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,117 @@
+use serial_test::serial;
+use super::*;
+
+fn strs(v: &[&str]) -> Vec<String> {
+	v.iter().map(|s| s.to_string()).collect()
+}
+
+fn add(s: &mut Stream, prms: &[&str]) -> Result<DataType, &'static str> {
+	let prms = strs(prms);
+	let a = parse_add(&prms)?;
+	s.add(&a, &prms[a.id_at]).map(|(id, _)| id)
+}
+
+fn ids(l: &DataType) -> Vec<String> {
+	match l {
+		DataType::List(l) => l.iter()
+			.map(|e| match e {
+				DataType::List(e) => match &e[0] {
+					DataType::BulkString(id) => id.clone(),
+					_ => String::new()
+				},
+				DataType::BulkString(id) => id.clone(),
+				_ => String::new()
+			})
+			.collect(),
+		_ => vec![]
+	}
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	assert_eq!(StreamId::parse("5-3", 0), Ok(StreamId {ms: 5, seq: 3}));
+	assert_eq!(StreamId::parse("5", 7), Ok(StreamId {ms: 5, seq: 7}));
+	assert_eq!(StreamId::parse("x-1", 0), Err(ERRMSG_BADID));
+	let mut s = Stream::default();
+	assert_eq!(add(&mut s, &["0-0", "f", "v"]), Err(ERRMSG_ZEROID));
+	assert_eq!(add(&mut s, &["1-1", "f"]), Err(ERRMSG_WRONGARGS));
+	assert_eq!(add(&mut s, &["1-1", "f", "v"]), Ok(DataType::bulkStr("1-1")));
+	assert_eq!(add(&mut s, &["1-1", "f", "v"]), Err(ERRMSG_SMALLID));
+	assert_eq!(add(&mut s, &["1-*", "f", "v"]), Ok(DataType::bulkStr("1-2")));
+	assert_eq!(add(&mut s, &["2-*", "f", "v"]), Ok(DataType::bulkStr("2-0")));
+	assert_eq!(
+		add(&mut s, &["maxlen", "=", "2", "3-0", "f", "v"]),
+		Ok(DataType::bulkStr("3-0"))
+	);
+	assert_eq!(s.len(), 2);
+	assert_eq!(
+		add(&mut s, &["maxlen", "1", "limit", "1", "4-0", "f", "v"]).err(),
+		Some(ERRMSG_LIMIT)
+	);
+	let all = s.range("-", "+", None, false).unwrap();
+	assert_eq!(ids(&all), strs(&["2-0", "3-0"]));
+	let rev = s.range("+", "(2-0", None, true).unwrap();
+	assert_eq!(ids(&rev), strs(&["3-0"]));
+	assert_eq!(ids(&s.range("2", "2", None, false).unwrap()), strs(&["2-0"]));
+	assert_eq!(s.delete(&[StreamId {ms: 2, seq: 0}]), 1);
+	assert_eq!(
+		s.set_id(&strs(&["2-5"])),
+		Err(ERRMSG_SETIDSMALL)
+	);
+	let t = parse_trim(&strs(&["minid", "4"])).unwrap();
+	assert_eq!(s.trim(&t), 1);
+	assert_eq!(s.len(), 0);
+	assert_eq!(add(&mut s, &["3-0", "f", "v"]), Err(ERRMSG_SMALLID));
+	assert!(parse_trim(&strs(&["maxlen", "-1"])).is_err());
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	let mut s = Stream::default();
+	for id in ["1-0", "2-0", "3-0"] {
+		add(&mut s, &[id, "f", "v"]).unwrap();
+	}
+	assert_eq!(
+		s.group("k", "create", &strs(&["g", "0"])),
+		Ok(DataType::str("OK"))
+	);
+	assert!(s.group("k", "create", &strs(&["g", "$"])).is_err());
+	let r = parse_read(&strs(&[
+		"group", "g", "alice", "count", "2", "streams", "k", ">"
+	])).unwrap();
+	let read = s.read_group("k", &r, ">").unwrap().unwrap();
+	assert_eq!(ids(&DataType::List(read)), strs(&["1-0", "2-0"]));
+	assert_eq!(s.read_group("k", &r, "0").unwrap().unwrap().len(), 2);
+	assert_eq!(s.ack("g", &[StreamId {ms: 1, seq: 0}]), 1);
+	let c = parse_claim(&strs(&["0", "2-0", "justid"])).unwrap();
+	assert_eq!(
+		s.claim("k", "g", "bob", &c),
+		Ok(DataType::List(vec![DataType::bulkStr("2-0")]))
+	);
+	match s.pending("k", "g", &[]) {
+		Ok(DataType::List(l)) => assert_eq!(l[0], DataType::Integer(1)),
+		other => panic!("unexpected pending {:?}", other)
+	}
+	let copy = match Stream::restore(
+		&s.rebuild(&DataType::bulkStr("k")).iter()
+			.map(|l| l.to_string())
+			.collect::<String>()
+	) {
+		Some(copy) => copy,
+		None => panic!("stream not restored")
+	};
+	assert_eq!(copy.entries, s.entries);
+	assert_eq!(copy.last_id, s.last_id);
+	assert_eq!(copy.groups["g"].pel, s.groups["g"].pel);
+	assert_eq!(copy.groups["g"].last_id, s.groups["g"].last_id);
+	assert_eq!(
+		s.group("k", "delconsumer", &strs(&["g", "bob"])),
+		Ok(DataType::Integer(1))
+	);
+	assert_eq!(
+		s.group("k", "destroy", &strs(&["g"])),
+		Ok(DataType::Integer(1))
+	);
+}
@@ -0,0 +1,329 @@
+use std::f64::consts::PI;
+
+use super::bytes;
+use super::datatype::DataType;
+
+pub const ERRMSG_BADDATA: &str = "ERR received bad data";
+pub const ERRMSG_EXISTS: &str = "ERR T-Digest: key already exists";
+pub const ERRMSG_NOKEY: &str = "ERR T-Digest: key does not exist";
+const ERRMSG_BADCOMPRESSION: &str = "ERR T-Digest: compression parameter \
+	needs to be a positive integer";
+const ERRMSG_BADNUMKEYS: &str = "ERR T-Digest: invalid numkeys";
+const ERRMSG_BADQUANTILE: &str = "ERR T-Digest: quantile should be in [0,1]";
+const ERRMSG_BADVALUE: &str = "ERR T-Digest: error parsing val parameter";
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+
+const MAGIC: &[u8] = b"KTD1";
+const MAX_COMPRESSION: u64 = 100000;
+
+// a merging t-digest, values summed up into centroids of a mean and a
+// weight, fewer and heavier in the middle than at either end by the k1
+// scale function; new values wait in a buffer until merged in by compress
+#[derive(Clone, Debug, PartialEq)]
+pub struct Digest {
+	compression: u64,
+	min: f64,
+	max: f64,
+	centroids: Vec<(f64, u64)>,
+	buffer: Vec<(f64, u64)>
+}
+
+impl Eq for Digest {}
+
+impl Default for Digest {
+	fn default() -> Digest {
+		Digest::new(100)
+	}
+}
+
+pub fn parse_compression(s: &str) -> Result<u64, &'static str> {
+	match s.parse::<u64>() {
+		Ok(n) if 0 < n && n <= MAX_COMPRESSION => Ok(n),
+		_ => Err(ERRMSG_BADCOMPRESSION)
+	}
+}
+
+// [COMPRESSION compression]
+pub fn parse_create(prms: &[String]) -> Result<Digest, &'static str> {
+	match prms {
+		[] => Ok(Digest::default()),
+		[o, c] if o.eq_ignore_ascii_case("compression") =>
+			Ok(Digest::new(parse_compression(c)?)),
+		_ => Err(ERRMSG_SYNERR)
+	}
+}
+
+// NUMKEYS SOURCE [SOURCE ...] [COMPRESSION compression] [OVERRIDE], the
+// sources, the compression and whether to override
+pub fn parse_merge(prms: &[String])
+	-> Result<(&[String], Option<u64>, bool), &'static str> {
+	let n = match prms[0].parse::<usize>() {
+		Ok(n) if 0 < n && n < prms.len() => n,
+		_ => return Err(ERRMSG_BADNUMKEYS)
+	};
+	let (mut compression, mut overriding) = (None, false);
+	let mut i = n + 1;
+	while i < prms.len() {
+		match prms[i].to_ascii_lowercase().as_str() {
+			"compression" if i + 1 < prms.len() => {
+				compression = Some(parse_compression(&prms[i + 1])?);
+				i += 1;
+			},
+			"override" => overriding = true,
+			_ => return Err(ERRMSG_SYNERR)
+		}
+		i += 1;
+	}
+	Ok((&prms[1..=n], compression, overriding))
+}
+
+pub fn parse_values(prms: &[String]) -> Result<Vec<f64>, &'static str> {
+	prms.iter().map(|s| match s.parse::<f64>() {
+		Ok(n) if n.is_finite() => Ok(n),
+		_ => Err(ERRMSG_BADVALUE)
+	}).collect()
+}
+
+pub fn parse_quantiles(prms: &[String]) -> Result<Vec<f64>, &'static str> {
+	prms.iter().map(|s| match s.parse::<f64>() {
+		Ok(q) if (0.0..=1.0).contains(&q) => Ok(q),
+		_ => Err(ERRMSG_BADQUANTILE)
+	}).collect()
+}
+
+// a double reply, nan standing for no answer
+pub fn float(n: f64) -> DataType {
+	match n.is_nan() {
+		true => DataType::bulkStr("nan"),
+		false => DataType::bulkStr(&n.to_string())
+	}
+}
+
+impl Digest {
+	pub fn new(compression: u64) -> Digest {
+		Digest {
+			compression,
+			min: f64::NAN,
+			max: f64::NAN,
+			centroids: vec![],
+			buffer: vec![]
+		}
+	}
+
+	pub fn compression(&self) -> u64 {
+		self.compression
+	}
+
+	pub fn count(&self) -> u64 {
+		self.centroids.iter().chain(self.buffer.iter()).map(|c| c.1).sum()
+	}
+
+	pub fn min(&self) -> f64 {
+		self.min
+	}
+
+	pub fn max(&self) -> f64 {
+		self.max
+	}
+
+	pub fn add(&mut self, v: f64) {
+		self.absorb(v, v, &[(v, 1)]);
+	}
+
+	// take in the centroids of d
+	pub fn merge(&mut self, d: &Digest) {
+		if 0 < d.count() {
+			let cs: Vec<(f64, u64)> = d.centroids.iter()
+				.chain(d.buffer.iter())
+				.cloned()
+				.collect();
+			self.absorb(d.min, d.max, &cs);
+		}
+	}
+
+	fn absorb(&mut self, min: f64, max: f64, cs: &[(f64, u64)]) {
+		self.min = self.min.min(min);
+		self.max = self.max.max(max);
+		self.buffer.extend_from_slice(cs);
+		if 5 * self.compression as usize <= self.buffer.len() {
+			self.compress();
+		}
+	}
+
+	// merge the buffer into the centroids, neighbours joining as long as
+	// they span at most 1 on the scale k(q) = c / 2pi * asin(2q - 1)
+	pub fn compress(&mut self) {
+		if self.buffer.is_empty() {
+			return;
+		}
+		let mut all = std::mem::take(&mut self.centroids);
+		all.append(&mut self.buffer);
+		all.sort_by(|a, b| a.0.total_cmp(&b.0));
+		let total = all.iter().map(|c| c.1).sum::<u64>() as f64;
+		let c = self.compression as f64;
+		let k = |q: f64|
+			c / (2.0 * PI) * (2.0 * q - 1.0).clamp(-1.0, 1.0).asin();
+		let (mut before, mut k_lo) = (0u64, k(0.0));
+		let mut cur = all[0];
+		for next in all.into_iter().skip(1) {
+			let q = (before + cur.1 + next.1) as f64 / total;
+			if k(q) - k_lo <= 1.0 {
+				let w = cur.1 + next.1;
+				cur.0 += (next.0 - cur.0) * next.1 as f64 / w as f64;
+				cur.1 = w;
+			} else {
+				before += cur.1;
+				k_lo = k(before as f64 / total);
+				self.centroids.push(cur);
+				cur = next;
+			}
+		}
+		self.centroids.push(cur);
+	}
+
+	// the value at quantile q, interpolated between the centroid centres
+	// and the extremes
+	pub fn quantile(&mut self, q: f64) -> f64 {
+		self.compress();
+		let n = self.count() as f64;
+		let cs = &self.centroids;
+		match cs.len() {
+			0 => return f64::NAN,
+			_ if 0.0 == q => return self.min,
+			_ if 1.0 == q => return self.max,
+			1 => return self.min + (self.max - self.min) * q,
+			_ => {}
+		}
+		let at = q * n;
+		let mut mid = cs[0].1 as f64 / 2.0;
+		if at < mid {
+			return self.min + (cs[0].0 - self.min) * at / mid;
+		}
+		for w in cs.windows(2) {
+			let next = mid + (w[0].1 + w[1].1) as f64 / 2.0;
+			if at < next {
+				return w[0].0 + (w[1].0 - w[0].0) * (at - mid) / (next - mid);
+			}
+			mid = next;
+		}
+		let last = cs[cs.len() - 1].0;
+		(last + (self.max - last) * (at - mid) / (n - mid)).min(self.max)
+	}
+
+	// the share of the values below v, plus half of those equal to it
+	pub fn cdf(&mut self, v: f64) -> f64 {
+		self.compress();
+		let n = self.count() as f64;
+		let cs = &self.centroids;
+		if cs.is_empty() {
+			return f64::NAN;
+		} else if v < self.min {
+			return 0.0;
+		} else if self.max < v {
+			return 1.0;
+		} else if self.min == self.max {
+			return 0.5;
+		}
+		let below = cs.iter().filter(|c| c.0 < v).map(|c| c.1).sum::<u64>();
+		let equal = cs.iter().filter(|c| c.0 == v).map(|c| c.1).sum::<u64>();
+		if 0 < equal {
+			return (below as f64 + equal as f64 / 2.0) / n;
+		}
+		let mut mid = cs[0].1 as f64 / 2.0;
+		if v < cs[0].0 {
+			return mid * (v - self.min) / (cs[0].0 - self.min) / n;
+		}
+		for w in cs.windows(2) {
+			let next = mid + (w[0].1 + w[1].1) as f64 / 2.0;
+			if v < w[1].0 {
+				return (mid + (next - mid) * (v - w[0].0) / (w[1].0 - w[0].0))
+					/ n;
+			}
+			mid = next;
+		}
+		let last = cs[cs.len() - 1].0;
+		(mid + (n - mid) * (v - last) / (self.max - last)) / n
+	}
+
+	// the estimated number of values below v, plus half of those equal to
+	// it, -1 below the minimum and -2 without any values
+	pub fn rank(&mut self, v: f64) -> i64 {
+		let n = self.count();
+		if 0 == n {
+			-2
+		} else if v < self.min {
+			-1
+		} else if self.max < v {
+			n as i64
+		} else {
+			(self.cdf(v) * n as f64).round() as i64
+		}
+	}
+
+	// bytes taken by the centroids
+	pub fn size(&self) -> usize {
+		16 * (self.centroids.capacity() + self.buffer.capacity())
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut v = MAGIC.to_vec();
+		for n in [self.compression, self.min.to_bits(), self.max.to_bits()] {
+			v.extend_from_slice(&n.to_le_bytes());
+		}
+		for cs in [&self.centroids, &self.buffer] {
+			v.extend_from_slice(&(cs.len() as u64).to_le_bytes());
+			for (m, w) in cs.iter() {
+				v.extend_from_slice(&m.to_bits().to_le_bytes());
+				v.extend_from_slice(&w.to_le_bytes());
+			}
+		}
+		v
+	}
+
+	pub fn parse(mut b: &[u8]) -> Result<Digest, &'static str> {
+		match read(&mut b) {
+			Some(d) if b.is_empty() => Ok(d),
+			_ => Err(ERRMSG_BADDATA)
+		}
+	}
+}
+
+// the centroids serialized at the start of b
+fn centroids(b: &mut &[u8]) -> Option<Vec<(f64, u64)>> {
+	let n = bytes::take_u64(b)? as usize;
+	if b.len() / 16 < n {
+		return None;
+	}
+	let mut cs = Vec::with_capacity(n);
+	for _ in 0..n {
+		let m = f64::from_bits(bytes::take_u64(b)?);
+		let w = bytes::take_u64(b)?;
+		match m.is_finite() && 0 < w {
+			true => cs.push((m, w)),
+			false => return None
+		}
+	}
+	Some(cs)
+}
+
+// the digest serialized at the start of b
+fn read(b: &mut &[u8]) -> Option<Digest> {
+	if MAGIC != bytes::take(b, MAGIC.len())? {
+		return None;
+	}
+	let compression = bytes::take_u64(b)?;
+	let min = f64::from_bits(bytes::take_u64(b)?);
+	let max = f64::from_bits(bytes::take_u64(b)?);
+	let mut d = Digest::new(compression);
+	d.centroids = centroids(b)?;
+	d.buffer = centroids(b)?;
+	d.min = min;
+	d.max = max;
+	match 0 < compression && compression <= MAX_COMPRESSION {
+		true => Some(d),
+		false => None
+	}
+}
+
+#[cfg(test)]
+mod tests;
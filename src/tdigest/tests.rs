@@ -0,0 +1,54 @@
+use serial_test::serial;
+use super::*;
+use crate::kv;
+
+fn prms(ps: &[&str]) -> Vec<String> {
+	ps.iter().map(|p| p.to_string()).collect()
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	assert_eq!(parse_create(&prms(&["compression", "0"])),
+		Err(ERRMSG_BADCOMPRESSION));
+	assert_eq!(parse_quantiles(&prms(&["1.5"])), Err(ERRMSG_BADQUANTILE));
+	assert_eq!(parse_values(&prms(&["nan"])), Err(ERRMSG_BADVALUE));
+	let srcs = prms(&["2", "a", "b", "override", "compression", "50"]);
+	let (ks, c, o) = parse_merge(&srcs).unwrap();
+	assert_eq!((ks.len(), c, o), (2, Some(50), true));
+	let mut d = Digest::default();
+	assert!(d.quantile(0.5).is_nan() && d.cdf(1.0).is_nan());
+	assert_eq!(d.rank(1.0), -2);
+	for v in 1..=10 {
+		d.add(v as f64);
+	}
+	assert_eq!((d.min(), d.max(), d.quantile(0.0)), (1.0, 10.0, 1.0));
+	assert_eq!(d.quantile(0.5), 5.5);
+	assert_eq!(d.cdf(5.0), 0.45);
+	assert_eq!((d.rank(0.0), d.rank(11.0)), (-1, 10));
+	let mut big = Digest::new(100);
+	for v in 0..100000 {
+		big.add((v % 1000) as f64);
+	}
+	assert!(big.centroids.len() < 500);
+	assert!((big.quantile(0.9) - 900.0).abs() < 5.0);
+	assert!((big.cdf(250.0) - 0.25).abs() < 0.01);
+	let mut m = Digest::new(100);
+	m.merge(&d);
+	m.merge(&big);
+	assert_eq!((m.count(), m.min(), m.max()), (100010, 0.0, 999.0));
+	assert_eq!(Digest::parse(&m.to_bytes()), Ok(m.clone()));
+	assert_eq!(float(f64::NAN), DataType::bulkStr("nan"));
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	assert_eq!(kv::tdigest_create("td", &[]), Ok(DataType::str("OK")));
+	assert_eq!(kv::tdigest_min("td"), Ok(DataType::bulkStr("nan")));
+	assert_eq!(kv::tdigest_max("td"), Ok(DataType::bulkStr("nan")));
+	let _ = kv::tdigest_add("td", &prms(&["1.5", "3", "-0.25"]));
+	assert_eq!(kv::tdigest_min("td"), Ok(DataType::bulkStr("-0.25")));
+	assert_eq!(kv::tdigest_max("td"), Ok(DataType::bulkStr("3")));
+	let _ = kv::del(&vec!["td".to_string()]);
+}
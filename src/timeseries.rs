@@ -0,0 +1,995 @@
+use super::bytes;
+use super::datatype::DataType;
+
+pub const ERRMSG_BADDATA: &str = "ERR received bad data";
+pub const ERRMSG_BADTIMESTAMP: &str = "ERR TSDB: invalid timestamp";
+pub const ERRMSG_BADVALUE: &str = "ERR TSDB: invalid value";
+pub const ERRMSG_EXISTS: &str = "ERR TSDB: key already exists";
+pub const ERRMSG_NOKEY: &str = "ERR TSDB: the key does not exist";
+pub const ERRMSG_NORULE: &str = "ERR TSDB: compaction rule does not exist";
+pub const ERRMSG_OLDINCR: &str = "ERR TSDB: timestamp must be equal to or \
+	higher than the maximum existing timestamp";
+pub const ERRMSG_RULEDST: &str =
+	"ERR TSDB: the destination key already has a dst rule";
+pub const ERRMSG_RULESRC: &str =
+	"ERR TSDB: the destination key already has a src rule";
+pub const ERRMSG_RULESAME: &str =
+	"ERR TSDB: the source key and destination key should be different";
+pub const ERRMSG_SRCSRC: &str =
+	"ERR TSDB: the source key already has a source rule";
+const ERRMSG_BADAGG: &str = "ERR TSDB: Unknown aggregation type";
+const ERRMSG_BADALIGN: &str = "ERR TSDB: unknown ALIGN parameter";
+const ERRMSG_BADBUCKET: &str =
+	"ERR TSDB: bucketDuration must be greater than zero";
+const ERRMSG_BADCHUNKSIZE: &str = "ERR TSDB: CHUNK_SIZE value must be a \
+	multiple of 8 in the range [48 .. 1048576]";
+const ERRMSG_BADCOUNT: &str = "ERR TSDB: Couldn't parse COUNT";
+const ERRMSG_BADENCODING: &str = "ERR TSDB: unknown ENCODING parameter";
+const ERRMSG_BADFILTER: &str = "ERR TSDB: failed parsing labels";
+const ERRMSG_BADPOLICY: &str = "ERR TSDB: Unknown DUPLICATE_POLICY";
+const ERRMSG_BADRETENTION: &str = "ERR TSDB: Couldn't parse RETENTION";
+const ERRMSG_BLOCKED: &str = "ERR TSDB: Error at upsert, update is not \
+	supported when DUPLICATE_POLICY is set to BLOCK mode";
+const ERRMSG_NOFILTER: &str = "ERR TSDB: missing FILTER argument";
+const ERRMSG_NOMATCHER: &str = "ERR TSDB: please provide at least one matcher";
+const ERRMSG_RETENTION: &str = "ERR TSDB: Timestamp is older than retention";
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+
+const MAGIC: &[u8] = b"KTS1";
+const CHUNK_SIZE: u64 = 4096;
+// the bits of a delta of deltas after each prefix of 1s
+const DOD_BITS: [u32; 5] = [0, 7, 9, 12, 64];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Policy {
+	Block,
+	First,
+	Last,
+	Min,
+	Max,
+	Sum
+}
+
+const POLICIES: [(Policy, &str); 6] = [
+	(Policy::Block, "block"),
+	(Policy::First, "first"),
+	(Policy::Last, "last"),
+	(Policy::Min, "min"),
+	(Policy::Max, "max"),
+	(Policy::Sum, "sum")
+];
+
+fn parse_policy(s: &str) -> Result<Policy, &'static str> {
+	POLICIES.iter()
+		.find(|(_, n)| n.eq_ignore_ascii_case(s))
+		.map(|(p, _)| *p)
+		.ok_or(ERRMSG_BADPOLICY)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Agg {
+	Avg,
+	Sum,
+	Min,
+	Max,
+	Range,
+	Count,
+	First,
+	Last,
+	StdP,
+	StdS,
+	VarP,
+	VarS
+}
+
+const AGGS: [(Agg, &str); 12] = [
+	(Agg::Avg, "avg"),
+	(Agg::Sum, "sum"),
+	(Agg::Min, "min"),
+	(Agg::Max, "max"),
+	(Agg::Range, "range"),
+	(Agg::Count, "count"),
+	(Agg::First, "first"),
+	(Agg::Last, "last"),
+	(Agg::StdP, "std.p"),
+	(Agg::StdS, "std.s"),
+	(Agg::VarP, "var.p"),
+	(Agg::VarS, "var.s")
+];
+
+// AGGREGATOR BUCKETDURATION
+fn parse_agg(agg: &str, bucket: &str) -> Result<(Agg, u64), &'static str> {
+	let a = AGGS.iter()
+		.find(|(_, n)| n.eq_ignore_ascii_case(agg))
+		.map(|(a, _)| *a)
+		.ok_or(ERRMSG_BADAGG)?;
+	match bucket.parse::<u64>() {
+		Ok(n) if 0 < n => Ok((a, n)),
+		_ => Err(ERRMSG_BADBUCKET)
+	}
+}
+
+// a bucket of samples summed up, the variance kept by Welford's method
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Acc {
+	count: u64,
+	sum: f64,
+	min: f64,
+	max: f64,
+	first: f64,
+	last: f64,
+	mean: f64,
+	m2: f64
+}
+
+impl Acc {
+	fn add(&mut self, v: f64) {
+		if 0 == self.count {
+			(self.min, self.max, self.first) = (v, v, v);
+		}
+		self.count += 1;
+		self.sum += v;
+		self.min = self.min.min(v);
+		self.max = self.max.max(v);
+		self.last = v;
+		let d = v - self.mean;
+		self.mean += d / self.count as f64;
+		self.m2 += d * (v - self.mean);
+	}
+
+	fn value(&self, agg: Agg) -> f64 {
+		let n = self.count as f64;
+		match agg {
+			Agg::Avg => self.sum / n,
+			Agg::Sum => self.sum,
+			Agg::Min => self.min,
+			Agg::Max => self.max,
+			Agg::Range => self.max - self.min,
+			Agg::Count => n,
+			Agg::First => self.first,
+			Agg::Last => self.last,
+			Agg::StdP => (self.m2 / n).sqrt(),
+			Agg::StdS => (self.m2 / (n - 1.0).max(1.0)).sqrt(),
+			Agg::VarP => self.m2 / n,
+			Agg::VarS => self.m2 / (n - 1.0).max(1.0)
+		}
+	}
+
+	fn to_bytes(&self, v: &mut Vec<u8>) {
+		v.extend_from_slice(&self.count.to_le_bytes());
+		for n in [self.sum, self.min, self.max, self.first, self.last,
+			self.mean, self.m2] {
+			v.extend_from_slice(&n.to_bits().to_le_bytes());
+		}
+	}
+}
+
+// the start of the bucket ts falls in
+fn bucket_of(ts: u64, bucket: u64, align: u64) -> u64 {
+	ts.saturating_sub((ts % bucket + bucket - align % bucket) % bucket)
+}
+
+// samples summed up by agg over buckets, each one stamped with its start
+fn aggregate(samples: &[(u64, f64)], agg: Agg, bucket: u64, align: u64)
+	-> Vec<(u64, f64)> {
+	let mut out: Vec<(u64, f64)> = vec![];
+	let mut open: Option<(u64, Acc)> = None;
+	for (ts, v) in samples.iter() {
+		let b = bucket_of(*ts, bucket, align);
+		match open.as_mut() {
+			Some((start, acc)) if b == *start => acc.add(*v),
+			_ => {
+				if let Some((start, acc)) = open.take() {
+					out.push((start, acc.value(agg)));
+				}
+				let mut acc = Acc::default();
+				acc.add(*v);
+				open = Some((b, acc));
+			}
+		}
+	}
+	if let Some((start, acc)) = open {
+		out.push((start, acc.value(agg)));
+	}
+	out
+}
+
+// a downsampling rule, the bucket being filled kept along
+#[derive(Clone, Debug, PartialEq)]
+struct Rule {
+	dest: String,
+	agg: Agg,
+	bucket: u64,
+	align: u64,
+	open: Option<(u64, Acc)>
+}
+
+// bits written most significant first
+struct Writer<'a> {
+	b: &'a mut Vec<u8>,
+	len: &'a mut usize
+}
+
+impl Writer<'_> {
+	fn put(&mut self, n: u64, bits: u32) {
+		for i in (0..bits).rev() {
+			if self.len.is_multiple_of(8) {
+				self.b.push(0);
+			}
+			if 0 != (n >> i) & 1 {
+				*self.b.last_mut().unwrap() |= 0x80 >> (*self.len % 8);
+			}
+			*self.len += 1;
+		}
+	}
+}
+
+struct Reader<'a> {
+	b: &'a [u8],
+	at: usize,
+	len: usize
+}
+
+impl Reader<'_> {
+	fn get(&mut self, bits: u32) -> Option<u64> {
+		if self.len < self.at + bits as usize {
+			return None;
+		}
+		let mut n = 0u64;
+		for _ in 0..bits {
+			let bit = self.b[self.at / 8] >> (7 - self.at % 8) & 1;
+			n = n << 1 | bit as u64;
+			self.at += 1;
+		}
+		Some(n)
+	}
+}
+
+// a run of samples packed Gorilla style, timestamps by the difference
+// between their deltas and values xored with the previous one, what it
+// takes to append kept along
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Chunk {
+	bits: Vec<u8>,
+	len: usize,
+	count: u64,
+	first: u64,
+	last: u64,
+	delta: u64,
+	// the last value, and the window of the meaningful bits of the last
+	// xor, 64 leading zeros standing for none
+	value: u64,
+	lead: u32,
+	trail: u32
+}
+
+impl Chunk {
+	fn from(samples: &[(u64, f64)]) -> Chunk {
+		let mut c = Chunk::default();
+		samples.iter().for_each(|(ts, v)| c.push(*ts, *v));
+		c
+	}
+
+	// append a sample, later than any other
+	fn push(&mut self, ts: u64, v: f64) {
+		let bits = v.to_bits();
+		let mut w = Writer {b: &mut self.bits, len: &mut self.len};
+		if 0 == self.count {
+			w.put(ts, 64);
+			w.put(bits, 64);
+			(self.first, self.lead) = (ts, 64);
+		} else {
+			let delta = ts.wrapping_sub(self.last);
+			let dod = delta.wrapping_sub(self.delta) as i64;
+			let n = match dod {
+				0 => 0,
+				-64..=63 => 1,
+				-256..=255 => 2,
+				-2048..=2047 => 3,
+				_ => 4
+			};
+			match n {
+				4 => w.put(0b1111, 4),
+				_ => w.put((1 << (n + 1)) - 2, n as u32 + 1)
+			}
+			w.put(dod as u64, DOD_BITS[n]);
+			let x = bits ^ self.value;
+			let (lead, trail) = (x.leading_zeros(), x.trailing_zeros());
+			if 0 == x {
+				w.put(0, 1);
+			} else if self.lead <= lead && self.trail <= trail {
+				w.put(0b10, 2);
+				w.put(x >> self.trail, 64 - self.lead - self.trail);
+			} else {
+				w.put(0b11, 2);
+				w.put(lead as u64, 6);
+				w.put((63 - lead - trail) as u64, 6);
+				w.put(x >> trail, 64 - lead - trail);
+				(self.lead, self.trail) = (lead, trail);
+			}
+			self.delta = delta;
+		}
+		self.count += 1;
+		self.last = ts;
+		self.value = bits;
+	}
+
+	fn samples(&self) -> Option<Vec<(u64, f64)>> {
+		let mut r = Reader {b: &self.bits, at: 0, len: self.len};
+		let mut out = Vec::with_capacity(self.count as usize);
+		if 0 == self.count {
+			return Some(out);
+		}
+		let (mut ts, mut bits) = (r.get(64)?, r.get(64)?);
+		out.push((ts, f64::from_bits(bits)));
+		let (mut delta, mut lead, mut trail) = (0u64, 64u32, 0u32);
+		for _ in 1..self.count {
+			let mut n = 0;
+			while n < 4 && 1 == r.get(1)? {
+				n += 1;
+			}
+			let b = DOD_BITS[n];
+			let dod = match b {
+				0 | 64 => r.get(b)?,
+				_ => ((r.get(b)? << (64 - b)) as i64 >> (64 - b)) as u64
+			};
+			delta = delta.wrapping_add(dod);
+			ts = ts.wrapping_add(delta);
+			if 1 == r.get(1)? {
+				if 1 == r.get(1)? {
+					lead = r.get(6)? as u32;
+					let sig = r.get(6)? as u32 + 1;
+					trail = 64u32.checked_sub(lead + sig)?;
+				} else if 64 == lead {
+					return None;
+				}
+				bits ^= r.get(64 - lead - trail)? << trail;
+			}
+			out.push((ts, f64::from_bits(bits)));
+		}
+		Some(out)
+	}
+}
+
+// a time series, its samples in chunks of about chunk_size bytes each,
+// those older than retention ms before the last one dropped unless it is 0
+#[derive(Clone, Debug, PartialEq)]
+pub struct Series {
+	retention: u64,
+	chunk_size: u64,
+	policy: Policy,
+	labels: Vec<(String, String)>,
+	// the series this one is downsampled from
+	source: Option<String>,
+	rules: Vec<Rule>,
+	chunks: Vec<Chunk>
+}
+
+impl Eq for Series {}
+
+impl Default for Series {
+	fn default() -> Series {
+		Series {
+			retention: 0,
+			chunk_size: CHUNK_SIZE,
+			policy: Policy::Block,
+			labels: vec![],
+			source: None,
+			rules: vec![],
+			chunks: vec![]
+		}
+	}
+}
+
+// what TS.CREATE, TS.ADD and TS.INCRBY take after the samples
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+	pub series: Series,
+	pub on_duplicate: Option<Policy>,
+	pub timestamp: Option<u64>
+}
+
+// a sample timestamp, * standing for now
+pub fn parse_timestamp(s: &str, now: u64) -> Result<u64, &'static str> {
+	match s {
+		"*" => Ok(now),
+		_ => s.parse::<u64>().map_err(|_| ERRMSG_BADTIMESTAMP)
+	}
+}
+
+pub fn parse_value(s: &str) -> Result<f64, &'static str> {
+	match s.parse::<f64>() {
+		Ok(v) if !v.is_nan() => Ok(v),
+		_ => Err(ERRMSG_BADVALUE)
+	}
+}
+
+// [RETENTION ms] [ENCODING enc] [CHUNK_SIZE size] [DUPLICATE_POLICY policy]
+// [ON_DUPLICATE policy] [TIMESTAMP ts] [LABELS label value ...], samples
+// being compressed whatever the encoding
+pub fn parse_options(prms: &[String], now: u64)
+	-> Result<Options, &'static str> {
+	let mut o = Options::default();
+	let mut i = 0;
+	while i < prms.len() {
+		let arg = prms.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+		match prms[i].to_ascii_lowercase().as_str() {
+			"retention" => o.series.retention = arg.parse::<u64>()
+				.map_err(|_| ERRMSG_BADRETENTION)?,
+			"encoding" if arg.eq_ignore_ascii_case("compressed") ||
+				arg.eq_ignore_ascii_case("uncompressed") => {},
+			"encoding" => return Err(ERRMSG_BADENCODING),
+			"chunk_size" => o.series.chunk_size = match arg.parse::<u64>() {
+				Ok(n) if (48..=1048576).contains(&n) && 0 == n % 8 => n,
+				_ => return Err(ERRMSG_BADCHUNKSIZE)
+			},
+			"duplicate_policy" => o.series.policy = parse_policy(arg)?,
+			"on_duplicate" => o.on_duplicate = Some(parse_policy(arg)?),
+			"timestamp" => o.timestamp = Some(parse_timestamp(arg, now)?),
+			"labels" if 1 == (prms.len() - i) % 2 => {
+				o.series.labels = prms[i + 1..].chunks(2)
+					.map(|l| (l[0].clone(), l[1].clone()))
+					.collect();
+				break;
+			},
+			_ => return Err(ERRMSG_SYNERR)
+		}
+		i += 2;
+	}
+	Ok(o)
+}
+
+// a label matcher of a filter, label=value, label!=value, label=(v1,v2)
+// or label!=(v1,v2), no value standing for the label not being set
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matcher {
+	label: String,
+	values: Vec<String>,
+	negated: bool
+}
+
+impl Matcher {
+	fn parse(s: &str) -> Result<Matcher, &'static str> {
+		let (label, value, negated) = match s.split_once('=') {
+			Some((l, v)) if l.ends_with('!') => (&l[..l.len() - 1], v, true),
+			Some((l, v)) => (l, v, false),
+			None => return Err(ERRMSG_BADFILTER)
+		};
+		if label.is_empty() {
+			return Err(ERRMSG_BADFILTER);
+		}
+		let values = match value.strip_prefix('(') {
+			Some(vs) => vs.strip_suffix(')').ok_or(ERRMSG_BADFILTER)?
+				.split(',')
+				.map(|v| v.to_string())
+				.collect(),
+			None if value.is_empty() => vec![],
+			None => vec![value.to_string()]
+		};
+		Ok(Matcher {label: label.to_string(), values, negated})
+	}
+
+	pub fn matches(&self, s: &Series) -> bool {
+		let v = s.label(&self.label);
+		let found = match (self.values.is_empty(), v) {
+			(true, v) => v.is_none(),
+			(false, Some(v)) => self.values.iter().any(|e| e == v),
+			(false, None) => false
+		};
+		found != self.negated
+	}
+}
+
+// FILTER label=value ..., at least one of them having to pick series by
+// their labels rather than leave them out
+pub fn parse_filter(prms: &[String]) -> Result<Vec<Matcher>, &'static str> {
+	let ms = prms.iter()
+		.map(|s| Matcher::parse(s))
+		.collect::<Result<Vec<Matcher>, &str>>()?;
+	match ms.iter().any(|m| !m.negated && !m.values.is_empty()) {
+		true => Ok(ms),
+		false => Err(ERRMSG_NOMATCHER)
+	}
+}
+
+// the labels TS.MRANGE replies with
+#[derive(Clone, Debug, PartialEq)]
+enum Labels {
+	None,
+	All,
+	Selected(Vec<String>)
+}
+
+// what TS.RANGE and TS.MRANGE take
+#[derive(Clone, Debug, PartialEq)]
+pub struct Range {
+	from: u64,
+	to: u64,
+	timestamps: Option<Vec<u64>>,
+	values: Option<(f64, f64)>,
+	count: Option<usize>,
+	align: u64,
+	aggregation: Option<(Agg, u64)>,
+	rev: bool,
+	labels: Labels,
+	filter: Vec<Matcher>
+}
+
+impl Range {
+	// whether the filter picks s
+	pub fn matches(&self, s: &Series) -> bool {
+		self.filter.iter().all(|m| m.matches(s))
+	}
+
+	// the labels of s to reply with, as pairs of a label and a value
+	pub fn labels_of(&self, s: &Series) -> DataType {
+		let pair = |l: &str, v: Option<&str>| DataType::List(vec![
+			DataType::bulkStr(l),
+			v.map_or(DataType::Null, DataType::bulkStr)
+		]);
+		DataType::List(match &self.labels {
+			Labels::None => vec![],
+			Labels::All => s.labels.iter()
+				.map(|(l, v)| pair(l, Some(v)))
+				.collect(),
+			Labels::Selected(ls) => ls.iter()
+				.map(|l| pair(l, s.label(l)))
+				.collect()
+		})
+	}
+}
+
+// FROMTIMESTAMP TOTIMESTAMP [FILTER_BY_TS ts ...] [FILTER_BY_VALUE min max]
+// [COUNT count] [ALIGN align] [AGGREGATION aggregator bucketduration],
+// and for TS.MRANGE [WITHLABELS | SELECTED_LABELS label ...] FILTER ...
+pub fn parse_range(prms: &[String], rev: bool, multi: bool)
+	-> Result<Range, &'static str> {
+	let bound = |s: &str, dflt: u64| match s {
+		"-" | "+" => Ok(dflt),
+		_ => s.parse::<u64>().map_err(|_| ERRMSG_BADTIMESTAMP)
+	};
+	let from = bound(&prms[0], 0)?;
+	let to = bound(&prms[1], u64::MAX)?;
+	let mut r = Range {
+		from, to, timestamps: None, values: None, count: None, align: 0,
+		aggregation: None, rev, labels: Labels::None, filter: vec![]
+	};
+	let keyword = |s: &String| matches!(
+		s.to_ascii_lowercase().as_str(),
+		"filter_by_ts" | "filter_by_value" | "count" | "align" |
+			"aggregation" | "withlabels" | "selected_labels" | "filter"
+	);
+	let mut i = 2;
+	while i < prms.len() {
+		let args = &prms[i + 1..];
+		let n = args.iter().position(keyword).unwrap_or(args.len());
+		match (prms[i].to_ascii_lowercase().as_str(), args) {
+			("filter_by_ts", _) if 0 < n => {
+				let mut ts = args[..n].iter()
+					.map(|s| s.parse::<u64>().map_err(|_| ERRMSG_BADTIMESTAMP))
+					.collect::<Result<Vec<u64>, &str>>()?;
+				ts.sort_unstable();
+				r.timestamps = Some(ts);
+				i += n;
+			},
+			("filter_by_value", [min, max, ..]) => {
+				r.values = Some((parse_value(min)?, parse_value(max)?));
+				i += 2;
+			},
+			("count", [c, ..]) => {
+				let c = c.parse::<usize>().map_err(|_| ERRMSG_BADCOUNT)?;
+				r.count = Some(c);
+				i += 1;
+			},
+			("align", [a, ..]) => {
+				r.align = match a.to_ascii_lowercase().as_str() {
+					"start" | "-" => from,
+					"end" | "+" => to,
+					_ => a.parse::<u64>().map_err(|_| ERRMSG_BADALIGN)?
+				};
+				i += 1;
+			},
+			("aggregation", [agg, bucket, ..]) => {
+				r.aggregation = Some(parse_agg(agg, bucket)?);
+				i += 2;
+			},
+			("withlabels", _) if multi => r.labels = Labels::All,
+			("selected_labels", _) if multi && 0 < n => {
+				r.labels = Labels::Selected(args[..n].to_vec());
+				i += n;
+			},
+			("filter", _) if multi => {
+				r.filter = parse_filter(args)?;
+				break;
+			},
+			_ => return Err(ERRMSG_SYNERR)
+		}
+		i += 1;
+	}
+	match multi && r.filter.is_empty() {
+		true => Err(ERRMSG_NOFILTER),
+		false => Ok(r)
+	}
+}
+
+// TS.CREATERULE SOURCE DESTINATION AGGREGATION aggregator bucketduration
+// [aligntimestamp], the rule made of the parameters after the keys
+pub fn parse_rule(prms: &[String]) -> Result<(Agg, u64, u64), &'static str> {
+	match prms {
+		[o, agg, bucket, rest @ ..] if o.eq_ignore_ascii_case("aggregation") &&
+			rest.len() < 2 => {
+			let (agg, bucket) = parse_agg(agg, bucket)?;
+			let align = match rest.first() {
+				Some(a) => a.parse::<u64>().map_err(|_| ERRMSG_BADTIMESTAMP)?,
+				None => 0
+			};
+			Ok((agg, bucket, align))
+		},
+		_ => Err(ERRMSG_SYNERR)
+	}
+}
+
+// a value reply, shortest representation first
+pub fn float(v: f64) -> DataType {
+	DataType::bulkStr(&v.to_string())
+}
+
+// the reply listing samples as pairs of a timestamp and a value
+pub fn reply(samples: &[(u64, f64)]) -> DataType {
+	DataType::List(samples.iter()
+		.map(|(ts, v)| DataType::List(vec![
+			DataType::Integer(*ts as i64),
+			float(*v)
+		]))
+		.collect())
+}
+
+impl Series {
+	pub fn label(&self, l: &str) -> Option<&str> {
+		self.labels.iter().find(|(n, _)| n == l).map(|(_, v)| v.as_str())
+	}
+
+	pub fn source(&self) -> Option<&str> {
+		self.source.as_deref()
+	}
+
+	pub fn has_rules(&self) -> bool {
+		!self.rules.is_empty()
+	}
+
+	pub fn set_source(&mut self, src: Option<&str>) {
+		self.source = src.map(|s| s.to_string());
+	}
+
+	pub fn add_rule(&mut self, dest: &str, rule: (Agg, u64, u64)) {
+		let (agg, bucket, align) = rule;
+		self.rules.push(Rule {
+			dest: dest.to_string(), agg, bucket, align, open: None
+		});
+	}
+
+	pub fn del_rule(&mut self, dest: &str) -> bool {
+		let n = self.rules.len();
+		self.rules.retain(|r| r.dest != dest);
+		n != self.rules.len()
+	}
+
+	pub fn count(&self) -> u64 {
+		self.chunks.iter().map(|c| c.count).sum()
+	}
+
+	// the last sample
+	pub fn last(&self) -> Option<(u64, f64)> {
+		self.chunks.last().map(|c| (c.last, f64::from_bits(c.value)))
+	}
+
+	// the oldest timestamp still kept
+	fn cutoff(&self) -> u64 {
+		match (self.retention, self.last()) {
+			(0, _) | (_, None) => 0,
+			(r, Some((last, _))) => last.saturating_sub(r)
+		}
+	}
+
+	// the samples from one timestamp to another, both included
+	pub fn range(&self, from: u64, to: u64) -> Vec<(u64, f64)> {
+		let from = from.max(self.cutoff());
+		self.chunks.iter()
+			.filter(|c| from <= c.last && c.first <= to)
+			.flat_map(|c| c.samples().unwrap_or_default())
+			.filter(|(ts, _)| (from..=to).contains(ts))
+			.collect()
+	}
+
+	// the samples a TS.RANGE picks, aggregated as asked
+	pub fn query(&self, r: &Range) -> Vec<(u64, f64)> {
+		let mut samples = self.range(r.from, r.to);
+		if let Some(ts) = &r.timestamps {
+			samples.retain(|(t, _)| ts.binary_search(t).is_ok());
+		}
+		if let Some((min, max)) = r.values {
+			samples.retain(|(_, v)| min <= *v && *v <= max);
+		}
+		if let Some((agg, bucket)) = r.aggregation {
+			samples = aggregate(&samples, agg, bucket, r.align);
+		}
+		if r.rev {
+			samples.reverse();
+		}
+		samples.truncate(r.count.unwrap_or(usize::MAX));
+		samples
+	}
+
+	// store a sample, settling on a value by policy, or the series' own
+	// policy, when there is one at ts already; gives the samples the
+	// rules downsampled into their destinations
+	pub fn add(&mut self, ts: u64, v: f64, policy: Option<Policy>)
+		-> Result<Vec<(String, u64, f64)>, &'static str> {
+		if ts < self.cutoff() {
+			return Err(ERRMSG_RETENTION);
+		}
+		let appended = self.last().is_none_or(|(last, _)| last < ts);
+		let v = match appended {
+			true => {
+				self.append(ts, v);
+				v
+			},
+			false => self.upsert(ts, v, policy.unwrap_or(self.policy))?
+		};
+		let cutoff = self.cutoff();
+		while 1 < self.chunks.len() && self.chunks[0].last < cutoff {
+			self.chunks.remove(0);
+		}
+		Ok(self.downsample(ts, v, appended))
+	}
+
+	fn append(&mut self, ts: u64, v: f64) {
+		match self.chunks.last_mut() {
+			Some(c) if (c.bits.len() as u64) < self.chunk_size => c.push(ts, v),
+			_ => self.chunks.push(Chunk::from(&[(ts, v)]))
+		}
+	}
+
+	// store a sample at or before the last one, giving the value kept
+	fn upsert(&mut self, ts: u64, v: f64, policy: Policy)
+		-> Result<f64, &'static str> {
+		let i = self.chunks.iter().position(|c| ts <= c.last).unwrap();
+		let mut samples = self.chunks[i].samples().unwrap_or_default();
+		let v = match samples.binary_search_by_key(&ts, |(t, _)| *t) {
+			Ok(j) => {
+				let old = samples[j].1;
+				samples[j].1 = match policy {
+					Policy::Block => return Err(ERRMSG_BLOCKED),
+					Policy::First => old,
+					Policy::Last => v,
+					Policy::Min => old.min(v),
+					Policy::Max => old.max(v),
+					Policy::Sum => old + v
+				};
+				samples[j].1
+			},
+			Err(j) => {
+				samples.insert(j, (ts, v));
+				v
+			}
+		};
+		self.chunks[i] = Chunk::from(&samples);
+		Ok(v)
+	}
+
+	// feed a stored sample to the rules, closing buckets it is beyond and
+	// going over those it changed again
+	fn downsample(&mut self, ts: u64, v: f64, appended: bool)
+		-> Vec<(String, u64, f64)> {
+		let mut rules = std::mem::take(&mut self.rules);
+		let mut out = vec![];
+		for r in rules.iter_mut() {
+			let b = bucket_of(ts, r.bucket, r.align);
+			let sum_up = |s: &Series| {
+				let mut acc = Acc::default();
+				let end = b.saturating_add(r.bucket - 1);
+				s.range(b, end).iter().for_each(|(_, v)| acc.add(*v));
+				acc
+			};
+			match r.open.as_mut() {
+				Some((start, _)) if b < *start => {
+					let acc = sum_up(self);
+					if 0 < acc.count {
+						out.push((r.dest.clone(), b, acc.value(r.agg)));
+					}
+				},
+				Some((start, acc)) if b == *start => match appended {
+					true => acc.add(v),
+					false => *acc = sum_up(self)
+				},
+				_ => {
+					if let Some((start, acc)) = r.open.take() {
+						out.push((r.dest.clone(), start, acc.value(r.agg)));
+					}
+					r.open = Some((b, sum_up(self)));
+				}
+			}
+		}
+		self.rules = rules;
+		out
+	}
+
+	// bytes taken by the chunks
+	pub fn size(&self) -> usize {
+		self.chunks.iter().map(|c| c.bits.capacity()).sum()
+	}
+
+	pub fn info(&self) -> DataType {
+		let (first, last) = match (self.chunks.first(), self.chunks.last()) {
+			(Some(f), Some(l)) => (f.first as i64, l.last as i64),
+			_ => (0, 0)
+		};
+		let policy = POLICIES.iter().find(|(p, _)| *p == self.policy)
+			.map_or("block", |(_, n)| n);
+		let labels = self.labels.iter()
+			.map(|(l, v)| DataType::List(vec![
+				DataType::bulkStr(l), DataType::bulkStr(v)
+			]))
+			.collect();
+		let rules = self.rules.iter()
+			.map(|r| DataType::List(vec![
+				DataType::bulkStr(&r.dest),
+				DataType::Integer(r.bucket as i64),
+				DataType::str(&AGGS.iter().find(|(a, _)| *a == r.agg)
+					.map_or("", |(_, n)| n).to_ascii_uppercase()),
+				DataType::Integer(r.align as i64)
+			]))
+			.collect();
+		let info = [
+			("totalSamples", DataType::Integer(self.count() as i64)),
+			("memoryUsage", DataType::Integer(self.size() as i64)),
+			("firstTimestamp", DataType::Integer(first)),
+			("lastTimestamp", DataType::Integer(last)),
+			("retentionTime", DataType::Integer(self.retention as i64)),
+			("chunkCount", DataType::Integer(self.chunks.len() as i64)),
+			("chunkSize", DataType::Integer(self.chunk_size as i64)),
+			("chunkType", DataType::str("compressed")),
+			("duplicatePolicy", DataType::str(policy)),
+			("labels", DataType::List(labels)),
+			("sourceKey", match &self.source {
+				Some(s) => DataType::bulkStr(s),
+				None => DataType::Null
+			}),
+			("rules", DataType::List(rules))
+		];
+		DataType::List(info.into_iter()
+			.flat_map(|(n, v)| [DataType::str(n), v])
+			.collect())
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut v = MAGIC.to_vec();
+		let policy = POLICIES.iter().position(|(p, _)| *p == self.policy);
+		for n in [self.retention, self.chunk_size, policy.unwrap() as u64] {
+			v.extend_from_slice(&n.to_le_bytes());
+		}
+		let mut strs = vec![self.source.as_deref().unwrap_or("")];
+		strs.extend(self.labels.iter().flat_map(|(l, x)| [l, x])
+			.map(|s| s.as_str()));
+		v.extend_from_slice(&(self.labels.len() as u64).to_le_bytes());
+		strs.iter().for_each(|s| put_str(&mut v, s));
+		v.extend_from_slice(&(self.rules.len() as u64).to_le_bytes());
+		for r in self.rules.iter() {
+			put_str(&mut v, &r.dest);
+			let agg = AGGS.iter().position(|(a, _)| *a == r.agg).unwrap();
+			for n in [agg as u64, r.bucket, r.align] {
+				v.extend_from_slice(&n.to_le_bytes());
+			}
+			match &r.open {
+				Some((start, acc)) => {
+					v.extend_from_slice(&start.to_le_bytes());
+					acc.to_bytes(&mut v);
+				},
+				None => v.extend_from_slice(&u64::MAX.to_le_bytes())
+			}
+		}
+		v.extend_from_slice(&(self.chunks.len() as u64).to_le_bytes());
+		for c in self.chunks.iter() {
+			v.extend_from_slice(&(c.len as u64).to_le_bytes());
+			v.extend_from_slice(&c.count.to_le_bytes());
+			v.extend_from_slice(&c.bits);
+		}
+		v
+	}
+
+	pub fn parse(mut b: &[u8]) -> Result<Series, &'static str> {
+		match read(&mut b) {
+			Some(s) if b.is_empty() => Ok(s),
+			_ => Err(ERRMSG_BADDATA)
+		}
+	}
+}
+
+fn put_str(v: &mut Vec<u8>, s: &str) {
+	let s = bytes::encode(s);
+	v.extend_from_slice(&(s.len() as u64).to_le_bytes());
+	v.extend_from_slice(&s);
+}
+
+fn take_str(b: &mut &[u8]) -> Option<String> {
+	let n = bytes::take_u64(b)? as usize;
+	Some(bytes::decode(bytes::take(b, n)?))
+}
+
+// the rule serialized at the start of b
+fn read_rule(b: &mut &[u8]) -> Option<Rule> {
+	let dest = take_str(b)?;
+	let agg = AGGS.get(bytes::take_u64(b)? as usize)?.0;
+	let bucket = bytes::take_u64(b)?;
+	let align = bytes::take_u64(b)?;
+	let open = match bytes::take_u64(b)? {
+		u64::MAX => None,
+		start => {
+			let count = bytes::take_u64(b)?;
+			let mut ns = [0f64; 7];
+			for n in ns.iter_mut() {
+				*n = f64::from_bits(bytes::take_u64(b)?);
+			}
+			let [sum, min, max, first, last, mean, m2] = ns;
+			Some((start, Acc {count, sum, min, max, first, last, mean, m2}))
+		}
+	};
+	match 0 < bucket {
+		true => Some(Rule {dest, agg, bucket, align, open}),
+		false => None
+	}
+}
+
+// the chunk serialized at the start of b, checked by unpacking it and
+// packing the samples again
+fn read_chunk(b: &mut &[u8]) -> Option<Chunk> {
+	let len = bytes::take_u64(b)? as usize;
+	let count = bytes::take_u64(b)?;
+	let bits = bytes::take(b, len.div_ceil(8))?.to_vec();
+	if 0 == count || len < count as usize {
+		return None;
+	}
+	let c = Chunk {bits, len, count, ..Chunk::default()};
+	let samples = c.samples()?;
+	if samples.windows(2).any(|w| w[1].0 <= w[0].0) {
+		return None;
+	}
+	let packed = Chunk::from(&samples);
+	match packed.bits == c.bits && packed.len == c.len {
+		true => Some(packed),
+		false => None
+	}
+}
+
+// the series serialized at the start of b
+fn read(b: &mut &[u8]) -> Option<Series> {
+	if MAGIC != bytes::take(b, MAGIC.len())? {
+		return None;
+	}
+	let retention = bytes::take_u64(b)?;
+	let chunk_size = bytes::take_u64(b)?;
+	let policy = POLICIES.get(bytes::take_u64(b)? as usize)?.0;
+	let nlabels = bytes::take_u64(b)?;
+	let source = match take_str(b)? {
+		s if s.is_empty() => None,
+		s => Some(s)
+	};
+	let mut s = Series {retention, chunk_size, policy, source,
+		..Series::default()};
+	for _ in 0..nlabels {
+		s.labels.push((take_str(b)?, take_str(b)?));
+	}
+	for _ in 0..bytes::take_u64(b)? {
+		s.rules.push(read_rule(b)?);
+	}
+	for _ in 0..bytes::take_u64(b)? {
+		s.chunks.push(read_chunk(b)?);
+	}
+	match s.chunks.windows(2).all(|w| w[0].last < w[1].first) {
+		true => Some(s),
+		false => None
+	}
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,44 @@
+use serial_test::serial;
+use super::*;
+
+fn prms(ps: &[&str]) -> Vec<String> {
+	ps.iter().map(|p| p.to_string()).collect()
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	let samples: Vec<(u64, f64)> = (0..1000u64)
+		.map(|i| (1000 + 10 * i + i % 3, (i % 7) as f64 * 0.5))
+		.chain([(u64::MAX - 1, f64::MIN), (u64::MAX, -0.0)])
+		.collect();
+	let c = Chunk::from(&samples);
+	assert_eq!(c.samples(), Some(samples.clone()));
+	assert!(c.bits.len() < 3 * samples.len());
+	let o = parse_options(&prms(&["retention", "100", "chunk_size", "48",
+		"labels", "room", "a", "kind", "temp"]), 0).unwrap();
+	let mut s = o.series;
+	assert_eq!(s.add(50, 1.0, None), Ok(vec![]));
+	s.add_rule("avg", (Agg::Avg, 10, 0));
+	for ts in 51..=120 {
+		s.add(ts, ts as f64, None).unwrap();
+	}
+	assert!(1 < s.chunks.len());
+	assert_eq!(s.add(10, 1.0, None), Err(ERRMSG_RETENTION));
+	assert_eq!(s.add(120, 1.0, None), Err(ERRMSG_BLOCKED));
+	assert_eq!(s.add(120, 1.0, Some(Policy::Sum)).unwrap(), vec![]);
+	assert_eq!(s.last(), Some((120, 121.0)));
+	let out = s.add(130, 0.0, None).unwrap();
+	assert_eq!(out, vec![("avg".to_string(), 120, 121.0)]);
+	assert_eq!(s.range(0, 25).len(), 0);
+	let r = parse_range(&prms(&["-", "+", "aggregation", "max", "20",
+		"count", "2"]), true, false).unwrap();
+	assert_eq!(s.query(&r), vec![(120, 121.0), (100, 119.0)]);
+	assert_eq!(parse_range(&prms(&["0", "+"]), false, true),
+		Err(ERRMSG_NOFILTER));
+	let r = parse_range(&prms(&["0", "+", "withlabels", "filter",
+		"room=(a,b)", "floor="]), false, true).unwrap();
+	assert!(r.matches(&s));
+	assert_eq!(parse_filter(&prms(&["room!=a"])), Err(ERRMSG_NOMATCHER));
+	assert_eq!(Series::parse(&s.to_bytes()), Ok(s.clone()));
+}
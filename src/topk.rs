@@ -0,0 +1,222 @@
+use std::cmp::Reverse;
+
+use super::bytes;
+use super::hll::murmur64a;
+
+pub const ERRMSG_BADDATA: &str = "ERR received bad data";
+pub const ERRMSG_EXISTS: &str = "ERR TopK: key already exists";
+pub const ERRMSG_NOKEY: &str = "ERR TopK: key does not exist";
+const ERRMSG_BADDECAY: &str =
+	"ERR TopK: invalid decay value. must be '<= 1' & '> 0'";
+const ERRMSG_BADDEPTH: &str = "ERR TopK: invalid depth";
+const ERRMSG_BADINCR: &str = "ERR TopK: increment must be an integer \
+	greater or equal to 1 and less than or equal to 100000";
+const ERRMSG_BADK: &str = "ERR TopK: invalid k";
+const ERRMSG_BADWIDTH: &str = "ERR TopK: invalid width";
+
+const MAGIC: &[u8] = b"KTK1";
+const FP_SEED: u64 = 0x5bd1_e995;
+const MAX_BUCKETS: usize = 1 << 26;
+const MAX_INCR: u64 = 100000;
+
+// the k heaviest items by HeavyKeeper, depth rows of width buckets each
+// holding the fingerprint of the item owning it and its count; a colliding
+// item decays the count with probability decay^count, taking the bucket
+// over at 0, the dice being rolled from a state of the sketch itself so
+// that replicas stay identical
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopK {
+	k: usize,
+	width: usize,
+	depth: usize,
+	decay: f64,
+	seed: u64,
+	fps: Vec<u64>,
+	counts: Vec<u64>,
+	// the top items and their counts, heaviest first
+	top: Vec<(u64, String)>
+}
+
+impl Eq for TopK {}
+
+// TOPK [WIDTH DEPTH DECAY]
+pub fn parse_reserve(prms: &[String]) -> Result<TopK, &'static str> {
+	let k = match prms[0].parse::<usize>() {
+		Ok(n) if 0 < n && n <= MAX_BUCKETS => n,
+		_ => return Err(ERRMSG_BADK)
+	};
+	if 1 == prms.len() {
+		return Ok(TopK::new(k, 8, 7, 0.9));
+	}
+	let width = match prms[1].parse::<usize>() {
+		Ok(n) if 0 < n => n,
+		_ => return Err(ERRMSG_BADWIDTH)
+	};
+	let depth = match prms[2].parse::<usize>() {
+		Ok(n) if 0 < n && n.saturating_mul(width) <= MAX_BUCKETS => n,
+		_ => return Err(ERRMSG_BADDEPTH)
+	};
+	match prms[3].parse::<f64>() {
+		Ok(d) if 0.0 < d && d <= 1.0 => Ok(TopK::new(k, width, depth, d)),
+		_ => Err(ERRMSG_BADDECAY)
+	}
+}
+
+// ITEM INCREMENT [ITEM INCREMENT ...]
+pub fn parse_incrs(prms: &[String])
+	-> Result<Vec<(&str, u64)>, &'static str> {
+	prms.chunks(2).map(|p| match p.get(1).map(|n| n.parse::<u64>()) {
+		Some(Ok(n)) if (1..=MAX_INCR).contains(&n) =>
+			Ok((p[0].as_str(), n)),
+		_ => Err(ERRMSG_BADINCR)
+	}).collect()
+}
+
+impl TopK {
+	pub fn new(k: usize, width: usize, depth: usize, decay: f64) -> TopK {
+		TopK {
+			k,
+			width,
+			depth,
+			decay,
+			seed: 0x2545_f491_4f6c_dd1d,
+			fps: vec![0; width * depth],
+			counts: vec![0; width * depth],
+			top: vec![]
+		}
+	}
+
+	// xorshift64*, a number in [0, 1)
+	fn roll(&mut self) -> f64 {
+		self.seed ^= self.seed >> 12;
+		self.seed ^= self.seed << 25;
+		self.seed ^= self.seed >> 27;
+		(self.seed.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64
+			/ (1u64 << 53) as f64
+	}
+
+	// count item n more times, returning the item it pushed out of the top
+	pub fn incr(&mut self, item: &str, n: u64) -> Option<String> {
+		let b = bytes::encode(item);
+		let fp = murmur64a(&b, FP_SEED);
+		let mut max = 0;
+		for i in 0..self.depth {
+			let c = i * self.width
+				+ (murmur64a(&b, i as u64) % self.width as u64) as usize;
+			if 0 == self.counts[c] || fp == self.fps[c] {
+				self.fps[c] = fp;
+				self.counts[c] = self.counts[c].saturating_add(n);
+				max = max.max(self.counts[c]);
+				continue;
+			}
+			for left in (1..=n).rev() {
+				if self.roll() < self.decay.powf(self.counts[c] as f64) {
+					self.counts[c] -= 1;
+					if 0 == self.counts[c] {
+						self.fps[c] = fp;
+						self.counts[c] = left;
+						max = max.max(left);
+						break;
+					}
+				}
+			}
+		}
+		self.rank(item, max)
+	}
+
+	// place item at count in the top, returning the item it replaced
+	fn rank(&mut self, item: &str, count: u64) -> Option<String> {
+		let out = match self.top.iter().position(|(_, e)| e == item) {
+			Some(i) => {
+				self.top[i].0 = count;
+				None
+			},
+			None if self.top.len() < self.k => {
+				self.top.push((count, item.to_string()));
+				None
+			},
+			None if self.top.last().is_some_and(|(n, _)| *n < count) => {
+				let (_, out) = self.top.pop().unwrap();
+				self.top.push((count, item.to_string()));
+				Some(out)
+			},
+			None => return None
+		};
+		self.top.sort_by_key(|(n, _)| Reverse(*n));
+		out
+	}
+
+	pub fn contains(&self, item: &str) -> bool {
+		self.top.iter().any(|(_, e)| e == item)
+	}
+
+	// the top items, heaviest first
+	pub fn list(&self) -> impl Iterator<Item = (&str, u64)> {
+		self.top.iter().map(|(n, e)| (e.as_str(), *n))
+	}
+
+	// bytes taken by the buckets and the top items
+	pub fn size(&self) -> usize {
+		16 * self.counts.len()
+			+ self.top.iter().map(|(_, e)| 8 + e.capacity()).sum::<usize>()
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut v = MAGIC.to_vec();
+		for n in [self.k as u64, self.width as u64, self.depth as u64,
+			self.decay.to_bits(), self.seed, self.top.len() as u64] {
+			v.extend_from_slice(&n.to_le_bytes());
+		}
+		for (fp, n) in self.fps.iter().zip(self.counts.iter()) {
+			v.extend_from_slice(&fp.to_le_bytes());
+			v.extend_from_slice(&n.to_le_bytes());
+		}
+		for (n, e) in self.top.iter() {
+			let e = bytes::encode(e);
+			v.extend_from_slice(&n.to_le_bytes());
+			v.extend_from_slice(&(e.len() as u64).to_le_bytes());
+			v.extend_from_slice(&e);
+		}
+		v
+	}
+
+	pub fn parse(mut b: &[u8]) -> Result<TopK, &'static str> {
+		match read(&mut b) {
+			Some(t) if b.is_empty() => Ok(t),
+			_ => Err(ERRMSG_BADDATA)
+		}
+	}
+}
+
+// the sketch serialized at the start of b
+fn read(b: &mut &[u8]) -> Option<TopK> {
+	if MAGIC != bytes::take(b, MAGIC.len())? {
+		return None;
+	}
+	let k = bytes::take_u64(b)? as usize;
+	let width = bytes::take_u64(b)? as usize;
+	let depth = bytes::take_u64(b)? as usize;
+	let decay = f64::from_bits(bytes::take_u64(b)?);
+	let seed = bytes::take_u64(b)?;
+	let n = bytes::take_u64(b)? as usize;
+	if 0 == k || 0 == width || 0 == depth || k < n || 0 == seed ||
+		!(0.0 < decay && decay <= 1.0) ||
+		MAX_BUCKETS < width.saturating_mul(depth) {
+		return None;
+	}
+	let mut t = TopK::new(k, width, depth, decay);
+	t.seed = seed;
+	for c in 0..width * depth {
+		t.fps[c] = bytes::take_u64(b)?;
+		t.counts[c] = bytes::take_u64(b)?;
+	}
+	for _ in 0..n {
+		let count = bytes::take_u64(b)?;
+		let len = bytes::take_u64(b)? as usize;
+		t.top.push((count, bytes::decode(bytes::take(b, len)?)));
+	}
+	Some(t)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,31 @@
+use serial_test::serial;
+use super::*;
+
+fn prms(ps: &[&str]) -> Vec<String> {
+	ps.iter().map(|p| p.to_string()).collect()
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	assert_eq!(parse_reserve(&prms(&["0"])), Err(ERRMSG_BADK));
+	let bad = prms(&["3", "10", "4", "1.5"]);
+	assert_eq!(parse_reserve(&bad), Err(ERRMSG_BADDECAY));
+	assert_eq!(parse_incrs(&prms(&["a", "0"])), Err(ERRMSG_BADINCR));
+	let mut t = parse_reserve(&prms(&["3", "50", "4", "0.9"])).unwrap();
+	for (item, n) in [("a", 100), ("b", 80), ("c", 60)] {
+		assert_eq!(t.incr(item, n), None);
+	}
+	for i in 0..200 {
+		t.incr(&format!("x{}", i % 40), 1);
+	}
+	assert_eq!(t.incr("d", 90), Some("c".to_string()));
+	let top: Vec<&str> = t.list().map(|(e, _)| e).collect();
+	assert_eq!(top, vec!["a", "d", "b"]);
+	assert!(t.contains("d") && !t.contains("c"));
+	let mut copy = TopK::parse(&t.to_bytes()).unwrap();
+	assert_eq!(copy, t);
+	assert_eq!(copy.incr("y", 5), t.incr("y", 5));
+	assert_eq!(copy, t);
+	assert_eq!(TopK::parse(b"KTK1"), Err(ERRMSG_BADDATA));
+}
@@ -0,0 +1,292 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use lazy_static::lazy_static;
+
+use super::client;
+use super::cluster;
+use super::command;
+use super::datatype::DataType;
+use super::pubsub;
+use super::request::Request;
+
+const ERRMSG_BADREDIR: &str =
+	"ERR The client ID you want redirect to does not exist";
+const ERRMSG_BCASTOPT: &str =
+	"ERR OPTIN and OPTOUT are not compatible with BCAST";
+const ERRMSG_CACHINGNO: &str = "ERR CLIENT CACHING NO is only valid when \
+	tracking is enabled in OPTOUT mode.";
+const ERRMSG_CACHINGYES: &str = "ERR CLIENT CACHING YES is only valid when \
+	tracking is enabled in OPTIN mode.";
+const ERRMSG_NOCACHING: &str = "ERR CLIENT CACHING can be called only when \
+	the client is in tracking mode with OPTIN or OPTOUT mode enabled";
+const ERRMSG_OPTINOUT: &str = "ERR You can't use both OPTIN and OPTOUT";
+const ERRMSG_PREFIXBCAST: &str =
+	"ERR PREFIX option requires BCAST mode to be enabled";
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+
+// invalidation messages go out as messages of this channel
+pub const CHANNEL: &str = "__redis__:invalidate";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Mode {
+	// keys read by the client are remembered
+	Default,
+	// every key starting with one of the prefixes is reported
+	Bcast,
+	// only keys read right after CLIENT CACHING YES are remembered
+	Optin,
+	// keys read right after CLIENT CACHING NO are not remembered
+	Optout
+}
+
+struct Tracker {
+	mode: Mode,
+	// client to receive the invalidations, 0 for the tracking client
+	redirect: u64,
+	prefixes: Vec<String>,
+	noloop: bool,
+	// CLIENT CACHING answer, for the command following it only
+	caching: Option<bool>,
+	// whether CLIENT CACHING was the last command
+	fresh: bool
+}
+
+#[derive(Default)]
+struct Table {
+	trackers: HashMap<u64, Tracker>,
+	// clients that read a key, in default, OPTIN and OPTOUT modes
+	keys: HashMap<String, HashSet<u64>>
+}
+
+lazy_static! {
+	static ref TABLE: Mutex<Table> = Mutex::new(Table::default());
+}
+
+// keeps the read and write paths cheap when nobody tracks
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// CLIENT TRACKING ON|OFF [REDIRECT id] [PREFIX prefix ...] [BCAST] [OPTIN]
+// [OPTOUT] [NOLOOP]
+pub fn tracking(prms: &[String]) -> Result<DataType, &'static str> {
+	let id = client::current();
+	match prms[0].to_ascii_lowercase().as_str() {
+		"off" => {
+			forget(id);
+			return Ok(DataType::str("OK"));
+		},
+		"on" => {},
+		_ => return Err(ERRMSG_SYNERR)
+	}
+	let mut t = Tracker {
+		mode: Mode::Default,
+		redirect: 0,
+		prefixes: vec![],
+		noloop: false,
+		caching: None,
+		fresh: false
+	};
+	let (mut bcast, mut optin, mut optout) = (false, false, false);
+	let mut i = 1;
+	while i < prms.len() {
+		match prms[i].to_ascii_lowercase().as_str() {
+			"bcast" => bcast = true,
+			"noloop" => t.noloop = true,
+			"optin" => optin = true,
+			"optout" => optout = true,
+			"prefix" if i + 1 < prms.len() => {
+				t.prefixes.push(prms[i + 1].clone());
+				i += 1;
+			},
+			"redirect" if i + 1 < prms.len() => {
+				t.redirect = match prms[i + 1].parse::<u64>() {
+					Ok(n) if client::exists(n) => n,
+					_ => return Err(ERRMSG_BADREDIR)
+				};
+				i += 1;
+			},
+			_ => return Err(ERRMSG_SYNERR)
+		}
+		i += 1;
+	}
+	t.mode = match (bcast, optin, optout) {
+		(_, true, true) => return Err(ERRMSG_OPTINOUT),
+		(true, true, _) | (true, _, true) => return Err(ERRMSG_BCASTOPT),
+		(true, _, _) => Mode::Bcast,
+		(_, true, _) => Mode::Optin,
+		(_, _, true) => Mode::Optout,
+		_ => Mode::Default
+	};
+	if !bcast && !t.prefixes.is_empty() {
+		return Err(ERRMSG_PREFIXBCAST);
+	}
+	if TABLE.lock().unwrap().trackers.insert(id, t).is_none() {
+		COUNT.fetch_add(1, Ordering::Relaxed);
+	}
+	client::set_flag(id, 't');
+	Ok(DataType::str("OK"))
+}
+
+// CLIENT CACHING YES|NO
+pub fn caching(yes: bool) -> Result<DataType, &'static str> {
+	let mut table = TABLE.lock().unwrap();
+	let t = match table.trackers.get_mut(&client::current()) {
+		Some(t) => t,
+		None => return Err(ERRMSG_NOCACHING)
+	};
+	match (t.mode, yes) {
+		(Mode::Optin, true) | (Mode::Optout, false) => {
+			t.caching = Some(yes);
+			t.fresh = true;
+			Ok(DataType::str("OK"))
+		},
+		(Mode::Optin, false) => Err(ERRMSG_CACHINGNO),
+		(Mode::Optout, true) => Err(ERRMSG_CACHINGYES),
+		_ => Err(ERRMSG_NOCACHING)
+	}
+}
+
+pub fn getredir() -> Result<DataType, &'static str> {
+	match TABLE.lock().unwrap().trackers.get(&client::current()) {
+		Some(t) => Ok(DataType::Integer(t.redirect as i64)),
+		None => Ok(DataType::Integer(-1))
+	}
+}
+
+pub fn trackinginfo() -> Result<DataType, &'static str> {
+	let table = TABLE.lock().unwrap();
+	let (flags, redirect, prefixes) =
+		match table.trackers.get(&client::current()) {
+			Some(t) => {
+				let mut flags = vec!["on"];
+				match t.mode {
+					Mode::Bcast => flags.push("bcast"),
+					Mode::Optin => flags.push("optin"),
+					Mode::Optout => flags.push("optout"),
+					Mode::Default => {}
+				}
+				match t.caching {
+					Some(true) => flags.push("caching-yes"),
+					Some(false) => flags.push("caching-no"),
+					None => {}
+				}
+				if t.noloop {
+					flags.push("noloop");
+				}
+				if 0 < t.redirect && !client::exists(t.redirect) {
+					flags.push("broken_redirect");
+				}
+				(flags, t.redirect as i64, t.prefixes.clone())
+			},
+			None => (vec!["off"], -1, vec![])
+		};
+	Ok(DataType::List(vec![
+		DataType::bulkStr("flags"),
+		DataType::List(flags.iter().map(|f| DataType::bulkStr(f)).collect()),
+		DataType::bulkStr("redirect"),
+		DataType::Integer(redirect),
+		DataType::bulkStr("prefixes"),
+		DataType::List(prefixes.iter().map(|p| DataType::bulkStr(p)).collect())
+	]))
+}
+
+// remember the keys read by a request of the current client
+pub fn remember(req: &Request) {
+	if 0 == COUNT.load(Ordering::Relaxed) || command::is_write(req) {
+		return;
+	}
+	let id = client::current();
+	let mut guard = TABLE.lock().unwrap();
+	let table = &mut *guard;
+	let wanted = match table.trackers.get(&id) {
+		Some(t) => match t.mode {
+			Mode::Default => true,
+			Mode::Bcast => false,
+			Mode::Optin => Some(true) == t.caching,
+			Mode::Optout => Some(false) != t.caching
+		},
+		None => false
+	};
+	if !wanted {
+		return;
+	}
+	for k in cluster::keys(req) {
+		table.keys.entry(k.to_string()).or_default().insert(id);
+	}
+}
+
+// a command of the current client completed, which ends the effect of a
+// CLIENT CACHING before it
+pub fn done() {
+	if 0 == COUNT.load(Ordering::Relaxed) {
+		return;
+	}
+	if let Some(t) = TABLE.lock().unwrap().trackers.get_mut(&client::current())
+	{
+		match t.fresh {
+			true => t.fresh = false,
+			false => t.caching = None
+		}
+	}
+}
+
+// tell the clients interested in key that it changed
+pub fn invalidate(key: &str) {
+	if 0 == COUNT.load(Ordering::Relaxed) {
+		return;
+	}
+	let me = client::current();
+	let targets = {
+		let mut guard = TABLE.lock().unwrap();
+		let table = &mut *guard;
+		let readers = table.keys.remove(key).unwrap_or_default();
+		table.trackers.iter()
+			.filter(|(id, t)| match t.mode {
+				Mode::Bcast => t.prefixes.is_empty()
+					|| t.prefixes.iter().any(|p| key.starts_with(p.as_str())),
+				_ => readers.contains(id)
+			})
+			.filter(|(id, t)| !t.noloop || **id != me)
+			.map(|(id, t)| if 0 < t.redirect {t.redirect} else {*id})
+			.collect::<Vec<_>>()
+	};
+	for id in targets {
+		pubsub::deliver(id, CHANNEL, DataType::List(vec![
+			DataType::bulkStr(key)
+		]));
+	}
+}
+
+// every key is gone, as after FLUSHALL
+pub fn invalidate_all() {
+	if 0 == COUNT.load(Ordering::Relaxed) {
+		return;
+	}
+	let targets = {
+		let mut table = TABLE.lock().unwrap();
+		table.keys.clear();
+		table.trackers.iter()
+			.map(|(id, t)| if 0 < t.redirect {t.redirect} else {*id})
+			.collect::<Vec<_>>()
+	};
+	for id in targets {
+		pubsub::deliver(id, CHANNEL, DataType::Null);
+	}
+}
+
+// stop tracking for client id, as on CLIENT TRACKING OFF or disconnection
+pub fn forget(id: u64) {
+	let mut table = TABLE.lock().unwrap();
+	if table.trackers.remove(&id).is_some() {
+		COUNT.fetch_sub(1, Ordering::Relaxed);
+		table.keys.retain(|_, ids| {
+			ids.remove(&id);
+			!ids.is_empty()
+		});
+		client::clear_flag(id, 't');
+	}
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,90 @@
+use serial_test::serial;
+use super::*;
+
+fn req(cmd: &str, prms: &[&str]) -> Request {
+	Request {
+		command: cmd.to_string(),
+		parameters: prms.iter().map(|s| s.to_string()).collect()
+	}
+}
+
+fn strs(v: &[&str]) -> Vec<String> {
+	v.iter().map(|s| s.to_string()).collect()
+}
+
+fn tracked(k: &str) -> bool {
+	TABLE.lock().unwrap().keys.contains_key(k)
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	client::set_current(7);
+	assert_eq!(getredir(), Ok(DataType::Integer(-1)));
+	assert_eq!(tracking(&strs(&["on", "redirect", "x"])), Err(ERRMSG_BADREDIR));
+	assert_eq!(
+		tracking(&strs(&["on", "prefix", "a"])),
+		Err(ERRMSG_PREFIXBCAST)
+	);
+	assert_eq!(
+		tracking(&strs(&["on", "optin", "optout"])),
+		Err(ERRMSG_OPTINOUT)
+	);
+	assert_eq!(
+		tracking(&strs(&["on", "bcast", "optin"])),
+		Err(ERRMSG_BCASTOPT)
+	);
+	assert_eq!(tracking(&strs(&["on"])), Ok(DataType::str("OK")));
+	assert_eq!(getredir(), Ok(DataType::Integer(0)));
+	assert_eq!(caching(true), Err(ERRMSG_NOCACHING));
+	remember(&req("mget", &["a", "b"]));
+	remember(&req("set", &["c", "v"]));
+	assert!(tracked("a") && tracked("b") && !tracked("c"));
+	invalidate("a");
+	assert!(!tracked("a") && tracked("b"));
+	assert_eq!(tracking(&strs(&["off"])), Ok(DataType::str("OK")));
+	assert!(!tracked("b"));
+	assert_eq!(0, COUNT.load(Ordering::Relaxed));
+	client::set_current(0);
+}
+
+#[test]
+#[serial]
+fn plan2() {
+	client::set_current(7);
+	assert_eq!(tracking(&strs(&["on", "optin"])), Ok(DataType::str("OK")));
+	assert_eq!(caching(false), Err(ERRMSG_CACHINGNO));
+	remember(&req("get", &["a"]));
+	done();
+	assert_eq!(caching(true), Ok(DataType::str("OK")));
+	done();
+	remember(&req("get", &["b"]));
+	done();
+	remember(&req("get", &["c"]));
+	done();
+	assert!(!tracked("a") && tracked("b") && !tracked("c"));
+	assert_eq!(
+		tracking(&strs(&["on", "bcast", "prefix", "p:", "noloop"])),
+		Ok(DataType::str("OK"))
+	);
+	assert_eq!(
+		trackinginfo(),
+		Ok(DataType::List(vec![
+			DataType::bulkStr("flags"),
+			DataType::List(vec![
+				DataType::bulkStr("on"),
+				DataType::bulkStr("bcast"),
+				DataType::bulkStr("noloop")
+			]),
+			DataType::bulkStr("redirect"),
+			DataType::Integer(0),
+			DataType::bulkStr("prefixes"),
+			DataType::List(vec![DataType::bulkStr("p:")])
+		]))
+	);
+	remember(&req("get", &["p:1"]));
+	assert!(!tracked("p:1"));
+	forget(7);
+	assert_eq!(0, COUNT.load(Ordering::Relaxed));
+	client::set_current(0);
+}
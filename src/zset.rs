@@ -0,0 +1,151 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+
+const ERRMSG_NOTFLOAT: &str = "ERR value is not a valid float";
+const ERRMSG_NXXX: &str =
+	"ERR XX and NX options at the same time are not compatible";
+const ERRMSG_SYNERR: &str = "ERR Syntax error";
+
+// a score ordered with f64::total_cmp, NaN never getting in
+#[derive(Clone, Copy, Debug)]
+struct Score(f64);
+
+impl PartialEq for Score {
+	fn eq(&self, o: &Self) -> bool {
+		Ordering::Equal == self.cmp(o)
+	}
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+	fn partial_cmp(&self, o: &Self) -> Option<Ordering> {
+		Some(self.cmp(o))
+	}
+}
+
+impl Ord for Score {
+	fn cmp(&self, o: &Self) -> Ordering {
+		self.0.total_cmp(&o.0)
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AddOptions {
+	pub nx: bool,
+	pub xx: bool,
+	pub ch: bool
+}
+
+// members ordered by score, then by name
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SortedSet {
+	order: BTreeSet<(Score, String)>,
+	scores: HashMap<String, u64>
+}
+
+pub fn parse_score(s: &str) -> Result<f64, &'static str> {
+	match s.parse::<f64>() {
+		Ok(n) if !n.is_nan() => Ok(n),
+		_ => Err(ERRMSG_NOTFLOAT)
+	}
+}
+
+// leading [NX | XX] [CH], returning the options and where they end
+pub fn parse_options(prms: &[String])
+	-> Result<(AddOptions, usize), &'static str> {
+	let mut o = AddOptions::default();
+	let mut i = 0;
+	while i < prms.len() {
+		match prms[i].to_ascii_lowercase().as_str() {
+			"nx" => o.nx = true,
+			"xx" => o.xx = true,
+			"ch" => o.ch = true,
+			_ => break
+		}
+		i += 1;
+	}
+	match o.nx && o.xx {
+		true => Err(ERRMSG_NXXX),
+		false => Ok((o, i))
+	}
+}
+
+// SCORE MEMBER [SCORE MEMBER ...]
+pub fn parse_pairs(prms: &[String])
+	-> Result<Vec<(f64, String)>, &'static str> {
+	if prms.is_empty() || !prms.len().is_multiple_of(2) {
+		return Err(ERRMSG_SYNERR);
+	}
+	prms.chunks(2)
+		.map(|p| Ok((parse_score(&p[0])?, p[1].clone())))
+		.collect()
+}
+
+impl SortedSet {
+	pub fn len(&self) -> usize {
+		self.scores.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.scores.is_empty()
+	}
+
+	pub fn capacity(&self) -> usize {
+		self.scores.keys().map(|m| 24 + 2 * m.capacity()).sum::<usize>()
+	}
+
+	pub fn score(&self, member: &str) -> Option<f64> {
+		self.scores.get(member).map(|bits| f64::from_bits(*bits))
+	}
+
+	// set the score of member, returning its previous one
+	pub fn insert(&mut self, member: &str, score: f64) -> Option<f64> {
+		// -0 sorts before 0 otherwise
+		let score = score + 0.0;
+		let old = self.remove(member);
+		self.order.insert((Score(score), member.to_string()));
+		self.scores.insert(member.to_string(), score.to_bits());
+		old
+	}
+
+	pub fn remove(&mut self, member: &str) -> Option<f64> {
+		let old = self.scores.remove(member).map(f64::from_bits)?;
+		self.order.remove(&(Score(old), member.to_string()));
+		Some(old)
+	}
+
+	// how many of the pairs were added, and how many got a new score
+	pub fn add(&mut self, opts: &AddOptions, pairs: &[(f64, String)])
+		-> (usize, usize) {
+		let (mut added, mut updated) = (0, 0);
+		for (score, member) in pairs.iter() {
+			match self.score(member) {
+				Some(_) if opts.nx => continue,
+				None if opts.xx => continue,
+				Some(s) if s == *score => continue,
+				Some(_) => updated += 1,
+				None => added += 1
+			}
+			self.insert(member, *score);
+		}
+		(added, updated)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (&str, f64)> {
+		self.order.iter().map(|(s, m)| (m.as_str(), s.0))
+	}
+
+	// the members scoring at least min and less than max
+	pub fn range(&self, min: f64, max: f64)
+		-> impl Iterator<Item = (&str, f64)> {
+		let max = if Score(max) < Score(min) {min} else {max};
+		let lo = Bound::Included((Score(min), String::new()));
+		let hi = Bound::Excluded((Score(max), String::new()));
+		self.order.range((lo, hi)).map(|(s, m)| (m.as_str(), s.0))
+	}
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,34 @@
+use serial_test::serial;
+use super::*;
+
+fn strs(v: &[&str]) -> Vec<String> {
+	v.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+#[serial]
+fn plan1() {
+	assert_eq!(parse_score("nan"), Err(ERRMSG_NOTFLOAT));
+	assert_eq!(parse_score("-inf"), Ok(f64::NEG_INFINITY));
+	assert_eq!(parse_options(&strs(&["nx", "xx", "1", "a"])), Err(ERRMSG_NXXX));
+	let (opts, i) = parse_options(&strs(&["ch", "1", "a"])).unwrap();
+	assert_eq!((opts.ch, i), (true, 1));
+	assert_eq!(parse_pairs(&strs(&["1"])), Err(ERRMSG_SYNERR));
+	let mut z = SortedSet::default();
+	let pairs = parse_pairs(&strs(&["2", "b", "1", "c", "1", "a"])).unwrap();
+	assert_eq!(z.add(&AddOptions::default(), &pairs), (3, 0));
+	let order = z.iter().map(|(e, _)| e).collect::<Vec<_>>();
+	assert_eq!(order, vec!["a", "c", "b"]);
+	let nx = AddOptions {nx: true, ..Default::default()};
+	assert_eq!(z.add(&nx, &[(5.0, "a".to_string())]), (0, 0));
+	let xx = AddOptions {xx: true, ..Default::default()};
+	let pairs = vec![(0.0, "a".to_string()), (0.0, "d".to_string())];
+	assert_eq!(z.add(&xx, &pairs), (0, 1));
+	assert_eq!(z.score("a"), Some(0.0));
+	let mid = z.range(1.0, 2.0).map(|(e, _)| e).collect::<Vec<_>>();
+	assert_eq!(mid, vec!["c"]);
+	assert_eq!(z.range(2.0, 1.0).count(), 0);
+	assert_eq!(z.remove("c"), Some(1.0));
+	assert_eq!(z.remove("c"), None);
+	assert_eq!(z.len(), 2);
+}